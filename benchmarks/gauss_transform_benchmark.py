@@ -0,0 +1,96 @@
+import time
+import numpy as np
+from sklearn.neighbors import KDTree
+import ironforest as irn
+from sklearn.datasets import make_blobs
+
+def silvermans_bandwidth(n: int, d: int) -> float:
+    return (n * (d + 2) / 4.0) ** (-1.0 / (d + 4))
+
+
+def benchmark_gauss_transform(points, queries, bandwidth, atol, leaf_size=32):
+    p = irn.ndutils.from_numpy(points)
+    q = irn.ndutils.from_numpy(queries)
+
+    t0 = time.perf_counter()
+    result = (
+        irn.spatial.KDTree.from_array(p, leaf_size=leaf_size, metric="euclidean")
+        .gauss_transform(q, bandwidth=bandwidth, atol=atol, normalize=True)
+    )
+    t1 = time.perf_counter()
+
+    return np.array(irn.Array.to_numpy(result)), t1 - t0
+
+
+def benchmark_aggtree(points, queries, bandwidth, atol, leaf_size=32):
+    p = irn.ndutils.from_numpy(points)
+    q = irn.ndutils.from_numpy(queries)
+
+    t0 = time.perf_counter()
+    result = (
+        irn.spatial.AggTree.from_array(p, leaf_size=leaf_size, metric="euclidean",
+                                        kernel="gaussian", bandwidth=bandwidth, atol=atol)
+        .kernel_density(q, normalize=True)
+    )
+    t1 = time.perf_counter()
+
+    return np.array(irn.Array.to_numpy(result)), t1 - t0
+
+
+def benchmark_sklearn(points, queries, bandwidth, leaf_size=32):
+    t0 = time.perf_counter()
+    result = (
+        KDTree(points, leaf_size=leaf_size, metric="euclidean")
+        .kernel_density(queries, h=bandwidth, kernel="gaussian")
+    )
+    t1 = time.perf_counter()
+
+    return np.array(result), t1 - t0
+
+
+def relative_errors(values, reference):
+    with np.errstate(divide='ignore', invalid='ignore'):
+        rel_err = np.abs(values - reference) / np.abs(reference)
+        rel_err = np.where(np.isfinite(rel_err), rel_err, 0.0)
+    return np.max(rel_err) * 100, np.mean(rel_err) * 100
+
+
+def gen_spherical_clusters():
+    n_blobs = int(n_points * 0.8)
+    n_noise = n_points - n_blobs
+
+    blobs, _ = make_blobs(n_samples=n_blobs, centers=10, cluster_std=0.1, n_features=d, random_state=seed) # type: ignore
+
+    blobs = (blobs - blobs.min(axis=0)) / (blobs.max(axis=0) - blobs.min(axis=0))
+    noise = rng.uniform(0.0, 1.0, size=(n_noise, d))
+
+    points = np.vstack([blobs, noise])
+    rng.shuffle(points)
+
+    queries, _ = make_blobs(n_samples=n_queries, centers=10, cluster_std=0.1, n_features=d, random_state=seed + 1) # type: ignore
+    queries = (queries - queries.min(axis=0)) / (queries.max(axis=0) - queries.min(axis=0))
+    return points, queries
+
+if __name__ == "__main__":
+    n_points = 100_000
+    n_queries = 10_000
+    dims = [1, 2, 3]
+    atol = 0.01
+    seed = 42
+    rng = np.random.default_rng(2)
+
+    print(f"\n{'dim':>5} {'bw':>8} {'fgt_time':>10} {'agg_time':>10} {'sk_time':>10} {'fgt_max%':>10} {'agg_max%':>10}")
+    print("-" * 70)
+
+    for d in dims:
+        points, queries = gen_spherical_clusters()
+        bw = silvermans_bandwidth(n_points, d)
+
+        fgt_vals, fgt_time = benchmark_gauss_transform(points, queries, bw, atol)
+        agg_vals, agg_time = benchmark_aggtree(points, queries, bw, atol)
+        sk_vals,  sk_time  = benchmark_sklearn(points, queries, bw)
+
+        fgt_max, _ = relative_errors(fgt_vals, sk_vals)
+        agg_max, _ = relative_errors(agg_vals, sk_vals)
+
+        print(f"{d:>5} {bw:>8.4f} {fgt_time:>10.3f} {agg_time:>10.3f} {sk_time:>10.3f} {fgt_max:>9.3f}% {agg_max:>9.3f}%")
@@ -4,6 +4,14 @@ from ironforest._core import (
     Array,
     SpatialIndex,
     TreeType,
+    TreeRecommendation,
+    recommend_tree,
+    IronForestError,
+    DimensionMismatchError,
+    EmptyIndexError,
+    InvalidKError,
+    NonFiniteError,
+    UninitializedError,
     linalg,
     ndutils,
     stats,
@@ -17,6 +25,14 @@ __all__ = [
     "Array",
     "SpatialIndex",
     "TreeType",
+    "TreeRecommendation",
+    "recommend_tree",
+    "IronForestError",
+    "DimensionMismatchError",
+    "EmptyIndexError",
+    "InvalidKError",
+    "NonFiniteError",
+    "UninitializedError",
     "ndutils",
     "linalg",
     "stats",
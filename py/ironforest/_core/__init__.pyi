@@ -450,6 +450,14 @@ class Array(Sequence[T_co], Generic[T_co]):
 
 from .spatial import SpatialIndex as SpatialIndex
 from .spatial import TreeType as TreeType
+from .spatial import TreeRecommendation as TreeRecommendation
+from .spatial import recommend_tree as recommend_tree
+from .spatial import IronForestError as IronForestError
+from .spatial import DimensionMismatchError as DimensionMismatchError
+from .spatial import EmptyIndexError as EmptyIndexError
+from .spatial import InvalidKError as InvalidKError
+from .spatial import NonFiniteError as NonFiniteError
+from .spatial import UninitializedError as UninitializedError
 
 from . import linalg as linalg
 from . import stats as stats
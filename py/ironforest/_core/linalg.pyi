@@ -1,7 +1,7 @@
 """Linear algebra routines operating on :class:`~ironforest._core.Array`.
 
 Includes matrix operations and decompositions such as matmul, dot,
-transpose, Cholesky, QR, least-squares, and eigendecomposition.
+transpose, Cholesky, QR, least-squares, eigendecomposition and SVD.
 
 Example::
 
@@ -127,6 +127,46 @@ def eigvals(a: ArrayLike) -> Array[float]:
     """
     ...
 
+def eigh(a: ArrayLike) -> tuple[Array[float], Array[float]]:
+    """Eigendecomposition of a symmetric matrix.
+
+    Uses Householder tridiagonalization and implicit-shift QL, which is much
+    faster and more accurate than :func:`eig` for symmetric input. Only the
+    lower triangle of ``a`` is read.
+
+    Returns:
+        Tuple of (eigenvalues, eigenvectors) where eigenvalues are in ascending
+        order and eigenvectors is an orthonormal 2D array with eigenvectors as
+        columns.
+
+    Raises:
+        ValueError: If matrix is not square.
+    """
+    ...
+
+def eigvalsh(a: ArrayLike) -> Array[float]:
+    """Eigenvalues of a symmetric matrix in ascending order. See :func:`eigh`."""
+    ...
+
+def svd(a: ArrayLike, full_matrices: bool = False) -> tuple[Array[float], Array[float], Array[float]]:
+    """Singular value decomposition ``a = U @ diag(S) @ Vt``.
+
+    Computed with one-sided Jacobi rotations.
+
+    Args:
+        a: Input matrix of shape (M, N).
+        full_matrices: If False (the default), U has shape (M, K) and Vt has
+            shape (K, N) with K = min(M, N). If True, U is (M, M) and Vt is
+            (N, N).
+
+    Returns:
+        Tuple of (U, S, Vt) with singular values S in descending order.
+
+    Raises:
+        ValueError: If ``a`` is not 2D.
+    """
+    ...
+
 def diagonal(a: ArrayLike, k: int | None = None) -> Array[float]:
     """Extract the k-th diagonal from a 2D array."""
     ...
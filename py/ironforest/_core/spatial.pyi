@@ -14,11 +14,30 @@ Example::
     print(result.indices)  # [0, 1] (or similar)
 """
 
-from typing import Optional, Literal, List, Tuple, overload
+from typing import Dict, Optional, Literal, List, Tuple, TypedDict, overload
 from enum import IntEnum
 from ironforest._core import Array, ArrayLike
 
 
+class IronForestError(ValueError):
+    """Base class for invalid input to spatial queries and indexes."""
+
+class DimensionMismatchError(IronForestError):
+    """Points or queries whose dimension differs from the index, or input that is not (n, dim)."""
+
+class EmptyIndexError(IronForestError):
+    """The operation needs at least one indexed point, e.g. sampling."""
+
+class InvalidKError(IronForestError):
+    """k is not a valid neighbor count (k must be at least 1)."""
+
+class NonFiniteError(IronForestError):
+    """Points or queries contain NaN or infinity under ``nan_policy="raise"``."""
+
+class UninitializedError(IronForestError):
+    """The index has no tree to query."""
+
+
 class SpatialIndex:
     """Unified spatial index with dynamic insertion and automatic rebuilds.
 
@@ -48,9 +67,11 @@ class SpatialIndex:
         metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
         rebuild_threshold: int = 1000,
         seed: int = 0,
-        projection: Literal["gaussian", "sparse"] = "gaussian",
+        projection: Literal["gaussian", "sparse", "achlioptas", "very_sparse", "hadamard"] = "gaussian",
         selection: Literal["first", "random", "variance"] = "variance",
         copy: bool = True,
+        nan_policy: Literal["raise", "drop", "ignore_dims"] = "raise",
+        workload: Literal["knn", "ann", "kde"] = "knn",
     ) -> None:
         """Construct a spatial index.
 
@@ -61,10 +82,14 @@ class SpatialIndex:
             metric: Distance metric.
             rebuild_threshold: Number of buffered points that triggers an
                 automatic tree rebuild.
-            seed: Random seed (RPTree only).
-            projection: Projection type (RPTree only).
+            seed: Random seed for RPTree projections and VPTree vantage selection.
+            projection: Projection type (RPTree only). See :class:`ProjectionReducer`.
             selection: Vantage-point selection method (VPTree only).
             copy: Whether to copy the input data.
+            nan_policy: How rows and queries containing NaN are handled, both here
+                and in :meth:`insert`. See :class:`BallTree`.
+            workload: The queries the index mostly serves. Only used by
+                ``tree_type="auto"``, see :func:`recommend_tree`.
         """
         ...
 
@@ -101,6 +126,16 @@ class SpatialIndex:
         """Element precision of the underlying tree."""
         ...
 
+    @property
+    def nan_policy(self) -> Literal["raise", "drop", "ignore_dims"]:
+        """How NaN coordinates are handled."""
+        ...
+
+    @property
+    def nan_rows(self) -> Array[int]:
+        """Input rows set aside for containing NaN, in input numbering."""
+        ...
+
     @property
     def dim(self) -> int:
         """Feature dimensionality."""
@@ -182,8 +217,10 @@ class SpatialIndex:
         self,
         queries: ArrayLike,
         bandwidth: float = 1.0,
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
         normalize: bool = False,
+        log_density: bool = False,
+        nu: float = 3.0
     ) -> float | Array[float]: ...
 
     @overload
@@ -191,8 +228,10 @@ class SpatialIndex:
         self,
         queries: None = None,
         bandwidth: float = 1.0,
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
         normalize: bool = False,
+        log_density: bool = False,
+        nu: float = 3.0
     ) -> float | Array[float]: ...
 
     @overload
@@ -203,6 +242,153 @@ class SpatialIndex:
         ...
 
 
+
+class SelectionThresholds(TypedDict, total=False):
+    """Overrides for the automatic tree selection rules."""
+    min_tree_points: int
+    """Below this many points a brute force scan is used. Default 1000."""
+    max_kd_dim: int
+    """KDTree up to this dimension. Default 16."""
+    max_tree_dim: int
+    """Above this dimension exact queries scan. Default 512."""
+    max_rp_ratio: float
+    """RPTree when intrinsic / ambient dimension is below this. Default 0.3."""
+    max_ball_intrinsic_dim: int
+    """BallTree up to this intrinsic dimension. Default 20."""
+    estimator: Literal["pca", "mle", "twonn", "correlation"]
+    """Intrinsic dimension estimator, see :func:`intrinsic_dim`. Default ``"mle"``."""
+    k: int
+    """Neighbors used by the ``"mle"`` estimator. Default 10."""
+    variance_threshold: float
+    """Explained variance defining the ``"pca"`` estimate. Default 0.95."""
+    sample_size: int
+    """Rows sampled to estimate the intrinsic dimension. Default 2000."""
+
+
+class TreeBenchmark(TypedDict):
+    build_seconds: float
+    query_seconds: float
+
+
+class TreeRecommendation:
+    """A tree chosen by :func:`recommend_tree` and the statistics behind it."""
+
+    @property
+    def tree_type(self) -> Literal["kd_tree", "ball_tree", "vp_tree", "rp_tree", "brute_force"]:
+        """The recommended tree, as reported by :attr:`SpatialIndex.tree_type`."""
+        ...
+
+    @property
+    def rule(self) -> Literal[
+        "small_n", "low_dim", "ann_high_dim", "very_high_dim",
+        "intrinsic_dim_unavailable", "low_intrinsic_ratio",
+        "low_intrinsic_dim", "high_intrinsic_dim",
+    ]:
+        """The selection rule that decided the tree."""
+        ...
+
+    @property
+    def reason(self) -> str:
+        """The rule applied to the measured statistics, in words."""
+        ...
+
+    @property
+    def workload(self) -> Literal["knn", "ann", "kde"]: ...
+
+    @property
+    def n_points(self) -> int: ...
+
+    @property
+    def dim(self) -> int: ...
+
+    @property
+    def intrinsic_dim(self) -> Optional[float]:
+        """The estimated intrinsic dimension, or ``None`` when the sample is
+        too small or the estimate is undefined."""
+        ...
+
+    @property
+    def estimator(self) -> Literal["pca", "mle", "twonn", "correlation"]:
+        """The estimator behind :attr:`intrinsic_dim`."""
+        ...
+
+    @property
+    def explained_variance(self) -> Array[float]:
+        """Cumulative explained variance ratio per principal axis."""
+        ...
+
+    @property
+    def benchmark(self) -> Dict[str, TreeBenchmark]:
+        """Build and query seconds per candidate tree. Empty unless
+        ``benchmark > 0`` was passed."""
+        ...
+
+
+def recommend_tree(
+    data: ArrayLike,
+    workload: Literal["knn", "ann", "kde"] = "knn",
+    benchmark: int = 0,
+    seed: int = 0,
+    thresholds: Optional[SelectionThresholds] = None,
+) -> TreeRecommendation:
+    """Recommend a tree for ``data`` and explain the choice.
+
+    Applies the same rules as ``SpatialIndex(tree_type="auto")``, but always
+    measures the explained-variance curve and reports which rule fired.
+
+    Args:
+        data: 2D data of shape ``(n_points, n_features)``.
+        workload: The queries the index will mostly serve. ``"ann"`` prefers
+            RPTree above ``max_kd_dim``; ``"kde"`` never picks RPTree and scans
+            when the intrinsic dimension is high.
+        benchmark: If positive, build every tree and time that many sampled
+            rows as queries under ``workload``.
+        seed: Seed for row sampling and the benchmarked trees.
+        thresholds: Overrides for the selection thresholds.
+    """
+    ...
+
+
+def intrinsic_dim(
+    data: ArrayLike,
+    method: Literal["pca", "mle", "twonn", "correlation"] = "mle",
+    k: int = 10,
+    variance_threshold: float = 0.95,
+    sample_size: int = 2000,
+    seed: int = 0,
+) -> float:
+    """Estimate the intrinsic dimension of ``data`` from a sample of at most
+    ``sample_size`` rows, drawn without replacement.
+
+    Args:
+        data: 2D data of shape ``(n_points, n_features)``.
+        method: ``"mle"`` is the Levina-Bickel maximum likelihood estimate over
+            the ``k`` nearest neighbors. ``"twonn"`` uses the ratio of second to
+            first neighbor distances. ``"correlation"`` is the slope of the pair
+            correlation integral at small radii, quadratic in ``sample_size``.
+            ``"pca"`` counts principal axes up to ``variance_threshold`` and
+            overestimates on curved manifolds. Neighbor methods use euclidean
+            distances and skip duplicated rows.
+        k: Neighbors used by ``"mle"``, at least 2.
+        variance_threshold: Explained variance used by ``"pca"``.
+        sample_size: Maximum number of rows sampled.
+        seed: Seed for row sampling.
+
+    Raises:
+        ValueError: If the sample is too small or every neighbor is equidistant.
+    """
+    ...
+
+def johnson_lindenstrauss_min_dim(n_samples: int, eps: float = 0.1) -> int:
+    """Smallest random projection dimension that the Johnson-Lindenstrauss lemma
+    guarantees keeps every pairwise distance among ``n_samples`` points within a
+    factor of ``1 ± eps``, with high probability.
+
+    Raises:
+        ValueError: If ``eps`` is outside (0, 1) or ``n_samples`` is 0.
+    """
+    ...
+
 class SpatialResult:
     """Result of a spatial query (knn or radius search).
 
@@ -339,7 +525,8 @@ class BallTree:
         array: Array[float],
         leaf_size: int = 20,
         metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
-        preserve_array: bool = True
+        preserve_array: bool = True,
+        nan_policy: Literal["raise", "drop", "ignore_dims"] = "raise"
     ) -> BallTree:
         """Construct a ball tree from a 2D array of points.
 
@@ -354,6 +541,17 @@ class BallTree:
                 - "manhattan": Manhattan (L1) distance (taxicab distance)
                 - "chebyshev": Chebyshev (L∞) distance (maximum coordinate difference)
                 - "cosine": The angular distance between two vectors
+            nan_policy: How rows and queries containing NaN are handled:
+                - "raise": Reject them with a ValueError (default)
+                - "drop": Leave such rows out of the tree; queries containing NaN
+                  find no neighbors and get NaN densities
+                - "ignore_dims": Keep such rows beside the tree and compare them,
+                  and queries containing NaN, over the coordinates both points
+                  observe, scaling euclidean and manhattan distances up to the
+                  full dimension
+                Infinite coordinates are handled as NaN.
+                Result indices always count the rows set aside, which are listed
+                in :attr:`nan_rows`.
 
         Returns:
             A constructed BallTree instance.
@@ -369,7 +567,8 @@ class BallTree:
         data: ArrayLike,
         leaf_size: int = 20,
         metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
-        copy: bool = True
+        copy: bool = True,
+        nan_policy: Literal["raise", "drop", "ignore_dims"] = "raise"
     ):
         """Construct a ball tree from a 2D array of points."""
         ...
@@ -379,6 +578,16 @@ class BallTree:
         """The floating point precision of the tree ('float32' or 'float64')."""
         ...
 
+    @property
+    def nan_policy(self) -> Literal["raise", "drop", "ignore_dims"]:
+        """How NaN coordinates are handled."""
+        ...
+
+    @property
+    def nan_rows(self) -> Array[int]:
+        """Input rows set aside for containing NaN, in input numbering."""
+        ...
+
     def query_radius(self, query: ArrayLike, radius: float) -> SpatialResult:
         """Find all points within a given radius of the query point.
 
@@ -456,8 +665,10 @@ class BallTree:
         self,
         queries: ArrayLike,
         bandwidth: float = 1.0,
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
-        normalize: bool = True
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = True,
+        log_density: bool = False,
+        nu: float = 3.0
     ) -> float | Array[float]: ...
 
     @overload
@@ -465,8 +676,10 @@ class BallTree:
         self,
         queries: ArrayLike,
         bandwidth: float = 1.0,
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
-        normalize: bool = True
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = True,
+        log_density: bool = False,
+        nu: float = 3.0
     ) -> float | Array[float]: ...
 
     @overload
@@ -474,11 +687,147 @@ class BallTree:
         self,
         queries: None = None,
         bandwidth: float = 1.0,
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
-        normalize: bool = True
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = True,
+        log_density: bool = False,
+        nu: float = 3.0
     ) -> float | Array[float]: ...
 
 
+    def sample(
+        self,
+        n: int,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        weights: ArrayLike | None = None,
+        seed: Optional[int] = None,
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Draw points from the kernel density estimate.
+
+        Each sample picks a reference point, uniformly or proportional to
+        ``weights``, and adds noise drawn from the kernel. Requires the
+        euclidean metric.
+
+        Args:
+            n: Number of points to draw.
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile used for the noise.
+            weights: Optional non-negative weight per indexed point, in original order.
+            seed: Random seed. A time-based seed is used when omitted.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Array of shape (n, dim).
+        """
+        ...
+
+    def conditional_density(
+        self,
+        queries: ArrayLike,
+        responses: ArrayLike,
+        y: ArrayLike,
+        bandwidth: float = 1.0,
+        response_bandwidth: float | None = None,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Conditional density p(y | x) from per-point response values.
+
+        Args:
+            queries: Query point (dim,) or batch of queries (n_queries, dim).
+            responses: One response value per indexed point, in original order.
+            y: Response values to evaluate the density at, shape (n_y,).
+            bandwidth: Kernel bandwidth in feature space.
+            response_bandwidth: Kernel bandwidth in response space. Defaults to ``bandwidth``.
+            kernel: Kernel profile, used in both spaces.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Array of shape (n_y,) for a single query or (n_queries, n_y). Rows are
+            NaN where no indexed point has kernel weight.
+        """
+        ...
+
+    def kde_gradient(
+        self,
+        queries: ArrayLike,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = False,
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Gradient of the kernel density estimate.
+
+        Requires the euclidean metric.
+
+        Args:
+            queries: Query point (dim,) or batch of queries (n_queries, dim).
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile.
+            normalize: Scale by the kernel normalization constant, matching
+                ``kernel_density(..., normalize=True)``.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Array of shape (dim,) for a single query or (n_queries, dim).
+        """
+        ...
+
+    def mean_shift(
+        self,
+        seeds: ArrayLike | None = None,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        max_iter: int = 300,
+        tol: float = 1e-5,
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Move each seed uphill to a local density mode.
+
+        Requires the euclidean metric.
+
+        Args:
+            seeds: Starting points (n_seeds, dim). Defaults to the indexed points.
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile used to weight neighbours.
+            max_iter: Maximum iterations per seed.
+            tol: Stop when a step is shorter than ``tol * bandwidth``.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Array of shape (n_seeds, dim) holding the converged modes.
+        """
+        ...
+
+    def mean_shift_cluster(
+        self,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        max_iter: int = 300,
+        tol: float = 1e-5,
+        merge_radius: float | None = None,
+        nu: float = 3.0
+    ) -> tuple[Array[float], Array[int]]:
+        """Cluster the indexed points by the mode they converge to.
+
+        Requires the euclidean metric.
+
+        Args:
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile used to weight neighbours.
+            max_iter: Maximum iterations per point.
+            tol: Stop when a step is shorter than ``tol * bandwidth``.
+            merge_radius: Modes closer than this are merged. Defaults to ``bandwidth / 2``.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Tuple of (centers, labels). Centers are ordered by density, highest
+            first; labels give the center index of each point.
+        """
+        ...
+
+
 class KDTree:
     """KD-tree for efficient nearest neighbor queries.
 
@@ -492,7 +841,8 @@ class KDTree:
         array: Array[float],
         leaf_size: int = 20,
         metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
-        preserve_array: bool = True
+        preserve_array: bool = True,
+        nan_policy: Literal["raise", "drop", "ignore_dims"] = "raise"
     ) -> KDTree:
         """Construct a KD-tree from a 2D array of points."""
         ...
@@ -502,7 +852,8 @@ class KDTree:
         data: ArrayLike,
         leaf_size: int = 20,
         metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
-        copy: bool = True
+        copy: bool = True,
+        nan_policy: Literal["raise", "drop", "ignore_dims"] = "raise"
     ):
         """Construct a KD-tree from a 2D array of points."""
         ...
@@ -512,6 +863,16 @@ class KDTree:
         """The floating point precision of the tree ('float32' or 'float64')."""
         ...
 
+    @property
+    def nan_policy(self) -> Literal["raise", "drop", "ignore_dims"]:
+        """How NaN coordinates are handled."""
+        ...
+
+    @property
+    def nan_rows(self) -> Array[int]:
+        """Input rows set aside for containing NaN, in input numbering."""
+        ...
+
     def query_radius(self, query: ArrayLike, radius: float) -> SpatialResult:
         """Find all points within a given radius of the query point."""
         ...
@@ -554,13 +915,46 @@ class KDTree:
         """Deserialize a tree from disk."""
         ...
 
+    def gauss_transform(
+        self,
+        queries: Optional[ArrayLike] = None,
+        bandwidth: float = 1.0,
+        atol: float = 0.01,
+        order: int = 8,
+        normalize: bool = False
+    ) -> float | Array[float]:
+        """Gaussian kernel density through a fast Gauss transform.
+
+        Large nodes store truncated Hermite expansions of their points, so many
+        queries in low dimensions are much cheaper than with `kernel_density`.
+        Requires the euclidean metric.
+
+        Args:
+            queries: Query points. Defaults to the indexed points.
+            bandwidth: Gaussian bandwidth.
+            atol: Largest absolute error of each unnormalized kernel sum.
+            order: Maximum Hermite terms per dimension. Each expanded node stores
+                order^dim coefficients, capped at 65536.
+            normalize: Divide by the Gaussian normalization constant.
+
+        Returns:
+            Density for a single query, otherwise an array of densities.
+
+        Raises:
+            ValueError: For non-euclidean trees, invalid parameters or when
+                order^dim exceeds the cap.
+        """
+        ...
+
     @overload
     def kernel_density(
         self,
         queries: ArrayLike,
         bandwidth: float = 1.0,
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
-        normalize: bool = True
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = True,
+        log_density: bool = False,
+        nu: float = 3.0
     ) -> float | Array[float]: ...
 
     @overload
@@ -568,8 +962,10 @@ class KDTree:
         self,
         queries: ArrayLike,
         bandwidth: float = 1.0,
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
-        normalize: bool = True
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = True,
+        log_density: bool = False,
+        nu: float = 3.0
     ) -> float | Array[float]: ...
 
     @overload
@@ -577,11 +973,147 @@ class KDTree:
         self,
         queries: None = None,
         bandwidth: float = 1.0,
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
-        normalize: bool = True
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = True,
+        log_density: bool = False,
+        nu: float = 3.0
     ) -> float | Array[float]: ...
 
 
+    def sample(
+        self,
+        n: int,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        weights: ArrayLike | None = None,
+        seed: Optional[int] = None,
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Draw points from the kernel density estimate.
+
+        Each sample picks a reference point, uniformly or proportional to
+        ``weights``, and adds noise drawn from the kernel. Requires the
+        euclidean metric.
+
+        Args:
+            n: Number of points to draw.
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile used for the noise.
+            weights: Optional non-negative weight per indexed point, in original order.
+            seed: Random seed. A time-based seed is used when omitted.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Array of shape (n, dim).
+        """
+        ...
+
+    def conditional_density(
+        self,
+        queries: ArrayLike,
+        responses: ArrayLike,
+        y: ArrayLike,
+        bandwidth: float = 1.0,
+        response_bandwidth: float | None = None,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Conditional density p(y | x) from per-point response values.
+
+        Args:
+            queries: Query point (dim,) or batch of queries (n_queries, dim).
+            responses: One response value per indexed point, in original order.
+            y: Response values to evaluate the density at, shape (n_y,).
+            bandwidth: Kernel bandwidth in feature space.
+            response_bandwidth: Kernel bandwidth in response space. Defaults to ``bandwidth``.
+            kernel: Kernel profile, used in both spaces.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Array of shape (n_y,) for a single query or (n_queries, n_y). Rows are
+            NaN where no indexed point has kernel weight.
+        """
+        ...
+
+    def kde_gradient(
+        self,
+        queries: ArrayLike,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = False,
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Gradient of the kernel density estimate.
+
+        Requires the euclidean metric.
+
+        Args:
+            queries: Query point (dim,) or batch of queries (n_queries, dim).
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile.
+            normalize: Scale by the kernel normalization constant, matching
+                ``kernel_density(..., normalize=True)``.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Array of shape (dim,) for a single query or (n_queries, dim).
+        """
+        ...
+
+    def mean_shift(
+        self,
+        seeds: ArrayLike | None = None,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        max_iter: int = 300,
+        tol: float = 1e-5,
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Move each seed uphill to a local density mode.
+
+        Requires the euclidean metric.
+
+        Args:
+            seeds: Starting points (n_seeds, dim). Defaults to the indexed points.
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile used to weight neighbours.
+            max_iter: Maximum iterations per seed.
+            tol: Stop when a step is shorter than ``tol * bandwidth``.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Array of shape (n_seeds, dim) holding the converged modes.
+        """
+        ...
+
+    def mean_shift_cluster(
+        self,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        max_iter: int = 300,
+        tol: float = 1e-5,
+        merge_radius: float | None = None,
+        nu: float = 3.0
+    ) -> tuple[Array[float], Array[int]]:
+        """Cluster the indexed points by the mode they converge to.
+
+        Requires the euclidean metric.
+
+        Args:
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile used to weight neighbours.
+            max_iter: Maximum iterations per point.
+            tol: Stop when a step is shorter than ``tol * bandwidth``.
+            merge_radius: Modes closer than this are merged. Defaults to ``bandwidth / 2``.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Tuple of (centers, labels). Centers are ordered by density, highest
+            first; labels give the center index of each point.
+        """
+        ...
+
+
 class VPTree:
     """Vantage-point tree for efficient nearest neighbor queries.
 
@@ -589,6 +1121,10 @@ class VPTree:
     and partitioning based on distances to those points. Each node selects a point
     as a vantage point and splits remaining points by their median distance to it.
     This structure can be more efficient than KD-trees for high-dimensional data.
+
+    The ``"random"`` and ``"variance"`` selections draw vantage points from a
+    generator seeded with ``seed``, so a given dataset and seed always build
+    the same tree.
     """
 
     @staticmethod
@@ -597,7 +1133,9 @@ class VPTree:
         leaf_size: int = 20,
         metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
         selection: Literal["first", "random", "variance"] = "variance",
-        preserve_array: bool = True
+        preserve_array: bool = True,
+        seed: int = 0,
+        nan_policy: Literal["raise", "drop", "ignore_dims"] = "raise"
     ) -> VPTree:
         """Construct a vantage-point tree from a 2D array of points."""
         ...
@@ -608,7 +1146,9 @@ class VPTree:
         leaf_size: int = 20,
         metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
         selection: Literal["first", "random", "variance"] = "variance",
-        copy: bool = True
+        copy: bool = True,
+        seed: int = 0,
+        nan_policy: Literal["raise", "drop", "ignore_dims"] = "raise"
     ):
         """Construct a vantage-point tree from a 2D array of points."""
         ...
@@ -618,6 +1158,16 @@ class VPTree:
         """The floating point precision of the tree ('float32' or 'float64')."""
         ...
 
+    @property
+    def nan_policy(self) -> Literal["raise", "drop", "ignore_dims"]:
+        """How NaN coordinates are handled."""
+        ...
+
+    @property
+    def nan_rows(self) -> Array[int]:
+        """Input rows set aside for containing NaN, in input numbering."""
+        ...
+
     def query_radius(self, query: ArrayLike, radius: float) -> SpatialResult:
         """Find all points within a given radius of the query point."""
         ...
@@ -626,6 +1176,21 @@ class VPTree:
         """Find the k nearest neighbors to the query point."""
         ...
 
+    def query_ann(self, query: ArrayLike, k: int, n_candidates: int | None = None, n_probes: int | None = None) -> SpatialResult:
+        """Find the approximate k nearest neighbors to the query point.
+
+        Args:
+            query: Query point (scalar, list, or array-like).
+            k: Number of nearest neighbors to return.
+            n_candidates: Number of candidates to keep. Defaults to 2k if None.
+            n_probes: Number of stochastic probes. Far subtrees are bounded by the
+                vantage point margin. Defaults to 1 if None.
+
+        Returns:
+            Spatial result object.
+        """
+        ...
+
     @overload
     def data(self, indices: ArrayLike) -> Array[float]: ...
     @overload
@@ -647,8 +1212,10 @@ class VPTree:
         self,
         queries: ArrayLike,
         bandwidth: float = 1.0,
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
-        normalize: bool = True
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = True,
+        log_density: bool = False,
+        nu: float = 3.0
     ) -> float | Array[float]: ...
 
     @overload
@@ -656,8 +1223,10 @@ class VPTree:
         self,
         queries: ArrayLike,
         bandwidth: float = 1.0,
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
-        normalize: bool = True
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = True,
+        log_density: bool = False,
+        nu: float = 3.0
     ) -> float | Array[float]: ...
 
     @overload
@@ -665,58 +1234,219 @@ class VPTree:
         self,
         queries: None = None,
         bandwidth: float = 1.0,
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
-        normalize: bool = True
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = True,
+        log_density: bool = False,
+        nu: float = 3.0
     ) -> float | Array[float]: ...
 
 
-class RPTree:
-    """Random Projection tree for efficient nearest neighbor queries.
-
-    An RP-tree recursively partitions data by projecting points onto random
-    directions and splitting at the median. This is more effective than
-    axis-aligned splits (KD-tree) in high-dimensional spaces.
-    """
-
-    @staticmethod
-    def from_array(
-        array: Array[float],
-        leaf_size: int = 20,
-        metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
-        projection: Literal["gaussian", "sparse"] = "gaussian",
-        seed: Optional[int] = None,
-        preserve_array: bool = True
-    ) -> RPTree:
-        """Construct an RP-tree from a 2D array of points."""
-        ...
-
-    def __init__(
+    def sample(
         self,
-        data: ArrayLike,
-        leaf_size: int = 20,
-        metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
-        projection: Literal["gaussian", "sparse"] = "gaussian",
+        n: int,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        weights: ArrayLike | None = None,
         seed: Optional[int] = None,
-        copy: bool = True
-    ):
-        """Construct an RP-tree from array-like data."""
-        ...
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Draw points from the kernel density estimate.
 
-    @property
-    def dtype(self) -> str:
-        """The floating point precision of the tree ('float32' or 'float64')."""
-        ...
+        Each sample picks a reference point, uniformly or proportional to
+        ``weights``, and adds noise drawn from the kernel. Requires the
+        euclidean metric.
 
-    def query_radius(self, query: ArrayLike, radius: float) -> SpatialResult:
-        """Find all points within a given radius of the query point."""
-        ...
+        Args:
+            n: Number of points to draw.
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile used for the noise.
+            weights: Optional non-negative weight per indexed point, in original order.
+            seed: Random seed. A time-based seed is used when omitted.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
 
-    def query_knn(self, query: ArrayLike, k: int) -> SpatialResult:
-        """Find the k nearest neighbors to the query point."""
+        Returns:
+            Array of shape (n, dim).
+        """
         ...
 
-    def query_ann(self, query: ArrayLike, k: int, n_candidates: int, n_probes: int | None = None) -> SpatialResult:
-        """Find the approximate k nearest neighbors to the query point.
+    def conditional_density(
+        self,
+        queries: ArrayLike,
+        responses: ArrayLike,
+        y: ArrayLike,
+        bandwidth: float = 1.0,
+        response_bandwidth: float | None = None,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Conditional density p(y | x) from per-point response values.
+
+        Args:
+            queries: Query point (dim,) or batch of queries (n_queries, dim).
+            responses: One response value per indexed point, in original order.
+            y: Response values to evaluate the density at, shape (n_y,).
+            bandwidth: Kernel bandwidth in feature space.
+            response_bandwidth: Kernel bandwidth in response space. Defaults to ``bandwidth``.
+            kernel: Kernel profile, used in both spaces.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Array of shape (n_y,) for a single query or (n_queries, n_y). Rows are
+            NaN where no indexed point has kernel weight.
+        """
+        ...
+
+    def kde_gradient(
+        self,
+        queries: ArrayLike,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = False,
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Gradient of the kernel density estimate.
+
+        Requires the euclidean metric.
+
+        Args:
+            queries: Query point (dim,) or batch of queries (n_queries, dim).
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile.
+            normalize: Scale by the kernel normalization constant, matching
+                ``kernel_density(..., normalize=True)``.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Array of shape (dim,) for a single query or (n_queries, dim).
+        """
+        ...
+
+    def mean_shift(
+        self,
+        seeds: ArrayLike | None = None,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        max_iter: int = 300,
+        tol: float = 1e-5,
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Move each seed uphill to a local density mode.
+
+        Requires the euclidean metric.
+
+        Args:
+            seeds: Starting points (n_seeds, dim). Defaults to the indexed points.
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile used to weight neighbours.
+            max_iter: Maximum iterations per seed.
+            tol: Stop when a step is shorter than ``tol * bandwidth``.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Array of shape (n_seeds, dim) holding the converged modes.
+        """
+        ...
+
+    def mean_shift_cluster(
+        self,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        max_iter: int = 300,
+        tol: float = 1e-5,
+        merge_radius: float | None = None,
+        nu: float = 3.0
+    ) -> tuple[Array[float], Array[int]]:
+        """Cluster the indexed points by the mode they converge to.
+
+        Requires the euclidean metric.
+
+        Args:
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile used to weight neighbours.
+            max_iter: Maximum iterations per point.
+            tol: Stop when a step is shorter than ``tol * bandwidth``.
+            merge_radius: Modes closer than this are merged. Defaults to ``bandwidth / 2``.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Tuple of (centers, labels). Centers are ordered by density, highest
+            first; labels give the center index of each point.
+        """
+        ...
+
+
+class RPTree:
+    """Random Projection tree for efficient nearest neighbor queries.
+
+    An RP-tree recursively partitions data by projecting points onto random
+    directions and splitting at the median. This is more effective than
+    axis-aligned splits (KD-tree) in high-dimensional spaces.
+
+    ``split`` chooses how each node is cut. ``"median"`` uses one random
+    direction. ``"principal"`` follows the top principal component of a
+    sample of the node's points, and ``"best_of"`` keeps the widest-spread of
+    several random directions. ``"max_margin"`` splits at the largest gap
+    between projections near the median rather than at the median itself.
+
+    ``projection`` draws each split direction as described under
+    :class:`ProjectionReducer`. A ``"hadamard"`` split direction is one row of a
+    randomized Hadamard matrix, a dense random sign vector, so it costs the
+    same to project onto as a ``"gaussian"`` one.
+    """
+
+    @staticmethod
+    def from_array(
+        array: Array[float],
+        leaf_size: int = 20,
+        metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
+        projection: Literal["gaussian", "sparse", "achlioptas", "very_sparse", "hadamard"] = "gaussian",
+        seed: Optional[int] = None,
+        preserve_array: bool = True,
+        split: Literal["median", "principal", "best_of", "max_margin"] = "median",
+        nan_policy: Literal["raise", "drop", "ignore_dims"] = "raise"
+    ) -> RPTree:
+        """Construct an RP-tree from a 2D array of points."""
+        ...
+
+    def __init__(
+        self,
+        data: ArrayLike,
+        leaf_size: int = 20,
+        metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
+        projection: Literal["gaussian", "sparse", "achlioptas", "very_sparse", "hadamard"] = "gaussian",
+        seed: Optional[int] = None,
+        copy: bool = True,
+        split: Literal["median", "principal", "best_of", "max_margin"] = "median",
+        nan_policy: Literal["raise", "drop", "ignore_dims"] = "raise"
+    ):
+        """Construct an RP-tree from array-like data."""
+        ...
+
+    @property
+    def dtype(self) -> str:
+        """The floating point precision of the tree ('float32' or 'float64')."""
+        ...
+
+    @property
+    def nan_policy(self) -> Literal["raise", "drop", "ignore_dims"]:
+        """How NaN coordinates are handled."""
+        ...
+
+    @property
+    def nan_rows(self) -> Array[int]:
+        """Input rows set aside for containing NaN, in input numbering."""
+        ...
+
+    def query_radius(self, query: ArrayLike, radius: float) -> SpatialResult:
+        """Find all points within a given radius of the query point."""
+        ...
+
+    def query_knn(self, query: ArrayLike, k: int) -> SpatialResult:
+        """Find the k nearest neighbors to the query point."""
+        ...
+
+    def query_ann(self, query: ArrayLike, k: int, n_candidates: int, n_probes: int | None = None) -> SpatialResult:
+        """Find the approximate k nearest neighbors to the query point.
 
         Args:
             query: Query point (scalar, list, or array-like).
@@ -740,6 +1470,96 @@ class RPTree:
         """Return training-data rows at original indices, or all points if omitted."""
         ...
 
+class RPForest:
+    """Forest of random projection trees sharing one copy of the data.
+
+    A single RP-tree misses neighbors that fall across a split boundary. The
+    forest builds ``n_trees`` trees in parallel from independent seeds and
+    merges their aNN candidates, skipping points an earlier tree already found.
+    Recall and throughput are tuned with ``n_trees`` and ``n_candidates``.
+    """
+
+    @staticmethod
+    def from_array(
+        array: Array[float],
+        n_trees: int = 8,
+        leaf_size: int = 20,
+        metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
+        projection: Literal["gaussian", "sparse", "achlioptas", "very_sparse", "hadamard"] = "gaussian",
+        seed: int = 0,
+        preserve_array: bool = True,
+        split: Literal["median", "principal", "best_of", "max_margin"] = "median",
+        nan_policy: Literal["raise", "drop", "ignore_dims"] = "raise"
+    ) -> RPForest:
+        """Construct an RP-forest from a 2D array of points."""
+        ...
+
+    def __init__(
+        self,
+        data: ArrayLike,
+        n_trees: int = 8,
+        leaf_size: int = 20,
+        metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
+        projection: Literal["gaussian", "sparse", "achlioptas", "very_sparse", "hadamard"] = "gaussian",
+        seed: int = 0,
+        copy: bool = True,
+        split: Literal["median", "principal", "best_of", "max_margin"] = "median",
+        nan_policy: Literal["raise", "drop", "ignore_dims"] = "raise"
+    ):
+        """Construct an RP-forest from array-like data."""
+        ...
+
+    @property
+    def dtype(self) -> str:
+        """The floating point precision of the forest ('float32' or 'float64')."""
+        ...
+
+    @property
+    def nan_policy(self) -> Literal["raise", "drop", "ignore_dims"]:
+        """How NaN coordinates are handled."""
+        ...
+
+    @property
+    def nan_rows(self) -> Array[int]:
+        """Input rows set aside for containing NaN, in input numbering."""
+        ...
+
+    @property
+    def n_trees(self) -> int:
+        """Number of trees in the forest."""
+        ...
+
+    def query_ann(self, query: ArrayLike, k: int, n_candidates: int | None = None, n_probes: int | None = None) -> SpatialResult:
+        """Find the approximate k nearest neighbors to the query point.
+
+        Args:
+            query: Query point (scalar, list, or array-like).
+            k: Number of nearest neighbors to return.
+            n_candidates: Number of candidates kept across all trees.
+                Defaults to 2k if None.
+            n_probes: Stochastic probes per tree. Defaults to 1 if None.
+
+        Returns:
+            Spatial result object.
+        """
+        ...
+
+    @overload
+    def data(self, indices: ArrayLike) -> Array: ...
+    @overload
+    def data(self, indices: None = None) -> Array:
+        """Return training-data rows at original indices, or all points if omitted."""
+        ...
+
+    def save(self, path: str) -> None:
+        """Serialize the forest to disk in MessagePack format."""
+        ...
+
+    @staticmethod
+    def load(path: str) -> RPForest:
+        """Deserialize a forest from disk."""
+        ...
+
 class SpectralTree:
     """Random Projection tree for efficient nearest neighbor queries.
 
@@ -755,7 +1575,8 @@ class SpectralTree:
         metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
         projection: Literal["gaussian", "sparse"] = "gaussian",
         seed: Optional[int] = None,
-        preserve_array: bool = True
+        preserve_array: bool = True,
+        nan_policy: Literal["raise", "drop", "ignore_dims"] = "raise"
     ) -> RPTree:
         """Construct an RP-tree from a 2D array of points."""
         ...
@@ -767,7 +1588,8 @@ class SpectralTree:
         metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
         projection: Literal["gaussian", "sparse"] = "gaussian",
         seed: Optional[int] = None,
-        copy: bool = True
+        copy: bool = True,
+        nan_policy: Literal["raise", "drop", "ignore_dims"] = "raise"
     ):
         """Construct an RP-tree from array-like data."""
         ...
@@ -777,6 +1599,16 @@ class SpectralTree:
         """The floating point precision of the tree ('float32' or 'float64')."""
         ...
 
+    @property
+    def nan_policy(self) -> Literal["raise", "drop", "ignore_dims"]:
+        """How NaN coordinates are handled."""
+        ...
+
+    @property
+    def nan_rows(self) -> Array[int]:
+        """Input rows set aside for containing NaN, in input numbering."""
+        ...
+
     def query_radius(self, query: ArrayLike, radius: float) -> SpatialResult:
         """Find all points within a given radius of the query point."""
         ...
@@ -817,6 +1649,11 @@ class AggTree:
     kernel density estimation using a Taylor expansion to approximate contributions
     from groups of points. The absolute tolerance (atol) controls how aggressively
     nodes are approximated during queries.
+
+    By default the full hierarchy is kept, so bandwidth, kernel and atol can be
+    changed per query without rebuilding. With ``compact=True`` the points inside
+    aggregated nodes are freed, which saves memory but fixes the kernel and
+    bandwidth to the values given at construction.
     """
 
     @staticmethod
@@ -824,10 +1661,14 @@ class AggTree:
         array: Array[float],
         leaf_size: int = 20,
         metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
         bandwidth: float = 1.0,
         atol: float = 0.01,
-        preserve_array: bool = True
+        preserve_array: bool = True,
+        compact: bool = False,
+        moments: Literal["isotropic", "covariance", "low_rank"] = "isotropic",
+        rank: Optional[int] = None,
+        nu: float = 3.0
     ) -> AggTree:
         """Construct an aggregation tree from a 2D array of points."""
         ...
@@ -837,12 +1678,26 @@ class AggTree:
         data: ArrayLike,
         leaf_size: int = 20,
         metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
         bandwidth: float = 1.0,
         atol: float = 0.01,
-        copy: bool = True
+        copy: bool = True,
+        compact: bool = False,
+        moments: Literal["isotropic", "covariance", "low_rank"] = "isotropic",
+        rank: Optional[int] = None,
+        nu: float = 3.0
     ):
-        """Construct an aggregation tree from a 2D array of points."""
+        """Construct an aggregation tree from a 2D array of points.
+
+        Args:
+            metric: Distance metric. Non-euclidean metrics approximate nodes by
+                the midpoint of their kernel range instead of a Taylor expansion.
+            moments: Second moments stored per node. "isotropic" keeps scalar
+                moments of the distance to the centroid. "covariance" stores the
+                full covariance matrix, and "low_rank" its leading ``rank``
+                principal axes (default 2). Both improve accuracy on elongated
+                clusters and require the euclidean metric.
+        """
         ...
 
     @property
@@ -862,37 +1717,152 @@ class AggTree:
     @overload
     def kernel_density(
         self,
-        queries: ArrayLike,
-        normalize: bool = True
-    ) -> float | Array[float]: ...
+        queries: ArrayLike | None = None,
+        bandwidth: Optional[float] = None,
+        kernel: Optional[Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"]] = None,
+        normalize: bool = True,
+        log_density: bool = False,
+        atol: Optional[float] = None,
+        rtol: Optional[float] = None,
+        return_error: Literal[False] = False,
+        nu: Optional[float] = None
+    ) -> float | Array[float]:
+        """Estimate kernel density at the query points.
+
+        Nodes whose error bound for the given bandwidth and kernel is below
+        ``atol`` are approximated from their moments. Parameters left as None
+        use the values the tree was built with. Compact trees raise ValueError
+        when the bandwidth or kernel differs from the build values.
+
+        Without ``queries``, densities are estimated at the indexed points in the
+        order they were added. Compact trees have dropped those points and raise
+        ValueError.
+
+        When ``rtol`` is given, ``atol`` is ignored and traversal is refined
+        until the certified error bound is within ``rtol`` of the true density.
+        Compacted leaves cannot be refined, so compact trees may miss the target.
+        """
+        ...
 
     @overload
     def kernel_density(
         self,
-        queries: ArrayLike,
-        normalize: bool = True
-    ) -> float | Array[float]: ...
+        queries: ArrayLike | None = None,
+        bandwidth: Optional[float] = None,
+        kernel: Optional[Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"]] = None,
+        normalize: bool = True,
+        log_density: bool = False,
+        atol: Optional[float] = None,
+        rtol: Optional[float] = None,
+        *,
+        return_error: Literal[True],
+        nu: Optional[float] = None
+    ) -> tuple[float, float] | tuple[Array[float], Array[float]]:
+        """Estimate kernel density and a certified upper bound on each query's error.
+
+        The bound is on the absolute error of the density, or on the absolute
+        error of the log density when ``log_density=True``.
+        """
+        ...
 
-    @overload
-    def kernel_density(
+    def sample(
         self,
-        queries: None = None,
-        normalize: bool = True
-    ) -> float | Array[float]: ...
+        n: int,
+        bandwidth: Optional[float] = None,
+        kernel: Optional[Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"]] = None,
+        seed: Optional[int] = None,
+        nu: Optional[float] = None
+    ) -> Array[float]:
+        """Draw points from the density estimate.
+
+        Leaves are picked proportional to their point count. Leaves whose points
+        were compacted away draw a reference point from a Gaussian matching the
+        node's centroid and variance. Requires the euclidean metric.
+
+        Args:
+            n: Number of points to draw.
+            bandwidth: Kernel bandwidth. Defaults to the build bandwidth.
+            kernel: Kernel profile. Defaults to the build kernel.
+            seed: Random seed. A time-based seed is used when omitted.
+            nu: Degrees of freedom of the ``"student_t"`` kernel. Defaults to 3, or to the
+                build value when ``kernel`` is omitted.
+
+        Returns:
+            Array of shape (n, dim).
+        """
+        ...
+
+    def query_knn(self, query: ArrayLike, k: int) -> SpatialResult:
+        """Find the exact k nearest neighbors using the tree's hierarchy.
+
+        Args:
+            query: Single point or 2D batch.
+            k: Number of neighbors.
+
+        Returns:
+            A :class:`SpatialResult`.
+
+        Raises:
+            ValueError: If the tree is compact.
+        """
+        ...
+
+    def query_radius(self, query: ArrayLike, radius: float) -> SpatialResult:
+        """Find all points within *radius* of each query point.
+
+        Args:
+            query: Single point or 2D batch.
+            radius: Search radius.
+
+        Returns:
+            A :class:`SpatialResult`.
+
+        Raises:
+            ValueError: If the tree is compact.
+        """
+        ...
+
+    def insert(self, points: ArrayLike) -> None:
+        """Add points without rebuilding the tree.
+
+        Inserted points are indexed after every point added so far. Touched
+        leaves are recomputed and split once they exceed `leaf_size`, and their
+        ancestors merge the updated child moments.
+
+        Args:
+            points: Points of shape (n, dim), or a single point of shape (dim,).
+
+        Raises:
+            ValueError: If the tree is compact.
+        """
+        ...
+
+    def remove(self, indices: List[int]) -> None:
+        """Remove points by index.
+
+        Args:
+            indices: Indices of the points to remove.
+
+        Raises:
+            ValueError: If the tree is compact, an index is unknown or repeated,
+                or every point would be removed.
+        """
+        ...
 
 
 class BruteForce:
     """Brute force nearest neighbor search.
 
-    Computes exact queries by comparing every point in the dataset.
-    No tree structure is built — useful as a correctness baseline or
+    Computes exact queries by comparing every point in the dataset, except
+    `query_ann`, which stops scanning early. No tree structure is built — useful as a correctness baseline or
     for very small datasets where tree construction overhead is not worthwhile.
     """
 
     @staticmethod
     def from_array(
         array: Array[float],
-        metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean"
+        metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
+        nan_policy: Literal["raise", "drop", "ignore_dims"] = "raise"
     ) -> BruteForce:
         """Construct a BruteForce search structure from a 2D array of points."""
         ...
@@ -901,6 +1871,7 @@ class BruteForce:
         self,
         data: Array[float],
         metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
+        nan_policy: Literal["raise", "drop", "ignore_dims"] = "raise",
     ):
         """Construct a BruteForce search structure from a 2D array of points."""
         ...
@@ -910,6 +1881,16 @@ class BruteForce:
         """The floating point precision of the tree ('float32' or 'float64')."""
         ...
 
+    @property
+    def nan_policy(self) -> Literal["raise", "drop", "ignore_dims"]:
+        """How NaN coordinates are handled."""
+        ...
+
+    @property
+    def nan_rows(self) -> Array[int]:
+        """Input rows set aside for containing NaN, in input numbering."""
+        ...
+
     def query_radius(self, query: ArrayLike, radius: float) -> SpatialResult:
         """Find all points within a given radius of the query point."""
         ...
@@ -918,6 +1899,22 @@ class BruteForce:
         """Find the k nearest neighbors to the query point."""
         ...
 
+    def query_ann(self, query: ArrayLike, k: int, n_candidates: int | None = None, n_probes: int | None = None) -> SpatialResult:
+        """Find the approximate k nearest neighbors to the query point.
+
+        Args:
+            query: Query point (scalar, list, or array-like).
+            k: Number of nearest neighbors to return.
+            n_candidates: Number of candidates to keep. Defaults to 2k if None.
+            n_probes: Number of stochastic probes. Points are scanned in blocks until
+                they rarely improve the candidates, and each probe resumes
+                the scan. Defaults to 1 if None.
+
+        Returns:
+            Spatial result object.
+        """
+        ...
+
     @overload
     def data(self, indices: ArrayLike) -> Array[float]: ...
     @overload
@@ -939,8 +1936,10 @@ class BruteForce:
         self,
         queries: ArrayLike,
         bandwidth: float = 1.0,
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
-        normalize: bool = True
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = True,
+        log_density: bool = False,
+        nu: float = 3.0
     ) -> float | Array[float]: ...
 
     @overload
@@ -948,8 +1947,10 @@ class BruteForce:
         self,
         queries: ArrayLike,
         bandwidth: float = 1.0,
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
-        normalize: bool = True
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = True,
+        log_density: bool = False,
+        nu: float = 3.0
     ) -> float | Array[float]: ...
 
     @overload
@@ -957,11 +1958,147 @@ class BruteForce:
         self,
         queries: None = None,
         bandwidth: float = 1.0,
-        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular"] = "gaussian",
-        normalize: bool = True
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = True,
+        log_density: bool = False,
+        nu: float = 3.0
     ) -> float | Array[float]: ...
 
 
+    def sample(
+        self,
+        n: int,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        weights: ArrayLike | None = None,
+        seed: Optional[int] = None,
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Draw points from the kernel density estimate.
+
+        Each sample picks a reference point, uniformly or proportional to
+        ``weights``, and adds noise drawn from the kernel. Requires the
+        euclidean metric.
+
+        Args:
+            n: Number of points to draw.
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile used for the noise.
+            weights: Optional non-negative weight per indexed point, in original order.
+            seed: Random seed. A time-based seed is used when omitted.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Array of shape (n, dim).
+        """
+        ...
+
+    def conditional_density(
+        self,
+        queries: ArrayLike,
+        responses: ArrayLike,
+        y: ArrayLike,
+        bandwidth: float = 1.0,
+        response_bandwidth: float | None = None,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Conditional density p(y | x) from per-point response values.
+
+        Args:
+            queries: Query point (dim,) or batch of queries (n_queries, dim).
+            responses: One response value per indexed point, in original order.
+            y: Response values to evaluate the density at, shape (n_y,).
+            bandwidth: Kernel bandwidth in feature space.
+            response_bandwidth: Kernel bandwidth in response space. Defaults to ``bandwidth``.
+            kernel: Kernel profile, used in both spaces.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Array of shape (n_y,) for a single query or (n_queries, n_y). Rows are
+            NaN where no indexed point has kernel weight.
+        """
+        ...
+
+    def kde_gradient(
+        self,
+        queries: ArrayLike,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        normalize: bool = False,
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Gradient of the kernel density estimate.
+
+        Requires the euclidean metric.
+
+        Args:
+            queries: Query point (dim,) or batch of queries (n_queries, dim).
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile.
+            normalize: Scale by the kernel normalization constant, matching
+                ``kernel_density(..., normalize=True)``.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Array of shape (dim,) for a single query or (n_queries, dim).
+        """
+        ...
+
+    def mean_shift(
+        self,
+        seeds: ArrayLike | None = None,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        max_iter: int = 300,
+        tol: float = 1e-5,
+        nu: float = 3.0
+    ) -> Array[float]:
+        """Move each seed uphill to a local density mode.
+
+        Requires the euclidean metric.
+
+        Args:
+            seeds: Starting points (n_seeds, dim). Defaults to the indexed points.
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile used to weight neighbours.
+            max_iter: Maximum iterations per seed.
+            tol: Stop when a step is shorter than ``tol * bandwidth``.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Array of shape (n_seeds, dim) holding the converged modes.
+        """
+        ...
+
+    def mean_shift_cluster(
+        self,
+        bandwidth: float = 1.0,
+        kernel: Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"] = "gaussian",
+        max_iter: int = 300,
+        tol: float = 1e-5,
+        merge_radius: float | None = None,
+        nu: float = 3.0
+    ) -> tuple[Array[float], Array[int]]:
+        """Cluster the indexed points by the mode they converge to.
+
+        Requires the euclidean metric.
+
+        Args:
+            bandwidth: Kernel bandwidth.
+            kernel: Kernel profile used to weight neighbours.
+            max_iter: Maximum iterations per point.
+            tol: Stop when a step is shorter than ``tol * bandwidth``.
+            merge_radius: Modes closer than this are merged. Defaults to ``bandwidth / 2``.
+            nu: Degrees of freedom of the ``"student_t"`` kernel.
+
+        Returns:
+            Tuple of (centers, labels). Centers are ordered by density, highest
+            first; labels give the center index of each point.
+        """
+        ...
+
+
 class ProjectionReducer:
     @property
     def input_dim(self) -> int: ...
@@ -973,7 +2110,7 @@ class ProjectionReducer:
         self,
         input_dim: int,
         output_dim: int,
-        projection_type: Literal["gaussian", "sparse"] = "gaussian",
+        projection_type: Literal["gaussian", "sparse", "achlioptas", "very_sparse", "hadamard"] = "gaussian",
         density: float = 0.1,
         seed: Optional[int] = None,
         copy: bool = True
@@ -982,8 +2119,13 @@ class ProjectionReducer:
 
         :param input_dim: Number of features in the input data.
         :param output_dim: Number of features in the projected space.
-        :param projection_type: The method of projection ('gaussian' or 'sparse').
-        :param density: The density of the projection matrix (used if type is 'sparse').
+        :param projection_type: The method of projection. 'gaussian' is a dense Gaussian
+            matrix. 'sparse' keeps a ``density`` fraction of entries, 'achlioptas' a third
+            and 'very_sparse' ``1/sqrt(input_dim)``, each as random signs. 'hadamard' is a
+            subsampled randomized Hadamard transform, projecting each row in
+            O(input_dim log input_dim), and needs output_dim at most input_dim rounded up
+            to a power of two.
+        :param density: The density of the projection matrix (used if type is 'sparse'), in (0, 1].
         :param seed: Random seed for reproducibility.
         """
         ...
@@ -991,20 +2133,27 @@ class ProjectionReducer:
     def transform(self, data: ArrayLike) -> Array[float]:
         """Projects the data into the lower-dimensional space.
 
-        :param data: Input array of shape (n_samples, input_dim).
-        :return: Projected PyArray of shape (n_samples, output_dim).
+        :param data: Input array of shape (n_samples, input_dim) or (input_dim,).
+        :return: Projected PyArray of shape (n_samples, output_dim), float32 for float32
+            input and float64 otherwise.
         """
         ...
 
     @staticmethod
     def fit_transform(
         data: ArrayLike,
-        output_dim: int,
-        projection_type: Literal["gaussian", "sparse"] = "gaussian",
+        output_dim: Optional[int] = None,
+        projection_type: Literal["gaussian", "sparse", "achlioptas", "very_sparse", "hadamard"] = "gaussian",
         density: float = 0.1,
-        seed: Optional[int] = None
+        seed: Optional[int] = None,
+        eps: Optional[float] = None,
     ) -> Tuple[ProjectionReducer, Array]:
-        """Fits a reducer to the data dimensions and returns both the reducer and the transformed data."""
+        """Fits a reducer to the data dimensions and returns both the reducer and the transformed data.
+
+        When ``output_dim`` is None it is chosen by :func:`johnson_lindenstrauss_min_dim`
+        for the number of rows and ``eps``, which defaults to 0.1. Passing both
+        ``output_dim`` and ``eps`` raises ValueError.
+        """
         ...
 
     def save(self, path: str) -> None:
@@ -1015,3 +2164,98 @@ class ProjectionReducer:
     def load(path: str) -> ProjectionReducer:
         """Deserialize a reducer from disk."""
         ...
+
+class PCAReducer:
+    """Principal component projection, fitted to the data it is built from.
+
+    Use it ahead of any spatial tree by indexing ``transform(data)`` and transforming
+    queries the same way.
+    """
+
+    @property
+    def input_dim(self) -> int: ...
+
+    @property
+    def output_dim(self) -> int: ...
+
+    @property
+    def whiten(self) -> bool: ...
+
+    @property
+    def mean(self) -> Array[float]:
+        """Per-feature mean subtracted before projecting, shape (input_dim,)."""
+        ...
+
+    @property
+    def components(self) -> Array[float]:
+        """Principal axes as rows, shape (output_dim, input_dim), by decreasing variance."""
+        ...
+
+    @property
+    def explained_variance(self) -> Array[float]:
+        """Variance along each kept component."""
+        ...
+
+    @property
+    def explained_variance_ratio(self) -> Array[float]:
+        """Fraction of the total variance along each kept component."""
+        ...
+
+    def __init__(
+        self,
+        data: ArrayLike,
+        n_components: Optional[int] = None,
+        variance_threshold: Optional[float] = None,
+        whiten: bool = False,
+        solver: Literal["auto", "full", "randomized"] = "auto",
+        n_oversamples: int = 10,
+        n_iter: int = 4,
+        seed: Optional[int] = None,
+    ) -> None:
+        """Fits principal components to data of shape (n_samples, input_dim).
+
+        :param data: Training data with at least two rows.
+        :param n_components: Number of components to keep.
+        :param variance_threshold: Keep the fewest components explaining this fraction of the
+            variance, in (0, 1]. Cannot be combined with n_components. With neither, every
+            component is kept.
+        :param whiten: Scale components to unit variance.
+        :param solver: 'full' decomposes the covariance (or the data when it is wider than
+            tall). 'randomized' uses a randomized SVD and needs n_components. 'auto' uses
+            the randomized solver when n_components is small relative to large data.
+        :param n_oversamples: Extra random directions sampled by the randomized solver.
+        :param n_iter: Power iterations run by the randomized solver.
+        :param seed: Random seed for the randomized solver.
+        """
+        ...
+
+    def transform(self, data: ArrayLike) -> Array[float]:
+        """Projects data of shape (n_samples, input_dim) or (input_dim,) onto the components."""
+        ...
+
+    def inverse_transform(self, data: ArrayLike) -> Array[float]:
+        """Maps data of shape (n_samples, output_dim) or (output_dim,) back to the input space."""
+        ...
+
+    @staticmethod
+    def fit_transform(
+        data: ArrayLike,
+        n_components: Optional[int] = None,
+        variance_threshold: Optional[float] = None,
+        whiten: bool = False,
+        solver: Literal["auto", "full", "randomized"] = "auto",
+        n_oversamples: int = 10,
+        n_iter: int = 4,
+        seed: Optional[int] = None,
+    ) -> Tuple[PCAReducer, Array]:
+        """Fits a reducer and returns it with the transformed data."""
+        ...
+
+    def save(self, path: str) -> None:
+        """Serialize the reducer to disk in MessagePack format."""
+        ...
+
+    @staticmethod
+    def load(path: str) -> PCAReducer:
+        """Deserialize a reducer from disk."""
+        ...
@@ -90,3 +90,24 @@ def spearman(a: ArrayLike, b: ArrayLike) -> float:
         Spearman correlation coefficient between -1 and 1.
     """
     ...
+
+def softmax(a: ArrayLike, fallback: ArrayLike | None = None) -> Array:
+    """Softmax along the last axis of a 1D or 2D array.
+
+    Args:
+        a: Input array of log-weights.
+        fallback: Log-weights of length ``a.shape[-1]`` used for rows whose
+            entries are all -inf. Such rows are NaN when omitted.
+
+    Returns:
+        Array of the same shape as ``a`` whose rows sum to 1.
+    """
+    ...
+
+def argmax(a: ArrayLike) -> int | Array[int]:
+    """Index of the largest element along the last axis.
+
+    Ties go to the first index and NaN entries are skipped. Returns an int
+    for a 1D array and an int array of shape (n,) for a 2D array.
+    """
+    ...
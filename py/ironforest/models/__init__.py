@@ -4,10 +4,14 @@ from .linear_regression import LinearRegression
 from .local_regression import LocalRegression
 from .knn import KNNClassifier
 from .knn import KNNRegressor
+from .kde import KDEClassifier
+from .kde import ConditionalKDE
 
 __all__ = [
     "LinearRegression",
     "LocalRegression",
     "KNNClassifier",
-    "KNNRegressor"
+    "KNNRegressor",
+    "KDEClassifier",
+    "ConditionalKDE",
 ]
@@ -0,0 +1,226 @@
+import math
+from typing import Literal, Sequence
+import ironforest as irn
+
+Kernel = Literal["gaussian", "epanechnikov", "uniform", "triangular", "exponential", "cosine", "biweight", "triweight", "cauchy", "student_t"]
+
+
+def _build_tree(X, tree_type, leaf_size, metric):
+    match tree_type:
+        case "kd":
+            return irn.spatial.KDTree(X, leaf_size, metric) # type: ignore
+        case "ball":
+            return irn.spatial.BallTree(X, leaf_size, metric) # type: ignore
+        case "vp":
+            return irn.spatial.VPTree(X, leaf_size, metric) # type: ignore
+        case "brute":
+            return irn.spatial.BruteForce(X, metric) # type: ignore
+    raise ValueError(f"Unknown tree type: {tree_type}")
+
+
+def _as_array(values) -> irn.Array:
+    # kernel_density returns a float for a single query
+    if isinstance(values, irn.Array):
+        return values
+    return irn.ndutils.asarray([values])
+
+
+class KDEClassifier:
+    """
+    Kernel density classifier.
+
+    Fits one kernel density estimate per class and scores queries by the
+    class-conditional density times the class prior.
+
+    Args:
+        bandwidth: Kernel bandwidth shared by every class
+        kernel: Kernel profile
+        tree: Spatial tree type built per class ("kd", "ball", "vp", "brute", "agg")
+        metric: Distance metric used for the density estimates
+        leaf_size: Maximum number of points stored in a tree leaf
+        priors: Class priors in sorted class order. Defaults to class frequencies
+        atol: Absolute error tolerance, only used by ``tree="agg"``
+        nu: Degrees of freedom of the ``"student_t"`` kernel
+    """
+    def __init__(
+        self,
+        bandwidth: float = 1.0,
+        kernel: Kernel = "gaussian",
+        tree: Literal["kd", "ball", "vp", "brute", "agg"] = "kd",
+        metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
+        leaf_size: int = 20,
+        priors: Sequence[float] | None = None,
+        atol: float = 0.01,
+        nu: float = 3.0,
+    ):
+        self.bandwidth = bandwidth
+        self.kernel = kernel
+        self.tree_type = tree
+        self.metric = metric
+        self.leaf_size = leaf_size
+        self.priors = priors
+        self.atol = atol
+        self.nu = nu
+
+    def fit(self, X, y) -> "KDEClassifier":
+        """
+        Fit the classifier.
+
+        Builds one tree per class label and stores the class priors.
+
+        Args:
+            X: Training data of shape (n_samples, n_features)
+            y: Target class labels of shape (n_samples,)
+
+        Returns:
+            self: Fitted estimator
+        """
+        X = irn.ndutils.asarray(X)
+        labels = irn.ndutils.asarray(y).tolist()
+        self.classes = sorted(set(labels))
+
+        self.trees = []
+        counts = []
+        for c in self.classes:
+            mask = [label == c for label in labels]
+            X_c = X[mask]
+            if self.tree_type == "agg":
+                tree = irn.spatial.AggTree(X_c, self.leaf_size, self.metric, self.kernel, self.bandwidth, self.atol, nu=self.nu) # type: ignore
+            else:
+                tree = _build_tree(X_c, self.tree_type, self.leaf_size, self.metric)
+            self.trees.append(tree)
+            counts.append(sum(mask))
+
+        if self.priors is None:
+            priors = [n / len(labels) for n in counts]
+        else:
+            priors = list(self.priors)
+            if len(priors) != len(self.classes):
+                raise ValueError(f"Expected {len(self.classes)} priors, got {len(priors)}")
+            if any(p <= 0.0 for p in priors):
+                raise ValueError("priors must be positive")
+            total = sum(priors)
+            priors = [p / total for p in priors]
+
+        self.log_priors = [math.log(p) for p in priors]
+        # kernel_density sums over points, so each class density is divided by its count
+        self.log_weights = [lp - math.log(n) for lp, n in zip(self.log_priors, counts)]
+        return self
+
+    def _log_joint(self, X) -> irn.Array:
+        X = irn.ndutils.asarray(X)
+        if X.ndim == 1:
+            X = X.reshape([1, X.shape[0]])
+
+        columns = []
+        for tree, log_w in zip(self.trees, self.log_weights):
+            if self.tree_type == "agg":
+                log_dens = tree.kernel_density(X, normalize=False, log_density=True)
+            else:
+                log_dens = tree.kernel_density(X, self.bandwidth, self.kernel, normalize=False, log_density=True, nu=self.nu)
+            columns.append(_as_array(log_dens) + log_w)
+
+        return irn.ndutils.column_stack(columns)
+
+    def predict_proba(self, X) -> irn.Array:
+        """
+        Estimate class probabilities for samples.
+
+        Queries outside the support of every class (compact kernels) fall
+        back to the class priors.
+
+        Args:
+            X: Input data of shape (n_samples, n_features) or (n_features,)
+
+        Returns:
+            Array of shape (n_samples, n_classes) containing class probabilities
+        """
+        return irn.stats.softmax(self._log_joint(X), fallback=self.log_priors)
+
+    def predict(self, X) -> irn.Array | float:
+        """
+        Predict class labels for samples.
+
+        Args:
+            X: Input data of shape (n_samples, n_features) or (n_features,)
+
+        Returns:
+            Predicted class labels. Returns a single label if one sample
+            is provided, otherwise an array of shape (n_samples,)
+        """
+        indices = irn.stats.argmax(self.predict_proba(X))
+        if indices.shape[0] == 1:
+            return self.classes[indices.item()]
+        return irn.ndutils.asarray(self.classes)[indices]
+
+
+class ConditionalKDE:
+    """
+    Kernel conditional density estimator for p(y | x).
+
+    Neighbours of a query are weighted by the kernel in feature space and
+    their responses are smoothed with the same kernel in response space.
+
+    Args:
+        bandwidth: Kernel bandwidth in feature space
+        response_bandwidth: Kernel bandwidth in response space. Defaults to ``bandwidth``
+        kernel: Kernel profile
+        tree: Spatial tree type used for the feature space ("kd", "ball", "vp", "brute")
+        metric: Distance metric used in feature space
+        leaf_size: Maximum number of points stored in a tree leaf
+        nu: Degrees of freedom of the ``"student_t"`` kernel
+    """
+    def __init__(
+        self,
+        bandwidth: float = 1.0,
+        response_bandwidth: float | None = None,
+        kernel: Kernel = "gaussian",
+        tree: Literal["kd", "ball", "vp", "brute"] = "kd",
+        metric: Literal["euclidean", "manhattan", "chebyshev", "cosine"] = "euclidean",
+        leaf_size: int = 20,
+        nu: float = 3.0,
+    ):
+        self.bandwidth = bandwidth
+        self.response_bandwidth = response_bandwidth
+        self.kernel = kernel
+        self.tree_type = tree
+        self.metric = metric
+        self.leaf_size = leaf_size
+        self.nu = nu
+
+    def fit(self, X, y) -> "ConditionalKDE":
+        """
+        Fit the estimator.
+
+        Args:
+            X: Training data of shape (n_samples, n_features)
+            y: Response values of shape (n_samples,)
+
+        Returns:
+            self: Fitted estimator
+        """
+        self.tree = _build_tree(irn.ndutils.asarray(X), self.tree_type, self.leaf_size, self.metric)
+        self.responses = irn.ndutils.asarray(y)
+        return self
+
+    def density(self, X, y) -> irn.Array:
+        """
+        Evaluate p(y | x) on a grid of response values.
+
+        Args:
+            X: Query points of shape (n_samples, n_features) or (n_features,)
+            y: Response values to evaluate, shape (n_y,)
+
+        Returns:
+            Array of shape (n_samples, n_y), or (n_y,) for a single query.
+            Rows are NaN where no training point has kernel weight.
+        """
+        return self.tree.conditional_density(
+            X,
+            self.responses,
+            y,
+            self.bandwidth,
+            self.response_bandwidth,
+            self.kernel,
+            nu=self.nu,
+        )
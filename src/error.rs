@@ -0,0 +1,77 @@
+use std::fmt;
+
+/// Errors from invalid input to spatial queries and indexes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum IronForestError {
+    /// Points or queries whose dimension differs from the index.
+    DimensionMismatch { expected: usize, got: usize },
+    /// Input that is not a 2D (n, dim) array.
+    InvalidShape { ndim: usize },
+    /// The operation needs at least one indexed point.
+    EmptyIndex,
+    /// k must be at least 1.
+    InvalidK { k: usize },
+    /// A row of the input contains NaN or infinity under `NanPolicy::Raise`. `input` names
+    /// the kind of row, such as "Point" or "Query".
+    NonFinite { input: &'static str, index: usize },
+    /// The index has no tree to query.
+    Uninitialized,
+    /// Any other argument outside its valid range.
+    InvalidArgument(String),
+}
+
+impl IronForestError {
+    pub fn invalid(msg: impl Into<String>) -> Self {
+        IronForestError::InvalidArgument(msg.into())
+    }
+}
+
+impl fmt::Display for IronForestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IronForestError::DimensionMismatch { expected, got } => {
+                write!(f, "Dimension mismatch: expected {}, got {}", expected, got)
+            }
+            IronForestError::InvalidShape { ndim } => {
+                write!(f, "Expected 2D array (n, dim), got {}D", ndim)
+            }
+            IronForestError::EmptyIndex => write!(f, "The index contains no points"),
+            IronForestError::InvalidK { k } => write!(f, "k must be at least 1, got {}", k),
+            IronForestError::NonFinite { input, index } => write!(
+                f,
+                "{} {} contains NaN or infinity. Use nan_policy='drop' or 'ignore_dims' to allow it",
+                input, index
+            ),
+            IronForestError::Uninitialized => write!(f, "SpatialIndex is uninitialized"),
+            IronForestError::InvalidArgument(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for IronForestError {}
+
+/// Checks that `dims` is (n, expected_dim), returning n.
+pub(crate) fn check_queries(dims: &[usize], expected_dim: usize) -> Result<usize, IronForestError> {
+    if dims.len() != 2 {
+        return Err(IronForestError::InvalidShape { ndim: dims.len() });
+    }
+    if dims[1] != expected_dim {
+        return Err(IronForestError::DimensionMismatch { expected: expected_dim, got: dims[1] });
+    }
+    Ok(dims[0])
+}
+
+/// Checks that a single query has `expected_dim` coordinates.
+pub(crate) fn check_query(len: usize, expected_dim: usize) -> Result<(), IronForestError> {
+    if len != expected_dim {
+        return Err(IronForestError::DimensionMismatch { expected: expected_dim, got: len });
+    }
+    Ok(())
+}
+
+pub(crate) fn check_k(k: usize) -> Result<(), IronForestError> {
+    if k == 0 {
+        return Err(IronForestError::InvalidK { k });
+    }
+    Ok(())
+}
@@ -1,6 +1,6 @@
 use std::iter::Sum;
 use serde::{Serialize, de::DeserializeOwned};
-use num_traits::{Float as NumFloat, ToPrimitive};
+use num_traits::{Float as NumFloat, ToPrimitive, float::TotalOrder};
 #[cfg(target_arch = "x86_64")]
 use std::arch::x86_64::*;
 
@@ -8,7 +8,8 @@ use std::arch::x86_64::*;
 /// decision trees, and other numeric subsystems.
 ///
 /// Backed by `num_traits::Float` (which provides all arithmetic, constants,
-/// sqrt/abs/etc. and PartialOrd) plus the bounds required for tree storage,
+/// sqrt/abs/etc. and PartialOrd) and `TotalOrder` for sorting without
+/// unwrapping comparisons, plus the bounds required for tree storage,
 /// parallelism, and serialization.
 ///
 /// Currently implemented for `f32` and `f64`. Designed so that `f16` (via
@@ -16,6 +17,7 @@ use std::arch::x86_64::*;
 /// structural changes.
 pub trait IronFloat:
     NumFloat
+    + TotalOrder
     + Sum
     + Copy
     + Send
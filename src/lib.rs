@@ -1,4 +1,5 @@
 pub mod iron_float;
+pub mod error;
 pub mod array;
 pub mod ops;
 pub mod random;
@@ -9,6 +10,7 @@ pub mod projection;
 
 
 pub use iron_float::IronFloat;
+pub use error::IronForestError;
 pub use array::{NdArray, Shape, Storage, BroadcastIter};
 pub use random::Generator;
 pub use spatial::{DistanceMetric, KernelType};
@@ -1,6 +1,8 @@
 use crate::array::ndarray::NdArray;
 use crate::array::shape::Shape;
 
+/// (U, S, Vt) from `svd`.
+pub type SvdFactors = (NdArray<f64>, NdArray<f64>, NdArray<f64>);
 
 impl NdArray<f64> {
     pub fn cholesky(&self) -> Result<NdArray<f64>, &'static str> {
@@ -186,7 +188,7 @@ impl NdArray<f64> {
 
         let mut indices: Vec<usize> = (0..n).collect();
         indices.sort_by(|&i, &j| {
-            eigenvalues[j].abs().partial_cmp(&eigenvalues[i].abs()).unwrap()
+            eigenvalues[j].abs().total_cmp(&eigenvalues[i].abs())
         });
 
         let sorted_eigenvalues: Vec<f64> = indices.iter().map(|&i| eigenvalues[i]).collect();
@@ -208,4 +210,353 @@ impl NdArray<f64> {
         let (eigenvalues, _) = self.eig()?;
         Ok(eigenvalues)
     }
-}
\ No newline at end of file
+
+    /// Eigendecomposition of a symmetric matrix, reading only its lower triangle. Uses
+    /// Householder tridiagonalization followed by implicit-shift QL. Returns eigenvalues in
+    /// ascending order and the matching orthonormal eigenvectors as columns.
+    pub fn eigh(&self) -> Result<(NdArray<f64>, NdArray<f64>), &'static str> {
+        if self.ndim() != 2 {
+            return Err("Eigendecomposition requires a 2D matrix");
+        }
+        let n = self.shape().dims()[0];
+        if n != self.shape().dims()[1] {
+            return Err("Eigendecomposition requires a square matrix");
+        }
+        if n == 0 {
+            return Ok((
+                NdArray::from_vec(Shape::d1(0), vec![]),
+                NdArray::from_vec(Shape::d2(0, 0), vec![]),
+            ));
+        }
+
+        let mut v = self.as_contiguous_slice().into_owned();
+        let mut d = vec![0.0; n];
+        let mut e = vec![0.0; n];
+        tridiagonalize(&mut v, &mut d, &mut e, n);
+        tridiagonal_ql(&mut v, &mut d, &mut e, n)?;
+
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|&i, &j| d[i].total_cmp(&d[j]));
+        let values = order.iter().map(|&i| d[i]).collect();
+        let mut vectors = Vec::with_capacity(n * n);
+        for row in 0..n {
+            vectors.extend(order.iter().map(|&i| v[row * n + i]));
+        }
+        Ok((
+            NdArray::from_vec(Shape::d1(n), values),
+            NdArray::from_vec(Shape::d2(n, n), vectors),
+        ))
+    }
+
+    /// Eigenvalues of a symmetric matrix in ascending order, see `eigh`.
+    pub fn eigvalsh(&self) -> Result<NdArray<f64>, &'static str> {
+        let (eigenvalues, _) = self.eigh()?;
+        Ok(eigenvalues)
+    }
+
+    /// Singular value decomposition A = U diag(S) Vt by one-sided Jacobi rotations, with
+    /// singular values in descending order. With `full_matrices` U is (m, m) and Vt is
+    /// (n, n), otherwise U is (m, k) and Vt is (k, n) for k = min(m, n).
+    pub fn svd(&self, full_matrices: bool) -> Result<SvdFactors, &'static str> {
+        if self.ndim() != 2 {
+            return Err("SVD requires a 2D matrix");
+        }
+        let (m, n) = (self.shape().dims()[0], self.shape().dims()[1]);
+
+        // Jacobi orthogonalizes columns, so work on the transpose of wide matrices
+        let a = self.as_contiguous_slice();
+        let (rows, cols, transposed) = if m >= n { (m, n, false) } else { (n, m, true) };
+        let mut w = vec![0.0; rows * cols];
+        for i in 0..m {
+            for j in 0..n {
+                match transposed {
+                    false => w[j * rows + i] = a[i * n + j],
+                    true => w[i * rows + j] = a[i * n + j],
+                }
+            }
+        }
+
+        // w holds columns contiguously: column j is w[j * rows..(j + 1) * rows]
+        let mut v = vec![0.0; cols * cols];
+        for j in 0..cols {
+            v[j * cols + j] = 1.0;
+        }
+        one_sided_jacobi(&mut w, &mut v, rows, cols)?;
+
+        let norms: Vec<f64> = (0..cols)
+            .map(|j| w[j * rows..(j + 1) * rows].iter().map(|x| x * x).sum::<f64>().sqrt())
+            .collect();
+        let mut order: Vec<usize> = (0..cols).collect();
+        order.sort_by(|&i, &j| norms[j].total_cmp(&norms[i]));
+
+        let tol = norms.iter().cloned().fold(0.0, f64::max) * rows as f64 * f64::EPSILON;
+        let mut left: Vec<Vec<f64>> = Vec::with_capacity(rows);
+        let mut right: Vec<Vec<f64>> = Vec::with_capacity(cols);
+        let mut sigma = Vec::with_capacity(cols);
+        for &j in &order {
+            sigma.push(norms[j]);
+            right.push(v[j * cols..(j + 1) * cols].to_vec());
+            if norms[j] > tol {
+                left.push(w[j * rows..(j + 1) * rows].iter().map(|x| x / norms[j]).collect());
+            }
+        }
+        // columns for zero singular values, and the full basis if asked, come from
+        // orthogonalizing the standard basis against the vectors found so far
+        complete_basis(&mut left, rows, if full_matrices { rows } else { cols });
+        complete_basis(&mut right, cols, cols);
+
+        let (u_cols, vt_rows) = match transposed {
+            false => (left, right),
+            true => (right, left),
+        };
+        let k = m.min(n);
+        let (u_width, vt_height) = if full_matrices { (m, n) } else { (k, k) };
+        let mut u = vec![0.0; m * u_width];
+        for (j, col) in u_cols.iter().take(u_width).enumerate() {
+            for i in 0..m {
+                u[i * u_width + j] = col[i];
+            }
+        }
+        let vt: Vec<f64> = vt_rows.iter().take(vt_height).flatten().cloned().collect();
+
+        Ok((
+            NdArray::from_vec(Shape::d2(m, u_width), u),
+            NdArray::from_vec(Shape::d1(k), sigma),
+            NdArray::from_vec(Shape::d2(vt_height, n), vt),
+        ))
+    }
+}
+
+/// Householder reduction of the symmetric row-major matrix in `v` to tridiagonal form,
+/// leaving the diagonal in `d`, the subdiagonal in `e[1..]` and the accumulated
+/// orthogonal transform in `v`.
+fn tridiagonalize(v: &mut [f64], d: &mut [f64], e: &mut [f64], n: usize) {
+    let at = |i: usize, j: usize| i * n + j;
+    for j in 0..n {
+        d[j] = v[at(n - 1, j)];
+    }
+
+    for i in (1..n).rev() {
+        let scale: f64 = d[..i].iter().map(|x| x.abs()).sum();
+        let mut h = 0.0;
+        if scale == 0.0 {
+            e[i] = d[i - 1];
+            for j in 0..i {
+                d[j] = v[at(i - 1, j)];
+                v[at(i, j)] = 0.0;
+                v[at(j, i)] = 0.0;
+            }
+        } else {
+            for x in d[..i].iter_mut() {
+                *x /= scale;
+                h += *x * *x;
+            }
+            let f = d[i - 1];
+            let g = if f > 0.0 { -h.sqrt() } else { h.sqrt() };
+            e[i] = scale * g;
+            h -= f * g;
+            d[i - 1] = f - g;
+            e[..i].fill(0.0);
+
+            for j in 0..i {
+                let f = d[j];
+                v[at(j, i)] = f;
+                let mut g = e[j] + v[at(j, j)] * f;
+                for k in j + 1..i {
+                    g += v[at(k, j)] * d[k];
+                    e[k] += v[at(k, j)] * f;
+                }
+                e[j] = g;
+            }
+            let mut f = 0.0;
+            for j in 0..i {
+                e[j] /= h;
+                f += e[j] * d[j];
+            }
+            let hh = f / (h + h);
+            for j in 0..i {
+                e[j] -= hh * d[j];
+            }
+            for j in 0..i {
+                let (f, g) = (d[j], e[j]);
+                for k in j..i {
+                    v[at(k, j)] -= f * e[k] + g * d[k];
+                }
+                d[j] = v[at(i - 1, j)];
+                v[at(i, j)] = 0.0;
+            }
+        }
+        d[i] = h;
+    }
+
+    for i in 0..n - 1 {
+        v[at(n - 1, i)] = v[at(i, i)];
+        v[at(i, i)] = 1.0;
+        let h = d[i + 1];
+        if h != 0.0 {
+            for k in 0..=i {
+                d[k] = v[at(k, i + 1)] / h;
+            }
+            for j in 0..=i {
+                let g: f64 = (0..=i).map(|k| v[at(k, i + 1)] * v[at(k, j)]).sum();
+                for k in 0..=i {
+                    v[at(k, j)] -= g * d[k];
+                }
+            }
+        }
+        for k in 0..=i {
+            v[at(k, i + 1)] = 0.0;
+        }
+    }
+    for j in 0..n {
+        d[j] = v[at(n - 1, j)];
+        v[at(n - 1, j)] = 0.0;
+    }
+    v[at(n - 1, n - 1)] = 1.0;
+    e[0] = 0.0;
+}
+
+/// Implicit-shift QL on the tridiagonal matrix from `tridiagonalize`, leaving eigenvalues
+/// in `d` and rotating `v` into the eigenvectors.
+fn tridiagonal_ql(v: &mut [f64], d: &mut [f64], e: &mut [f64], n: usize) -> Result<(), &'static str> {
+    const MAX_ITER: usize = 60;
+    for i in 1..n {
+        e[i - 1] = e[i];
+    }
+    e[n - 1] = 0.0;
+
+    let mut f = 0.0;
+    let mut tst1: f64 = 0.0;
+    for l in 0..n {
+        tst1 = tst1.max(d[l].abs() + e[l].abs());
+        let mut m = l;
+        while m < n - 1 && e[m].abs() > f64::EPSILON * tst1 {
+            m += 1;
+        }
+
+        if m > l {
+            let mut iter = 0;
+            loop {
+                iter += 1;
+                if iter > MAX_ITER {
+                    return Err("Eigendecomposition did not converge");
+                }
+                let g = d[l];
+                let mut p = (d[l + 1] - g) / (2.0 * e[l]);
+                let mut r = p.hypot(1.0);
+                if p < 0.0 {
+                    r = -r;
+                }
+                d[l] = e[l] / (p + r);
+                d[l + 1] = e[l] * (p + r);
+                let dl1 = d[l + 1];
+                let h = g - d[l];
+                for x in d[l + 2..].iter_mut() {
+                    *x -= h;
+                }
+                f += h;
+
+                p = d[m];
+                let (mut c, mut c2, mut c3) = (1.0, 1.0, 1.0);
+                let el1 = e[l + 1];
+                let (mut s, mut s2) = (0.0, 0.0);
+                for i in (l..m).rev() {
+                    c3 = c2;
+                    c2 = c;
+                    s2 = s;
+                    let g = c * e[i];
+                    let h = c * p;
+                    r = p.hypot(e[i]);
+                    e[i + 1] = s * r;
+                    s = e[i] / r;
+                    c = p / r;
+                    p = c * d[i] - s * g;
+                    d[i + 1] = h + s * (c * g + s * d[i]);
+                    for k in 0..n {
+                        let h = v[k * n + i + 1];
+                        v[k * n + i + 1] = s * v[k * n + i] + c * h;
+                        v[k * n + i] = c * v[k * n + i] - s * h;
+                    }
+                }
+                p = -s * s2 * c3 * el1 * e[l] / dl1;
+                e[l] = s * p;
+                d[l] = c * p;
+                if e[l].abs() <= f64::EPSILON * tst1 {
+                    break;
+                }
+            }
+        }
+        d[l] += f;
+        e[l] = 0.0;
+    }
+    Ok(())
+}
+
+/// Rotates pairs of the `cols` columns in `w` (each `rows` long, stored contiguously) until
+/// they are mutually orthogonal, applying the same rotations to the columns of `v`.
+fn one_sided_jacobi(w: &mut [f64], v: &mut [f64], rows: usize, cols: usize) -> Result<(), &'static str> {
+    const MAX_SWEEPS: usize = 60;
+    for _ in 0..MAX_SWEEPS {
+        let mut rotated = false;
+        for p in 0..cols {
+            for q in p + 1..cols {
+                let (wp, wq) = (&w[p * rows..(p + 1) * rows], &w[q * rows..(q + 1) * rows]);
+                let alpha: f64 = wp.iter().map(|x| x * x).sum();
+                let beta: f64 = wq.iter().map(|x| x * x).sum();
+                let gamma: f64 = wp.iter().zip(wq).map(|(x, y)| x * y).sum();
+                if gamma.abs() <= f64::EPSILON * (alpha * beta).sqrt() || gamma == 0.0 {
+                    continue;
+                }
+                rotated = true;
+                let zeta = (beta - alpha) / (2.0 * gamma);
+                let t = zeta.signum() / (zeta.abs() + (1.0 + zeta * zeta).sqrt());
+                let c = 1.0 / (1.0 + t * t).sqrt();
+                let s = c * t;
+                rotate_columns(w, rows, p, q, c, s);
+                rotate_columns(v, cols, p, q, c, s);
+            }
+        }
+        if !rotated {
+            return Ok(());
+        }
+    }
+    Err("SVD did not converge")
+}
+
+fn rotate_columns(a: &mut [f64], len: usize, p: usize, q: usize, c: f64, s: f64) {
+    let (head, tail) = a.split_at_mut(q * len);
+    let cp = &mut head[p * len..(p + 1) * len];
+    let cq = &mut tail[..len];
+    for (x, y) in cp.iter_mut().zip(cq.iter_mut()) {
+        let (xp, xq) = (*x, *y);
+        *x = c * xp - s * xq;
+        *y = s * xp + c * xq;
+    }
+}
+
+/// Extends the orthonormal vectors in `basis` (each of length `dim`) to `target` vectors by
+/// orthogonalizing standard basis vectors against them. Residuals above 0.5 / sqrt(dim) are
+/// kept, which always leaves enough candidates to finish.
+fn complete_basis(basis: &mut Vec<Vec<f64>>, dim: usize, target: usize) {
+    let min_norm = 0.5 / (dim as f64).sqrt();
+    let mut candidate = 0;
+    while basis.len() < target && candidate < dim {
+        let mut x = vec![0.0; dim];
+        x[candidate] = 1.0;
+        candidate += 1;
+        // twice is enough for orthogonality in floating point
+        for _ in 0..2 {
+            for b in basis.iter() {
+                let proj: f64 = b.iter().zip(&x).map(|(bi, xi)| bi * xi).sum();
+                for (xi, bi) in x.iter_mut().zip(b) {
+                    *xi -= proj * bi;
+                }
+            }
+        }
+        let norm = x.iter().map(|v| v * v).sum::<f64>().sqrt();
+        if norm > min_norm {
+            x.iter_mut().for_each(|v| *v /= norm);
+            basis.push(x);
+        }
+    }
+}
@@ -1,5 +1,7 @@
 pub(crate) mod random_projection;
 pub(crate) mod projection_reducer;
+pub(crate) mod pca_reducer;
 
-pub use random_projection::{ProjectionType, RandomProjection};
-pub use projection_reducer::ProjectionReducer;
\ No newline at end of file
+pub use random_projection::{johnson_lindenstrauss_min_dim, ProjectionFloat, ProjectionType, RandomProjection, SplitMode};
+pub use projection_reducer::ProjectionReducer;
+pub use pca_reducer::{PCAComponents, PCAReducer, PCASolver};
\ No newline at end of file
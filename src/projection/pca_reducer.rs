@@ -0,0 +1,349 @@
+use crate::array::{NdArray, Shape};
+use crate::error::{IronForestError, check_queries};
+use crate::linalg::basic::simd_dot;
+use crate::random::Generator;
+use serde::{Deserialize, Serialize};
+
+// Randomized SVD is used by `PCASolver::Auto` once data has more than this many rows or
+// columns and fewer than 80% of the possible components are kept
+const RANDOMIZED_MIN_SIZE: usize = 500;
+
+/// How many principal components a `PCAReducer` keeps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PCAComponents {
+    /// Every component, min(n_samples, n_features).
+    All,
+    Count(usize),
+    /// The fewest leading components whose explained variance ratio reaches the threshold.
+    Variance(f64),
+}
+
+/// How a `PCAReducer` finds its components.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PCASolver {
+    /// Randomized for large data when only a few components are kept, otherwise full.
+    Auto,
+    /// Eigendecomposition of the covariance, or a thin SVD of the centered data when it has
+    /// more features than samples.
+    Full,
+    /// Halko et al.'s randomized SVD with `n_oversamples` extra directions and `n_iter`
+    /// power iterations. Requires `PCAComponents::Count`.
+    Randomized { n_oversamples: usize, n_iter: usize },
+}
+
+impl PCASolver {
+    pub fn randomized() -> Self {
+        PCASolver::Randomized { n_oversamples: 10, n_iter: 4 }
+    }
+}
+
+/// Principal component analysis, projecting centered data onto its leading principal axes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PCAReducer {
+    mean: Vec<f64>,
+    /// output_dim x input_dim, one unit-length component per row.
+    components: Vec<f64>,
+    explained_variance: Vec<f64>,
+    explained_variance_ratio: Vec<f64>,
+    whiten: bool,
+    input_dim: usize,
+    output_dim: usize,
+}
+
+impl PCAReducer {
+    /// Fits principal axes to `data` of shape (n_samples, n_features). With `whiten`,
+    /// transformed components are scaled to unit variance.
+    pub fn fit(
+        data: &NdArray<f64>,
+        n_components: PCAComponents,
+        whiten: bool,
+        solver: PCASolver,
+        rng: &mut Generator,
+    ) -> Result<Self, IronForestError> {
+        if data.ndim() != 2 {
+            return Err(IronForestError::InvalidShape { ndim: data.ndim() });
+        }
+        let (n, d) = (data.shape().dims()[0], data.shape().dims()[1]);
+        if n < 2 || d == 0 {
+            return Err(IronForestError::invalid("PCA requires at least 2 samples and 1 feature"));
+        }
+        let max_components = n.min(d);
+        match n_components {
+            PCAComponents::Count(k) if k == 0 || k > max_components => {
+                return Err(IronForestError::invalid("n_components must be between 1 and min(n_samples, n_features)"));
+            }
+            PCAComponents::Variance(t) if !(t > 0.0 && t <= 1.0) => {
+                return Err(IronForestError::invalid("variance threshold must be in (0, 1]"));
+            }
+            _ => {}
+        }
+
+        let flat = data.as_contiguous_slice();
+        let mut mean = vec![0.0; d];
+        for row in flat.chunks_exact(d) {
+            for (m, x) in mean.iter_mut().zip(row) {
+                *m += x;
+            }
+        }
+        mean.iter_mut().for_each(|m| *m /= n as f64);
+        let mut centered = flat.into_owned();
+        for row in centered.chunks_exact_mut(d) {
+            for (x, m) in row.iter_mut().zip(&mean) {
+                *x -= m;
+            }
+        }
+        let total_variance = centered.iter().map(|x| x * x).sum::<f64>() / (n - 1) as f64;
+
+        let solver = match (solver, n_components) {
+            (PCASolver::Auto, PCAComponents::Count(k))
+                if n.max(d) > RANDOMIZED_MIN_SIZE && (k as f64) < 0.8 * max_components as f64 => {
+                PCASolver::randomized()
+            }
+            (PCASolver::Auto, _) => PCASolver::Full,
+            (PCASolver::Randomized { .. }, PCAComponents::Count(_)) => solver,
+            (PCASolver::Randomized { .. }, _) => {
+                return Err(IronForestError::invalid("the randomized solver requires a fixed number of components"));
+            }
+            (s, _) => s,
+        };
+        let (mut components, variances) = match solver {
+            PCASolver::Randomized { n_oversamples, n_iter } => {
+                let PCAComponents::Count(k) = n_components else { unreachable!() };
+                randomized_components(&centered, n, d, k, n_oversamples, n_iter, rng)?
+            }
+            _ => full_components(&centered, n, d)?,
+        };
+
+        let ratios: Vec<f64> = variances.iter()
+            .map(|v| if total_variance > 0.0 { v / total_variance } else { 0.0 })
+            .collect();
+        let k = match n_components {
+            PCAComponents::All => variances.len(),
+            PCAComponents::Count(k) => k,
+            PCAComponents::Variance(t) => {
+                let mut cumsum = 0.0;
+                ratios.iter()
+                    .position(|r| {
+                        cumsum += r;
+                        cumsum >= t - 1e-12
+                    })
+                    .map_or(ratios.len(), |i| i + 1)
+            }
+        };
+        components.truncate(k * d);
+        flip_signs(&mut components, d);
+
+        Ok(Self {
+            mean,
+            components,
+            explained_variance: variances[..k].to_vec(),
+            explained_variance_ratio: ratios[..k].to_vec(),
+            whiten,
+            input_dim: d,
+            output_dim: k,
+        })
+    }
+
+    pub fn transform(&self, data: &NdArray<f64>) -> Result<NdArray<f64>, IronForestError> {
+        let n = check_queries(data.shape().dims(), self.input_dim)?;
+        let flat = data.as_contiguous_slice();
+        let scale = self.component_scales();
+        let mut centered = vec![0.0; self.input_dim];
+        let mut out = Vec::with_capacity(n * self.output_dim);
+        for row in flat.chunks_exact(self.input_dim) {
+            for ((c, x), m) in centered.iter_mut().zip(row).zip(&self.mean) {
+                *c = x - m;
+            }
+            for (component, s) in self.components.chunks_exact(self.input_dim).zip(&scale) {
+                out.push(simd_dot(&centered, component) / s);
+            }
+        }
+        Ok(NdArray::from_vec(Shape::d2(n, self.output_dim), out))
+    }
+
+    /// Maps reduced points back to the input space. Exact for points in the span of the
+    /// kept components.
+    pub fn inverse_transform(&self, data: &NdArray<f64>) -> Result<NdArray<f64>, IronForestError> {
+        let n = check_queries(data.shape().dims(), self.output_dim)?;
+        let flat = data.as_contiguous_slice();
+        let scale = self.component_scales();
+        let mut out = Vec::with_capacity(n * self.input_dim);
+        for row in flat.chunks_exact(self.output_dim) {
+            let mut point = self.mean.clone();
+            for ((z, component), s) in row.iter().zip(self.components.chunks_exact(self.input_dim)).zip(&scale) {
+                let w = z * s;
+                for (p, c) in point.iter_mut().zip(component) {
+                    *p += w * c;
+                }
+            }
+            out.extend_from_slice(&point);
+        }
+        Ok(NdArray::from_vec(Shape::d2(n, self.input_dim), out))
+    }
+
+    pub fn fit_transform(
+        data: &NdArray<f64>,
+        n_components: PCAComponents,
+        whiten: bool,
+        solver: PCASolver,
+        rng: &mut Generator,
+    ) -> Result<(Self, NdArray<f64>), IronForestError> {
+        let reducer = Self::fit(data, n_components, whiten, solver, rng)?;
+        let transformed = reducer.transform(data)?;
+        Ok((reducer, transformed))
+    }
+
+    /// Per-component divisor applied by `transform`: the standard deviation when whitening,
+    /// otherwise 1. Components without variance are left unscaled.
+    fn component_scales(&self) -> Vec<f64> {
+        self.explained_variance.iter()
+            .map(|&v| if self.whiten && v > 0.0 { v.sqrt() } else { 1.0 })
+            .collect()
+    }
+
+    pub fn input_dim(&self) -> usize { self.input_dim }
+    pub fn output_dim(&self) -> usize { self.output_dim }
+    pub fn whiten(&self) -> bool { self.whiten }
+    pub fn mean(&self) -> &[f64] { &self.mean }
+    pub fn components(&self) -> &[f64] { &self.components }
+    pub fn explained_variance(&self) -> &[f64] { &self.explained_variance }
+    pub fn explained_variance_ratio(&self) -> &[f64] { &self.explained_variance_ratio }
+}
+
+/// Every principal axis of the row-major centered data, as rows, with their variances in
+/// descending order.
+fn full_components(centered: &[f64], n: usize, d: usize) -> Result<(Vec<f64>, Vec<f64>), IronForestError> {
+    let denom = (n - 1) as f64;
+    if d <= n {
+        let mut cov = vec![0.0; d * d];
+        for row in centered.chunks_exact(d) {
+            for j in 0..d {
+                let rj = row[j];
+                for (acc, rk) in cov[j * d..j * d + j + 1].iter_mut().zip(row) {
+                    *acc += rj * rk;
+                }
+            }
+        }
+        cov.iter_mut().for_each(|c| *c /= denom);
+        // eigh reads the lower triangle filled above
+        let (values, vectors) = NdArray::from_vec(Shape::d2(d, d), cov).eigh().map_err(IronForestError::invalid)?;
+        let values = values.as_slice_unchecked();
+        let vectors = vectors.as_slice_unchecked();
+        let mut components = Vec::with_capacity(d * d);
+        for col in (0..d).rev() {
+            components.extend((0..d).map(|row| vectors[row * d + col]));
+        }
+        let variances = values.iter().rev().map(|v| v.max(0.0)).collect();
+        Ok((components, variances))
+    } else {
+        let (_, s, vt) = NdArray::from_vec(Shape::d2(n, d), centered.to_vec()).svd(false).map_err(IronForestError::invalid)?;
+        let variances = s.as_slice_unchecked().iter().map(|s| s * s / denom).collect();
+        Ok((vt.into_vec(), variances))
+    }
+}
+
+/// The leading `k` principal axes by randomized SVD: a Gaussian sketch of `k + n_oversamples`
+/// directions, refined by `n_iter` power iterations, then an exact SVD of the small
+/// projected matrix.
+fn randomized_components(
+    centered: &[f64],
+    n: usize,
+    d: usize,
+    k: usize,
+    n_oversamples: usize,
+    n_iter: usize,
+    rng: &mut Generator,
+) -> Result<(Vec<f64>, Vec<f64>), IronForestError> {
+    let l = (k + n_oversamples).min(n.min(d));
+    let rows: Vec<&[f64]> = centered.chunks_exact(d).collect();
+
+    // X v for each of the l feature-space vectors, giving l sample-space columns
+    let apply = |dirs: &[Vec<f64>]| -> Vec<Vec<f64>> {
+        dirs.iter().map(|v| rows.iter().map(|r| simd_dot(r, v)).collect()).collect()
+    };
+    // X^T y for each of the l sample-space columns
+    let apply_t = |cols: &[Vec<f64>]| -> Vec<Vec<f64>> {
+        cols.iter().map(|y| {
+            let mut out = vec![0.0; d];
+            for (r, &w) in rows.iter().zip(y) {
+                for (o, x) in out.iter_mut().zip(r.iter()) {
+                    *o += w * x;
+                }
+            }
+            out
+        }).collect()
+    };
+
+    let omega: Vec<Vec<f64>> = (0..l).map(|_| (0..d).map(|_| rng.next_gaussian()).collect()).collect();
+    let mut q = apply(&omega);
+    orthonormalize(&mut q);
+    for _ in 0..n_iter {
+        let mut z = apply_t(&q);
+        orthonormalize(&mut z);
+        q = apply(&z);
+        orthonormalize(&mut q);
+    }
+
+    // B = Q^T X is small (l x d), so its SVD is exact and cheap
+    let b: Vec<f64> = apply_t(&q).into_iter().flatten().collect();
+    let (_, s, vt) = NdArray::from_vec(Shape::d2(l, d), b).svd(false).map_err(IronForestError::invalid)?;
+    let denom = (n - 1) as f64;
+    let variances = s.as_slice_unchecked().iter().map(|s| s * s / denom).collect();
+    Ok((vt.into_vec(), variances))
+}
+
+/// Modified Gram-Schmidt, run twice for orthogonality in floating point. Columns that
+/// vanish are zeroed.
+fn orthonormalize(cols: &mut [Vec<f64>]) {
+    for _ in 0..2 {
+        for j in 0..cols.len() {
+            let (done, rest) = cols.split_at_mut(j);
+            let col = &mut rest[0];
+            for prev in done.iter() {
+                let proj = simd_dot(col, prev);
+                for (c, p) in col.iter_mut().zip(prev) {
+                    *c -= proj * p;
+                }
+            }
+            let norm = simd_dot(col, col).sqrt();
+            let inv = if norm > f64::EPSILON { 1.0 / norm } else { 0.0 };
+            col.iter_mut().for_each(|c| *c *= inv);
+        }
+    }
+}
+
+/// Makes each component's largest-magnitude entry positive so fits are reproducible.
+fn flip_signs(components: &mut [f64], d: usize) {
+    for component in components.chunks_exact_mut(d) {
+        let largest = component.iter().cloned().fold(0.0, |acc: f64, x| if x.abs() > acc.abs() { x } else { acc });
+        if largest < 0.0 {
+            component.iter_mut().for_each(|c| *c = -*c);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_input_is_an_error() {
+        let mut rng = Generator::from_seed(0);
+        let data = NdArray::from_vec(Shape::d2(4, 3), (0..12).map(|i| (i * i % 7) as f64).collect());
+        let flat = NdArray::from_vec(Shape::d1(12), data.as_slice_unchecked().to_vec());
+
+        assert_eq!(
+            PCAReducer::fit(&flat, PCAComponents::All, false, PCASolver::Full, &mut rng).unwrap_err(),
+            IronForestError::InvalidShape { ndim: 1 },
+        );
+        assert!(matches!(
+            PCAReducer::fit(&data, PCAComponents::Count(4), false, PCASolver::Full, &mut rng),
+            Err(IronForestError::InvalidArgument(_)),
+        ));
+
+        let pca = PCAReducer::fit(&data, PCAComponents::Count(2), false, PCASolver::Full, &mut rng).unwrap();
+        let wide = NdArray::from_vec(Shape::d2(1, 4), vec![0.0; 4]);
+        assert_eq!(pca.transform(&wide).unwrap_err(), IronForestError::DimensionMismatch { expected: 3, got: 4 });
+        assert_eq!(pca.inverse_transform(&wide).unwrap_err(), IronForestError::DimensionMismatch { expected: 2, got: 4 });
+    }
+}
@@ -1,6 +1,7 @@
 use crate::array::{NdArray};
+use crate::error::IronForestError;
 use crate::random::Generator;
-use super::{RandomProjection, ProjectionType};
+use super::{ProjectionFloat, RandomProjection, ProjectionType};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -14,25 +15,29 @@ impl ProjectionReducer {
         output_dim: usize,
         projection_type: ProjectionType,
         rng: &mut Generator,
-    ) -> Self {
-        let projection = RandomProjection::new(input_dim, output_dim, projection_type, rng);
-        Self { projection }
+    ) -> Result<Self, IronForestError> {
+        let projection = RandomProjection::new(input_dim, output_dim, projection_type, rng)?;
+        Ok(Self { projection })
     }
 
-    pub fn transform(&self, data: &NdArray<f64>) -> NdArray<f64> {
+    /// Projects `data` in its own precision, so f32 input stays f32.
+    pub fn transform<T: ProjectionFloat>(&self, data: &NdArray<T>) -> Result<NdArray<T>, IronForestError> {
         self.projection.project(data)
     }
 
-    pub fn fit_transform(
-        data: &NdArray<f64>,
+    pub fn fit_transform<T: ProjectionFloat>(
+        data: &NdArray<T>,
         output_dim: usize,
         projection_type: ProjectionType,
         rng: &mut Generator,
-    ) -> (Self, NdArray<f64>) {
-        let input_dim = data.shape().dims()[1];
-        let reducer = Self::fit(input_dim, output_dim, projection_type, rng);
-        let transformed = reducer.transform(data);
-        (reducer, transformed)
+    ) -> Result<(Self, NdArray<T>), IronForestError> {
+        let dims = data.shape().dims();
+        if dims.len() != 2 {
+            return Err(IronForestError::InvalidShape { ndim: dims.len() });
+        }
+        let reducer = Self::fit(dims[1], output_dim, projection_type, rng)?;
+        let transformed = reducer.transform(data)?;
+        Ok((reducer, transformed))
     }
 
     pub fn input_dim(&self) -> usize { self.projection.input_dim }
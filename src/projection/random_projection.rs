@@ -1,53 +1,119 @@
 use crate::array::{NdArray, Shape};
+use crate::error::{IronForestError, check_queries};
 use crate::linalg::basic::simd_dot;
 use crate::random::Generator;
-use num_traits::ToPrimitive;
+use crate::IronFloat;
 use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+// Points used to estimate a node's spread or covariance when choosing a split
+const SPLIT_SAMPLE: usize = 256;
+const POWER_ITERATIONS: usize = 32;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum ProjectionType {
     Gaussian,
+    /// Entries are nonzero with the given probability.
     Sparse(f64),
-    // Achlioptas,
+    /// Achlioptas' database-friendly projection, entries of ±1 with probability 1/6 each.
+    Achlioptas,
+    /// Li et al.'s very sparse projection, entries of ±1 with probability 1/(2√d) each.
+    VerySparse,
+    /// Subsampled randomized Hadamard transform, projecting a point in O(d log d). A tree
+    /// split uses a single row of the randomized Hadamard matrix, which is a dense random
+    /// sign vector, so splits get no speedup from the fast transform.
+    Hadamard,
+}
+
+impl ProjectionType {
+    /// Probability that an entry of a sparse projection is nonzero.
+    fn density(self, dim: usize) -> Option<f64> {
+        match self {
+            ProjectionType::Sparse(density) => Some(density),
+            ProjectionType::Achlioptas => Some(1.0 / 3.0),
+            ProjectionType::VerySparse => Some(1.0 / (dim as f64).sqrt()),
+            ProjectionType::Gaussian | ProjectionType::Hadamard => None,
+        }
+    }
+
+    /// Rejects a `Sparse` density outside (0, 1], which would scale entries by infinity
+    /// or leave every entry zero.
+    pub fn check(self) -> Result<(), IronForestError> {
+        match self {
+            ProjectionType::Sparse(density) if !(density > 0.0 && density <= 1.0) => Err(IronForestError::invalid(
+                format!("Sparse projection density must be in (0, 1], got {}", density),
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Smallest output dimension that the Johnson–Lindenstrauss lemma guarantees keeps every
+/// pairwise distance among `n_samples` points within a factor of 1 ± `eps`.
+pub fn johnson_lindenstrauss_min_dim(n_samples: usize, eps: f64) -> Result<usize, IronForestError> {
+    if !(eps > 0.0 && eps < 1.0) {
+        return Err(IronForestError::invalid("eps must be in (0, 1)"));
+    }
+    if n_samples == 0 {
+        return Err(IronForestError::invalid("n_samples must be at least 1"));
+    }
+    let denominator = eps * eps / 2.0 - eps * eps * eps / 3.0;
+    Ok((4.0 * (n_samples as f64).ln() / denominator).ceil().max(1.0) as usize)
+}
+
+/// How random projection trees pick a node's split.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum SplitMode {
+    /// Random direction, split at the median.
+    #[default]
+    Median,
+    /// Leading principal axis of the node, split at the median.
+    Principal,
+    /// Random direction with the widest spread out of `m`, split at the median.
+    BestOf(usize),
+    /// Random direction, split in the widest gap within the middle half of the node.
+    MaxMargin,
 }
 
+/// A direction stored in the precision of the points it projects, so f32 trees project
+/// without converting coordinates.
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub enum ProjectionDirection {
-    Dense(Vec<f64>),
-    Sparse(SparseVector),
+#[serde(bound = "T: IronFloat")]
+pub enum ProjectionDirection<T: IronFloat = f64> {
+    Dense(Vec<T>),
+    Sparse(SparseVector<T>),
     Empty,
 }
 
-impl ProjectionDirection {
-    /// Optimised projection for f64 slices (uses SIMD internally).
-    pub fn project(&self, point: &[f64]) -> f64 {
+impl<T: IronFloat> ProjectionDirection<T> {
+    /// Projects `point`, with SIMD for dense directions.
+    pub fn project(&self, point: &[T]) -> T {
         match self {
-            Self::Dense(v) => simd_dot(point, v),
+            Self::Dense(v) => T::dot_product_slice(point, v),
             Self::Sparse(sv) => sv.dot(point),
-            Self::Empty => 0.0,
+            Self::Empty => T::zero(),
         }
     }
 
-    /// Generic projection for any numeric slice (converts to f64 scalar).
-    /// Used by RPTree<f32> and other non-f64 trees.
-    pub fn project_t<F: ToPrimitive + Copy>(&self, point: &[F]) -> f64 {
+    pub fn cast<U: IronFloat>(&self) -> ProjectionDirection<U> {
         match self {
-            Self::Dense(v) => point.iter().zip(v).map(|(x, a)| x.to_f64().unwrap() * a).sum(),
-            Self::Sparse(sv) => sv.dot_t(point),
-            Self::Empty => 0.0,
+            Self::Dense(v) => ProjectionDirection::Dense(v.iter().map(|&x| U::from(x).unwrap()).collect()),
+            Self::Sparse(sv) => ProjectionDirection::Sparse(sv.cast()),
+            Self::Empty => ProjectionDirection::Empty,
         }
     }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct SparseVector {
+#[serde(bound = "T: IronFloat")]
+pub struct SparseVector<T: IronFloat = f64> {
     pub indices: Vec<usize>,
-    pub values: Vec<f64>,
+    pub values: Vec<T>,
     pub dim: usize,
 }
 
-impl SparseVector {
-    pub fn dot(&self, dense: &[f64]) -> f64 {
+impl<T: IronFloat> SparseVector<T> {
+    pub fn dot(&self, dense: &[T]) -> T {
         debug_assert!(self.dim <= dense.len());
         self.indices.iter()
             .zip(&self.values)
@@ -55,21 +121,103 @@ impl SparseVector {
             .sum()
     }
 
-    pub fn dot_t<F: ToPrimitive + Copy>(&self, dense: &[F]) -> f64 {
-        debug_assert!(self.dim <= dense.len());
-        self.indices.iter()
-            .zip(&self.values)
-            .map(|(&i, &v)| v * dense[i].to_f64().unwrap())
-            .sum()
+    pub fn cast<U: IronFloat>(&self) -> SparseVector<U> {
+        SparseVector {
+            indices: self.indices.clone(),
+            values: self.values.iter().map(|&v| U::from(v).unwrap()).collect(),
+            dim: self.dim,
+        }
     }
 }
 
+/// Random signs, a Walsh–Hadamard transform over the input zero-padded to a power of two,
+/// then `rows` of the result, scaled to preserve norms in expectation.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubsampledHadamard {
+    pub signs: Vec<f64>,
+    pub rows: Vec<usize>,
+    pub padded_dim: usize,
+}
+
+impl SubsampledHadamard {
+    /// `output_dim` must be at most `padded_dim`, as checked by `RandomProjection::new`.
+    fn new(input_dim: usize, output_dim: usize, rng: &mut Generator) -> Self {
+        let padded_dim = input_dim.next_power_of_two();
+        let signs = (0..input_dim).map(|_| rademacher(rng)).collect();
+        let mut rows: Vec<usize> = (0..padded_dim).collect();
+        rng.partial_shuffle(&mut rows, output_dim);
+        rows.truncate(output_dim);
+        Self { signs, rows, padded_dim }
+    }
+
+    /// Projects `point` into `out`, using `buffer` as padded_dim scratch space.
+    fn apply<T: IronFloat>(&self, point: &[T], buffer: &mut [T], out: &mut Vec<T>) {
+        buffer.fill(T::zero());
+        for ((b, &x), &s) in buffer.iter_mut().zip(point).zip(&self.signs) {
+            *b = if s < 0.0 { -x } else { x };
+        }
+        walsh_hadamard(buffer);
+        let scale = T::from(1.0 / (self.rows.len() as f64).sqrt()).unwrap();
+        out.extend(self.rows.iter().map(|&r| buffer[r] * scale));
+    }
+}
+
+/// In-place unnormalized fast Walsh–Hadamard transform. `values.len()` must be a power of two.
+fn walsh_hadamard<T: IronFloat>(values: &mut [T]) {
+    let n = values.len();
+    let mut h = 1;
+    while h < n {
+        for block in values.chunks_exact_mut(2 * h) {
+            let (lo, hi) = block.split_at_mut(h);
+            for (a, b) in lo.iter_mut().zip(hi.iter_mut()) {
+                let (x, y) = (*a, *b);
+                *a = x + y;
+                *b = x - y;
+            }
+        }
+        h *= 2;
+    }
+}
+
+fn rademacher(rng: &mut Generator) -> f64 {
+    if rng.next_f64() < 0.5 { 1.0 } else { -1.0 }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RandomProjection {
     pub input_dim: usize,
     pub output_dim: usize,
     pub projection_type: ProjectionType,
+    /// input_dim x output_dim for `Gaussian`, empty otherwise.
     pub matrix: NdArray<f64>,
+    /// One sparse vector per output dimension for the sparse projection types.
+    #[serde(default)]
+    pub columns: Vec<SparseVector>,
+    #[serde(default)]
+    pub hadamard: Option<SubsampledHadamard>,
+    /// One direction per output dimension in each precision, built by the first projection
+    /// in that precision.
+    #[serde(skip)]
+    directions_f64: OnceLock<Vec<ProjectionDirection<f64>>>,
+    #[serde(skip)]
+    directions_f32: OnceLock<Vec<ProjectionDirection<f32>>>,
+}
+
+/// Precisions a `RandomProjection` projects natively, each with its own cached directions.
+pub trait ProjectionFloat: IronFloat {
+    fn directions(projection: &RandomProjection) -> &OnceLock<Vec<ProjectionDirection<Self>>>;
+}
+
+impl ProjectionFloat for f64 {
+    fn directions(projection: &RandomProjection) -> &OnceLock<Vec<ProjectionDirection<f64>>> {
+        &projection.directions_f64
+    }
+}
+
+impl ProjectionFloat for f32 {
+    fn directions(projection: &RandomProjection) -> &OnceLock<Vec<ProjectionDirection<f32>>> {
+        &projection.directions_f32
+    }
 }
 
 impl RandomProjection {
@@ -78,13 +226,42 @@ impl RandomProjection {
         output_dim: usize,
         projection_type: ProjectionType,
         rng: &mut Generator,
-    ) -> Self {
-        let matrix = match projection_type {
-            ProjectionType::Gaussian => Self::gaussian_matrix(input_dim, output_dim, rng),
-            ProjectionType::Sparse(_) => unimplemented!("Sparse projection matrix not yet supported, use generate_direction for tree splits")
+    ) -> Result<Self, IronForestError> {
+        if input_dim == 0 || output_dim == 0 {
+            return Err(IronForestError::invalid("input_dim and output_dim must be at least 1"));
+        }
+        projection_type.check()?;
+        if let ProjectionType::Hadamard = projection_type
+            && output_dim > input_dim.next_power_of_two()
+        {
+            return Err(IronForestError::invalid(format!(
+                "Hadamard projection needs output_dim <= {} for input_dim {}",
+                input_dim.next_power_of_two(), input_dim
+            )));
+        }
+        let mut projection = Self {
+            input_dim,
+            output_dim,
+            projection_type,
+            matrix: NdArray::from_vec(Shape::d2(0, 0), Vec::new()),
+            columns: Vec::new(),
+            hadamard: None,
+            directions_f64: OnceLock::new(),
+            directions_f32: OnceLock::new(),
         };
-
-        Self { input_dim, output_dim, projection_type, matrix }
+        match projection_type {
+            ProjectionType::Gaussian => projection.matrix = Self::gaussian_matrix(input_dim, output_dim, rng),
+            ProjectionType::Hadamard => projection.hadamard = Some(SubsampledHadamard::new(input_dim, output_dim, rng)),
+            sparse => {
+                let density = sparse.density(input_dim).unwrap();
+                // entries of ±sqrt(1 / (density * k)) have variance 1/k, like the Gaussian matrix
+                let scale = 1.0 / (density * output_dim as f64).sqrt();
+                projection.columns = (0..output_dim)
+                    .map(|_| Self::generate_sign_vector(input_dim, density, scale, rng))
+                    .collect();
+            }
+        }
+        Ok(projection)
     }
 
     fn gaussian_matrix(input_dim: usize, output_dim: usize, rng: &mut Generator) -> NdArray<f64> {
@@ -108,9 +285,39 @@ impl RandomProjection {
             ProjectionType::Sparse(density) => {
                 ProjectionDirection::Sparse(Self::generate_sparse_vector(dim, density, rng))
             }
+            ProjectionType::Achlioptas | ProjectionType::VerySparse => {
+                let density = projection_type.density(dim).unwrap();
+                let mut direction = Self::generate_sign_vector(dim, density, 1.0, rng);
+                if direction.indices.is_empty() {
+                    direction.indices.push(rng.usize_below(dim));
+                    direction.values.push(1.0);
+                }
+                let scale = 1.0 / (direction.indices.len() as f64).sqrt();
+                direction.values.iter_mut().for_each(|v| *v *= scale);
+                ProjectionDirection::Sparse(direction)
+            }
+            ProjectionType::Hadamard => {
+                // a row of the randomized Hadamard matrix is a dense random sign vector, so a
+                // single split direction gains nothing from the fast transform
+                let scale = 1.0 / (dim as f64).sqrt();
+                ProjectionDirection::Dense((0..dim).map(|_| rademacher(rng) * scale).collect())
+            }
         }
     }
 
+    /// Entries of ±`scale`, each nonzero with probability `density`.
+    fn generate_sign_vector(dim: usize, density: f64, scale: f64, rng: &mut Generator) -> SparseVector {
+        let mut indices = Vec::new();
+        let mut values = Vec::new();
+        for i in 0..dim {
+            if rng.next_f64() < density {
+                indices.push(i);
+                values.push(rademacher(rng) * scale);
+            }
+        }
+        SparseVector { indices, values, dim }
+    }
+
     fn generate_sparse_vector(
         dim: usize,
         density: f64,
@@ -141,13 +348,40 @@ impl RandomProjection {
     }
 
 
-    pub fn project(&self, data: &NdArray<f64>) -> NdArray<f64> {
-        assert_eq!(
-            data.shape().dims()[1], self.input_dim,
-            "Data dim {} doesn't match projection input_dim {}",
-            data.shape().dims()[1], self.input_dim
-        );
-        data.dot(&self.matrix)
+    /// Projects the rows of `data` in their own precision.
+    pub fn project<T: ProjectionFloat>(&self, data: &NdArray<T>) -> Result<NdArray<T>, IronForestError> {
+        let n = check_queries(data.shape().dims(), self.input_dim)?;
+        let flat = data.as_contiguous_slice();
+        let rows = flat.chunks_exact(self.input_dim);
+        let mut out = Vec::with_capacity(n * self.output_dim);
+        if let Some(hadamard) = &self.hadamard {
+            let mut buffer = vec![T::zero(); hadamard.padded_dim];
+            for row in rows {
+                hadamard.apply(row, &mut buffer, &mut out);
+            }
+        } else {
+            let directions = T::directions(self).get_or_init(|| self.directions_as());
+            for row in rows {
+                out.extend(directions.iter().map(|d| d.project(row)));
+            }
+        }
+        Ok(NdArray::from_vec(Shape::d2(n, self.output_dim), out))
+    }
+
+    /// The Gaussian matrix or sparse columns as one direction per output dimension, cast to
+    /// `T`. Gaussian columns are transposed so each output coordinate is a contiguous dot
+    /// product.
+    fn directions_as<T: IronFloat>(&self) -> Vec<ProjectionDirection<T>> {
+        match self.projection_type {
+            ProjectionType::Gaussian => {
+                let k = self.output_dim;
+                let m = self.matrix.as_slice_unchecked();
+                (0..k)
+                    .map(|j| ProjectionDirection::Dense((0..self.input_dim).map(|i| T::from(m[i * k + j]).unwrap()).collect()))
+                    .collect()
+            }
+            _ => self.columns.iter().map(|c| ProjectionDirection::Sparse(c.cast())).collect(),
+        }
     }
 
     pub fn project_onto(data: &NdArray<f64>, vector: &[f64]) -> Vec<f64> {
@@ -159,43 +393,155 @@ impl RandomProjection {
         result.as_slice_unchecked().to_vec()
     }
 
-    pub fn rp_split<F: ToPrimitive + Copy>(
-        data: &NdArray<F>,
-        indices: &mut [usize],
-        start: usize,
-        end: usize,
-        dim: usize,
+    /// Direction for splitting the node holding `indices`, drawn in f64 and stored in the
+    /// data's precision. Spread and covariance are estimated from an evenly strided sample
+    /// of at most `SPLIT_SAMPLE` points.
+    pub fn split_direction<T: IronFloat>(
+        data: &NdArray<T>,
+        indices: &[usize],
         projection_type: ProjectionType,
+        split_mode: SplitMode,
         rng: &mut Generator,
-    ) -> (ProjectionDirection, f64, usize) {
-        let direction = Self::generate_direction(dim, projection_type, rng);
-
-        let mut indexed: Vec<(f64, usize)> = (start..end)
-            .map(|i| {
-                let row = data.row(indices[i]);
-                let proj = direction.project_t(row);
-                (proj, indices[i])
-            })
+    ) -> ProjectionDirection<T> {
+        let dim = data.shape().dims()[1];
+        let step = indices.len().div_ceil(SPLIT_SAMPLE).max(1);
+        let sample = || indices.iter().step_by(step).map(|&i| data.row(i));
+
+        match split_mode {
+            SplitMode::Median | SplitMode::MaxMargin => Self::generate_direction(dim, projection_type, rng).cast(),
+            SplitMode::BestOf(m) => {
+                let spread = |direction: &ProjectionDirection<T>| {
+                    let proj: Vec<f64> = sample().map(|row| direction.project(row).to_f64().unwrap()).collect();
+                    let mean = proj.iter().sum::<f64>() / proj.len() as f64;
+                    proj.iter().map(|p| (p - mean).powi(2)).sum::<f64>()
+                };
+                (0..m.max(1))
+                    .map(|_| {
+                        let direction = Self::generate_direction(dim, projection_type, rng).cast();
+                        (spread(&direction), direction)
+                    })
+                    .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal))
+                    .unwrap()
+                    .1
+            }
+            SplitMode::Principal => {
+                let fallback = Self::generate_direction(dim, ProjectionType::Gaussian, rng);
+                let rows: Vec<T> = sample().flatten().copied().collect();
+                let n = rows.len() / dim;
+                if n < 2 {
+                    return fallback.cast();
+                }
+                let cov = NdArray::from_vec(Shape::new(vec![n, dim]), rows).covariance_mut();
+                let cov = cov.as_slice_unchecked();
+
+                let ProjectionDirection::Dense(mut v) = fallback else { unreachable!() };
+                for _ in 0..POWER_ITERATIONS {
+                    let w: Vec<f64> = (0..dim).map(|r| simd_dot(&cov[r * dim..(r + 1) * dim], &v)).collect();
+                    let norm = w.iter().map(|x| x * x).sum::<f64>().sqrt();
+                    if norm <= f64::EPSILON {
+                        break;
+                    }
+                    let w: Vec<f64> = w.into_iter().map(|x| x / norm).collect();
+                    let converged = simd_dot(&w, &v).abs() >= 1.0 - 1e-10;
+                    v = w;
+                    if converged {
+                        break;
+                    }
+                }
+                ProjectionDirection::Dense(v).cast()
+            }
+        }
+    }
+
+    /// Partitions `indices` by their projection onto `direction`, returning the split value
+    /// and the size of the left side. In median mode the split is the projection of the
+    /// first point on the right, so points below it go left and ties may land on either
+    /// side. In max-margin mode the split sits midway across a gap and points below it go
+    /// left.
+    pub fn split_indices<T: IronFloat>(
+        data: &NdArray<T>,
+        indices: &mut [usize],
+        direction: &ProjectionDirection<T>,
+        split_mode: SplitMode,
+    ) -> (T, usize) {
+        let n = indices.len();
+        let mut indexed: Vec<(T, usize)> = indices.iter()
+            .map(|&i| (direction.project(data.row(i)), i))
             .collect();
 
-        let mid_offset = (end - start) / 2;
-        indexed.select_nth_unstable_by(mid_offset, |a, b| {
-            a.0.partial_cmp(&b.0).unwrap()
-        });
+        let (split, mid) = match split_mode {
+            SplitMode::MaxMargin => {
+                // widest gap between consecutive projections within the middle half
+                indexed.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+                let lo = (n / 4).max(1);
+                let hi = (3 * n / 4).max(lo + 1).min(n);
+                let mid = (lo..hi)
+                    .max_by(|&a, &b| {
+                        let gap_a = indexed[a].0 - indexed[a - 1].0;
+                        let gap_b = indexed[b].0 - indexed[b - 1].0;
+                        gap_a.total_cmp(&gap_b)
+                            .then_with(|| b.abs_diff(n / 2).cmp(&a.abs_diff(n / 2)))
+                    })
+                    .unwrap();
+                ((indexed[mid - 1].0 + indexed[mid].0) * T::from(0.5).unwrap(), mid)
+            }
+            _ => {
+                let mid_offset = n / 2;
+                indexed.select_nth_unstable_by(mid_offset, |a, b| {
+                    a.0.total_cmp(&b.0)
+                });
+                (indexed[mid_offset].0, mid_offset.max(1))
+            }
+        };
 
-        let split = indexed[mid_offset].0;
+        for (slot, &(_, idx)) in indices.iter_mut().zip(&indexed) {
+            *slot = idx;
+        }
+        (split, mid)
+    }
+}
 
-        for (i, &(_, idx)) in indexed.iter().enumerate() {
-            indices[start + i] = idx;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn f32_and_f64_projections_agree() {
+        let mut rng = Generator::from_seed(3);
+        let data = rng.standard_normal(Shape::d2(5, 12));
+        let data32 = data.map(|x| x as f32);
+        for projection_type in [ProjectionType::Gaussian, ProjectionType::Achlioptas, ProjectionType::Hadamard] {
+            let projection = RandomProjection::new(12, 4, projection_type, &mut rng).unwrap();
+            let expected = projection.project(&data).unwrap();
+            for _ in 0..2 {
+                let got = projection.project(&data32).unwrap();
+                for (a, b) in expected.as_slice_unchecked().iter().zip(got.as_slice_unchecked()) {
+                    assert!((a - *b as f64).abs() < 1e-4, "{:?}: {} vs {}", projection_type, a, b);
+                }
+            }
         }
+    }
 
-        let mut mid = start + mid_offset;
-        if mid == start {
-            mid = start + 1;
-        } else if mid == end {
-            mid = end - 1;
+    #[test]
+    fn invalid_dimensions_are_errors() {
+        let mut rng = Generator::from_seed(0);
+        assert!(RandomProjection::new(12, 16, ProjectionType::Hadamard, &mut rng).is_ok());
+        assert!(RandomProjection::new(12, 17, ProjectionType::Hadamard, &mut rng).is_err());
+        assert!(RandomProjection::new(0, 4, ProjectionType::Gaussian, &mut rng).is_err());
+        for density in [0.0, -0.5, 1.5, f64::NAN] {
+            assert!(RandomProjection::new(12, 4, ProjectionType::Sparse(density), &mut rng).is_err());
         }
+        assert!(RandomProjection::new(12, 4, ProjectionType::Sparse(1.0), &mut rng).is_ok());
 
-        (direction, split, mid)
+        let projection = RandomProjection::new(12, 4, ProjectionType::Gaussian, &mut rng).unwrap();
+        let wide = rng.standard_normal(Shape::d2(3, 13));
+        assert_eq!(projection.project(&wide).unwrap_err(), IronForestError::DimensionMismatch { expected: 12, got: 13 });
+        let flat = rng.standard_normal(Shape::d1(12));
+        assert_eq!(projection.project(&flat).unwrap_err(), IronForestError::InvalidShape { ndim: 1 });
+        let reducer = crate::projection::ProjectionReducer::fit(12, 4, ProjectionType::Gaussian, &mut rng).unwrap();
+        assert_eq!(reducer.transform(&wide).unwrap_err(), IronForestError::DimensionMismatch { expected: 12, got: 13 });
+
+        assert!(johnson_lindenstrauss_min_dim(100, 1.5).is_err());
+        assert!(johnson_lindenstrauss_min_dim(0, 0.1).is_err());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,36 @@
+use pyo3::prelude::*;
+use pyo3::create_exception;
+use pyo3::exceptions::PyValueError;
+use crate::error::IronForestError as CoreError;
+
+create_exception!(ironforest._core, IronForestError, PyValueError, "Base class for invalid input to spatial queries and indexes.");
+create_exception!(ironforest._core, DimensionMismatchError, IronForestError, "Points or queries whose dimension differs from the index.");
+create_exception!(ironforest._core, EmptyIndexError, IronForestError, "The operation needs at least one indexed point.");
+create_exception!(ironforest._core, InvalidKError, IronForestError, "k is not a valid neighbor count.");
+create_exception!(ironforest._core, NonFiniteError, IronForestError, "Input contains NaN or infinity under nan_policy='raise'.");
+create_exception!(ironforest._core, UninitializedError, IronForestError, "The index has no tree to query.");
+
+impl From<CoreError> for PyErr {
+    fn from(e: CoreError) -> PyErr {
+        let msg = e.to_string();
+        match e {
+            CoreError::DimensionMismatch { .. } | CoreError::InvalidShape { .. } => DimensionMismatchError::new_err(msg),
+            CoreError::EmptyIndex => EmptyIndexError::new_err(msg),
+            CoreError::InvalidK { .. } => InvalidKError::new_err(msg),
+            CoreError::NonFinite { .. } => NonFiniteError::new_err(msg),
+            CoreError::Uninitialized => UninitializedError::new_err(msg),
+            CoreError::InvalidArgument(_) => IronForestError::new_err(msg),
+        }
+    }
+}
+
+pub fn register_exceptions(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("IronForestError", py.get_type::<IronForestError>())?;
+    m.add("DimensionMismatchError", py.get_type::<DimensionMismatchError>())?;
+    m.add("EmptyIndexError", py.get_type::<EmptyIndexError>())?;
+    m.add("InvalidKError", py.get_type::<InvalidKError>())?;
+    m.add("NonFiniteError", py.get_type::<NonFiniteError>())?;
+    m.add("UninitializedError", py.get_type::<UninitializedError>())?;
+    Ok(())
+}
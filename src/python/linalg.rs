@@ -12,6 +12,9 @@ pub fn register_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(eig, m)?)?;
     m.add_function(wrap_pyfunction!(eig_with_params, m)?)?;
     m.add_function(wrap_pyfunction!(eigvals, m)?)?;
+    m.add_function(wrap_pyfunction!(eigh, m)?)?;
+    m.add_function(wrap_pyfunction!(eigvalsh, m)?)?;
+    m.add_function(wrap_pyfunction!(svd, m)?)?;
     m.add_function(wrap_pyfunction!(diagonal, m)?)?;
     m.add_function(wrap_pyfunction!(outer, m)?)?;
     m.add_function(wrap_pyfunction!(lstsq, m)?)?;
@@ -89,6 +92,32 @@ fn eigvals(a: ArrayLike) -> PyResult<PyArray> {
         .map_err(|e| PyValueError::new_err(e))
 }
 
+#[pyfunction]
+fn eigh(a: ArrayLike) -> PyResult<(PyArray, PyArray)> {
+    a.into_ndarray()?.eigh()
+        .map(|(vals, vecs)| (PyArray { inner: ArrayData::Float(vals), alive: true }, PyArray { inner: ArrayData::Float(vecs), alive: true }))
+        .map_err(PyValueError::new_err)
+}
+
+#[pyfunction]
+fn eigvalsh(a: ArrayLike) -> PyResult<PyArray> {
+    a.into_ndarray()?.eigvalsh()
+        .map(|vals| PyArray { inner: ArrayData::Float(vals), alive: true })
+        .map_err(PyValueError::new_err)
+}
+
+#[pyfunction]
+#[pyo3(signature = (a, full_matrices=false))]
+fn svd(a: ArrayLike, full_matrices: bool) -> PyResult<(PyArray, PyArray, PyArray)> {
+    a.into_ndarray()?.svd(full_matrices)
+        .map(|(u, s, vt)| (
+            PyArray { inner: ArrayData::Float(u), alive: true },
+            PyArray { inner: ArrayData::Float(s), alive: true },
+            PyArray { inner: ArrayData::Float(vt), alive: true },
+        ))
+        .map_err(PyValueError::new_err)
+}
+
 #[pyfunction]
 #[pyo3(signature = (a, k=None))]
 fn diagonal(a: ArrayLike, k: Option<isize>) -> PyResult<PyArray> {
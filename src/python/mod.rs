@@ -4,6 +4,7 @@ use pyo3::types::PyAny;
 use pyo3::{FromPyObject, Borrowed};
 use numpy::{PyArrayDyn, PyArrayMethods, PyUntypedArray, PyUntypedArrayMethods};
 use crate::array::{NdArray, Shape};
+use crate::error::IronForestError;
 
 /// Validates that `v` is a non-empty, rectangular 2-D nested list.
 /// Returns `(rows, cols)` on success, or a `PyValueError` if the list is empty
@@ -262,10 +263,7 @@ impl<'py> ArrayLike<'py> {
         match shape.len() {
             1 => {
                 if shape[0] != expected_dim {
-                    return Err(PyValueError::new_err(format!(
-                        "Query dimension {} doesn't match expected dimension {}",
-                        shape[0], expected_dim
-                    )));
+                    return Err(IronForestError::DimensionMismatch { expected: expected_dim, got: shape[0] }.into());
                 }
                 Ok(NdArray::from_vec(
                     Shape::new(vec![1, shape[0]]),
@@ -274,10 +272,7 @@ impl<'py> ArrayLike<'py> {
             }
             2 => {
                 if shape[1] != expected_dim {
-                    return Err(PyValueError::new_err(format!(
-                        "Query dimension {} doesn't match expected dimension {}",
-                        shape[1], expected_dim
-                    )));
+                    return Err(IronForestError::DimensionMismatch { expected: expected_dim, got: shape[1] }.into());
                 }
                 Ok(arr)
             }
@@ -410,10 +405,7 @@ impl<'py> ArrayLike<'py> {
         match shape.len() {
             1 => {
                 if shape[0] != expected_dim {
-                    return Err(PyValueError::new_err(format!(
-                        "Query dimension {} doesn't match expected dimension {}",
-                        shape[0], expected_dim
-                    )));
+                    return Err(IronForestError::DimensionMismatch { expected: expected_dim, got: shape[0] }.into());
                 }
                 Ok(NdArray::from_vec(
                     Shape::new(vec![1, shape[0]]),
@@ -422,10 +414,7 @@ impl<'py> ArrayLike<'py> {
             }
             2 => {
                 if shape[1] != expected_dim {
-                    return Err(PyValueError::new_err(format!(
-                        "Query dimension {} doesn't match expected dimension {}",
-                        shape[1], expected_dim
-                    )));
+                    return Err(IronForestError::DimensionMismatch { expected: expected_dim, got: shape[1] }.into());
                 }
                 Ok(arr)
             }
@@ -541,6 +530,7 @@ pub mod stats;
 pub mod random;
 pub mod spatial;
 pub mod spatial_index;
+pub mod error;
 
 pub use array::{PyArrayIter, PyIntArrayIter};
 pub use random::PyGenerator;
@@ -555,6 +545,9 @@ fn _core(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<spatial::PyKDTree>()?;
     m.add_class::<spatial_index::PySpatialIndex>()?;
     m.add_class::<spatial_index::PyTreeType>()?;
+    m.add_class::<spatial_index::PyTreeRecommendation>()?;
+    m.add_function(wrap_pyfunction!(spatial_index::recommend_tree, m)?)?;
+    error::register_exceptions(m)?;
 
     let sys_modules = m.py().import("sys")?.getattr("modules")?;
 
@@ -1,15 +1,22 @@
 use pyo3::prelude::*;
 use pyo3::exceptions::PyValueError;
 use pyo3::types::PyAny;
-use crate::Generator;
+use crate::{Generator, IronFloat};
 use crate::array::{NdArray, Shape};
-use crate::projection::{ProjectionReducer, ProjectionType};
-use crate::spatial::trees::{AggTree, AggTree32, BallTree, BallTree32, BruteForce, BruteForce32, KDTree, KDTree32, RPTree, RPTree32, SpectralTree, SpectralTree32, VPTree, VPTree32, VantagePointSelection};
-use crate::spatial::{DistanceMetric, KernelType, SpatialTree};
-use crate::spatial::queries::{KnnQuery, RadiusQuery, KdeQuery, AnnQuery};
-use super::{PyArray, ArrayData, ArrayLike};
+use crate::projection::{johnson_lindenstrauss_min_dim, PCAComponents, PCAReducer, PCASolver, ProjectionFloat, ProjectionReducer, ProjectionType, SplitMode};
+use crate::spatial::trees::{AggTree, AggTree32, ErrorTarget, MomentMode, BallTree, BallTree32, BruteForce, BruteForce32, KDTree, KDTree32, RPForest, RPForest32, RPTree, RPTree32, SpectralTree, SpectralTree32, VPTree, VPTree32, VantagePointSelection};
+use crate::spatial::{DistanceMetric, KernelType, NanPolicy, NanRows, Neighbors, SpatialTree};
+use crate::error::check_k;
+use crate::spatial::nan_policy::has_non_finite;
+use crate::spatial::spatial_index::QueryResult;
+use crate::spatial::queries::{KnnQuery, RadiusQuery, KdeQuery, AnnQuery, MeanShiftQuery};
+use crate::spatial::queries::gauss_transform::MAX_GAUSS_TERMS;
+use super::{PyArray, ArrayData, ArrayLike, FloatNdArray};
+use super::spatial_index::query_result_to_py;
+use super::error::{NonFiniteError, UninitializedError};
 use pyo3::types::PyBytes;
 use rmp_serde;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use std::io::{Write, Read};
 use num_traits::{ToPrimitive, NumCast};
 
@@ -210,7 +217,7 @@ impl PySpatialResult {
 //macro for accessing tree inner. Has been changed to an option for serialization
 macro_rules! tree {
     ($self:expr) => {
-        $self.inner.as_ref().ok_or_else(|| PyValueError::new_err("Tree is uninitialized"))?
+        $self.inner.as_ref().ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?
     };
 }
 
@@ -232,30 +239,39 @@ pub(crate) fn scalar_or_array(py: Python<'_>, values: Vec<f64>, is_single: bool)
     }
 }
 
-/// Recovers the original-order point matrix from a tree's internal (permuted) storage.
-///
-/// Trees reorder their input data during construction for cache efficiency. This function
-/// undoes that permutation so `data()` always returns rows in the order the user inserted them.
-pub(crate) fn get_tree_data(
-    tree_indices: &[usize],
-    raw_data: &[f64],
-    n_points: usize,
-    dim: usize,
-    indices: Option<ArrayLike>,
-) -> PyResult<PyArray> {
-    
+/// Points of a tree in its own row numbering, the order of the array it was built from.
+pub(crate) trait TreeRows {
+    fn tree_rows(&self) -> Vec<f64>;
+}
 
-    let mut original_data = vec![0.0f64; n_points * dim];
-    for (tree_pos, &orig_idx) in tree_indices.iter().enumerate() {
-        original_data[orig_idx * dim..(orig_idx + 1) * dim]
-            .copy_from_slice(&raw_data[tree_pos * dim..(tree_pos + 1) * dim]);
+impl<S: SpatialTree> TreeRows for S {
+    fn tree_rows(&self) -> Vec<f64> {
+        let dim = self.dim();
+        let mut rows = vec![0.0f64; self.n_points() * dim];
+        for (pos, &row) in self.indices().iter().enumerate() {
+            for (out, v) in rows[row * dim..(row + 1) * dim].iter_mut().zip(self.get_point(pos)) {
+                *out = v.to_f64().unwrap();
+            }
+        }
+        rows
     }
+}
 
+// The forest never reorders its data, so rows are already in original order
+impl<T: IronFloat> TreeRows for crate::spatial::trees::rp_forest::RPForest<T> {
+    fn tree_rows(&self) -> Vec<f64> {
+        self.data.as_contiguous_slice().iter().map(|v| v.to_f64().unwrap()).collect()
+    }
+}
+
+/// Rows of the input-ordered point matrix `rows`, or all of them.
+pub(crate) fn select_data(rows: Vec<f64>, dim: usize, indices: Option<ArrayLike>) -> PyResult<PyArray> {
+    let n_points = rows.len().checked_div(dim).unwrap_or(0);
     match indices {
         None => Ok(PyArray {
             inner: ArrayData::Float(NdArray::from_vec(
                 Shape::new(vec![n_points, dim]),
-                original_data,
+                rows,
             )),
             alive: true
         }),
@@ -271,7 +287,7 @@ pub(crate) fn get_tree_data(
                         orig_idx, n_points
                     )));
                 }
-                result.extend_from_slice(&original_data[i * dim..(i + 1) * dim]);
+                result.extend_from_slice(&rows[i * dim..(i + 1) * dim]);
             }
             Ok(PyArray {
                 inner: ArrayData::Float(NdArray::from_vec(
@@ -284,6 +300,60 @@ pub(crate) fn get_tree_data(
     }
 }
 
+/// Every input row as a query, rows set aside included, for methods that default to
+/// querying the indexed points.
+fn input_queries<T: IronFloat>(nan: &NanRows, tree_rows: Vec<f64>, dim: usize) -> NdArray<T> {
+    let rows: Vec<T> = nan.input_data(tree_rows).into_iter()
+        .map(|v| <T as NumCast>::from(v).unwrap())
+        .collect();
+    NdArray::from_vec(Shape::new(vec![rows.len() / dim.max(1), dim]), rows)
+}
+
+/// An AggTree's points ordered by index, the order they were added in, as default
+/// queries. Compact trees keep only the rows of leaves they could not approximate.
+fn agg_input_queries<T: IronFloat>(tree: &crate::spatial::trees::agg_tree::AggTree<T>) -> PyResult<NdArray<T>> {
+    if tree.compact {
+        return Err(PyValueError::new_err(
+            "Compact AggTree dropped the raw points used as default queries; pass queries or rebuild with compact=False",
+        ));
+    }
+    let mut slots: Vec<usize> = (0..tree.n_points).collect();
+    slots.sort_unstable_by_key(|&slot| tree.indices[slot]);
+    let mut rows = Vec::with_capacity(tree.n_points * tree.dim);
+    for slot in slots {
+        rows.extend_from_slice(tree.data.row(slot));
+    }
+    Ok(NdArray::from_vec(Shape::new(vec![tree.n_points, tree.dim]), rows))
+}
+
+fn f64_neighbors<T: IronFloat>(results: Vec<Vec<(usize, T)>>) -> Vec<Vec<(usize, f64)>> {
+    results.into_iter()
+        .map(|hits| hits.into_iter().map(|(i, d)| (i, d.to_f64().unwrap())).collect())
+        .collect()
+}
+
+/// Sets aside rows of tree input containing NaN according to `nan_policy`.
+pub(crate) fn split_nan<T: IronFloat>(data: NdArray<T>, nan_policy: &str) -> PyResult<(NdArray<T>, NanRows)> {
+    let policy = parse_nan_policy(nan_policy)?;
+    Ok(NanRows::split(data, policy)?)
+}
+
+/// Serialized tree followed by its NaN rows.
+fn state_bytes<S: Serialize>(tag: u8, tree: &S, nan: &NanRows) -> PyResult<Vec<u8>> {
+    let mut bytes = vec![tag];
+    bytes.extend(rmp_serde::to_vec(tree).map_err(|e| PyValueError::new_err(e.to_string()))?);
+    bytes.extend(rmp_serde::to_vec(nan).map_err(|e| PyValueError::new_err(e.to_string()))?);
+    Ok(bytes)
+}
+
+/// Reads a tree and the NaN rows after it. States saved before NaN handling have none.
+fn read_state<S: DeserializeOwned>(payload: &[u8]) -> PyResult<(S, NanRows)> {
+    let mut de = rmp_serde::Deserializer::from_read_ref(payload);
+    let tree = S::deserialize(&mut de).map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let nan = NanRows::deserialize(&mut de).unwrap_or_default();
+    Ok((tree, nan))
+}
+
 // =============================================================================
 // Parsing
 // =============================================================================
@@ -301,19 +371,60 @@ pub(crate) fn parse_metric(metric: &str) -> PyResult<DistanceMetric> {
     }
 }
 
-pub(crate) fn parse_kernel(kernel: &str) -> PyResult<KernelType> {
+/// `nu` sets the degrees of freedom of the student-t kernel and is ignored by the others.
+pub(crate) fn parse_kernel(kernel: &str, nu: f64) -> PyResult<KernelType> {
     match kernel.to_lowercase().as_str() {
         "gaussian" => Ok(KernelType::Gaussian),
         "epanechnikov" => Ok(KernelType::Epanechnikov),
         "uniform" => Ok(KernelType::Uniform),
         "triangular" => Ok(KernelType::Triangular),
+        "exponential" | "laplace" => Ok(KernelType::Exponential),
+        "cosine" => Ok(KernelType::Cosine),
+        "biweight" => Ok(KernelType::Biweight),
+        "triweight" => Ok(KernelType::Triweight),
+        "cauchy" => Ok(KernelType::StudentT(1.0)),
+        "student_t" if nu.is_finite() && nu > 0.0 => Ok(KernelType::StudentT(nu)),
+        "student_t" => Err(PyValueError::new_err(format!("nu must be positive and finite, got {}", nu))),
         _ => Err(PyValueError::new_err(format!(
-            "Unknown kernel type '{}'. Valid options: 'gaussian', 'epanechnikov', 'uniform', 'triangular'",
+            "Unknown kernel type '{}'. Valid options: 'gaussian', 'epanechnikov', 'uniform', 'triangular', \
+             'exponential', 'cosine', 'biweight', 'triweight', 'cauchy', 'student_t'",
             kernel
         ))),
     }
 }
 
+pub(crate) fn check_euclidean(metric: &DistanceMetric, operation: &str) -> PyResult<()> {
+    match metric {
+        DistanceMetric::Euclidean => Ok(()),
+        other => Err(PyValueError::new_err(format!(
+            "{} requires the euclidean metric, tree uses {:?}", operation, other
+        ))),
+    }
+}
+
+pub(crate) fn parse_moments(moments: &str, rank: Option<usize>, metric: &DistanceMetric) -> PyResult<MomentMode> {
+    let mode = match moments.to_lowercase().as_str() {
+        "isotropic" => return Ok(MomentMode::Isotropic),
+        "covariance" => MomentMode::Covariance,
+        "low_rank" => match rank.unwrap_or(2) {
+            0 => return Err(PyValueError::new_err("rank must be at least 1")),
+            r => MomentMode::LowRank(r),
+        },
+        _ => return Err(PyValueError::new_err(format!(
+            "Unknown moments '{}'. Valid options: 'isotropic', 'covariance', 'low_rank'",
+            moments
+        ))),
+    };
+    if !matches!(metric, DistanceMetric::Euclidean) {
+        return Err(PyValueError::new_err(format!("moments='{}' requires the euclidean metric", moments)));
+    }
+    Ok(mode)
+}
+
+pub(crate) fn check_normalizable(kernel: KernelType, dim: usize) -> PyResult<()> {
+    Ok(kernel.check_normalizable(dim)?)
+}
+
 pub(crate) fn parse_vantage_selection(selection: &str) -> PyResult<VantagePointSelection> {
     match selection.to_lowercase().as_str() {
         "first" => Ok(VantagePointSelection::First),
@@ -329,15 +440,49 @@ pub(crate) fn parse_vantage_selection(selection: &str) -> PyResult<VantagePointS
 pub(crate) fn parse_projection_type(projection: &str, density: f64) -> PyResult<ProjectionType> {
     match projection.to_lowercase().as_str() {
         "gaussian" => Ok(ProjectionType::Gaussian),
-        "sparse" => Ok(ProjectionType::Sparse(density)),
-        // "achlioptas" => Ok(ProjectionType::Achlioptas),
+        "sparse" => {
+            let projection_type = ProjectionType::Sparse(density);
+            projection_type.check()?;
+            Ok(projection_type)
+        }
+        "achlioptas" => Ok(ProjectionType::Achlioptas),
+        "very_sparse" => Ok(ProjectionType::VerySparse),
+        "hadamard" => Ok(ProjectionType::Hadamard),
         _ => Err(PyValueError::new_err(format!(
-            "Unknown projection type '{}'. Valid options: 'gaussian'",
+            "Unknown projection type '{}'. Valid options: 'gaussian', 'sparse', 'achlioptas', 'very_sparse', 'hadamard'",
             projection
         ))),
     }
 }
 
+// Random directions compared per node by the "best_of" split
+const BEST_OF_DIRECTIONS: usize = 8;
+
+pub(crate) fn parse_split_mode(split: &str) -> PyResult<SplitMode> {
+    match split.to_lowercase().as_str() {
+        "median" => Ok(SplitMode::Median),
+        "principal" | "pca" => Ok(SplitMode::Principal),
+        "best_of" => Ok(SplitMode::BestOf(BEST_OF_DIRECTIONS)),
+        "max_margin" => Ok(SplitMode::MaxMargin),
+        _ => Err(PyValueError::new_err(format!(
+            "Unknown split mode '{}'. Valid options: 'median', 'principal', 'best_of', 'max_margin'",
+            split
+        ))),
+    }
+}
+
+pub(crate) fn parse_nan_policy(nan_policy: &str) -> PyResult<NanPolicy> {
+    match nan_policy.to_lowercase().as_str() {
+        "raise" => Ok(NanPolicy::Raise),
+        "drop" => Ok(NanPolicy::Drop),
+        "ignore_dims" => Ok(NanPolicy::IgnoreDims),
+        _ => Err(PyValueError::new_err(format!(
+            "Unknown nan_policy '{}'. Valid options: 'raise', 'drop', 'ignore_dims'",
+            nan_policy
+        ))),
+    }
+}
+
 // =============================================================================
 // Query Macros
 // =============================================================================
@@ -349,100 +494,66 @@ pub(crate) fn parse_projection_type(projection: &str, density: f64) -> PyResult<
 // because underlying data is managed differently so KDE without params becomes
 // difficult.
 macro_rules! knn_body {
-    ($tree:expr, $queries_arr:expr, $is_batch:expr, $k:expr) => {{
-        let n_queries = $queries_arr.shape().dims()[0];
-        if $is_batch {
-            let results = $tree.query_knn_batch(&$queries_arr, $k);
-            let (indices, distances): (Vec<i64>, Vec<f64>) = results.into_iter()
-                .flatten()
-                .map(|(i, d)| (i as i64, d.to_f64().unwrap()))
-                .unzip();
-            if n_queries == 1 {
-                Ok(PySpatialResult::from_single(indices, distances))
+    ($nan:expr, $tree:expr, $queries_arr:expr, $is_batch:expr, $k:expr) => {{
+        check_k($k)?;
+        let want = Neighbors::Knn($k);
+        let results = $nan.neighbors($tree.metric, $tree.reports_reduced(), &$queries_arr, want, || $tree.tree_rows(), |q| {
+            if $is_batch {
+                Ok(f64_neighbors($tree.query_knn_batch(q, $k)?))
             } else {
-                Ok(PySpatialResult::from_batch_knn(indices, distances, n_queries, $k))
+                Ok(f64_neighbors(vec![$tree.query_knn(&q.as_slice_unchecked()[..$tree.dim], $k)?]))
             }
-        } else {
-            let query_slice = &$queries_arr.as_slice_unchecked()[..$tree.dim];
-            let results = $tree.query_knn(query_slice, $k);
-            let (indices, distances): (Vec<i64>, Vec<f64>) = results.into_iter()
-                .map(|(i, d)| (i as i64, d.to_f64().unwrap())).unzip();
-            Ok(PySpatialResult::from_single(indices, distances))
-        }
+        })?;
+        Ok(query_result_to_py(QueryResult::from_neighbors(results, want)))
     }};
 }
 
 macro_rules! ann_body {
-    ($tree:expr, $queries_arr:expr, $is_batch:expr, $k:expr, $n_candidates:expr, $n_probes:expr) => {{
-        let n_queries = $queries_arr.shape().dims()[0];
-        if $is_batch {
-            let results = match $n_probes {
-                Some(n_probes) => $tree.query_ann_stochastic_batch(&$queries_arr, $k, $n_candidates, n_probes),
-                None => $tree.query_ann_batch(&$queries_arr, $k, $n_candidates),
-            };
-            let (indices, distances): (Vec<i64>, Vec<f64>) = results.into_iter()
-                .flatten()
-                .map(|(i, d)| (i as i64, d.to_f64().unwrap()))
-                .unzip();
-            if n_queries == 1 {
-                Ok(PySpatialResult::from_single(indices, distances))
+    ($nan:expr, $tree:expr, $queries_arr:expr, $is_batch:expr, $k:expr, $n_candidates:expr, $n_probes:expr) => {{
+        check_k($k)?;
+        let want = Neighbors::Knn($k);
+        let results = $nan.neighbors($tree.metric, $tree.reports_reduced(), &$queries_arr, want, || $tree.tree_rows(), |q| {
+            let results = if $is_batch {
+                match $n_probes {
+                    Some(n_probes) => $tree.query_ann_stochastic_batch(q, $k, $n_candidates, n_probes)?,
+                    None => $tree.query_ann_batch(q, $k, $n_candidates)?,
+                }
             } else {
-                Ok(PySpatialResult::from_batch_knn(indices, distances, n_queries, $k))
-            }
-        } else {
-            let query_slice = &$queries_arr.as_slice_unchecked()[..$tree.dim];
-            let results = match $n_probes {
-                Some(n_probes) => $tree.query_ann_stochastic(query_slice, $k, $n_candidates, n_probes),
-                None => $tree.query_ann(query_slice, $k, $n_candidates),
+                let query_slice = &q.as_slice_unchecked()[..$tree.dim];
+                vec![match $n_probes {
+                    Some(n_probes) => $tree.query_ann_stochastic(query_slice, $k, $n_candidates, n_probes),
+                    None => $tree.query_ann(query_slice, $k, $n_candidates),
+                }]
             };
-            let (indices, distances): (Vec<i64>, Vec<f64>) = results.into_iter()
-                .map(|(i, d)| (i as i64, d.to_f64().unwrap())).unzip();
-            Ok(PySpatialResult::from_single(indices, distances))
-        }
+            Ok(f64_neighbors(results))
+        })?;
+        Ok(query_result_to_py(QueryResult::from_neighbors(results, want)))
     }};
 }
 
 macro_rules! radius_body {
-    ($tree:expr, $queries_arr:expr, $is_batch:expr, $rad:expr) => {{
-        if $is_batch {
-            let n_queries = $queries_arr.shape().dims()[0];
-            let results = $tree.query_radius_batch(&$queries_arr, $rad);
-            if n_queries == 1 {
-                let batch = results.into_iter().next().unwrap_or_default();
-                let (indices, distances): (Vec<i64>, Vec<f64>) = batch.into_iter()
-                    .map(|(i, d)| (i as i64, d.to_f64().unwrap())).unzip();
-                Ok(PySpatialResult::from_single(indices, distances))
+    ($nan:expr, $tree:expr, $queries_arr:expr, $is_batch:expr, $rad:expr) => {{
+        let want = Neighbors::Radius($rad.to_f64().unwrap());
+        let results = $nan.neighbors($tree.metric, $tree.reports_reduced(), &$queries_arr, want, || $tree.tree_rows(), |q| {
+            if $is_batch {
+                Ok(f64_neighbors($tree.query_radius_batch(q, $rad)?))
             } else {
-                let mut all_indices = Vec::new();
-                let mut all_distances = Vec::new();
-                let mut counts = Vec::with_capacity(results.len());
-                for batch in results {
-                    counts.push(batch.len() as i64);
-                    for (i, d) in batch {
-                        all_indices.push(i as i64);
-                        all_distances.push(d.to_f64().unwrap());
-                    }
-                }
-                Ok(PySpatialResult::from_batch_radius(all_indices, all_distances, counts))
+                Ok(f64_neighbors(vec![$tree.query_radius(&q.as_slice_unchecked()[..$tree.dim], $rad)?]))
             }
-        } else {
-            let query_slice = &$queries_arr.as_slice_unchecked()[..$tree.dim];
-            let results = $tree.query_radius(query_slice, $rad);
-            let (indices, distances): (Vec<i64>, Vec<f64>) = results.into_iter()
-                .map(|(i, d)| (i as i64, d.to_f64().unwrap())).unzip();
-            Ok(PySpatialResult::from_single(indices, distances))
-        }
+        })?;
+        Ok(query_result_to_py(QueryResult::from_neighbors(results, want)))
     }};
 }
 
 macro_rules! kde_body {
-    ($tree:expr, $queries_arr:expr, $bandwidth:expr, $kernel_type:expr, $normalize:expr, $py:expr) => {{
-        let result = $tree.kernel_density(&$queries_arr, $bandwidth, $kernel_type, $normalize);
-        if result.shape().dims()[0] == 1 {
-            Ok(result.as_slice_unchecked()[0].into_pyobject($py)?.into_any().unbind())
-        } else {
-            Ok(PyArray { inner: ArrayData::Float(result), alive: true }.into_pyobject($py)?.into_any().unbind())
-        }
+    ($nan:expr, $tree:expr, $queries_arr:expr, $bandwidth:expr, $kernel_type:expr, $normalize:expr, $log_density:expr, $py:expr) => {{
+        let result = $nan.density(
+            $tree.metric, $tree.reports_reduced(), &$queries_arr, $kernel_type, $bandwidth, $normalize, $log_density,
+            || $tree.tree_rows(),
+            |q| Ok($tree.kernel_density(q, $bandwidth, $kernel_type, $normalize, $log_density)?.as_contiguous_slice().to_vec()),
+        )?;
+        let is_single = result.len() == 1;
+        scalar_or_array($py, result, is_single)
     }};
 }
 
@@ -454,15 +565,15 @@ macro_rules! impl_knn_query {
             fn query_knn(&self, query: ArrayLike, k: usize) -> PyResult<PySpatialResult> {
                 let is_batch = query.ndim() == 2;
                 let inner = self.inner.as_ref()
-                    .ok_or_else(|| PyValueError::new_err("Tree is uninitialized"))?;
+                    .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
                 match inner {
                     SpatialInner::F64(tree) => {
                         let q = query.into_spatial_query_ndarray(tree.dim)?;
-                        knn_body!(tree, q, is_batch, k)
+                        knn_body!(self.nan, tree, q, is_batch, k)
                     }
                     SpatialInner::F32(tree) => {
                         let q = query.into_f32_spatial_query_ndarray(tree.dim)?;
-                        knn_body!(tree, q, is_batch, k)
+                        knn_body!(self.nan, tree, q, is_batch, k)
                     }
                 }
             }
@@ -485,15 +596,15 @@ macro_rules! impl_ann_query {
                 let n_candidates = n_candidates.unwrap_or(k * 2);
                 let is_batch = query.ndim() == 2;
                 let inner = self.inner.as_ref()
-                    .ok_or_else(|| PyValueError::new_err("Tree is uninitialized"))?;
+                    .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
                 match inner {
                     SpatialInner::F64(tree) => {
                         let q = query.into_spatial_query_ndarray(tree.dim)?;
-                        ann_body!(tree, q, is_batch, k, n_candidates, n_probes)
+                        ann_body!(self.nan, tree, q, is_batch, k, n_candidates, n_probes)
                     }
                     SpatialInner::F32(tree) => {
                         let q = query.into_f32_spatial_query_ndarray(tree.dim)?;
-                        ann_body!(tree, q, is_batch, k, n_candidates, n_probes)
+                        ann_body!(self.nan, tree, q, is_batch, k, n_candidates, n_probes)
                     }
                 }
             }
@@ -509,16 +620,16 @@ macro_rules! impl_radius_query {
             fn query_radius(&self, query: ArrayLike, radius: f64) -> PyResult<PySpatialResult> {
                 let is_batch = query.ndim() == 2;
                 let inner = self.inner.as_ref()
-                    .ok_or_else(|| PyValueError::new_err("Tree is uninitialized"))?;
+                    .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
                 match inner {
                     SpatialInner::F64(tree) => {
                         let q = query.into_spatial_query_ndarray(tree.dim)?;
-                        radius_body!(tree, q, is_batch, radius)
+                        radius_body!(self.nan, tree, q, is_batch, radius)
                     }
                     SpatialInner::F32(tree) => {
                         let q = query.into_f32_spatial_query_ndarray(tree.dim)?;
                         let rad: f32 = <f32 as NumCast>::from(radius).unwrap();
-                        radius_body!(tree, q, is_batch, rad)
+                        radius_body!(self.nan, tree, q, is_batch, rad)
                     }
                 }
             }
@@ -530,7 +641,8 @@ macro_rules! impl_kde_query {
     ($py_type:ty) => {
         #[pymethods]
         impl $py_type {
-            #[pyo3(signature = (queries=None, bandwidth=None, kernel=None, normalize=None))]
+            #[pyo3(signature = (queries=None, bandwidth=None, kernel=None, normalize=None, log_density=false, nu=3.0))]
+            #[allow(clippy::too_many_arguments)]
             fn kernel_density(
                 &self,
                 py: Python<'_>,
@@ -538,37 +650,235 @@ macro_rules! impl_kde_query {
                 bandwidth: Option<f64>,
                 kernel: Option<&str>,
                 normalize: Option<bool>,
+                log_density: bool,
+                nu: f64,
             ) -> PyResult<Py<PyAny>> {
                 let bandwidth = bandwidth.unwrap_or(1.0);
-                let kernel_type = parse_kernel(kernel.unwrap_or("gaussian"))?;
+                let kernel_type = parse_kernel(kernel.unwrap_or("gaussian"), nu)?;
                 let normalize = normalize.unwrap_or(false);
                 let inner = self.inner.as_ref()
-                    .ok_or_else(|| PyValueError::new_err("Tree is uninitialized"))?;
+                    .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+                let dim = match inner { SpatialInner::F64(t) => t.dim, SpatialInner::F32(t) => t.dim };
+                if normalize { check_normalizable(kernel_type, dim)?; }
                 match inner {
                     SpatialInner::F64(tree) => {
                         let queries_arr = if let Some(q) = queries {
                             q.into_spatial_query_ndarray(tree.dim)?
                         } else {
-                            NdArray::from_vec(
-                                Shape::new(vec![tree.n_points, tree.dim]),
-                                tree.data().to_vec()
-                            )
+                            input_queries(&self.nan, tree.tree_rows(), tree.dim)
                         };
-                        kde_body!(tree, queries_arr, bandwidth, kernel_type, normalize, py)
+                        kde_body!(self.nan, tree, queries_arr, bandwidth, kernel_type, normalize, log_density, py)
                     }
                     SpatialInner::F32(tree) => {
                         let queries_arr = if let Some(q) = queries {
                             q.into_f32_spatial_query_ndarray(tree.dim)?
                         } else {
-                            NdArray::from_vec(
-                                Shape::new(vec![tree.n_points, tree.dim]),
-                                tree.data().to_vec()
-                            )
+                            input_queries(&self.nan, tree.tree_rows(), tree.dim)
                         };
-                        kde_body!(tree, queries_arr, bandwidth, kernel_type, normalize, py)
+                        kde_body!(self.nan, tree, queries_arr, bandwidth, kernel_type, normalize, log_density, py)
                     }
                 }
             }
+
+            #[pyo3(signature = (n, bandwidth=1.0, kernel="gaussian", weights=None, seed=None, nu=3.0))]
+            fn sample(
+                &self,
+                n: usize,
+                bandwidth: f64,
+                kernel: &str,
+                weights: Option<ArrayLike>,
+                seed: Option<u64>,
+                nu: f64,
+            ) -> PyResult<PyArray> {
+                let kernel_type = parse_kernel(kernel, nu)?;
+                if bandwidth <= 0.0 {
+                    return Err(PyValueError::new_err("bandwidth must be positive"));
+                }
+                let inner = self.inner.as_ref()
+                    .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+                let (n_points, dim, metric) = match inner {
+                    SpatialInner::F64(t) => (t.n_points, t.dim, &t.metric),
+                    SpatialInner::F32(t) => (t.n_points, t.dim, &t.metric),
+                };
+                check_euclidean(metric, "sample")?;
+                if !kernel_type.is_normalizable(dim) {
+                    return Err(PyValueError::new_err(format!(
+                        "Kernel {:?} has infinite mass in {} dimensions and cannot be sampled", kernel_type, dim
+                    )));
+                }
+                let weights = weights.map(|w| w.into_ndarray()).transpose()?
+                    .map(|w| self.nan.tree_values(w.as_contiguous_slice().to_vec(), n_points));
+                if let Some(w) = &weights {
+                    if w.len() != n_points {
+                        return Err(PyValueError::new_err(format!(
+                            "Expected {} weights, one per indexed point, got {}", n_points, w.len()
+                        )));
+                    }
+                    if w.iter().any(|&v| !v.is_finite() || v < 0.0) || w.iter().sum::<f64>() <= 0.0 {
+                        return Err(PyValueError::new_err("weights must be finite, non-negative and not all zero"));
+                    }
+                }
+                let mut rng = match seed {
+                    Some(s) => Generator::from_seed(s),
+                    None => Generator::new(),
+                };
+                let result = match inner {
+                    SpatialInner::F64(tree) => tree.sample(n, bandwidth, kernel_type, weights.as_deref(), &mut rng),
+                    SpatialInner::F32(tree) => tree.sample(n, bandwidth, kernel_type, weights.as_deref(), &mut rng),
+                }?;
+                Ok(PyArray { inner: ArrayData::Float(result), alive: true })
+            }
+
+            #[pyo3(signature = (queries, responses, y, bandwidth=1.0, response_bandwidth=None, kernel="gaussian", nu=3.0))]
+            #[allow(clippy::too_many_arguments)]
+            fn conditional_density(
+                &self,
+                queries: ArrayLike,
+                responses: ArrayLike,
+                y: ArrayLike,
+                bandwidth: f64,
+                response_bandwidth: Option<f64>,
+                kernel: &str,
+                nu: f64,
+            ) -> PyResult<PyArray> {
+                let kernel_type = parse_kernel(kernel, nu)?;
+                let response_bandwidth = response_bandwidth.unwrap_or(bandwidth);
+                if bandwidth <= 0.0 || response_bandwidth <= 0.0 {
+                    return Err(PyValueError::new_err("bandwidth and response_bandwidth must be positive"));
+                }
+                let is_batch = queries.ndim() == 2;
+                let y = y.into_ndarray()?.as_contiguous_slice().to_vec();
+                let inner = self.inner.as_ref()
+                    .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+                let n_points = match inner { SpatialInner::F64(t) => t.n_points, SpatialInner::F32(t) => t.n_points };
+                let responses = self.nan.tree_values(responses.into_ndarray()?.as_contiguous_slice().to_vec(), n_points);
+                if responses.len() != n_points {
+                    return Err(PyValueError::new_err(format!(
+                        "Expected {} responses, one per indexed point, got {}", n_points, responses.len()
+                    )));
+                }
+                let result = match inner {
+                    SpatialInner::F64(tree) => {
+                        let q = queries.into_spatial_query_ndarray(tree.dim)?;
+                        self.nan.per_query(&q, y.len(), |q| tree.conditional_density(q, &responses, &y, bandwidth, response_bandwidth, kernel_type))
+                    }
+                    SpatialInner::F32(tree) => {
+                        let q = queries.into_f32_spatial_query_ndarray(tree.dim)?;
+                        self.nan.per_query(&q, y.len(), |q| tree.conditional_density(q, &responses, &y, bandwidth, response_bandwidth, kernel_type))
+                    }
+                }?;
+                let result = if is_batch { result } else { result.reshape(vec![y.len()]) };
+                Ok(PyArray { inner: ArrayData::Float(result), alive: true })
+            }
+        }
+    };
+}
+
+macro_rules! impl_mean_shift_query {
+    ($py_type:ty) => {
+        #[pymethods]
+        impl $py_type {
+            #[pyo3(signature = (queries, bandwidth=1.0, kernel="gaussian", normalize=false, nu=3.0))]
+            fn kde_gradient(
+                &self,
+                queries: ArrayLike,
+                bandwidth: f64,
+                kernel: &str,
+                normalize: bool,
+                nu: f64,
+            ) -> PyResult<PyArray> {
+                let kernel_type = parse_kernel(kernel, nu)?;
+                let is_batch = queries.ndim() == 2;
+                let inner = self.inner.as_ref()
+                    .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+                let result = match inner {
+                    SpatialInner::F64(tree) => {
+                        check_euclidean(&tree.metric, "kde_gradient")?;
+                        if normalize { check_normalizable(kernel_type, tree.dim)?; }
+                        let q = queries.into_spatial_query_ndarray(tree.dim)?;
+                        self.nan.per_query(&q, tree.dim, |q| tree.kde_gradient(q, bandwidth, kernel_type, normalize))
+                    }
+                    SpatialInner::F32(tree) => {
+                        check_euclidean(&tree.metric, "kde_gradient")?;
+                        if normalize { check_normalizable(kernel_type, tree.dim)?; }
+                        let q = queries.into_f32_spatial_query_ndarray(tree.dim)?;
+                        self.nan.per_query(&q, tree.dim, |q| tree.kde_gradient(q, bandwidth, kernel_type, normalize))
+                    }
+                }?;
+                let result = if is_batch { result } else { result.reshape(vec![result.len()]) };
+                Ok(PyArray { inner: ArrayData::Float(result), alive: true })
+            }
+
+            #[pyo3(signature = (seeds=None, bandwidth=1.0, kernel="gaussian", max_iter=300, tol=1e-5, nu=3.0))]
+            fn mean_shift(
+                &self,
+                seeds: Option<ArrayLike>,
+                bandwidth: f64,
+                kernel: &str,
+                max_iter: usize,
+                tol: f64,
+                nu: f64,
+            ) -> PyResult<PyArray> {
+                let kernel_type = parse_kernel(kernel, nu)?;
+                let inner = self.inner.as_ref()
+                    .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+                let modes = match inner {
+                    SpatialInner::F64(tree) => {
+                        check_euclidean(&tree.metric, "mean_shift")?;
+                        let seeds_arr = match seeds {
+                            Some(s) => s.into_spatial_query_ndarray(tree.dim)?,
+                            None => input_queries(&self.nan, tree.tree_rows(), tree.dim),
+                        };
+                        self.nan.per_query(&seeds_arr, tree.dim, |s| tree.mean_shift(s, bandwidth, kernel_type, max_iter, tol))
+                    }
+                    SpatialInner::F32(tree) => {
+                        check_euclidean(&tree.metric, "mean_shift")?;
+                        let seeds_arr = match seeds {
+                            Some(s) => s.into_f32_spatial_query_ndarray(tree.dim)?,
+                            None => input_queries(&self.nan, tree.tree_rows(), tree.dim),
+                        };
+                        self.nan.per_query(&seeds_arr, tree.dim, |s| tree.mean_shift(s, bandwidth, kernel_type, max_iter, tol))
+                    }
+                }?;
+                Ok(PyArray { inner: ArrayData::Float(modes), alive: true })
+            }
+
+            #[pyo3(signature = (bandwidth=1.0, kernel="gaussian", max_iter=300, tol=1e-5, merge_radius=None, nu=3.0))]
+            fn mean_shift_cluster(
+                &self,
+                bandwidth: f64,
+                kernel: &str,
+                max_iter: usize,
+                tol: f64,
+                merge_radius: Option<f64>,
+                nu: f64,
+            ) -> PyResult<(PyArray, PyArray)> {
+                let kernel_type = parse_kernel(kernel, nu)?;
+                let merge_radius = merge_radius.unwrap_or(bandwidth / 2.0);
+                let inner = self.inner.as_ref()
+                    .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+                let (centers, labels) = match inner {
+                    SpatialInner::F64(tree) => {
+                        check_euclidean(&tree.metric, "mean_shift_cluster")?;
+                        tree.mean_shift_cluster(bandwidth, kernel_type, max_iter, tol, merge_radius)
+                    }
+                    SpatialInner::F32(tree) => {
+                        check_euclidean(&tree.metric, "mean_shift_cluster")?;
+                        tree.mean_shift_cluster(bandwidth, kernel_type, max_iter, tol, merge_radius)
+                    }
+                }?;
+                // Rows set aside keep the label -1
+                let n = labels.len() + self.nan.rows.len();
+                let mut input_labels = vec![-1i64; n];
+                for (row, l) in labels.into_iter().enumerate() {
+                    input_labels[self.nan.input_row(row)] = l as i64;
+                }
+                let labels = input_labels;
+                Ok((
+                    PyArray { inner: ArrayData::Float(centers), alive: true },
+                    PyArray { inner: ArrayData::Int(NdArray::from_vec(Shape::d1(n), labels)), alive: true },
+                ))
+            }
         }
     };
 }
@@ -580,10 +890,10 @@ macro_rules! impl_data_query {
             #[pyo3(signature = (indices=None))]
             fn data(&self, indices: Option<ArrayLike>) -> PyResult<PyArray> {
                 let inner = self.inner.as_ref()
-                    .ok_or_else(|| PyValueError::new_err("Tree is uninitialized"))?;
+                    .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
                 match inner {
-                    SpatialInner::F64(tree) => get_tree_data(tree.indices(), tree.data(), tree.n_points, tree.dim, indices),
-                    SpatialInner::F32(tree) => get_tree_data_f32(tree.indices(), tree.data(), tree.n_points, tree.dim, indices),
+                    SpatialInner::F64(tree) => select_data(self.nan.input_data(tree.tree_rows()), tree.dim, indices),
+                    SpatialInner::F32(tree) => select_data(self.nan.input_data(tree.tree_rows()), tree.dim, indices),
                 }
             }
         }
@@ -606,16 +916,42 @@ macro_rules! impl_dtype_getter {
     };
 }
 
+macro_rules! impl_nan_getters {
+    ($py_type:ty) => {
+        #[pymethods]
+        impl $py_type {
+            #[getter]
+            fn nan_policy(&self) -> &str {
+                match self.nan.policy {
+                    NanPolicy::Raise => "raise",
+                    NanPolicy::Drop => "drop",
+                    NanPolicy::IgnoreDims => "ignore_dims",
+                }
+            }
+
+            #[getter]
+            fn nan_rows(&self) -> PyArray {
+                let rows: Vec<i64> = self.nan.rows.iter().map(|&i| i as i64).collect();
+                PyArray { inner: ArrayData::Int(NdArray::from_vec(Shape::d1(rows.len()), rows)), alive: true }
+            }
+        }
+    };
+}
+
 impl_data_query!(PyBallTree);
 impl_data_query!(PyKDTree);
 impl_data_query!(PyVPTree);
 impl_data_query!(PyBruteForce);
 impl_data_query!(PyRPTree);
 impl_data_query!(PySpectralTree);
+impl_data_query!(PyRPForest);
 
 impl_ann_query!(PyBallTree);
 impl_ann_query!(PyKDTree);
+impl_ann_query!(PyVPTree);
+impl_ann_query!(PyBruteForce);
 impl_ann_query!(PyRPTree);
+impl_ann_query!(PyRPForest);
 impl_ann_query!(PySpectralTree);
 
 impl_knn_query!(PyBallTree);
@@ -637,14 +973,28 @@ impl_kde_query!(PyKDTree);
 impl_kde_query!(PyVPTree);
 impl_kde_query!(PyBruteForce);
 
+impl_mean_shift_query!(PyBallTree);
+impl_mean_shift_query!(PyKDTree);
+impl_mean_shift_query!(PyVPTree);
+impl_mean_shift_query!(PyBruteForce);
+
 impl_dtype_getter!(PyBallTree);
 impl_dtype_getter!(PyKDTree);
 impl_dtype_getter!(PyVPTree);
 impl_dtype_getter!(PyBruteForce);
 impl_dtype_getter!(PyRPTree);
+impl_dtype_getter!(PyRPForest);
 impl_dtype_getter!(PySpectralTree);
 impl_dtype_getter!(PyAggTree);
 
+impl_nan_getters!(PyBallTree);
+impl_nan_getters!(PyKDTree);
+impl_nan_getters!(PyVPTree);
+impl_nan_getters!(PyBruteForce);
+impl_nan_getters!(PyRPTree);
+impl_nan_getters!(PyRPForest);
+impl_nan_getters!(PySpectralTree);
+
 
 // =============================================================================
 // Serialization Macro
@@ -660,18 +1010,11 @@ macro_rules! impl_spatial_serialization {
         impl $py_type {
             fn __getstate__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
                 let inner = self.inner.as_ref()
-                    .ok_or_else(|| PyValueError::new_err("Tree is uninitialized"))?;
-                let mut bytes = Vec::new();
-                match inner {
-                    SpatialInner::F64(tree) => {
-                        bytes.push(0u8);
-                        bytes.extend(rmp_serde::to_vec(tree).map_err(|e| PyValueError::new_err(e.to_string()))?);
-                    }
-                    SpatialInner::F32(tree) => {
-                        bytes.push(1u8);
-                        bytes.extend(rmp_serde::to_vec(tree).map_err(|e| PyValueError::new_err(e.to_string()))?);
-                    }
-                }
+                    .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+                let bytes = match inner {
+                    SpatialInner::F64(tree) => state_bytes(0u8, tree, &self.nan)?,
+                    SpatialInner::F32(tree) => state_bytes(1u8, tree, &self.nan)?,
+                };
                 Ok(PyBytes::new(py, &bytes))
             }
 
@@ -693,28 +1036,23 @@ macro_rules! impl_spatial_serialization {
                 }
                 let tag = bytes[0];
                 let payload = &bytes[1..];
-                self.inner = Some(match tag {
-                    0 => SpatialInner::F64(rmp_serde::from_slice::<$t64>(payload).map_err(|e| PyValueError::new_err(e.to_string()))?),
-                    1 => SpatialInner::F32(rmp_serde::from_slice::<$t32>(payload).map_err(|e| PyValueError::new_err(e.to_string()))?),
+                let (inner, nan) = match tag {
+                    0 => read_state::<$t64>(payload).map(|(t, nan)| (SpatialInner::F64(t), nan))?,
+                    1 => read_state::<$t32>(payload).map(|(t, nan)| (SpatialInner::F32(t), nan))?,
                     _ => return Err(PyValueError::new_err(format!("Unknown dtype tag: {}", tag))),
-                });
+                };
+                self.inner = Some(inner);
+                self.nan = nan;
                 Ok(())
             }
 
             fn save(&self, path: &str) -> PyResult<()> {
                 let inner = self.inner.as_ref()
-                    .ok_or_else(|| PyValueError::new_err("Tree is uninitialized"))?;
-                let mut bytes = Vec::new();
-                match inner {
-                    SpatialInner::F64(tree) => {
-                        bytes.push(0u8);
-                        bytes.extend(rmp_serde::to_vec(tree).map_err(|e| PyValueError::new_err(e.to_string()))?);
-                    }
-                    SpatialInner::F32(tree) => {
-                        bytes.push(1u8);
-                        bytes.extend(rmp_serde::to_vec(tree).map_err(|e| PyValueError::new_err(e.to_string()))?);
-                    }
-                }
+                    .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+                let bytes = match inner {
+                    SpatialInner::F64(tree) => state_bytes(0u8, tree, &self.nan)?,
+                    SpatialInner::F32(tree) => state_bytes(1u8, tree, &self.nan)?,
+                };
                 std::fs::File::create(path)
                     .and_then(|mut f| f.write_all(&bytes))
                     .map_err(|e| PyValueError::new_err(e.to_string()))
@@ -731,12 +1069,12 @@ macro_rules! impl_spatial_serialization {
                 }
                 let tag = bytes[0];
                 let payload = &bytes[1..];
-                let inner = match tag {
-                    0 => SpatialInner::F64(rmp_serde::from_slice::<$t64>(payload).map_err(|e| PyValueError::new_err(e.to_string()))?),
-                    1 => SpatialInner::F32(rmp_serde::from_slice::<$t32>(payload).map_err(|e| PyValueError::new_err(e.to_string()))?),
+                let (inner, nan) = match tag {
+                    0 => read_state::<$t64>(payload).map(|(t, nan)| (SpatialInner::F64(t), nan))?,
+                    1 => read_state::<$t32>(payload).map(|(t, nan)| (SpatialInner::F32(t), nan))?,
                     _ => return Err(PyValueError::new_err(format!("Unknown dtype tag: {}", tag))),
                 };
-                Ok($constructor { inner: Some(inner) })
+                Ok($constructor { inner: Some(inner), nan })
             }
         }
     };
@@ -799,8 +1137,10 @@ impl_spatial_serialization!(PyVPTree, VPTree, VPTree32, PyVPTree);
 impl_spatial_serialization!(PyBruteForce, BruteForce, BruteForce32, PyBruteForce);
 impl_spatial_serialization!(PyAggTree, AggTree, AggTree32, PyAggTree);
 impl_spatial_serialization!(PyRPTree, RPTree, RPTree32, PyRPTree);
+impl_spatial_serialization!(PyRPForest, RPForest, RPForest32, PyRPForest);
 impl_spatial_serialization!(PySpectralTree, SpectralTree, SpectralTree32, PySpectralTree);
 impl_simple_serialization!(PyProjectionReducer, ProjectionReducer, PyProjectionReducer);
+impl_simple_serialization!(PyPCAReducer, PCAReducer, PyPCAReducer);
 
 // =============================================================================
 // Tree Types
@@ -809,36 +1149,41 @@ impl_simple_serialization!(PyProjectionReducer, ProjectionReducer, PyProjectionR
 #[pyclass(name = "BallTree", module = "ironforest._core.spatial")]
 pub struct PyBallTree {
     inner: Option<SpatialInner<BallTree, BallTree32>>,
+    nan: NanRows,
 }
 
 #[pymethods]
 impl PyBallTree {
     #[staticmethod]
-    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", preserve_array=true))]
-    fn from_array(mut array: PyRefMut<'_, PyArray>, leaf_size: Option<usize>, metric: Option<&str>, preserve_array: bool) -> PyResult<Self> {
+    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", preserve_array=true, nan_policy="raise"))]
+    fn from_array(mut array: PyRefMut<'_, PyArray>, leaf_size: Option<usize>, metric: Option<&str>, preserve_array: bool, nan_policy: &str) -> PyResult<Self> {
         let leaf_size = leaf_size.unwrap_or(20);
         let metric = parse_metric(metric.unwrap_or("euclidean"))?;
         if matches!(array.inner, ArrayData::Float32(_)) {
             let data = if preserve_array { array.as_view_float32()? } else { array.take_float32()?.to_contiguous() };
-            Ok(PyBallTree { inner: Some(SpatialInner::F32(BallTree32::new(data, leaf_size, metric))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyBallTree { inner: Some(SpatialInner::F32(BallTree32::new(data, leaf_size, metric))), nan })
         } else {
             let data = if preserve_array { array.as_view_float()? } else { array.take_float()?.to_contiguous() };
-            Ok(PyBallTree { inner: Some(SpatialInner::F64(BallTree::new(data, leaf_size, metric))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyBallTree { inner: Some(SpatialInner::F64(BallTree::new(data, leaf_size, metric))), nan })
         }
     }
 
     #[new]
-    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", copy=true))]
-    fn __init__(array: ArrayLike, leaf_size: Option<usize>, metric: Option<&str>, copy: bool) -> PyResult<Self> {
+    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", copy=true, nan_policy="raise"))]
+    fn __init__(array: ArrayLike, leaf_size: Option<usize>, metric: Option<&str>, copy: bool, nan_policy: &str) -> PyResult<Self> {
         let leaf_size = leaf_size.unwrap_or(20);
         let metric = parse_metric(metric.unwrap_or("euclidean"))?;
         let use_f32 = array.is_f32();
         if use_f32 {
             let data = if copy { array.into_f32_ndarray()?.to_contiguous() } else { array.into_f32_ndarray()? };
-            Ok(PyBallTree { inner: Some(SpatialInner::F32(BallTree32::new(data, leaf_size, metric))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyBallTree { inner: Some(SpatialInner::F32(BallTree32::new(data, leaf_size, metric))), nan })
         } else {
             let data = if copy { array.into_ndarray()?.to_contiguous() } else { array.into_ndarray()? };
-            Ok(PyBallTree { inner: Some(SpatialInner::F64(BallTree::new(data, leaf_size, metric))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyBallTree { inner: Some(SpatialInner::F64(BallTree::new(data, leaf_size, metric))), nan })
         }
     }
 }
@@ -846,36 +1191,93 @@ impl PyBallTree {
 #[pyclass(name = "KDTree", module = "ironforest._core.spatial")]
 pub struct PyKDTree {
     inner: Option<SpatialInner<KDTree, KDTree32>>,
+    nan: NanRows,
 }
 
 #[pymethods]
 impl PyKDTree {
     #[staticmethod]
-    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", preserve_array=true))]
-    fn from_array(mut array: PyRefMut<'_, PyArray>, leaf_size: Option<usize>, metric: Option<&str>, preserve_array: bool) -> PyResult<Self> {
+    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", preserve_array=true, nan_policy="raise"))]
+    fn from_array(mut array: PyRefMut<'_, PyArray>, leaf_size: Option<usize>, metric: Option<&str>, preserve_array: bool, nan_policy: &str) -> PyResult<Self> {
         let leaf_size = leaf_size.unwrap_or(20);
         let metric = parse_metric(metric.unwrap_or("euclidean"))?;
         if matches!(array.inner, ArrayData::Float32(_)) {
             let data = if preserve_array { array.as_view_float32()? } else { array.take_float32()?.to_contiguous() };
-            Ok(PyKDTree { inner: Some(SpatialInner::F32(KDTree32::new(data, leaf_size, metric))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyKDTree { inner: Some(SpatialInner::F32(KDTree32::new(data, leaf_size, metric))), nan })
         } else {
             let data = if preserve_array { array.as_view_float()? } else { array.take_float()?.to_contiguous() };
-            Ok(PyKDTree { inner: Some(SpatialInner::F64(KDTree::new(data, leaf_size, metric))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyKDTree { inner: Some(SpatialInner::F64(KDTree::new(data, leaf_size, metric))), nan })
         }
     }
 
     #[new]
-    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", copy=true))]
-    fn __init__(array: ArrayLike, leaf_size: Option<usize>, metric: Option<&str>, copy: bool) -> PyResult<Self> {
+    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", copy=true, nan_policy="raise"))]
+    fn __init__(array: ArrayLike, leaf_size: Option<usize>, metric: Option<&str>, copy: bool, nan_policy: &str) -> PyResult<Self> {
         let leaf_size = leaf_size.unwrap_or(20);
         let metric = parse_metric(metric.unwrap_or("euclidean"))?;
         let use_f32 = array.is_f32();
         if use_f32 {
             let data = if copy { array.into_f32_ndarray()?.to_contiguous() } else { array.into_f32_ndarray()? };
-            Ok(PyKDTree { inner: Some(SpatialInner::F32(KDTree32::new(data, leaf_size, metric))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyKDTree { inner: Some(SpatialInner::F32(KDTree32::new(data, leaf_size, metric))), nan })
         } else {
             let data = if copy { array.into_ndarray()?.to_contiguous() } else { array.into_ndarray()? };
-            Ok(PyKDTree { inner: Some(SpatialInner::F64(KDTree::new(data, leaf_size, metric))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyKDTree { inner: Some(SpatialInner::F64(KDTree::new(data, leaf_size, metric))), nan })
+        }
+    }
+
+    #[pyo3(signature = (queries=None, bandwidth=1.0, atol=0.01, order=8, normalize=false))]
+    fn gauss_transform(
+        &self,
+        py: Python<'_>,
+        queries: Option<ArrayLike>,
+        bandwidth: f64,
+        atol: f64,
+        order: usize,
+        normalize: bool,
+    ) -> PyResult<Py<PyAny>> {
+        let inner = self.inner.as_ref()
+            .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+        let (metric, dim) = match inner {
+            SpatialInner::F64(t) => (&t.metric, t.dim),
+            SpatialInner::F32(t) => (&t.metric, t.dim),
+        };
+        check_euclidean(metric, "gauss_transform")?;
+        if bandwidth <= 0.0 {
+            return Err(PyValueError::new_err("bandwidth must be positive"));
+        }
+        if atol < 0.0 {
+            return Err(PyValueError::new_err("atol must be non-negative"));
+        }
+        if order == 0 || order.checked_pow(dim as u32).is_none_or(|t| t > MAX_GAUSS_TERMS) {
+            return Err(PyValueError::new_err(format!(
+                "order must be at least 1 and order^dim at most {}; the fast Gauss transform targets low dimensions", MAX_GAUSS_TERMS
+            )));
+        }
+        let result = match inner {
+            SpatialInner::F64(tree) => {
+                let queries_arr = match queries {
+                    Some(q) => q.into_spatial_query_ndarray(tree.dim)?,
+                    None => input_queries(&self.nan, tree.tree_rows(), tree.dim),
+                };
+                self.nan.per_query(&queries_arr, 1, |q| tree.gauss_transform(q, bandwidth, atol, order, normalize))
+            }
+            SpatialInner::F32(tree) => {
+                let queries_arr = match queries {
+                    Some(q) => q.into_f32_spatial_query_ndarray(tree.dim)?,
+                    None => input_queries(&self.nan, tree.tree_rows(), tree.dim),
+                };
+                self.nan.per_query(&queries_arr, 1, |q| tree.gauss_transform(q, bandwidth, atol, order, normalize))
+            }
+        }?;
+        let result = result.reshape(vec![result.len()]);
+        if result.shape().dims()[0] == 1 {
+            Ok(result.as_slice_unchecked()[0].into_pyobject(py)?.into_any().unbind())
+        } else {
+            Ok(PyArray { inner: ArrayData::Float(result), alive: true }.into_pyobject(py)?.into_any().unbind())
         }
     }
 }
@@ -883,38 +1285,43 @@ impl PyKDTree {
 #[pyclass(name = "VPTree", module = "ironforest._core.spatial")]
 pub struct PyVPTree {
     inner: Option<SpatialInner<VPTree, VPTree32>>,
+    nan: NanRows,
 }
 
 #[pymethods]
 impl PyVPTree {
     #[staticmethod]
-    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", selection="variance", preserve_array=true))]
-    fn from_array(mut array: PyRefMut<'_, PyArray>, leaf_size: Option<usize>, metric: Option<&str>, selection: Option<&str>, preserve_array: bool) -> PyResult<Self> {
+    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", selection="variance", preserve_array=true, seed=0, nan_policy="raise"))]
+    fn from_array(mut array: PyRefMut<'_, PyArray>, leaf_size: Option<usize>, metric: Option<&str>, selection: Option<&str>, preserve_array: bool, seed: u64, nan_policy: &str) -> PyResult<Self> {
         let leaf_size = leaf_size.unwrap_or(20);
         let metric = parse_metric(metric.unwrap_or("euclidean"))?;
         let selection_method = parse_vantage_selection(selection.unwrap_or("variance"))?;
         if matches!(array.inner, ArrayData::Float32(_)) {
             let data = if preserve_array { array.as_view_float32()? } else { array.take_float32()?.to_contiguous() };
-            Ok(PyVPTree { inner: Some(SpatialInner::F32(VPTree32::new(data, leaf_size, metric, selection_method))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyVPTree { inner: Some(SpatialInner::F32(VPTree32::new(data, leaf_size, metric, selection_method, seed))), nan })
         } else {
             let data = if preserve_array { array.as_view_float()? } else { array.take_float()?.to_contiguous() };
-            Ok(PyVPTree { inner: Some(SpatialInner::F64(VPTree::new(data, leaf_size, metric, selection_method))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyVPTree { inner: Some(SpatialInner::F64(VPTree::new(data, leaf_size, metric, selection_method, seed))), nan })
         }
     }
 
     #[new]
-    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", selection="variance", copy=true))]
-    fn __init__(array: ArrayLike, leaf_size: Option<usize>, metric: Option<&str>, selection: Option<&str>, copy: bool) -> PyResult<Self> {
+    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", selection="variance", copy=true, seed=0, nan_policy="raise"))]
+    fn __init__(array: ArrayLike, leaf_size: Option<usize>, metric: Option<&str>, selection: Option<&str>, copy: bool, seed: u64, nan_policy: &str) -> PyResult<Self> {
         let leaf_size = leaf_size.unwrap_or(20);
         let metric = parse_metric(metric.unwrap_or("euclidean"))?;
         let selection_method = parse_vantage_selection(selection.unwrap_or("random"))?;
         let use_f32 = array.is_f32();
         if use_f32 {
             let data = if copy { array.into_f32_ndarray()?.to_contiguous() } else { array.into_f32_ndarray()? };
-            Ok(PyVPTree { inner: Some(SpatialInner::F32(VPTree32::new(data, leaf_size, metric, selection_method))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyVPTree { inner: Some(SpatialInner::F32(VPTree32::new(data, leaf_size, metric, selection_method, seed))), nan })
         } else {
             let data = if copy { array.into_ndarray()?.to_contiguous() } else { array.into_ndarray()? };
-            Ok(PyVPTree { inner: Some(SpatialInner::F64(VPTree::new(data, leaf_size, metric, selection_method))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyVPTree { inner: Some(SpatialInner::F64(VPTree::new(data, leaf_size, metric, selection_method, seed))), nan })
         }
     }
 }
@@ -922,43 +1329,120 @@ impl PyVPTree {
 #[pyclass(name = "RPTree", module = "ironforest._core.spatial")]
 pub struct PyRPTree {
     inner: Option<SpatialInner<RPTree, RPTree32>>,
+    nan: NanRows,
 }
 
 #[pymethods]
 impl PyRPTree {
     #[staticmethod]
-    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", projection="gaussian", seed=0, preserve_array=true))]
-    fn from_array(mut array: PyRefMut<'_, PyArray>, leaf_size: Option<usize>, metric: Option<&str>, projection: Option<&str>, seed: u64, preserve_array: bool) -> PyResult<Self> {
+    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", projection="gaussian", seed=0, preserve_array=true, split="median", nan_policy="raise"))]
+    #[allow(clippy::too_many_arguments)]
+    fn from_array(mut array: PyRefMut<'_, PyArray>, leaf_size: Option<usize>, metric: Option<&str>, projection: Option<&str>, seed: u64, preserve_array: bool, split: &str, nan_policy: &str) -> PyResult<Self> {
         let leaf_size = leaf_size.unwrap_or(20);
         let metric = parse_metric(metric.unwrap_or("euclidean"))?;
+        let split_mode = parse_split_mode(split)?;
         if matches!(array.inner, ArrayData::Float32(_)) {
             let data = if preserve_array { array.as_view_float32()? } else { array.take_float32()?.to_contiguous() };
+            let (data, nan) = split_nan(data, nan_policy)?;
             let ndim = data.shape().dims()[1] as f64;
             let projection_method = parse_projection_type(projection.unwrap_or("gaussian"), 1.0 / ndim.sqrt())?;
-            Ok(PyRPTree { inner: Some(SpatialInner::F32(RPTree32::new(data, leaf_size, metric, projection_method, seed))) })
+            Ok(PyRPTree { inner: Some(SpatialInner::F32(RPTree32::new(data, leaf_size, metric, projection_method, split_mode, seed))), nan })
         } else {
             let ndim = array.as_float()?.ndim() as f64;
             let projection_method = parse_projection_type(projection.unwrap_or("gaussian"), 1.0 / ndim.sqrt())?;
             let data = if preserve_array { array.as_view_float()? } else { array.take_float()?.to_contiguous() };
-            Ok(PyRPTree { inner: Some(SpatialInner::F64(RPTree::new(data, leaf_size, metric, projection_method, seed))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyRPTree { inner: Some(SpatialInner::F64(RPTree::new(data, leaf_size, metric, projection_method, split_mode, seed))), nan })
         }
     }
 
     #[new]
-    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", projection="gaussian", seed=0, copy=true))]
-    fn __init__(array: ArrayLike, leaf_size: Option<usize>, metric: Option<&str>, projection: Option<&str>, seed: u64, copy: bool) -> PyResult<Self> {
+    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", projection="gaussian", seed=0, copy=true, split="median", nan_policy="raise"))]
+    #[allow(clippy::too_many_arguments)]
+    fn __init__(array: ArrayLike, leaf_size: Option<usize>, metric: Option<&str>, projection: Option<&str>, seed: u64, copy: bool, split: &str, nan_policy: &str) -> PyResult<Self> {
         let leaf_size = leaf_size.unwrap_or(20);
         let metric = parse_metric(metric.unwrap_or("euclidean"))?;
+        let split_mode = parse_split_mode(split)?;
         let use_f32 = array.is_f32();
         if use_f32 {
             let data = if copy { array.into_f32_ndarray()?.to_contiguous() } else { array.into_f32_ndarray()? };
+            let (data, nan) = split_nan(data, nan_policy)?;
             let ndim = data.shape().dims()[1] as f64;
             let projection_method = parse_projection_type(projection.unwrap_or("gaussian"), 1.0 / ndim.sqrt())?;
-            Ok(PyRPTree { inner: Some(SpatialInner::F32(RPTree32::new(data, leaf_size, metric, projection_method, seed))) })
+            Ok(PyRPTree { inner: Some(SpatialInner::F32(RPTree32::new(data, leaf_size, metric, projection_method, split_mode, seed))), nan })
         } else {
             let data = if copy { array.into_ndarray()?.to_contiguous() } else { array.into_ndarray()? };
+            let (data, nan) = split_nan(data, nan_policy)?;
             let projection_method = parse_projection_type(projection.unwrap_or("gaussian"), 1.0 / f64::sqrt(data.ndim() as f64))?;
-            Ok(PyRPTree { inner: Some(SpatialInner::F64(RPTree::new(data, leaf_size, metric, projection_method, seed))) })
+            Ok(PyRPTree { inner: Some(SpatialInner::F64(RPTree::new(data, leaf_size, metric, projection_method, split_mode, seed))), nan })
+        }
+    }
+}
+
+#[pyclass(name = "RPForest", module = "ironforest._core.spatial")]
+pub struct PyRPForest {
+    inner: Option<SpatialInner<RPForest, RPForest32>>,
+    nan: NanRows,
+}
+
+#[pymethods]
+impl PyRPForest {
+    #[staticmethod]
+    #[pyo3(signature = (array, n_trees=8, leaf_size=20, metric="euclidean", projection="gaussian", seed=0, preserve_array=true, split="median", nan_policy="raise"))]
+    #[allow(clippy::too_many_arguments)]
+    fn from_array(mut array: PyRefMut<'_, PyArray>, n_trees: usize, leaf_size: Option<usize>, metric: Option<&str>, projection: Option<&str>, seed: u64, preserve_array: bool, split: &str, nan_policy: &str) -> PyResult<Self> {
+        if n_trees == 0 {
+            return Err(PyValueError::new_err("n_trees must be at least 1"));
+        }
+        let leaf_size = leaf_size.unwrap_or(20);
+        let metric = parse_metric(metric.unwrap_or("euclidean"))?;
+        let split_mode = parse_split_mode(split)?;
+        if matches!(array.inner, ArrayData::Float32(_)) {
+            let data = if preserve_array { array.as_view_float32()? } else { array.take_float32()?.to_contiguous() };
+            let (data, nan) = split_nan(data, nan_policy)?;
+            let ndim = data.shape().dims()[1] as f64;
+            let projection_method = parse_projection_type(projection.unwrap_or("gaussian"), 1.0 / ndim.sqrt())?;
+            Ok(PyRPForest { inner: Some(SpatialInner::F32(RPForest32::new(data, n_trees, leaf_size, metric, projection_method, split_mode, seed))), nan })
+        } else {
+            let ndim = array.as_float()?.ndim() as f64;
+            let projection_method = parse_projection_type(projection.unwrap_or("gaussian"), 1.0 / ndim.sqrt())?;
+            let data = if preserve_array { array.as_view_float()? } else { array.take_float()?.to_contiguous() };
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyRPForest { inner: Some(SpatialInner::F64(RPForest::new(data, n_trees, leaf_size, metric, projection_method, split_mode, seed))), nan })
+        }
+    }
+
+    #[new]
+    #[pyo3(signature = (array, n_trees=8, leaf_size=20, metric="euclidean", projection="gaussian", seed=0, copy=true, split="median", nan_policy="raise"))]
+    #[allow(clippy::too_many_arguments)]
+    fn __init__(array: ArrayLike, n_trees: usize, leaf_size: Option<usize>, metric: Option<&str>, projection: Option<&str>, seed: u64, copy: bool, split: &str, nan_policy: &str) -> PyResult<Self> {
+        if n_trees == 0 {
+            return Err(PyValueError::new_err("n_trees must be at least 1"));
+        }
+        let leaf_size = leaf_size.unwrap_or(20);
+        let metric = parse_metric(metric.unwrap_or("euclidean"))?;
+        let split_mode = parse_split_mode(split)?;
+        let use_f32 = array.is_f32();
+        if use_f32 {
+            let data = if copy { array.into_f32_ndarray()?.to_contiguous() } else { array.into_f32_ndarray()? };
+            let (data, nan) = split_nan(data, nan_policy)?;
+            let ndim = data.shape().dims()[1] as f64;
+            let projection_method = parse_projection_type(projection.unwrap_or("gaussian"), 1.0 / ndim.sqrt())?;
+            Ok(PyRPForest { inner: Some(SpatialInner::F32(RPForest32::new(data, n_trees, leaf_size, metric, projection_method, split_mode, seed))), nan })
+        } else {
+            let data = if copy { array.into_ndarray()?.to_contiguous() } else { array.into_ndarray()? };
+            let (data, nan) = split_nan(data, nan_policy)?;
+            let projection_method = parse_projection_type(projection.unwrap_or("gaussian"), 1.0 / f64::sqrt(data.ndim() as f64))?;
+            Ok(PyRPForest { inner: Some(SpatialInner::F64(RPForest::new(data, n_trees, leaf_size, metric, projection_method, split_mode, seed))), nan })
+        }
+    }
+
+    #[getter]
+    fn n_trees(&self) -> PyResult<usize> {
+        match self.inner.as_ref() {
+            Some(SpatialInner::F64(forest)) => Ok(forest.n_trees()),
+            Some(SpatialInner::F32(forest)) => Ok(forest.n_trees()),
+            None => Err(UninitializedError::new_err("Tree is uninitialized")),
         }
     }
 }
@@ -966,36 +1450,41 @@ impl PyRPTree {
 #[pyclass(name = "SpectralTree", module = "ironforest._core.spatial")]
 pub struct PySpectralTree {
     inner: Option<SpatialInner<SpectralTree, SpectralTree32>>,
+    nan: NanRows,
 }
 
 #[pymethods]
 impl PySpectralTree {
     #[staticmethod]
-    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", k_local=10, seed=0, preserve_array=true))]
-    fn from_array(mut array: PyRefMut<'_, PyArray>, leaf_size: Option<usize>, metric: Option<&str>, k_local: usize, seed: u64, preserve_array: bool) -> PyResult<Self> {
+    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", k_local=10, seed=0, preserve_array=true, nan_policy="raise"))]
+    fn from_array(mut array: PyRefMut<'_, PyArray>, leaf_size: Option<usize>, metric: Option<&str>, k_local: usize, seed: u64, preserve_array: bool, nan_policy: &str) -> PyResult<Self> {
         let leaf_size = leaf_size.unwrap_or(20);
         let metric = parse_metric(metric.unwrap_or("euclidean"))?;
         if matches!(array.inner, ArrayData::Float32(_)) {
             let data = if preserve_array { array.as_view_float32()? } else { array.take_float32()?.to_contiguous() };
-            Ok(PySpectralTree { inner: Some(SpatialInner::F32(SpectralTree32::new(data, leaf_size, metric, k_local, seed))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PySpectralTree { inner: Some(SpatialInner::F32(SpectralTree32::new(data, leaf_size, metric, k_local, seed))), nan })
         } else {
             let data = if preserve_array { array.as_view_float()? } else { array.take_float()?.to_contiguous() };
-            Ok(PySpectralTree { inner: Some(SpatialInner::F64(SpectralTree::new(data, leaf_size, metric, k_local, seed))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PySpectralTree { inner: Some(SpatialInner::F64(SpectralTree::new(data, leaf_size, metric, k_local, seed))), nan })
         }
     }
 
     #[new]
-    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", k_local=10, seed=0, copy=true))]
-    fn __init__(array: ArrayLike, leaf_size: Option<usize>, metric: Option<&str>, k_local: usize, seed: u64, copy: bool) -> PyResult<Self> {
+    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", k_local=10, seed=0, copy=true, nan_policy="raise"))]
+    fn __init__(array: ArrayLike, leaf_size: Option<usize>, metric: Option<&str>, k_local: usize, seed: u64, copy: bool, nan_policy: &str) -> PyResult<Self> {
         let leaf_size = leaf_size.unwrap_or(20);
         let metric = parse_metric(metric.unwrap_or("euclidean"))?;
         let use_f32 = array.is_f32();
         if use_f32 {
             let data = if copy { array.into_f32_ndarray()?.to_contiguous() } else { array.into_f32_ndarray()? };
-            Ok(PySpectralTree { inner: Some(SpatialInner::F32(SpectralTree32::new(data, leaf_size, metric, k_local, seed))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PySpectralTree { inner: Some(SpatialInner::F32(SpectralTree32::new(data, leaf_size, metric, k_local, seed))), nan })
         } else {
             let data = if copy { array.into_ndarray()?.to_contiguous() } else { array.into_ndarray()? };
-            Ok(PySpectralTree { inner: Some(SpatialInner::F64(SpectralTree::new(data, leaf_size, metric, k_local, seed))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PySpectralTree { inner: Some(SpatialInner::F64(SpectralTree::new(data, leaf_size, metric, k_local, seed))), nan })
         }
     }
 }
@@ -1003,34 +1492,39 @@ impl PySpectralTree {
 #[pyclass(name = "BruteForce", module = "ironforest._core.spatial")]
 pub struct PyBruteForce {
     inner: Option<SpatialInner<BruteForce, BruteForce32>>,
+    nan: NanRows,
 }
 
 #[pymethods]
 impl PyBruteForce {
     #[staticmethod]
-    #[pyo3(signature = (array, metric="euclidean"))]
-    fn from_array(array: PyRefMut<'_, PyArray>, metric: Option<&str>) -> PyResult<Self> {
+    #[pyo3(signature = (array, metric="euclidean", nan_policy="raise"))]
+    fn from_array(array: PyRefMut<'_, PyArray>, metric: Option<&str>, nan_policy: &str) -> PyResult<Self> {
         let metric = parse_metric(metric.unwrap_or("euclidean"))?;
         if matches!(array.inner, ArrayData::Float32(_)) {
             let data = array.as_view_float32()?;
-            Ok(PyBruteForce { inner: Some(SpatialInner::F32(BruteForce32::new(data, metric))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyBruteForce { inner: Some(SpatialInner::F32(BruteForce32::new(data, metric))), nan })
         } else {
             let data = array.as_view_float()?;
-            Ok(PyBruteForce { inner: Some(SpatialInner::F64(BruteForce::new(data, metric))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyBruteForce { inner: Some(SpatialInner::F64(BruteForce::new(data, metric))), nan })
         }
     }
 
     #[new]
-    #[pyo3(signature = (array, metric="euclidean"))]
-    fn __init__(array: ArrayLike, metric: Option<&str>) -> PyResult<Self> {
+    #[pyo3(signature = (array, metric="euclidean", nan_policy="raise"))]
+    fn __init__(array: ArrayLike, metric: Option<&str>, nan_policy: &str) -> PyResult<Self> {
         let metric = parse_metric(metric.unwrap_or("euclidean"))?;
         let use_f32 = array.is_f32();
         if use_f32 {
             let data = array.into_f32_ndarray()?;
-            Ok(PyBruteForce { inner: Some(SpatialInner::F32(BruteForce32::new(data, metric))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyBruteForce { inner: Some(SpatialInner::F32(BruteForce32::new(data, metric))), nan })
         } else {
             let data = array.into_ndarray()?;
-            Ok(PyBruteForce { inner: Some(SpatialInner::F64(BruteForce::new(data, metric))) })
+            let (data, nan) = split_nan(data, nan_policy)?;
+            Ok(PyBruteForce { inner: Some(SpatialInner::F64(BruteForce::new(data, metric))), nan })
         }
     }
 }
@@ -1038,12 +1532,14 @@ impl PyBruteForce {
 #[pyclass(name = "AggTree", module = "ironforest._core.spatial")]
 pub struct PyAggTree {
     inner: Option<SpatialInner<AggTree, AggTree32>>,
+    nan: NanRows,
 }
 
 #[pymethods]
 impl PyAggTree {
     #[staticmethod]
-    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", kernel="gaussian", bandwidth=1.0, atol=0.01, preserve_array=true))]
+    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", kernel="gaussian", bandwidth=1.0, atol=0.01, preserve_array=true, compact=false, moments="isotropic", rank=None, nu=3.0))]
+    #[allow(clippy::too_many_arguments)]
     fn from_array(
         mut array: PyRefMut<'_, PyArray>,
         leaf_size: Option<usize>,
@@ -1052,23 +1548,31 @@ impl PyAggTree {
         bandwidth: Option<f64>,
         atol: Option<f64>,
         preserve_array: bool,
+        compact: bool,
+        moments: &str,
+        rank: Option<usize>,
+        nu: f64,
     ) -> PyResult<Self> {
         let leaf_size = leaf_size.unwrap_or(20);
         let metric = parse_metric(metric.unwrap_or("euclidean"))?;
-        let kernel = parse_kernel(kernel.unwrap_or("gaussian"))?;
+        let kernel = parse_kernel(kernel.unwrap_or("gaussian"), nu)?;
         let bandwidth = bandwidth.unwrap_or(1.0);
         let atol = atol.unwrap_or(0.01);
+        let moments = parse_moments(moments, rank, &metric)?;
         if matches!(array.inner, ArrayData::Float32(_)) {
             let data = if preserve_array { array.as_view_float32()? } else { array.take_float32()?.to_contiguous() };
-            Ok(PyAggTree { inner: Some(SpatialInner::F32(AggTree32::new(data, leaf_size, metric, kernel, bandwidth, atol))) })
+            check_finite(&data, "Row")?;
+            Ok(PyAggTree { inner: Some(SpatialInner::F32(AggTree32::new(data, leaf_size, metric, kernel, bandwidth, atol, compact, moments))), nan: NanRows::default() })
         } else {
             let data = if preserve_array { array.as_view_float()? } else { array.take_float()?.to_contiguous() };
-            Ok(PyAggTree { inner: Some(SpatialInner::F64(AggTree::new(data, leaf_size, metric, kernel, bandwidth, atol))) })
+            check_finite(&data, "Row")?;
+            Ok(PyAggTree { inner: Some(SpatialInner::F64(AggTree::new(data, leaf_size, metric, kernel, bandwidth, atol, compact, moments))), nan: NanRows::default() })
         }
     }
 
     #[new]
-    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", kernel="gaussian", bandwidth=1.0, atol=0.01, copy=true))]
+    #[pyo3(signature = (array, leaf_size=20, metric="euclidean", kernel="gaussian", bandwidth=1.0, atol=0.01, copy=true, compact=false, moments="isotropic", rank=None, nu=3.0))]
+    #[allow(clippy::too_many_arguments)]
     fn __init__(
         array: ArrayLike,
         leaf_size: Option<usize>,
@@ -1077,61 +1581,263 @@ impl PyAggTree {
         bandwidth: Option<f64>,
         atol: Option<f64>,
         copy: bool,
+        compact: bool,
+        moments: &str,
+        rank: Option<usize>,
+        nu: f64,
     ) -> PyResult<Self> {
         let leaf_size = leaf_size.unwrap_or(20);
         let metric = parse_metric(metric.unwrap_or("euclidean"))?;
-        let kernel = parse_kernel(kernel.unwrap_or("gaussian"))?;
+        let kernel = parse_kernel(kernel.unwrap_or("gaussian"), nu)?;
         let bandwidth = bandwidth.unwrap_or(1.0);
         let atol = atol.unwrap_or(0.01);
+        let moments = parse_moments(moments, rank, &metric)?;
         let use_f32 = array.is_f32();
         if use_f32 {
             let data = if copy { array.into_f32_ndarray()?.to_contiguous() } else { array.into_f32_ndarray()? };
-            Ok(PyAggTree { inner: Some(SpatialInner::F32(AggTree32::new(data, leaf_size, metric, kernel, bandwidth, atol))) })
+            check_finite(&data, "Row")?;
+            Ok(PyAggTree { inner: Some(SpatialInner::F32(AggTree32::new(data, leaf_size, metric, kernel, bandwidth, atol, compact, moments))), nan: NanRows::default() })
         } else {
             let data = if copy { array.into_ndarray()?.to_contiguous() } else { array.into_ndarray()? };
-            Ok(PyAggTree { inner: Some(SpatialInner::F64(AggTree::new(data, leaf_size, metric, kernel, bandwidth, atol))) })
+            check_finite(&data, "Row")?;
+            Ok(PyAggTree { inner: Some(SpatialInner::F64(AggTree::new(data, leaf_size, metric, kernel, bandwidth, atol, compact, moments))), nan: NanRows::default() })
         }
     }
 
-    #[pyo3(signature = (queries=None, normalize=true))]
+    #[pyo3(signature = (queries=None, bandwidth=None, kernel=None, normalize=true, log_density=false, atol=None, rtol=None, return_error=false, nu=None))]
+    #[allow(clippy::too_many_arguments)]
     fn kernel_density(
         &self,
         py: Python<'_>,
         queries: Option<ArrayLike>,
+        bandwidth: Option<f64>,
+        kernel: Option<&str>,
         normalize: Option<bool>,
+        log_density: bool,
+        atol: Option<f64>,
+        rtol: Option<f64>,
+        return_error: bool,
+        nu: Option<f64>,
     ) -> PyResult<Py<PyAny>> {
         let normalize = normalize.unwrap_or(false);
         let inner = self.inner.as_ref()
-            .ok_or_else(|| PyValueError::new_err("Tree is uninitialized"))?;
-        let result = match inner {
+            .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+        let (bandwidth, kernel, dim) = self.query_params(bandwidth, kernel, nu)?;
+        let atol = match (atol, inner) {
+            (Some(a), _) => a,
+            (None, SpatialInner::F64(t)) => t.atol,
+            (None, SpatialInner::F32(t)) => t.atol,
+        };
+        if atol < 0.0 {
+            return Err(PyValueError::new_err("atol must be non-negative"));
+        }
+        let target = match rtol {
+            Some(r) if r <= 0.0 => return Err(PyValueError::new_err("rtol must be positive")),
+            Some(r) => ErrorTarget::Relative(r),
+            None => ErrorTarget::Absolute(atol),
+        };
+        if normalize {
+            check_normalizable(kernel, dim)?;
+        }
+        let (result, error) = match inner {
             SpatialInner::F64(tree) => {
                 let queries_arr = if let Some(q) = queries {
-                    q.into_spatial_query_ndarray(tree.dim)?
+                    let q = q.into_spatial_query_ndarray(tree.dim)?;
+                    check_finite(&q, "Query")?;
+                    q
                 } else {
-                    tree.data.clone()
+                    agg_input_queries(tree)?
                 };
-                tree.kernel_density(&queries_arr, normalize)
+                tree.kernel_density(&queries_arr, bandwidth, kernel, target, normalize, log_density)
             }
             SpatialInner::F32(tree) => {
                 let queries_arr = if let Some(q) = queries {
-                    q.into_f32_spatial_query_ndarray(tree.dim)?
+                    let q = q.into_f32_spatial_query_ndarray(tree.dim)?;
+                    check_finite(&q, "Query")?;
+                    q
                 } else {
-                    tree.data.clone()
+                    agg_input_queries(tree)?
                 };
-                tree.kernel_density(&queries_arr, normalize)
+                tree.kernel_density(&queries_arr, bandwidth, kernel, target, normalize, log_density)
+            }
+        }?;
+        let to_py = |values: NdArray<f64>| -> PyResult<Py<PyAny>> {
+            if values.shape().dims()[0] == 1 {
+                Ok(values.as_slice_unchecked()[0].into_pyobject(py)?.into_any().unbind())
+            } else {
+                Ok(PyArray { inner: ArrayData::Float(values), alive: true }.into_pyobject(py)?.into_any().unbind())
             }
         };
-        if result.shape().dims()[0] == 1 {
-            Ok(result.as_slice_unchecked()[0].into_pyobject(py)?.into_any().unbind())
+        if return_error {
+            Ok((to_py(result)?, to_py(error)?).into_pyobject(py)?.into_any().unbind())
         } else {
-            Ok(PyArray { inner: ArrayData::Float(result), alive: true }.into_pyobject(py)?.into_any().unbind())
+            to_py(result)
+        }
+    }
+
+    #[pyo3(signature = (n, bandwidth=None, kernel=None, seed=None, nu=None))]
+    fn sample(&self, n: usize, bandwidth: Option<f64>, kernel: Option<&str>, seed: Option<u64>, nu: Option<f64>) -> PyResult<PyArray> {
+        let inner = self.inner.as_ref()
+            .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+        let (bandwidth, kernel, dim) = self.query_params(bandwidth, kernel, nu)?;
+        let metric = match inner {
+            SpatialInner::F64(t) => &t.metric,
+            SpatialInner::F32(t) => &t.metric,
+        };
+        check_euclidean(metric, "sample")?;
+        if !kernel.is_normalizable(dim) {
+            return Err(PyValueError::new_err(format!(
+                "Kernel {:?} has infinite mass in {} dimensions and cannot be sampled", kernel, dim
+            )));
+        }
+        let mut rng = match seed {
+            Some(s) => Generator::from_seed(s),
+            None => Generator::new(),
+        };
+        let result = match inner {
+            SpatialInner::F64(tree) => tree.sample(n, bandwidth, kernel, &mut rng),
+            SpatialInner::F32(tree) => tree.sample(n, bandwidth, kernel, &mut rng),
+        }?;
+        Ok(PyArray { inner: ArrayData::Float(result), alive: true })
+    }
+
+    #[pyo3(signature = (query, k))]
+    fn query_knn(&self, query: ArrayLike, k: usize) -> PyResult<PySpatialResult> {
+        let is_batch = query.ndim() == 2;
+        let inner = self.inner.as_ref()
+            .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+        match inner {
+            SpatialInner::F64(tree) => {
+                let q = query.into_spatial_query_ndarray(tree.dim)?;
+                check_finite(&q, "Query")?;
+                knn_body!(self.nan, tree, q, is_batch, k)
+            }
+            SpatialInner::F32(tree) => {
+                let q = query.into_f32_spatial_query_ndarray(tree.dim)?;
+                check_finite(&q, "Query")?;
+                knn_body!(self.nan, tree, q, is_batch, k)
+            }
         }
     }
+
+    #[pyo3(signature = (query, radius))]
+    fn query_radius(&self, query: ArrayLike, radius: f64) -> PyResult<PySpatialResult> {
+        let is_batch = query.ndim() == 2;
+        let inner = self.inner.as_ref()
+            .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+        match inner {
+            SpatialInner::F64(tree) => {
+                let q = query.into_spatial_query_ndarray(tree.dim)?;
+                check_finite(&q, "Query")?;
+                radius_body!(self.nan, tree, q, is_batch, radius)
+            }
+            SpatialInner::F32(tree) => {
+                let q = query.into_f32_spatial_query_ndarray(tree.dim)?;
+                check_finite(&q, "Query")?;
+                let rad: f32 = <f32 as NumCast>::from(radius).unwrap();
+                radius_body!(self.nan, tree, q, is_batch, rad)
+            }
+        }
+    }
+
+    fn insert(&mut self, points: ArrayLike) -> PyResult<()> {
+        let inner = self.inner.as_mut()
+            .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+        match inner {
+            SpatialInner::F64(tree) => {
+                let points = points.into_spatial_query_ndarray(tree.dim)?;
+                check_finite(&points, "Point")?;
+                tree.insert(&points)?;
+            }
+            SpatialInner::F32(tree) => {
+                let points = points.into_f32_spatial_query_ndarray(tree.dim)?;
+                check_finite(&points, "Point")?;
+                tree.insert(&points)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn remove(&mut self, indices: Vec<usize>) -> PyResult<()> {
+        let inner = self.inner.as_mut()
+            .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+        match inner {
+            SpatialInner::F64(tree) => {
+                tree.remove(&indices)?;
+            }
+            SpatialInner::F32(tree) => {
+                tree.remove(&indices)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// AggTree keeps no rows aside, since inserts and removals renumber its points
+fn check_finite<T: IronFloat>(points: &NdArray<T>, what: &str) -> PyResult<()> {
+    let n = points.shape().dims()[0];
+    match (0..n).find(|&i| has_non_finite(points.row(i))) {
+        Some(i) => Err(NonFiniteError::new_err(format!("{} {} contains NaN or infinity, which AggTree does not support", what, i))),
+        None => Ok(()),
+    }
+}
+
+impl PyAggTree {
+    /// Resolves per-query overrides against the build values. A `nu` without a kernel
+    /// overrides the degrees of freedom of a student-t build kernel. Compact trees dropped
+    /// the points needed to re-evaluate nodes, so they reject overrides.
+    fn query_params(&self, bandwidth: Option<f64>, kernel: Option<&str>, nu: Option<f64>) -> PyResult<(f64, KernelType, usize)> {
+        let inner = self.inner.as_ref()
+            .ok_or_else(|| UninitializedError::new_err("Tree is uninitialized"))?;
+        let (build_bandwidth, build_kernel, dim, compact) = match inner {
+            SpatialInner::F64(t) => (t.bandwidth, t.kernel, t.dim, t.compact),
+            SpatialInner::F32(t) => (t.bandwidth, t.kernel, t.dim, t.compact),
+        };
+        let bandwidth = bandwidth.unwrap_or(build_bandwidth);
+        let kernel = match (kernel, nu) {
+            (Some(k), nu) => parse_kernel(k, nu.unwrap_or(3.0))?,
+            (None, Some(nu)) if matches!(build_kernel, KernelType::StudentT(_)) => parse_kernel("student_t", nu)?,
+            (None, _) => build_kernel,
+        };
+        if bandwidth <= 0.0 {
+            return Err(PyValueError::new_err("bandwidth must be positive"));
+        }
+        if compact && (bandwidth != build_bandwidth || kernel != build_kernel) {
+            return Err(PyValueError::new_err(
+                "Compact AggTree only supports its build kernel and bandwidth; rebuild with compact=False to change them"
+            ));
+        }
+        Ok((bandwidth, kernel, dim))
+    }
 }
 
 // =============================================================================
 // Misc Types
 // =============================================================================
+fn reduce_rows<T: ProjectionFloat>(reducer: &ProjectionReducer, mut input_ndarray: NdArray<T>) -> PyResult<NdArray<T>> {
+    let current_dims = input_ndarray.shape().dims();
+
+    //allows for users to input 1d arrays instead of ones of "correct" shape
+    let was_1d = current_dims.len() == 1;
+    if was_1d {
+        let n_features = current_dims[0];
+        input_ndarray = input_ndarray.reshape(vec![1, n_features]);
+    }
+    let transformed = reducer.transform(&input_ndarray)?;
+    if was_1d {
+        let len = transformed.as_slice_unchecked().len();
+        return Ok(transformed.reshape(vec![len]));
+    }
+    Ok(transformed)
+}
+
+/// Output dimension that keeps pairwise distances between n_samples points within 1 ± eps.
+#[pyfunction]
+#[pyo3(name = "johnson_lindenstrauss_min_dim", signature = (n_samples, eps=0.1))]
+fn jl_min_dim(n_samples: usize, eps: f64) -> PyResult<usize> {
+    Ok(johnson_lindenstrauss_min_dim(n_samples, eps)?)
+}
+
 #[pyclass(name = "ProjectionReducer", module = "ironforest._core.spatial")]
 pub struct PyProjectionReducer {
     inner: Option<ProjectionReducer>,
@@ -1154,65 +1860,71 @@ impl PyProjectionReducer {
             None => Generator::new(),
         };
 
-        let reducer = ProjectionReducer::fit(input_dim, output_dim, p_type, &mut rng);
+        let reducer = ProjectionReducer::fit(input_dim, output_dim, p_type, &mut rng)?;
         Ok(PyProjectionReducer { inner: Some(reducer) })
     }
 
     #[staticmethod]
-    #[pyo3(signature = (data, output_dim, projection_type="gaussian", density=0.1, seed=None))]
+    #[pyo3(signature = (data, output_dim=None, projection_type="gaussian", density=0.1, seed=None, eps=None))]
     pub fn fit_transform(
         data: ArrayLike,
-        output_dim: usize,
+        output_dim: Option<usize>,
         projection_type: &str,
         density: f64,
         seed: Option<u64>,
+        eps: Option<f64>,
     ) -> PyResult<(Self, PyArray)> {
         let p_type = parse_projection_type(projection_type, density)?;
         let mut rng = match seed {
             Some(s) => Generator::from_seed(s),
             None => Generator::new(),
         };
-        let input_ndarray = data.into_ndarray()?;
-
-        let (reducer, transformed) = ProjectionReducer::fit_transform(
-            &input_ndarray,
-            output_dim,
-            p_type,
-            &mut rng
-        );
+        let input_ndarray = data.into_float_ndarray()?;
+        let dims = match &input_ndarray {
+            FloatNdArray::F32(a) => a.shape().dims(),
+            FloatNdArray::F64(a) => a.shape().dims(),
+        };
+        if dims.len() != 2 {
+            return Err(crate::error::IronForestError::InvalidShape { ndim: dims.len() }.into());
+        }
+        let (n, input_dim) = (dims[0], dims[1]);
+        let output_dim = match (output_dim, eps) {
+            (Some(_), Some(_)) => {
+                return Err(PyValueError::new_err("Pass either output_dim or eps, not both"));
+            }
+            (Some(k), None) => k,
+            (None, eps) => {
+                let eps = eps.unwrap_or(0.1);
+                let k = johnson_lindenstrauss_min_dim(n, eps)?;
+                if k > input_dim {
+                    return Err(PyValueError::new_err(format!(
+                        "eps={} needs {} dimensions for {} samples, more than the {} input features",
+                        eps, k, n, input_dim
+                    )));
+                }
+                k
+            }
+        };
+        let reducer = ProjectionReducer::fit(input_dim, output_dim, p_type, &mut rng)?;
+        let transformed = match input_ndarray {
+            FloatNdArray::F32(a) => ArrayData::Float32(reducer.transform(&a)?),
+            FloatNdArray::F64(a) => ArrayData::Float(reducer.transform(&a)?),
+        };
 
         Ok((
             PyProjectionReducer { inner: Some(reducer) },
-            PyArray { inner: ArrayData::Float(transformed), alive: true }
+            PyArray { inner: transformed, alive: true }
         ))
     }
 
+    /// Projects float32 input to float32 and anything else to float64.
     pub fn transform(&self, data: ArrayLike) -> PyResult<PyArray> {
         let reducer = tree!(self); //tree macro just gets from option, maybe rename?
-        let mut input_ndarray = data.into_ndarray()?;
-        let current_dims = input_ndarray.shape().dims();
-        
-        //allows for users to input 1d arrays instead of ones of "correct" shape
-        let was_1d = current_dims.len() == 1;
-        if was_1d {
-            let n_features = current_dims[0];
-            input_ndarray = input_ndarray.reshape(vec![1, n_features]);
-        }
-
-        if input_ndarray.shape().dims()[1] != reducer.input_dim() {
-            return Err(PyValueError::new_err(format!(
-                "Dimension mismatch: expected {}, got {}",
-                reducer.input_dim(),
-                input_ndarray.shape().dims()[1]
-            )));
-        }
-        let transformed = reducer.transform(&input_ndarray);
-        if was_1d {
-            let len = transformed.as_slice_unchecked().len();
-            return Ok(PyArray { inner: ArrayData::Float(transformed.reshape(vec![len])), alive: true });
-        }
-
-        Ok(PyArray { inner: ArrayData::Float(transformed), alive: true })
+        let transformed = match data.into_float_ndarray()? {
+            FloatNdArray::F32(a) => ArrayData::Float32(reduce_rows(reducer, a)?),
+            FloatNdArray::F64(a) => ArrayData::Float(reduce_rows(reducer, a)?),
+        };
+        Ok(PyArray { inner: transformed, alive: true })
     }
 
     #[getter]
@@ -1230,52 +1942,184 @@ impl PyProjectionReducer {
     }
 }
 
-// =============================================================================
-// f32 Tree Support
-// =============================================================================
-
-/// Like `get_tree_data` but for f32-backed trees; upcasts to f64 for output since python is f64
-pub(crate) fn get_tree_data_f32(
-    tree_indices: &[usize],
-    raw_data: &[f32],
-    n_points: usize,
-    dim: usize,
-    indices: Option<ArrayLike>,
-) -> PyResult<PyArray> {
-    let mut original_data = vec![0.0f64; n_points * dim];
-    for (tree_pos, &orig_idx) in tree_indices.iter().enumerate() {
-        for d in 0..dim {
-            original_data[orig_idx * dim + d] = raw_data[tree_pos * dim + d] as f64;
+fn pca_options(
+    n_components: Option<usize>,
+    variance_threshold: Option<f64>,
+    solver: &str,
+    n_oversamples: usize,
+    n_iter: usize,
+) -> PyResult<(PCAComponents, PCASolver)> {
+    let components = match (n_components, variance_threshold) {
+        (Some(_), Some(_)) => {
+            return Err(PyValueError::new_err("Pass n_components or variance_threshold, not both"));
         }
-    }
+        (Some(k), None) => PCAComponents::Count(k),
+        (None, Some(t)) => PCAComponents::Variance(t),
+        (None, None) => PCAComponents::All,
+    };
+    let solver = match solver.to_lowercase().as_str() {
+        "auto" => PCASolver::Auto,
+        "full" => PCASolver::Full,
+        "randomized" => PCASolver::Randomized { n_oversamples, n_iter },
+        _ => return Err(PyValueError::new_err(format!(
+            "Unknown solver '{}'. Valid options: 'auto', 'full', 'randomized'",
+            solver
+        ))),
+    };
+    Ok((components, solver))
+}
 
-    match indices {
-        None => Ok(PyArray {
-            inner: ArrayData::Float(NdArray::from_vec(Shape::new(vec![n_points, dim]), original_data)),
-            alive: true,
-        }),
-        Some(idx) => {
-            let idx_arr = idx.into_i64_ndarray()?;
-            let k = idx_arr.len();
-            let mut result = Vec::with_capacity(k * dim);
-            for &orig_idx in idx_arr.as_slice_unchecked() {
-                let i = orig_idx as usize;
-                if i >= n_points {
-                    return Err(PyValueError::new_err(format!(
-                        "Index {} out of bounds for tree with {} points", orig_idx, n_points
-                    )));
-                }
-                result.extend_from_slice(&original_data[i * dim..(i + 1) * dim]);
-            }
-            Ok(PyArray {
-                inner: ArrayData::Float(NdArray::from_vec(Shape::new(vec![k, dim]), result)),
-                alive: true,
-            })
-        }
+/// Reshapes 1D input to a single row, checking the feature count.
+fn rows_of(data: ArrayLike, dim: usize) -> PyResult<(NdArray<f64>, bool)> {
+    let arr = data.into_ndarray()?;
+    let was_1d = arr.ndim() == 1;
+    let arr = if was_1d {
+        let n_features = arr.shape().dims()[0];
+        arr.reshape(vec![1, n_features])
+    } else {
+        arr
+    };
+    let got = arr.shape().dims()[1];
+    if got != dim {
+        return Err(crate::error::IronForestError::DimensionMismatch { expected: dim, got }.into());
     }
+    Ok((arr, was_1d))
+}
+
+fn float_array(arr: NdArray<f64>, was_1d: bool) -> PyArray {
+    let arr = if was_1d {
+        let len = arr.len();
+        arr.reshape(vec![len])
+    } else {
+        arr
+    };
+    PyArray { inner: ArrayData::Float(arr), alive: true }
+}
+
+#[pyclass(name = "PCAReducer", module = "ironforest._core.spatial")]
+pub struct PyPCAReducer {
+    inner: Option<PCAReducer>,
 }
 
+#[pymethods]
+impl PyPCAReducer {
+    #[new]
+    #[pyo3(signature = (
+        data,
+        n_components=None,
+        variance_threshold=None,
+        whiten=false,
+        solver="auto",
+        n_oversamples=10,
+        n_iter=4,
+        seed=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn __init__(
+        data: ArrayLike,
+        n_components: Option<usize>,
+        variance_threshold: Option<f64>,
+        whiten: bool,
+        solver: &str,
+        n_oversamples: usize,
+        n_iter: usize,
+        seed: Option<u64>,
+    ) -> PyResult<Self> {
+        let (components, solver) = pca_options(n_components, variance_threshold, solver, n_oversamples, n_iter)?;
+        let mut rng = match seed {
+            Some(s) => Generator::from_seed(s),
+            None => Generator::new(),
+        };
+        let reducer = PCAReducer::fit(&data.into_ndarray()?, components, whiten, solver, &mut rng)?;
+        Ok(PyPCAReducer { inner: Some(reducer) })
+    }
+
+    #[staticmethod]
+    #[pyo3(signature = (
+        data,
+        n_components=None,
+        variance_threshold=None,
+        whiten=false,
+        solver="auto",
+        n_oversamples=10,
+        n_iter=4,
+        seed=None,
+    ))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn fit_transform(
+        data: ArrayLike,
+        n_components: Option<usize>,
+        variance_threshold: Option<f64>,
+        whiten: bool,
+        solver: &str,
+        n_oversamples: usize,
+        n_iter: usize,
+        seed: Option<u64>,
+    ) -> PyResult<(Self, PyArray)> {
+        let (components, solver) = pca_options(n_components, variance_threshold, solver, n_oversamples, n_iter)?;
+        let mut rng = match seed {
+            Some(s) => Generator::from_seed(s),
+            None => Generator::new(),
+        };
+        let (reducer, transformed) = PCAReducer::fit_transform(&data.into_ndarray()?, components, whiten, solver, &mut rng)?;
+        Ok((
+            PyPCAReducer { inner: Some(reducer) },
+            PyArray { inner: ArrayData::Float(transformed), alive: true },
+        ))
+    }
+
+    pub fn transform(&self, data: ArrayLike) -> PyResult<PyArray> {
+        let reducer = tree!(self);
+        let (arr, was_1d) = rows_of(data, reducer.input_dim())?;
+        Ok(float_array(reducer.transform(&arr)?, was_1d))
+    }
 
+    pub fn inverse_transform(&self, data: ArrayLike) -> PyResult<PyArray> {
+        let reducer = tree!(self);
+        let (arr, was_1d) = rows_of(data, reducer.output_dim())?;
+        Ok(float_array(reducer.inverse_transform(&arr)?, was_1d))
+    }
+
+    #[getter]
+    pub fn input_dim(&self) -> PyResult<usize> {
+        Ok(tree!(self).input_dim())
+    }
+
+    #[getter]
+    pub fn output_dim(&self) -> PyResult<usize> {
+        Ok(tree!(self).output_dim())
+    }
+
+    #[getter]
+    pub fn whiten(&self) -> PyResult<bool> {
+        Ok(tree!(self).whiten())
+    }
+
+    #[getter]
+    pub fn mean(&self) -> PyResult<PyArray> {
+        let mean = tree!(self).mean().to_vec();
+        Ok(PyArray { inner: ArrayData::Float(NdArray::from_vec(Shape::d1(mean.len()), mean)), alive: true })
+    }
+
+    #[getter]
+    pub fn components(&self) -> PyResult<PyArray> {
+        let reducer = tree!(self);
+        let shape = Shape::d2(reducer.output_dim(), reducer.input_dim());
+        Ok(PyArray { inner: ArrayData::Float(NdArray::from_vec(shape, reducer.components().to_vec())), alive: true })
+    }
+
+    #[getter]
+    pub fn explained_variance(&self) -> PyResult<PyArray> {
+        let v = tree!(self).explained_variance().to_vec();
+        Ok(PyArray { inner: ArrayData::Float(NdArray::from_vec(Shape::d1(v.len()), v)), alive: true })
+    }
+
+    #[getter]
+    pub fn explained_variance_ratio(&self) -> PyResult<PyArray> {
+        let v = tree!(self).explained_variance_ratio().to_vec();
+        Ok(PyArray { inner: ArrayData::Float(NdArray::from_vec(Shape::d1(v.len()), v)), alive: true })
+    }
+}
 
 // =============================================================================
 // Module Registration
@@ -1286,21 +2130,25 @@ pub(crate) fn get_tree_data_f32(
 fn _reconstruct(py: Python<'_>, cls: &Bound<'_, PyAny>) -> PyResult<Py<PyAny>> {
     // Determine which type to create with inner: None, bypassing __init__
     if cls.eq(py.get_type::<PyKDTree>())? {
-        Ok(Py::new(py, PyKDTree { inner: None })?.into_any())
+        Ok(Py::new(py, PyKDTree { inner: None, nan: NanRows::default() })?.into_any())
     } else if cls.eq(py.get_type::<PyBallTree>())? {
-        Ok(Py::new(py, PyBallTree { inner: None })?.into_any())
+        Ok(Py::new(py, PyBallTree { inner: None, nan: NanRows::default() })?.into_any())
     } else if cls.eq(py.get_type::<PyVPTree>())? {
-        Ok(Py::new(py, PyVPTree { inner: None })?.into_any())
+        Ok(Py::new(py, PyVPTree { inner: None, nan: NanRows::default() })?.into_any())
     } else if cls.eq(py.get_type::<PyBruteForce>())? {
-        Ok(Py::new(py, PyBruteForce { inner: None })?.into_any())
+        Ok(Py::new(py, PyBruteForce { inner: None, nan: NanRows::default() })?.into_any())
     } else if cls.eq(py.get_type::<PyRPTree>())? {
-        Ok(Py::new(py, PyRPTree { inner: None })?.into_any())
+        Ok(Py::new(py, PyRPTree { inner: None, nan: NanRows::default() })?.into_any())
+    } else if cls.eq(py.get_type::<PyRPForest>())? {
+        Ok(Py::new(py, PyRPForest { inner: None, nan: NanRows::default() })?.into_any())
     } else if cls.eq(py.get_type::<PySpectralTree   >())? {
-        Ok(Py::new(py, PySpectralTree { inner: None })?.into_any())
+        Ok(Py::new(py, PySpectralTree { inner: None, nan: NanRows::default() })?.into_any())
     } else if cls.eq(py.get_type::<PyAggTree>())? {
-        Ok(Py::new(py, PyAggTree { inner: None })?.into_any())
+        Ok(Py::new(py, PyAggTree { inner: None, nan: NanRows::default() })?.into_any())
     } else if cls.eq(py.get_type::<PyProjectionReducer>())? {
         Ok(Py::new(py, PyProjectionReducer { inner: None })?.into_any())
+    } else if cls.eq(py.get_type::<PyPCAReducer>())? {
+        Ok(Py::new(py, PyPCAReducer { inner: None })?.into_any())
     } else {
         Err(PyValueError::new_err("Unknown spatial type for reconstruction"))
     }
@@ -1311,6 +2159,7 @@ pub fn register_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
 
     m.add_function(wrap_pyfunction!(_reconstruct, m)?)?;
     m.add_class::<PyProjectionReducer>()?;
+    m.add_class::<PyPCAReducer>()?;
     m.add_class::<PyBallTree>()?;
     m.add_class::<PyKDTree>()?;
     m.add_class::<PyVPTree>()?;
@@ -1318,8 +2167,14 @@ pub fn register_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<PyAggTree>()?;
     m.add_class::<PyBruteForce>()?;
     m.add_class::<PyRPTree>()?;
+    m.add_class::<PyRPForest>()?;
     m.add_class::<PySpectralTree>()?;
     m.add_class::<super::spatial_index::PySpatialIndex>()?;
     m.add_class::<super::spatial_index::PyTreeType>()?;
+    m.add_class::<super::spatial_index::PyTreeRecommendation>()?;
+    m.add_function(wrap_pyfunction!(super::spatial_index::recommend_tree, m)?)?;
+    m.add_function(wrap_pyfunction!(super::spatial_index::intrinsic_dim, m)?)?;
+    m.add_function(wrap_pyfunction!(jl_min_dim, m)?)?;
+    super::error::register_exceptions(m)?;
     Ok(())
 }
@@ -1,15 +1,17 @@
 use pyo3::prelude::*;
 use pyo3::exceptions::PyValueError;
-use pyo3::types::PyAny;
+use pyo3::types::{PyAny, PyDict};
 
 use crate::{Generator, IronFloat};
 use crate::array::{NdArray, Shape};
-use crate::spatial::DistanceMetric;
-use crate::spatial::spatial_index::{SpatialIndex, TreeType, QueryInput, QueryResult};
+use crate::spatial::{DistanceMetric, NanPolicy};
+use crate::spatial::spatial_index::{SpatialIndex, SpatialIndexBuilder, TreeType, QueryInput, QueryResult, RPTreeOptions, VPTreeOptions};
+use crate::spatial::IntrinsicDimEstimator;
+use crate::spatial::tree_selection::{self, SelectionThresholds, TreeRecommendation, Workload};
 use super::{PyArray, ArrayData, ArrayLike};
 use super::spatial::{
     PySpatialResult,
-    parse_metric, parse_kernel, parse_vantage_selection, parse_projection_type,
+    parse_metric, parse_kernel, parse_vantage_selection, parse_projection_type, parse_nan_policy,
 };
 
 // =============================================================================
@@ -42,46 +44,15 @@ fn parse_tree_type(s: &str) -> PyResult<PyTreeType> {
     }
 }
 
-fn auto_select_tree<T: IronFloat>(
-    data: &NdArray<T>,
-    rng: &mut Generator,
-) -> TreeType {
-    let dims = data.shape().dims();
-    let n = dims[0];
-    let d = dims[1];
-
-    if n < 1000 {
-        return TreeType::BruteForce;
-    }
-    if d <= 16 {
-        return TreeType::KDTree;
-    }
-    if d > 512 {
-        return TreeType::BruteForce;
-    }
-
-    let id = match data.intrinsic_dim(0.95, 2000, rng) {
-        Ok(id) => id,
-        Err(_) => return TreeType::KDTree,
-    };
-
-    if (id as f64) / (d as f64) < 0.3 {
-        return TreeType::RPTree;
-    }
-    if id <= 20 {
-        return TreeType::BallTree;
-    }
-
-    TreeType::VPTree
+fn rp_options<T: IronFloat>(projection: &str, data: &NdArray<T>) -> PyResult<RPTreeOptions> {
+    let dim = data.shape().dims().get(1).copied().unwrap_or(1);
+    let projection = parse_projection_type(projection, 1.0 / f64::sqrt(dim as f64))?;
+    Ok(RPTreeOptions { projection, ..Default::default() })
 }
 
-fn resolve_tree_type<T: IronFloat>(
-    tt: PyTreeType,
-    data: &NdArray<T>,
-    rng: &mut Generator,
-) -> TreeType {
+fn to_tree_type(tt: PyTreeType) -> TreeType {
     match tt {
-        PyTreeType::Auto => auto_select_tree(data, rng),
+        PyTreeType::Auto => TreeType::Auto,
         PyTreeType::KDTree => TreeType::KDTree,
         PyTreeType::BallTree => TreeType::BallTree,
         PyTreeType::VPTree => TreeType::VPTree,
@@ -90,11 +61,79 @@ fn resolve_tree_type<T: IronFloat>(
     }
 }
 
-fn to_py_err(e: String) -> PyErr {
-    PyValueError::new_err(e)
+fn tree_type_name(tt: TreeType) -> &'static str {
+    match tt {
+        TreeType::KDTree => "kd_tree",
+        TreeType::BallTree => "ball_tree",
+        TreeType::VPTree => "vp_tree",
+        TreeType::RPTree => "rp_tree",
+        TreeType::BruteForce => "brute_force",
+        TreeType::Auto => "auto",
+    }
+}
+
+fn parse_workload(s: &str) -> PyResult<Workload> {
+    match s.to_lowercase().as_str() {
+        "knn" => Ok(Workload::Knn),
+        "ann" => Ok(Workload::Ann),
+        "kde" => Ok(Workload::Kde),
+        _ => Err(PyValueError::new_err(format!(
+            "Unknown workload '{}'. Valid options: 'knn', 'ann', 'kde'",
+            s
+        ))),
+    }
+}
+
+fn parse_estimator(method: &str, k: usize, variance_threshold: f64) -> PyResult<IntrinsicDimEstimator> {
+    match method.to_lowercase().as_str() {
+        "pca" => Ok(IntrinsicDimEstimator::Pca { variance_threshold }),
+        "mle" => Ok(IntrinsicDimEstimator::Mle { k }),
+        "twonn" => Ok(IntrinsicDimEstimator::TwoNn),
+        "correlation" => Ok(IntrinsicDimEstimator::Correlation),
+        _ => Err(PyValueError::new_err(format!(
+            "Unknown intrinsic dimension method '{}'. Valid options: 'pca', 'mle', 'twonn', 'correlation'",
+            method
+        ))),
+    }
+}
+
+fn estimator_name(estimator: IntrinsicDimEstimator) -> &'static str {
+    match estimator {
+        IntrinsicDimEstimator::Pca { .. } => "pca",
+        IntrinsicDimEstimator::Mle { .. } => "mle",
+        IntrinsicDimEstimator::TwoNn => "twonn",
+        IntrinsicDimEstimator::Correlation => "correlation",
+    }
+}
+
+fn parse_thresholds(overrides: Option<&Bound<'_, PyDict>>) -> PyResult<SelectionThresholds> {
+    let mut t = SelectionThresholds::default();
+    let Some(overrides) = overrides else {
+        return Ok(t);
+    };
+    let mut method = "mle".to_owned();
+    let mut k = 10;
+    let mut variance_threshold = 0.95;
+    for (key, value) in overrides.iter() {
+        let key: String = key.extract()?;
+        match key.as_str() {
+            "min_tree_points" => t.min_tree_points = value.extract()?,
+            "max_kd_dim" => t.max_kd_dim = value.extract()?,
+            "max_tree_dim" => t.max_tree_dim = value.extract()?,
+            "max_rp_ratio" => t.max_rp_ratio = value.extract()?,
+            "max_ball_intrinsic_dim" => t.max_ball_intrinsic_dim = value.extract()?,
+            "sample_size" => t.sample_size = value.extract()?,
+            "estimator" => method = value.extract()?,
+            "k" => k = value.extract()?,
+            "variance_threshold" => variance_threshold = value.extract()?,
+            _ => return Err(PyValueError::new_err(format!("Unknown selection threshold '{}'", key))),
+        }
+    }
+    t.estimator = parse_estimator(&method, k, variance_threshold)?;
+    Ok(t)
 }
 
-fn query_result_to_py(qr: QueryResult) -> PySpatialResult {
+pub(crate) fn query_result_to_py(qr: QueryResult) -> PySpatialResult {
     match (qr.counts, qr.k) {
         (Some(counts), _) => PySpatialResult::from_batch_radius(qr.indices, qr.distances, counts),
         (None, Some(k)) => PySpatialResult::from_batch_knn(qr.indices, qr.distances, qr.n_queries, k),
@@ -124,7 +163,10 @@ impl PySpatialIndex {
         projection = "gaussian",
         selection = "variance",
         copy = true,
+        nan_policy = "raise",
+        workload = "knn",
     ))]
+    #[allow(clippy::too_many_arguments)]
     fn __init__(
         data: ArrayLike,
         tree_type: &str,
@@ -135,29 +177,27 @@ impl PySpatialIndex {
         projection: &str,
         selection: &str,
         copy: bool,
+        nan_policy: &str,
+        workload: &str,
     ) -> PyResult<Self> {
-        let metric = parse_metric(metric)?;
-        let vp_selection = parse_vantage_selection(selection)?;
-        let use_f32 = data.is_f32();
-        let mut rng = Generator::from_seed(seed);
-
-        if use_f32 {
+        let builder = SpatialIndexBuilder::new()
+            .tree_type(to_tree_type(parse_tree_type(tree_type)?))
+            .leaf_size(leaf_size)
+            .metric(parse_metric(metric)?)
+            .rebuild_threshold(rebuild_threshold)
+            .seed(seed)
+            .nan_policy(parse_nan_policy(nan_policy)?)
+            .workload(parse_workload(workload)?)
+            .vp_options(VPTreeOptions { selection: parse_vantage_selection(selection)? });
+
+        let inner = if data.is_f32() {
             let arr = if copy { data.into_f32_ndarray()?.to_contiguous() } else { data.into_f32_ndarray()? };
-            let dim = arr.shape().dims()[1];
-            let projection_type = parse_projection_type(projection, 1.0 / f64::sqrt(dim as f64))?;
-            let parsed_type = resolve_tree_type(parse_tree_type(tree_type)?, &arr, &mut rng);
-            Ok(PySpatialIndex {
-                inner: SpatialIndex::new_f32(arr, parsed_type, leaf_size, metric, rebuild_threshold, seed, projection_type, vp_selection),
-            })
+            builder.rp_options(rp_options(projection, &arr)?).build(arr)?
         } else {
             let arr = if copy { data.into_ndarray()?.to_contiguous() } else { data.into_ndarray()? };
-            let dim = arr.shape().dims()[1];
-            let projection_type = parse_projection_type(projection, 1.0 / f64::sqrt(dim as f64))?;
-            let parsed_type = resolve_tree_type(parse_tree_type(tree_type)?, &arr, &mut rng);
-            Ok(PySpatialIndex {
-                inner: SpatialIndex::new_f64(arr, parsed_type, leaf_size, metric, rebuild_threshold, seed, projection_type, vp_selection),
-            })
-        }
+            builder.rp_options(rp_options(projection, &arr)?).build(arr)?
+        };
+        Ok(PySpatialIndex { inner })
     }
 
     // =========================================================================
@@ -170,17 +210,17 @@ impl PySpatialIndex {
             let arr = points.into_f32_ndarray()?;
             let shape = arr.shape().dims().to_vec();
             let point_dim = if ndim == 1 { shape[0] } else { shape[1] };
-            self.inner.insert_f32(arr.as_slice_unchecked(), point_dim).map_err(to_py_err)
+            Ok(self.inner.insert_f32(arr.as_slice_unchecked(), point_dim)?)
         } else {
             let arr = points.into_ndarray()?;
             let shape = arr.shape().dims().to_vec();
             let point_dim = if ndim == 1 { shape[0] } else { shape[1] };
-            self.inner.insert_f64(arr.as_slice_unchecked(), point_dim).map_err(to_py_err)
+            Ok(self.inner.insert_f64(arr.as_slice_unchecked(), point_dim)?)
         }
     }
 
     fn flush(&mut self) -> PyResult<()> {
-        self.inner.flush().map_err(to_py_err)
+        Ok(self.inner.flush()?)
     }
 
     // =========================================================================
@@ -189,13 +229,7 @@ impl PySpatialIndex {
 
     #[getter]
     fn tree_type(&self) -> PyResult<String> {
-        match self.inner.tree_type(){
-            TreeType::KDTree => Ok("kd_tree".to_owned()),
-            TreeType::BallTree => Ok("ball_tree".to_owned()),
-            TreeType::VPTree => Ok("vp_tree".to_owned()),
-            TreeType::RPTree => Ok("rp_tree".to_owned()),
-            TreeType::BruteForce => Ok("brute_force".to_owned()),
-        }
+        Ok(tree_type_name(self.inner.tree_type()).to_owned())
     }
 
     #[getter]
@@ -210,7 +244,7 @@ impl PySpatialIndex {
 
     #[getter]
     fn n_points(&self) -> PyResult<usize> {
-        self.inner.n_points().map_err(to_py_err)
+        Ok(self.inner.n_points()?)
     }
 
     #[getter]
@@ -218,6 +252,21 @@ impl PySpatialIndex {
         self.inner.pending_count()
     }
 
+    #[getter]
+    fn nan_policy(&self) -> &str {
+        match self.inner.nan_rows().policy {
+            NanPolicy::Raise => "raise",
+            NanPolicy::Drop => "drop",
+            NanPolicy::IgnoreDims => "ignore_dims",
+        }
+    }
+
+    #[getter]
+    fn nan_rows(&self) -> PyArray {
+        let rows: Vec<i64> = self.inner.nan_rows().rows.iter().map(|&i| i as i64).collect();
+        PyArray { inner: ArrayData::Int(NdArray::from_vec(Shape::d1(rows.len()), rows)), alive: true }
+    }
+
     #[getter]
     fn metric(&self) -> &str {
         match self.inner.metric() {
@@ -248,11 +297,11 @@ impl PySpatialIndex {
         let dim = self.inner.dim();
         if self.inner.use_f32() {
             let q = query.into_f32_spatial_query_ndarray(dim)?;
-            let result = self.inner.query_knn(QueryInput::F32(&q), is_batch, k).map_err(to_py_err)?;
+            let result = self.inner.query_knn(QueryInput::F32(&q), is_batch, k)?;
             Ok(query_result_to_py(result))
         } else {
             let q = query.into_spatial_query_ndarray(dim)?;
-            let result = self.inner.query_knn(QueryInput::F64(&q), is_batch, k).map_err(to_py_err)?;
+            let result = self.inner.query_knn(QueryInput::F64(&q), is_batch, k)?;
             Ok(query_result_to_py(result))
         }
     }
@@ -270,11 +319,11 @@ impl PySpatialIndex {
         let dim = self.inner.dim();
         if self.inner.use_f32() {
             let q = query.into_f32_spatial_query_ndarray(dim)?;
-            let result = self.inner.query_ann(QueryInput::F32(&q), is_batch, k, n_candidates, n_probes).map_err(to_py_err)?;
+            let result = self.inner.query_ann(QueryInput::F32(&q), is_batch, k, n_candidates, n_probes)?;
             Ok(query_result_to_py(result))
         } else {
             let q = query.into_spatial_query_ndarray(dim)?;
-            let result = self.inner.query_ann(QueryInput::F64(&q), is_batch, k, n_candidates, n_probes).map_err(to_py_err)?;
+            let result = self.inner.query_ann(QueryInput::F64(&q), is_batch, k, n_candidates, n_probes)?;
             Ok(query_result_to_py(result))
         }
     }
@@ -285,16 +334,17 @@ impl PySpatialIndex {
         let dim = self.inner.dim();
         if self.inner.use_f32() {
             let q = query.into_f32_spatial_query_ndarray(dim)?;
-            let result = self.inner.query_radius(QueryInput::F32(&q), is_batch, radius).map_err(to_py_err)?;
+            let result = self.inner.query_radius(QueryInput::F32(&q), is_batch, radius)?;
             Ok(query_result_to_py(result))
         } else {
             let q = query.into_spatial_query_ndarray(dim)?;
-            let result = self.inner.query_radius(QueryInput::F64(&q), is_batch, radius).map_err(to_py_err)?;
+            let result = self.inner.query_radius(QueryInput::F64(&q), is_batch, radius)?;
             Ok(query_result_to_py(result))
         }
     }
 
-    #[pyo3(signature = (queries=None, bandwidth=None, kernel=None, normalize=None))]
+    #[pyo3(signature = (queries=None, bandwidth=None, kernel=None, normalize=None, log_density=false, nu=3.0))]
+    #[allow(clippy::too_many_arguments)]
     fn kernel_density(
         &self,
         py: Python<'_>,
@@ -302,9 +352,11 @@ impl PySpatialIndex {
         bandwidth: Option<f64>,
         kernel: Option<&str>,
         normalize: Option<bool>,
+        log_density: bool,
+        nu: f64,
     ) -> PyResult<Py<PyAny>> {
         let bandwidth = bandwidth.unwrap_or(1.0);
-        let kernel_type = parse_kernel(kernel.unwrap_or("gaussian"))?;
+        let kernel_type = parse_kernel(kernel.unwrap_or("gaussian"), nu)?;
         let do_normalize = normalize.unwrap_or(false);
         let dim = self.inner.dim();
 
@@ -312,20 +364,20 @@ impl PySpatialIndex {
             match queries {
                 Some(q) => {
                     let q_arr = q.into_f32_spatial_query_ndarray(dim)?;
-                    self.inner.kernel_density(Some(QueryInput::F32(&q_arr)), bandwidth, kernel_type, do_normalize).map_err(to_py_err)?
+                    self.inner.kernel_density(Some(QueryInput::F32(&q_arr)), bandwidth, kernel_type, do_normalize, log_density)?
                 }
                 None => {
-                    self.inner.kernel_density(None, bandwidth, kernel_type, do_normalize).map_err(to_py_err)?
+                    self.inner.kernel_density(None, bandwidth, kernel_type, do_normalize, log_density)?
                 }
             }
         } else {
             match queries {
                 Some(q) => {
                     let q_arr = q.into_spatial_query_ndarray(dim)?;
-                    self.inner.kernel_density(Some(QueryInput::F64(&q_arr)), bandwidth, kernel_type, do_normalize).map_err(to_py_err)?
+                    self.inner.kernel_density(Some(QueryInput::F64(&q_arr)), bandwidth, kernel_type, do_normalize, log_density)?
                 }
                 None => {
-                    self.inner.kernel_density(None, bandwidth, kernel_type, do_normalize).map_err(to_py_err)?
+                    self.inner.kernel_density(None, bandwidth, kernel_type, do_normalize, log_density)?
                 }
             }
         };
@@ -347,10 +399,131 @@ impl PySpatialIndex {
             None => None,
         };
 
-        let (data, n_rows, n_cols) = self.inner.data(idx_vec.as_deref()).map_err(to_py_err)?;
+        let (data, n_rows, n_cols) = self.inner.data(idx_vec.as_deref())?;
         Ok(PyArray {
             inner: ArrayData::Float(NdArray::from_vec(Shape::new(vec![n_rows, n_cols]), data)),
             alive: true,
         })
     }
 }
+
+// =============================================================================
+// Tree Recommendation
+// =============================================================================
+
+#[pyclass(name = "TreeRecommendation", module = "ironforest._core.spatial")]
+pub struct PyTreeRecommendation {
+    inner: TreeRecommendation,
+}
+
+#[pymethods]
+impl PyTreeRecommendation {
+    #[getter]
+    fn tree_type(&self) -> &str {
+        tree_type_name(self.inner.tree_type)
+    }
+
+    #[getter]
+    fn rule(&self) -> &str {
+        self.inner.rule.name()
+    }
+
+    #[getter]
+    fn reason(&self) -> &str {
+        &self.inner.reason
+    }
+
+    #[getter]
+    fn workload(&self) -> &str {
+        match self.inner.workload {
+            Workload::Knn => "knn",
+            Workload::Ann => "ann",
+            Workload::Kde => "kde",
+        }
+    }
+
+    #[getter]
+    fn n_points(&self) -> usize {
+        self.inner.n_points
+    }
+
+    #[getter]
+    fn dim(&self) -> usize {
+        self.inner.dim
+    }
+
+    #[getter]
+    fn intrinsic_dim(&self) -> Option<f64> {
+        self.inner.intrinsic_dim
+    }
+
+    #[getter]
+    fn estimator(&self) -> &str {
+        estimator_name(self.inner.estimator)
+    }
+
+    #[getter]
+    fn explained_variance(&self) -> PyArray {
+        let curve = self.inner.explained_variance.clone();
+        PyArray { inner: ArrayData::Float(NdArray::from_vec(Shape::d1(curve.len()), curve)), alive: true }
+    }
+
+    /// Build and query seconds per candidate tree, keyed by tree name.
+    #[getter]
+    fn benchmark<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let out = PyDict::new(py);
+        for b in &self.inner.benchmark {
+            let times = PyDict::new(py);
+            times.set_item("build_seconds", b.build_seconds)?;
+            times.set_item("query_seconds", b.query_seconds)?;
+            out.set_item(tree_type_name(b.tree_type), times)?;
+        }
+        Ok(out)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("TreeRecommendation(tree_type='{}', rule='{}')", self.tree_type(), self.rule())
+    }
+}
+
+#[pyfunction]
+#[pyo3(signature = (data, workload = "knn", benchmark = 0, seed = 0, thresholds = None))]
+pub fn recommend_tree(
+    data: ArrayLike,
+    workload: &str,
+    benchmark: usize,
+    seed: u64,
+    thresholds: Option<&Bound<'_, PyDict>>,
+) -> PyResult<PyTreeRecommendation> {
+    let workload = parse_workload(workload)?;
+    let thresholds = parse_thresholds(thresholds)?;
+    let inner = if data.is_f32() {
+        tree_selection::recommend_tree(&data.into_f32_ndarray()?, workload, &thresholds, benchmark, seed)?
+    } else {
+        tree_selection::recommend_tree(&data.into_ndarray()?, workload, &thresholds, benchmark, seed)?
+    };
+    Ok(PyTreeRecommendation { inner })
+}
+
+#[pyfunction]
+#[pyo3(signature = (data, method = "mle", k = 10, variance_threshold = 0.95, sample_size = 2000, seed = 0))]
+pub fn intrinsic_dim(
+    data: ArrayLike,
+    method: &str,
+    k: usize,
+    variance_threshold: f64,
+    sample_size: usize,
+    seed: u64,
+) -> PyResult<f64> {
+    if data.ndim() != 2 {
+        return Err(crate::error::IronForestError::InvalidShape { ndim: data.ndim() }.into());
+    }
+    let estimator = parse_estimator(method, k, variance_threshold)?;
+    let mut rng = Generator::from_seed(seed);
+    let result = if data.is_f32() {
+        data.into_f32_ndarray()?.estimate_intrinsic_dim(estimator, sample_size, &mut rng)
+    } else {
+        data.into_ndarray()?.estimate_intrinsic_dim(estimator, sample_size, &mut rng)
+    };
+    result.map_err(PyValueError::new_err)
+}
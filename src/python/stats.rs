@@ -15,6 +15,8 @@ pub fn register_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(all, m)?)?;
     m.add_function(wrap_pyfunction!(pearson, m)?)?;
     m.add_function(wrap_pyfunction!(spearman, m)?)?;
+    m.add_function(wrap_pyfunction!(softmax, m)?)?;
+    m.add_function(wrap_pyfunction!(argmax, m)?)?;
     Ok(())
 }
 
@@ -93,3 +95,22 @@ fn pearson(a: ArrayLike, b: ArrayLike) -> PyResult<f64> {
 fn spearman(a: ArrayLike, b: ArrayLike) -> PyResult<f64> {
     Ok(a.into_ndarray()?.spearman(&b.into_ndarray()?))
 }
+
+#[pyfunction]
+#[pyo3(signature = (a, fallback=None))]
+fn softmax(a: ArrayLike, fallback: Option<ArrayLike>) -> PyResult<PyArray> {
+    let fallback = fallback.map(|f| f.into_ndarray()).transpose()?;
+    let result = a.into_ndarray()?
+        .softmax(fallback.as_ref().map(|f| f.as_slice_unchecked()))?;
+    Ok(PyArray { inner: ArrayData::Float(result), alive: true })
+}
+
+#[pyfunction]
+fn argmax(py: Python<'_>, a: ArrayLike) -> PyResult<Py<PyAny>> {
+    let arr = a.into_ndarray()?;
+    let indices = arr.argmax()?;
+    if arr.ndim() == 1 {
+        return Ok(indices.as_slice_unchecked()[0].into_pyobject(py)?.into_any().unbind());
+    }
+    Ok(PyArray { inner: ArrayData::Int(indices), alive: true }.into_pyobject(py)?.into_any().unbind())
+}
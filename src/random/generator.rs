@@ -159,7 +159,7 @@ impl Generator {
         z.exp()
     }
 
-    fn sample_gamma_single(&mut self, alpha: f64) -> f64 {
+    pub(crate) fn sample_gamma_single(&mut self, alpha: f64) -> f64 {
         if alpha < 1.0 {
             return self.sample_gamma_single(alpha + 1.0) * self.next_f64().powf(1.0 / alpha);
         }
@@ -208,13 +208,15 @@ impl Generator {
         assert!(beta_param > 0.0, "beta must be positive");
 
         let data: Vec<f64> = (0..shape.size())
-            .map(|_| {
-                let x = self.sample_gamma_single(alpha);
-                let y = self.sample_gamma_single(beta_param);
-                x / (x + y)
-            })
+            .map(|_| self.sample_beta_single(alpha, beta_param))
             .collect();
 
         NdArray::from_vec(shape, data)
     }
+
+    pub(crate) fn sample_beta_single(&mut self, alpha: f64, beta_param: f64) -> f64 {
+        let x = self.sample_gamma_single(alpha);
+        let y = self.sample_gamma_single(beta_param);
+        x / (x + y)
+    }
 }
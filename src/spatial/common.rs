@@ -1,6 +1,9 @@
 use std::cmp::Ordering;
-use crate::stats::special::gamma;
+use crate::stats::special::ln_gamma;
+use std::f64::consts::{FRAC_PI_2, FRAC_PI_4, PI};
 use serde::{Deserialize, Serialize};
+use crate::random::Generator;
+use crate::error::IronForestError;
 use std::borrow::Cow;
 pub use crate::iron_float::IronFloat;
 
@@ -99,12 +102,20 @@ fn chebyshev<T: IronFloat>(a: &[T], b: &[T]) -> T {
     m
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub enum KernelType {
     Gaussian,
     Epanechnikov,
     Uniform,
     Triangular,
+    /// Laplace kernel, exp(-u).
+    Exponential,
+    Cosine,
+    Biweight,
+    Triweight,
+    /// Radial Student-t profile (1 + u^2/nu)^(-(nu+1)/2). Cauchy is `StudentT(1.0)`.
+    /// Heavy tailed, only normalizable in dimensions below nu + 1.
+    StudentT(f64),
 }
 
 impl KernelType {
@@ -124,17 +135,205 @@ impl KernelType {
             KernelType::Triangular => {
                 if u < one { one - u } else { zero }
             }
+            KernelType::Exponential => (-u).exp(),
+            KernelType::Cosine => {
+                if u < one {
+                    T::from(FRAC_PI_4).unwrap() * (T::from(FRAC_PI_2).unwrap() * u).cos()
+                } else { zero }
+            }
+            KernelType::Biweight => {
+                if u < one {
+                    let s = one - u * u;
+                    T::from(15.0 / 16.0).unwrap() * s * s
+                } else { zero }
+            }
+            KernelType::Triweight => {
+                if u < one {
+                    let s = one - u * u;
+                    T::from(35.0 / 32.0).unwrap() * s * s * s
+                } else { zero }
+            }
+            KernelType::StudentT(nu) => {
+                let nu = T::from(*nu).unwrap();
+                (one + u * u / nu).powf(-(nu + one) * half)
+            }
+        }
+    }
+
+    /// Natural log of `evaluate`, computed directly so Gaussian tails don't underflow.
+    pub fn log_evaluate(&self, dist: f64, h: f64) -> f64 {
+        let u = dist / h;
+        match self {
+            KernelType::Gaussian => -0.5 * u * u,
+            KernelType::Exponential => -u,
+            KernelType::StudentT(nu) => -0.5 * (nu + 1.0) * (u * u / nu).ln_1p(),
+            _ => self.evaluate(dist, h).ln(),
+        }
+    }
+
+    /// Whether the kernel integrates to a finite value in `dim` dimensions.
+    pub fn is_normalizable(&self, dim: usize) -> bool {
+        match self {
+            KernelType::StudentT(nu) => *nu + 1.0 > dim as f64,
+            _ => true,
+        }
+    }
+
+    /// Errors when normalizing the kernel in `dim` dimensions would divide by an infinite mass.
+    pub fn check_normalizable(&self, dim: usize) -> Result<(), IronForestError> {
+        if self.is_normalizable(dim) {
+            Ok(())
+        } else {
+            Err(IronForestError::invalid(format!(
+                "Kernel {:?} has infinite mass in {} dimensions and cannot be normalized; pass normalize=False",
+                self, dim
+            )))
         }
     }
 
+    /// Integral of `evaluate` over R^dim with unit bandwidth.
     pub fn normalization_constant(&self, dim: usize) -> f64 {
+        self.log_normalization_constant(dim).exp()
+    }
+
+    /// Natural log of `normalization_constant`, safe for large `dim`. Infinite when the
+    /// kernel is not normalizable in `dim` dimensions.
+    pub fn log_normalization_constant(&self, dim: usize) -> f64 {
+        let d = dim as f64;
+        let log_unit_ball = (d / 2.0) * PI.ln() - ln_gamma(d / 2.0 + 1.0);
+        match self {
+            KernelType::Gaussian => (d / 2.0) * (2.0 * PI).ln(),
+            KernelType::Uniform => log_unit_ball + 0.5f64.ln(),
+            KernelType::Epanechnikov => log_unit_ball + (0.75 * 2.0 / (d + 2.0)).ln(),
+            KernelType::Triangular => log_unit_ball - (d + 1.0).ln(),
+            // Surface area of the unit sphere times Gamma(d)
+            KernelType::Exponential => log_unit_ball + d.ln() + ln_gamma(d),
+            KernelType::Cosine => {
+                log_unit_ball + d.ln() + (FRAC_PI_4 * cosine_radial_moment(dim)).ln()
+            }
+            KernelType::Biweight => {
+                log_unit_ball + (15.0 / 16.0 * 8.0 / ((d + 2.0) * (d + 4.0))).ln()
+            }
+            KernelType::Triweight => {
+                log_unit_ball + (35.0 / 32.0 * 48.0 / ((d + 2.0) * (d + 4.0) * (d + 6.0))).ln()
+            }
+            KernelType::StudentT(_) if !self.is_normalizable(dim) => f64::INFINITY,
+            KernelType::StudentT(nu) => {
+                let p = 0.5 * (nu + 1.0);
+                (d / 2.0) * (PI * nu).ln() + ln_gamma(p - d / 2.0) - ln_gamma(p)
+            }
+        }
+    }
+
+    /// Draws an offset in R^dim whose density is proportional to the kernel at bandwidth `h`.
+    /// The radius is sampled from its radial law and paired with a uniform direction.
+    pub fn sample_offset(&self, dim: usize, h: f64, rng: &mut Generator) -> Result<Vec<f64>, IronForestError> {
         let d = dim as f64;
-        let unit_ball = std::f64::consts::PI.powf(d / 2.0) / gamma(d / 2.0 + 1.0);
+        let mut offset: Vec<f64> = (0..dim).map(|_| rng.next_gaussian()).collect();
+        let u = match self {
+            KernelType::Gaussian => {
+                offset.iter_mut().for_each(|x| *x *= h);
+                return Ok(offset);
+            }
+            KernelType::Uniform => rng.sample_beta_single(d, 1.0),
+            KernelType::Triangular => rng.sample_beta_single(d, 2.0),
+            // (1 - u^2)^p in d dimensions gives u^2 ~ Beta(d/2, p + 1)
+            KernelType::Epanechnikov => rng.sample_beta_single(d / 2.0, 2.0).sqrt(),
+            KernelType::Biweight => rng.sample_beta_single(d / 2.0, 3.0).sqrt(),
+            KernelType::Triweight => rng.sample_beta_single(d / 2.0, 4.0).sqrt(),
+            KernelType::Exponential => rng.sample_gamma_single(d),
+            KernelType::Cosine => loop {
+                let u = rng.sample_beta_single(d, 1.0);
+                if rng.next_f64() < (FRAC_PI_2 * u).cos() {
+                    break u;
+                }
+            },
+            KernelType::StudentT(nu) => {
+                self.check_normalizable(dim)?;
+                // Multivariate t with nu + 1 - d degrees of freedom, rescaled to this profile
+                let chi2 = 2.0 * rng.sample_gamma_single(0.5 * (nu + 1.0 - d));
+                let scale = h * (nu / chi2).sqrt();
+                offset.iter_mut().for_each(|x| *x *= scale);
+                return Ok(offset);
+            }
+        };
+        let norm = offset.iter().map(|x| x * x).sum::<f64>().sqrt();
+        let scale = if norm > 0.0 { h * u / norm } else { 0.0 };
+        offset.iter_mut().for_each(|x| *x *= scale);
+        Ok(offset)
+    }
+
+    /// First derivative divided by the kernel value at `r`.
+    pub fn first_derivative_ratio(&self, r: f64, h: f64) -> f64 {
+        match self {
+            KernelType::Gaussian => -r / (h * h),
+            KernelType::Exponential => -1.0 / h,
+            _ => {
+                let k0 = self.evaluate(r, h);
+                if k0 > 0.0 { self.first_derivative(r, h) / k0 } else { 0.0 }
+            }
+        }
+    }
+
+    /// Second, third and fourth derivatives divided by the kernel value at `r`.
+    /// Lets Taylor expansions be evaluated in log space when the kernel underflows.
+    pub fn derivative_ratios(&self, r: f64, h: f64) -> (f64, f64, f64) {
+        match self {
+            KernelType::Gaussian => {
+                let u = r / h;
+                let u2 = u * u;
+                let h2 = h * h;
+                ((u2 - 1.0) / h2, (3.0 * u - u2 * u) / (h2 * h), (u2 * u2 - 6.0 * u2 + 3.0) / (h2 * h2))
+            }
+            KernelType::Exponential => {
+                let h2 = h * h;
+                (1.0 / h2, -1.0 / (h2 * h), 1.0 / (h2 * h2))
+            }
+            _ => {
+                let k0 = self.evaluate(r, h);
+                if k0 > 0.0 {
+                    (
+                        self.evaluate_second_derivative(r, h) / k0,
+                        self.third_derivative(r, h) / k0,
+                        self.fourth_derivative(r, h) / k0,
+                    )
+                } else {
+                    (0.0, 0.0, 0.0)
+                }
+            }
+        }
+    }
+
+    /// Derivative of `evaluate` with respect to the distance.
+    pub fn first_derivative<T: IronFloat>(&self, r: T, h: T) -> T {
+        let u = r / h;
+        let one = T::one();
+        let zero = T::zero();
         match self {
-            KernelType::Gaussian => (2.0 * std::f64::consts::PI).powf(d / 2.0),
-            KernelType::Uniform => unit_ball,
-            KernelType::Epanechnikov => unit_ball * 2.0 / (d + 2.0),
-            KernelType::Triangular => unit_ball * 1.0 / (d + 1.0),
+            KernelType::Gaussian => -u * (T::from(-0.5).unwrap() * u * u).exp() / h,
+            KernelType::Epanechnikov => if u < one { T::from(-1.5).unwrap() * u / h } else { zero },
+            KernelType::Uniform => zero,
+            KernelType::Triangular => if u < one { -one / h } else { zero },
+            KernelType::Exponential => -(-u).exp() / h,
+            KernelType::Cosine => {
+                if u < one {
+                    let a = T::from(FRAC_PI_2).unwrap();
+                    -T::from(FRAC_PI_4).unwrap() * a * (a * u).sin() / h
+                } else { zero }
+            }
+            KernelType::Biweight => {
+                if u < one { T::from(-15.0 / 4.0).unwrap() * u * (one - u * u) / h } else { zero }
+            }
+            KernelType::Triweight => {
+                if u < one {
+                    let s = one - u * u;
+                    T::from(-105.0 / 16.0).unwrap() * u * s * s / h
+                } else { zero }
+            }
+            KernelType::StudentT(nu) => {
+                let (p, _, s) = student_t_terms(*nu, u);
+                -T::from(2.0 * p / nu).unwrap() * u * s.powf(T::from(-p - 1.0).unwrap()) / h
+            }
         }
     }
 
@@ -142,6 +341,7 @@ impl KernelType {
         let u = r / h;
         let one = T::one();
         let zero = T::zero();
+        let h2 = h * h;
         match self {
             KernelType::Gaussian => {
                 let k = (T::from(-0.5).unwrap() * u * u).exp();
@@ -150,18 +350,63 @@ impl KernelType {
             KernelType::Epanechnikov => if u < one { T::from(-1.5).unwrap() / (h * h) } else { zero },
             KernelType::Triangular => zero,
             KernelType::Uniform => zero,
+            KernelType::Exponential => (-u).exp() / h2,
+            KernelType::Cosine => {
+                if u < one {
+                    let a = T::from(FRAC_PI_2).unwrap();
+                    -T::from(FRAC_PI_4).unwrap() * a * a * (a * u).cos() / h2
+                } else { zero }
+            }
+            KernelType::Biweight => {
+                if u < one {
+                    T::from(15.0 / 16.0).unwrap() * (T::from(12.0).unwrap() * u * u - T::from(4.0).unwrap()) / h2
+                } else { zero }
+            }
+            KernelType::Triweight => {
+                if u < one {
+                    let u2 = u * u;
+                    T::from(35.0 / 32.0).unwrap()
+                        * (T::from(36.0).unwrap() * u2 - T::from(30.0).unwrap() * u2 * u2 - T::from(6.0).unwrap()) / h2
+                } else { zero }
+            }
+            KernelType::StudentT(nu) => {
+                let (p, t, s) = student_t_terms(*nu, u);
+                let a = T::from(2.0 * p / nu).unwrap();
+                -a * s.powf(T::from(-p - 2.0).unwrap()) * (one - T::from(2.0 * p + 1.0).unwrap() * t) / h2
+            }
         }
     }
 
     pub fn third_derivative<T: IronFloat>(&self, r: T, h: T) -> T {
         let u = r / h;
         let h3 = h * h * h;
+        let one = T::one();
         let zero = T::zero();
         match self {
             KernelType::Gaussian => {
                 let k = (T::from(-0.5).unwrap() * u * u).exp();
                 (T::from(3.0).unwrap() * u - u * u * u) / h3 * k
             }
+            KernelType::Exponential => -(-u).exp() / h3,
+            KernelType::Cosine => {
+                if u < one {
+                    let a = T::from(FRAC_PI_2).unwrap();
+                    T::from(FRAC_PI_4).unwrap() * a * a * a * (a * u).sin() / h3
+                } else { zero }
+            }
+            KernelType::Biweight => {
+                if u < one { T::from(15.0 / 16.0 * 24.0).unwrap() * u / h3 } else { zero }
+            }
+            KernelType::Triweight => {
+                if u < one {
+                    T::from(35.0 / 32.0).unwrap() * (T::from(72.0).unwrap() * u - T::from(120.0).unwrap() * u * u * u) / h3
+                } else { zero }
+            }
+            KernelType::StudentT(nu) => {
+                let (p, t, s) = student_t_terms(*nu, u);
+                let a = T::from(4.0 * p * (p + 1.0) / (nu * nu)).unwrap();
+                a * u * s.powf(T::from(-p - 3.0).unwrap()) * (T::from(3.0).unwrap() - T::from(2.0 * p + 1.0).unwrap() * t) / h3
+            }
             _ => zero,
         }
     }
@@ -169,6 +414,7 @@ impl KernelType {
     pub fn fourth_derivative<T: IronFloat>(&self, r: T, h: T) -> T {
         let u = r / h;
         let h4 = h * h * h * h;
+        let one = T::one();
         let zero = T::zero();
         match self {
             KernelType::Gaussian => {
@@ -176,15 +422,37 @@ impl KernelType {
                 let u2 = u * u;
                 (u2 * u2 - T::from(6.0).unwrap() * u2 + T::from(3.0).unwrap()) * k / h4
             }
+            KernelType::Exponential => (-u).exp() / h4,
+            KernelType::Cosine => {
+                if u < one {
+                    let a = T::from(FRAC_PI_2).unwrap();
+                    T::from(FRAC_PI_4).unwrap() * a * a * a * a * (a * u).cos() / h4
+                } else { zero }
+            }
+            KernelType::Biweight => {
+                if u < one { T::from(15.0 / 16.0 * 24.0).unwrap() / h4 } else { zero }
+            }
+            KernelType::Triweight => {
+                if u < one {
+                    T::from(35.0 / 32.0).unwrap() * (T::from(72.0).unwrap() - T::from(360.0).unwrap() * u * u) / h4
+                } else { zero }
+            }
+            KernelType::StudentT(nu) => {
+                let (p, t, s) = student_t_terms(*nu, u);
+                let a = T::from(4.0 * p * (p + 1.0) / (nu * nu)).unwrap();
+                let poly = T::from(3.0).unwrap() - T::from(12.0 * p + 18.0).unwrap() * t
+                    + T::from((2.0 * p + 1.0) * (2.0 * p + 3.0)).unwrap() * t * t;
+                a * s.powf(T::from(-p - 4.0).unwrap()) * poly / h4
+            }
             _ => zero,
         }
     }
 
     pub fn node_error_bound<T: IronFloat>(&self, n: T, radius: T, h: T) -> T {
         let ratio = radius / h;
+        let r5 = ratio * ratio * ratio * ratio * ratio;
         match self {
             KernelType::Gaussian => {
-                let r5 = ratio * ratio * ratio * ratio * ratio;
                 (n / T::from(120.0).unwrap()) * T::from(2.5221).unwrap() * r5
             }
             KernelType::Epanechnikov => {
@@ -196,8 +464,112 @@ impl KernelType {
             KernelType::Triangular => {
                 n * ratio
             }
+            // Every derivative of exp(-u) is bounded by 1
+            KernelType::Exponential => {
+                (n / T::from(120.0).unwrap()) * r5
+            }
+            KernelType::Cosine => {
+                n * ratio * T::from(FRAC_PI_4).unwrap()
+            }
+            KernelType::Biweight => {
+                n * ratio * T::from(15.0 / 16.0).unwrap()
+            }
+            KernelType::Triweight => {
+                n * ratio * T::from(35.0 / 32.0).unwrap()
+            }
+            // Lipschitz bound
+            KernelType::StudentT(_) => {
+                n * ratio * T::from(self.max_slope()).unwrap()
+            }
+        }
+    }
+
+    /// Supremum of |dK/du|. The uniform kernel is discontinuous and uses its height instead.
+    pub fn max_slope(&self) -> f64 {
+        match self {
+            KernelType::Gaussian => (-0.5f64).exp(),
+            KernelType::Epanechnikov => 1.5,
+            KernelType::Uniform => 0.5,
+            KernelType::Triangular => 1.0,
+            KernelType::Exponential => 1.0,
+            KernelType::Cosine => FRAC_PI_4 * FRAC_PI_2,
+            // Peaks at u = 1/sqrt(3)
+            KernelType::Biweight => 2.5 / 3.0f64.sqrt(),
+            // Peaks at u = 1/sqrt(5)
+            KernelType::Triweight => 4.2 / 5.0f64.sqrt(),
+            // Peaks at u = sqrt(nu / (nu + 2))
+            KernelType::StudentT(nu) => {
+                let p = 0.5 * (nu + 1.0);
+                let u = (nu / (nu + 2.0)).sqrt();
+                (2.0 * p / nu) * u * (1.0 + u * u / nu).powf(-p - 1.0)
+            }
         }
     }
+
+    /// Error of approximating every point in a node by the midpoint of its smallest and
+    /// largest possible kernel values, used where no Taylor expansion applies.
+    pub fn midpoint_error_bound<T: IronFloat>(&self, n: T, radius: T, h: T) -> T {
+        n * (radius / h) * T::from(self.max_slope()).unwrap()
+    }
+}
+
+/// Returns (p, u^2/nu, 1 + u^2/nu) for the Student-t profile derivatives.
+#[inline]
+fn student_t_terms<T: IronFloat>(nu: f64, u: T) -> (f64, T, T) {
+    let t = u * u / T::from(nu).unwrap();
+    (0.5 * (nu + 1.0), t, T::one() + t)
+}
+
+/// Integral of r^(d-1) cos(pi r / 2) over [0, 1] by composite Simpson's rule.
+fn cosine_radial_moment(dim: usize) -> f64 {
+    const STEPS: usize = 2048;
+    let step = 1.0 / STEPS as f64;
+    let f = |r: f64| r.powi(dim as i32 - 1) * (FRAC_PI_2 * r).cos();
+    let mut sum = f(0.0) + f(1.0);
+    for i in 1..STEPS {
+        let w = if i % 2 == 1 { 4.0 } else { 2.0 };
+        sum += w * f(i as f64 * step);
+    }
+    sum * step / 3.0
+}
+
+/// Streaming log-sum-exp accumulator. Adds terms given in log space without
+/// leaving it, so sums of underflowing kernel values stay finite.
+#[derive(Clone, Copy, Debug)]
+pub struct LogSumExp {
+    max: f64,
+    sum: f64,
+}
+
+impl Default for LogSumExp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogSumExp {
+    pub fn new() -> Self {
+        LogSumExp { max: f64::NEG_INFINITY, sum: 0.0 }
+    }
+
+    #[inline]
+    pub fn add(&mut self, log_val: f64) {
+        if log_val == f64::NEG_INFINITY {
+            return;
+        }
+        if log_val > self.max {
+            self.sum = self.sum * (self.max - log_val).exp() + 1.0;
+            self.max = log_val;
+        } else {
+            self.sum += (log_val - self.max).exp();
+        }
+    }
+
+    /// Current `ln(sum(exp(x_i)))`, `-inf` when empty.
+    #[inline]
+    pub fn value(&self) -> f64 {
+        if self.sum == 0.0 { f64::NEG_INFINITY } else { self.max + self.sum.ln() }
+    }
 }
 
 pub struct HeapItem<T: IronFloat> {
@@ -207,7 +579,7 @@ pub struct HeapItem<T: IronFloat> {
 
 impl<T: IronFloat> PartialEq for HeapItem<T> {
     fn eq(&self, other: &Self) -> bool {
-        self.distance == other.distance
+        self.cmp(other) == Ordering::Equal
     }
 }
 
@@ -221,6 +593,50 @@ impl<T: IronFloat> PartialOrd for HeapItem<T> {
 
 impl<T: IronFloat> Ord for HeapItem<T> {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.distance.partial_cmp(&other.distance).unwrap_or(Ordering::Equal)
+        // NaN distances order after every other, so heaps of nearest items evict them first
+        self.distance.total_cmp(&other.distance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::array::{NdArray, Shape};
+    use crate::spatial::queries::KdeQuery;
+    use crate::spatial::trees::KDTree;
+
+    #[test]
+    fn heap_items_order_nan_distances_last() {
+        let mut heap: std::collections::BinaryHeap<HeapItem<f64>> = [1.0, f64::NAN, 0.5, f64::INFINITY, 2.0].iter()
+            .enumerate()
+            .map(|(index, &distance)| HeapItem { distance, index })
+            .collect();
+        let popped: Vec<usize> = std::iter::from_fn(|| heap.pop().map(|item| item.index)).collect();
+        assert_eq!(popped, vec![1, 3, 4, 0, 2]);
+    }
+
+    #[test]
+    fn student_t_outside_its_range_is_an_error() {
+        let kernel = KernelType::StudentT(1.0);
+        assert!(kernel.check_normalizable(1).is_ok());
+        assert!(matches!(kernel.check_normalizable(3), Err(IronForestError::InvalidArgument(_))));
+        assert_eq!(kernel.log_normalization_constant(3), f64::INFINITY);
+
+        let mut rng = Generator::from_seed(0);
+        assert!(kernel.sample_offset(3, 1.0, &mut rng).is_err());
+        assert_eq!(kernel.sample_offset(1, 1.0, &mut rng).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn student_t_density_normalizes_only_when_finite() {
+        let data = Generator::from_seed(1).standard_normal(Shape::new(vec![50, 3]));
+        let tree = KDTree::new(data, 10, DistanceMetric::Euclidean);
+        let queries = NdArray::from_vec(Shape::new(vec![1, 3]), vec![0.0; 3]);
+        let kernel = KernelType::StudentT(1.0);
+        assert!(tree.kernel_density(&queries, 1.0, kernel, true, false).is_err());
+        assert!(tree.kernel_density(&queries, 1.0, kernel, false, false).is_ok());
+        assert!(tree.kernel_density(&queries, 1.0, KernelType::StudentT(3.0), true, false).is_ok());
+        let mut rng = Generator::from_seed(2);
+        assert!(tree.sample(5, 1.0, kernel, None, &mut rng).is_err());
     }
 }
@@ -3,8 +3,19 @@ pub(crate) mod queries;
 pub(crate) mod trees;
 pub(crate) mod spatial_tree;
 pub(crate) mod spatial_stats;
+pub mod nan_policy;
 pub mod spatial_index;
+pub mod tree_selection;
 
 pub use common::{DistanceMetric, KernelType, HeapItem, IronFloat};
 pub use spatial_tree::SpatialTree;
-pub use spatial_index::{SpatialIndex, TreeType, QueryResult, QueryInput};
\ No newline at end of file
+pub use spatial_stats::IntrinsicDimEstimator;
+pub use nan_policy::{NanPolicy, NanRows, Neighbors};
+pub use spatial_index::{
+    SpatialIndex, SpatialIndexBuilder, TreeType, QueryResult, QueryInput,
+    IndexData, IndexFloat, RPTreeOptions, VPTreeOptions,
+};
+pub use tree_selection::{
+    SelectionRule, SelectionThresholds, TreeBenchmark, TreeRecommendation, Workload,
+    auto_select_tree, benchmark_trees, recommend_tree, select_tree,
+};
\ No newline at end of file
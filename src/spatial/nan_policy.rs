@@ -0,0 +1,424 @@
+use crate::array::{NdArray, Shape};
+use crate::error::IronForestError;
+use crate::spatial::common::{DistanceMetric, IronFloat, KernelType, LogSumExp};
+use serde::{Deserialize, Serialize};
+
+/// How NaN coordinates in points and queries are handled. Infinite coordinates, which
+/// leave no finite distance, are handled as NaN.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NanPolicy {
+    /// Inputs containing NaN are rejected.
+    #[default]
+    Raise,
+    /// Rows containing NaN are left out of the tree, and queries containing NaN find nothing.
+    Drop,
+    /// Rows containing NaN are kept beside the tree and compared over the coordinates both
+    /// points observe, as are queries containing NaN.
+    IgnoreDims,
+}
+
+/// What a query asks for, used when merging neighbors from rows kept beside a tree.
+#[derive(Clone, Copy, Debug)]
+pub enum Neighbors {
+    Knn(usize),
+    Radius(f64),
+}
+
+/// True when `row` has a NaN or infinite coordinate.
+pub fn has_non_finite<T: IronFloat>(row: &[T]) -> bool {
+    row.iter().any(|x| !x.is_finite())
+}
+
+impl DistanceMetric {
+    /// Distance over the coordinates observed in both points, scaled up to the full dimension
+    /// for the euclidean and manhattan metrics. `None` when no coordinate is shared. `reduced`
+    /// matches the tree's `SpatialTree::REDUCED`: cosine is then `1 - cos` rather than the
+    /// chord distance between the normalized points.
+    pub fn partial_distance(self, a: &[f64], b: &[f64], reduced: bool) -> Option<f64> {
+        let pairs = || a.iter().zip(b).filter(|(x, y)| x.is_finite() && y.is_finite());
+        let observed = pairs().count();
+        if observed == 0 {
+            return None;
+        }
+        let scale = a.len() as f64 / observed as f64;
+        Some(match self {
+            DistanceMetric::Euclidean => (scale * pairs().map(|(x, y)| (x - y).powi(2)).sum::<f64>()).sqrt(),
+            DistanceMetric::Manhattan => scale * pairs().map(|(x, y)| (x - y).abs()).sum::<f64>(),
+            DistanceMetric::Chebyshev => pairs().map(|(x, y)| (x - y).abs()).fold(0.0, f64::max),
+            DistanceMetric::Cosine => {
+                let (dot, aa, bb) = pairs().fold((0.0, 0.0, 0.0), |(d, p, q), (x, y)| (d + x * y, p + x * x, q + y * y));
+                // Squared distance between the normalized points; zero vectors stay at the origin
+                let unit = |n: f64| if n == 0.0 { 0.0 } else { 1.0 };
+                let cos = if aa == 0.0 || bb == 0.0 { 0.0 } else { dot / (aa * bb).sqrt() };
+                let chord_sq = (unit(aa) + unit(bb) - 2.0 * cos).max(0.0);
+                if reduced { chord_sq / 2.0 } else { chord_sq.sqrt() }
+            }
+        })
+    }
+}
+
+/// Rows set aside from a tree because they contain NaN, and the input row of every tree
+/// row. Tree rows are numbered in the order the tree was given them, input rows in the
+/// order the user passed them, counting the rows set aside.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct NanRows {
+    pub policy: NanPolicy,
+    pub dim: usize,
+    /// Input row of each tree row. Empty until a row is set aside, meaning the identity.
+    pub row_map: Vec<usize>,
+    /// Input rows set aside, with their coordinates.
+    pub rows: Vec<usize>,
+    pub data: Vec<f64>,
+}
+
+impl NanRows {
+    pub fn new(policy: NanPolicy, dim: usize) -> Self {
+        NanRows { policy, dim, ..Default::default() }
+    }
+
+    /// Applies `policy` to tree input, returning the rows to build on. Data without NaN is
+    /// returned untouched.
+    pub fn split<T: IronFloat>(data: NdArray<T>, policy: NanPolicy) -> Result<(NdArray<T>, Self), IronForestError> {
+        let dims = data.shape().dims();
+        if dims.len() != 2 {
+            return Err(IronForestError::InvalidShape { ndim: dims.len() });
+        }
+        let (n, dim) = (dims[0], dims[1]);
+        let mut nan = NanRows::new(policy, dim);
+        if !(0..n).any(|i| has_non_finite(data.row(i))) {
+            return Ok((data, nan));
+        }
+
+        let mut kept = Vec::with_capacity(n * dim);
+        for i in 0..n {
+            let row = data.row(i);
+            if has_non_finite(row) {
+                nan.set_aside(i, row, nan.row_map.len())?;
+            } else {
+                nan.row_map.push(i);
+                kept.extend_from_slice(row);
+            }
+        }
+        let n_kept = nan.row_map.len();
+        Ok((NdArray::from_vec(Shape::new(vec![n_kept, dim]), kept), nan))
+    }
+
+    /// Applies the policy to rows appended after `n_tree` tree rows, returning those to
+    /// index. Nothing is recorded if any row is rejected.
+    pub fn push_rows<T: IronFloat>(&mut self, flat: &[T], n_tree: usize) -> Result<Vec<T>, IronForestError> {
+        let dim = self.dim.max(1);
+        if self.policy == NanPolicy::Raise
+            && let Some(i) = flat.chunks(dim).position(has_non_finite)
+        {
+            return Err(IronForestError::NonFinite { input: "Point", index: i });
+        }
+        let mut kept = Vec::with_capacity(flat.len());
+        let mut n_tree = n_tree;
+        for row in flat.chunks(dim) {
+            let input = n_tree + self.rows.len();
+            if has_non_finite(row) {
+                self.set_aside(input, row, n_tree)?;
+            } else {
+                if !self.rows.is_empty() {
+                    self.row_map.push(input);
+                }
+                kept.extend_from_slice(row);
+                n_tree += 1;
+            }
+        }
+        Ok(kept)
+    }
+
+    fn set_aside<T: IronFloat>(&mut self, input: usize, row: &[T], n_tree: usize) -> Result<(), IronForestError> {
+        if self.policy == NanPolicy::Raise {
+            return Err(IronForestError::NonFinite { input: "Row", index: input });
+        }
+        if self.rows.is_empty() && self.row_map.len() < n_tree {
+            self.row_map = (0..n_tree).collect();
+        }
+        self.rows.push(input);
+        self.data.extend(row.iter().map(|x| x.to_f64().unwrap()));
+        Ok(())
+    }
+
+    /// True when tree rows are input rows and queries need no special handling.
+    pub fn is_identity(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    pub fn input_row(&self, tree_row: usize) -> usize {
+        if self.rows.is_empty() { tree_row } else { self.row_map[tree_row] }
+    }
+
+    /// Tree row values from per-input-row `values`, dropping those of rows set aside.
+    /// Values already given per tree row are returned as they are.
+    pub fn tree_values(&self, values: Vec<f64>, n_tree: usize) -> Vec<f64> {
+        if self.rows.is_empty() || values.len() != n_tree + self.rows.len() {
+            return values;
+        }
+        self.row_map.iter().map(|&i| values[i]).collect()
+    }
+
+    /// Input-ordered rows from tree-ordered `tree_rows`, with the rows set aside in place.
+    pub fn input_data(&self, tree_rows: Vec<f64>) -> Vec<f64> {
+        if self.rows.is_empty() {
+            return tree_rows;
+        }
+        let dim = self.dim;
+        let n_input = tree_rows.len() / dim + self.rows.len();
+        let mut out = vec![0.0f64; n_input * dim];
+        for (tree_row, &input) in self.row_map.iter().enumerate() {
+            out[input * dim..(input + 1) * dim].copy_from_slice(&tree_rows[tree_row * dim..(tree_row + 1) * dim]);
+        }
+        for (j, &input) in self.rows.iter().enumerate() {
+            out[input * dim..(input + 1) * dim].copy_from_slice(&self.data[j * dim..(j + 1) * dim]);
+        }
+        out
+    }
+
+    /// Query rows containing NaN, or an error under `Raise`.
+    pub fn missing_queries<T: IronFloat>(&self, queries: &NdArray<T>) -> Result<Vec<usize>, IronForestError> {
+        let n = queries.shape().dims()[0];
+        let missing: Vec<usize> = (0..n).filter(|&i| has_non_finite(queries.row(i))).collect();
+        match (self.policy, missing.first()) {
+            (NanPolicy::Raise, Some(&index)) => Err(IronForestError::NonFinite { input: "Query", index }),
+            _ => Ok(missing),
+        }
+    }
+
+    /// Runs `tree` on the query rows without NaN, and scatters its rows of output back with
+    /// NaN rows for the others. For queries the tree answers on its own, such as gradients.
+    /// `width` is the length of each output row.
+    pub fn per_query<T: IronFloat>(
+        &self,
+        queries: &NdArray<T>,
+        width: usize,
+        tree: impl FnOnce(&NdArray<T>) -> Result<NdArray<f64>, IronForestError>,
+    ) -> Result<NdArray<f64>, IronForestError> {
+        let missing = self.missing_queries(queries)?;
+        if missing.is_empty() {
+            return tree(queries);
+        }
+        let n = queries.shape().dims()[0];
+        let mut result = vec![f64::NAN; n * width];
+        if missing.len() < n {
+            let out = tree(&select_rows(queries, &missing))?;
+            let found = out.as_contiguous_slice();
+            for (j, i) in complete_rows(n, &missing).enumerate() {
+                result[i * width..(i + 1) * width].copy_from_slice(&found[j * width..(j + 1) * width]);
+            }
+        }
+        Ok(NdArray::from_vec(Shape::new(vec![n, width]), result))
+    }
+
+    /// Neighbors of every query in input row numbering. `tree` answers the query rows without
+    /// NaN in tree row numbering, and `tree_rows` gives the tree's points in tree row order
+    /// for queries that must be scanned.
+    pub fn neighbors<T: IronFloat>(
+        &self,
+        metric: DistanceMetric,
+        reduced: bool,
+        queries: &NdArray<T>,
+        want: Neighbors,
+        tree_rows: impl FnOnce() -> Vec<f64>,
+        tree: impl FnOnce(&NdArray<T>) -> Result<Vec<Vec<(usize, f64)>>, IronForestError>,
+    ) -> Result<Vec<Vec<(usize, f64)>>, IronForestError> {
+        let missing = self.missing_queries(queries)?;
+        if missing.is_empty() && self.is_identity() {
+            return tree(queries);
+        }
+        let n = queries.shape().dims()[0];
+        let found = match missing.len() {
+            0 => tree(queries)?,
+            m if m == n => Vec::new(),
+            _ => tree(&select_rows(queries, &missing))?,
+        };
+        let scan_rows = if missing.is_empty() || self.policy != NanPolicy::IgnoreDims { Vec::new() } else { tree_rows() };
+
+        let mut found = found.into_iter();
+        let mut next_missing = missing.iter().peekable();
+        let mut results = Vec::with_capacity(n);
+        for i in 0..n {
+            let query = to_f64(queries.row(i));
+            if next_missing.next_if(|&&m| m == i).is_some() {
+                results.push(match self.policy {
+                    NanPolicy::IgnoreDims => {
+                        let tree_hits = self.scan(metric, reduced, &query, &scan_rows, (0..scan_rows.len() / self.dim).map(|r| self.input_row(r)));
+                        select(tree_hits.chain(self.scan_aside(metric, reduced, &query)).collect(), want)
+                    }
+                    _ => Vec::new(),
+                });
+                continue;
+            }
+            let mut hits: Vec<(usize, f64)> = found.next().unwrap_or_default().into_iter()
+                .map(|(r, d)| (self.input_row(r), d))
+                .collect();
+            if self.policy == NanPolicy::IgnoreDims && !self.rows.is_empty() {
+                hits.extend(self.scan_aside(metric, reduced, &query));
+                hits = select(hits, want);
+            }
+            results.push(hits);
+        }
+        Ok(results)
+    }
+
+    /// Kernel density of every query, combining the tree's answer for query rows without
+    /// NaN with contributions from rows set aside. Queries containing NaN get NaN under
+    /// `Drop` and a scan over all points under `IgnoreDims`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn density<T: IronFloat>(
+        &self,
+        metric: DistanceMetric,
+        reduced: bool,
+        queries: &NdArray<T>,
+        kernel: KernelType,
+        bandwidth: f64,
+        normalize: bool,
+        log_density: bool,
+        tree_rows: impl FnOnce() -> Vec<f64>,
+        tree: impl FnOnce(&NdArray<T>) -> Result<Vec<f64>, IronForestError>,
+    ) -> Result<Vec<f64>, IronForestError> {
+        let missing = self.missing_queries(queries)?;
+        if missing.is_empty() && self.is_identity() {
+            return tree(queries);
+        }
+        let n = queries.shape().dims()[0];
+        let found = match missing.len() {
+            0 => tree(queries)?,
+            m if m == n => Vec::new(),
+            _ => tree(&select_rows(queries, &missing))?,
+        };
+        let scan_rows = if missing.is_empty() || self.policy != NanPolicy::IgnoreDims { Vec::new() } else { tree_rows() };
+        let aside = self.policy == NanPolicy::IgnoreDims && !self.rows.is_empty();
+        let norm = if normalize { bandwidth.powi(self.dim as i32) * kernel.normalization_constant(self.dim) } else { 1.0 };
+
+        let kernel_sum = |query: &[f64], rows: &[f64]| -> f64 {
+            rows.chunks(self.dim)
+                .filter_map(|row| metric.partial_distance(query, row, reduced))
+                .map(|d| kernel.evaluate(d, bandwidth))
+                .sum::<f64>() / norm
+        };
+
+        let mut found = found.into_iter();
+        let mut next_missing = missing.iter().peekable();
+        let mut results = Vec::with_capacity(n);
+        for i in 0..n {
+            let query = to_f64(queries.row(i));
+            if next_missing.next_if(|&&m| m == i).is_some() {
+                results.push(match self.policy {
+                    NanPolicy::IgnoreDims => {
+                        let total = kernel_sum(&query, &scan_rows) + kernel_sum(&query, &self.data);
+                        if log_density { total.ln() } else { total }
+                    }
+                    _ => f64::NAN,
+                });
+                continue;
+            }
+            let value = found.next().unwrap_or(f64::NAN);
+            if !aside {
+                results.push(value);
+                continue;
+            }
+            let extra = kernel_sum(&query, &self.data);
+            results.push(if log_density {
+                let mut acc = LogSumExp::new();
+                acc.add(value);
+                acc.add(extra.ln());
+                acc.value()
+            } else {
+                value + extra
+            });
+        }
+        Ok(results)
+    }
+
+    fn scan<'a>(
+        &'a self,
+        metric: DistanceMetric,
+        reduced: bool,
+        query: &'a [f64],
+        rows: &'a [f64],
+        ids: impl Iterator<Item = usize> + 'a,
+    ) -> impl Iterator<Item = (usize, f64)> + 'a {
+        rows.chunks(self.dim.max(1))
+            .zip(ids)
+            .filter_map(move |(row, id)| metric.partial_distance(query, row, reduced).map(|d| (id, d)))
+    }
+
+    fn scan_aside<'a>(&'a self, metric: DistanceMetric, reduced: bool, query: &'a [f64]) -> impl Iterator<Item = (usize, f64)> + 'a {
+        self.scan(metric, reduced, query, &self.data, self.rows.iter().copied())
+    }
+}
+
+fn to_f64<T: IronFloat>(row: &[T]) -> Vec<f64> {
+    row.iter().map(|x| x.to_f64().unwrap()).collect()
+}
+
+fn complete_rows(n: usize, missing: &[usize]) -> impl Iterator<Item = usize> + '_ {
+    let mut next = missing.iter().peekable();
+    (0..n).filter(move |&i| next.next_if(|&&m| m == i).is_none())
+}
+
+fn select_rows<T: IronFloat>(queries: &NdArray<T>, missing: &[usize]) -> NdArray<T> {
+    let dims = queries.shape().dims();
+    let (n, dim) = (dims[0], dims[1]);
+    let mut rows = Vec::with_capacity((n - missing.len()) * dim);
+    for i in complete_rows(n, missing) {
+        rows.extend_from_slice(queries.row(i));
+    }
+    NdArray::from_vec(Shape::new(vec![n - missing.len(), dim]), rows)
+}
+
+/// Sorted nearest `k`, or those within the radius.
+fn select(mut hits: Vec<(usize, f64)>, want: Neighbors) -> Vec<(usize, f64)> {
+    if let Neighbors::Radius(r) = want {
+        hits.retain(|&(_, d)| d <= r);
+    }
+    hits.sort_by(|a, b| a.1.total_cmp(&b.1));
+    if let Neighbors::Knn(k) = want {
+        hits.truncate(k);
+    }
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial::queries::KnnQuery;
+    use crate::spatial::trees::{BruteForce, VPTree, VantagePointSelection};
+
+    #[test]
+    fn infinite_coordinates_are_handled_as_nan() {
+        let data = || NdArray::from_vec(Shape::new(vec![3, 2]), vec![0.0, 1.0, f64::INFINITY, 2.0, 3.0, 4.0]);
+        assert_eq!(NanRows::split(data(), NanPolicy::Raise).unwrap_err(), IronForestError::NonFinite { input: "Row", index: 1 });
+
+        let (kept, nan) = NanRows::split(data(), NanPolicy::IgnoreDims).unwrap();
+        assert_eq!(kept.shape().dims(), &[2, 2]);
+        assert_eq!(nan.rows, vec![1]);
+        let query = NdArray::from_vec(Shape::new(vec![1, 2]), vec![f64::NEG_INFINITY, 0.0]);
+        assert_eq!(nan.missing_queries(&query).unwrap(), vec![0]);
+        assert_eq!(DistanceMetric::Euclidean.partial_distance(&[f64::INFINITY, 2.0], &[5.0, 4.0], false), Some(8f64.sqrt()));
+
+        let raise = NanRows::new(NanPolicy::Raise, 2);
+        assert_eq!(raise.missing_queries(&query).unwrap_err(), IronForestError::NonFinite { input: "Query", index: 0 });
+    }
+
+    #[test]
+    fn cosine_partial_distance_matches_tree_distances() {
+        let points = vec![1.0, 0.0, 0.5, 2.0, -1.0, 0.3, 0.0, 0.0, 0.0];
+        let query = [0.4, 1.0, -0.2];
+        let data = || NdArray::from_vec(Shape::new(vec![3, 3]), points.clone());
+
+        let normalized = DistanceMetric::Cosine.pre_transform(&query);
+
+        let vp = VPTree::new(data(), 1, DistanceMetric::Cosine, VantagePointSelection::First, 0);
+        let brute = BruteForce::new(data(), DistanceMetric::Cosine);
+        for (tree_hits, reduced) in [(vp.query_knn(&normalized, 3).unwrap(), false), (brute.query_knn(&normalized, 3).unwrap(), true)] {
+            for (i, d) in tree_hits {
+                let row = &points[i * 3..(i + 1) * 3];
+                let partial = DistanceMetric::Cosine.partial_distance(&query, row, reduced).unwrap();
+                assert!((partial - d).abs() < 1e-12, "row {}: {} vs {}", i, partial, d);
+            }
+        }
+    }
+}
@@ -3,7 +3,9 @@ use std::cmp::Reverse;
 use crate::{Generator, array::NdArray, spatial::HeapItem};
 use rayon::prelude::*;
 use crate::spatial::SpatialTree;
-use num_traits::{ToPrimitive, identities::Zero as _, Float};
+use crate::spatial::queries::BatchNeighbors;
+use crate::error::{IronForestError, check_k, check_queries};
+use num_traits::{ToPrimitive, identities::Zero as _, Float, float::TotalOrder as _};
 
 
 const ANN_PAR_THRESHOLD: usize = 512;
@@ -87,25 +89,23 @@ pub trait AnnQuery: SpatialTree {
             })
             .collect();
 
-        results.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        results.sort_by(|a, b| a.1.total_cmp(&b.1));
         results.truncate(k);
         results
     }
 
-    fn query_ann_batch(&self, queries: &NdArray<Self::Float>, k: usize, n_candidates: usize) -> Vec<Vec<(usize, Self::Float)>> {
-        let shape = queries.shape().dims();
-        assert!(shape.len() == 2, "Expected 2D array (n_queries, dim)");
-        let n_queries = shape[0];
-        let dim = shape[1];
-        assert_eq!(dim, self.dim(), "Query dimension must match tree dimension");
+    fn query_ann_batch(&self, queries: &NdArray<Self::Float>, k: usize, n_candidates: usize) -> Result<BatchNeighbors<Self::Float>, IronForestError> {
+        check_k(k)?;
+        let n_queries = check_queries(queries.shape().dims(), self.dim())?;
+        let dim = self.dim();
 
         let queries_cow = queries.as_contiguous_slice();
         let queries_slice: &[Self::Float] = &queries_cow;
-        if n_queries >= ANN_PAR_THRESHOLD {
+        Ok(if n_queries >= ANN_PAR_THRESHOLD {
             self.par_ann_batch(queries_slice, n_queries, dim, k, n_candidates)
         } else {
             self.seq_ann_batch(queries_slice, n_queries, dim, k, n_candidates)
-        }
+        })
     }
 
     fn seq_ann_batch(&self, queries: &[Self::Float], n_queries: usize, dim: usize, k: usize, n_candidates: usize) -> Vec<Vec<(usize, Self::Float)>> {
@@ -262,16 +262,15 @@ pub trait AnnQuery: SpatialTree {
         k: usize,
         n_candidates: usize,
         n_probes: usize,
-    ) -> Vec<Vec<(usize, Self::Float)>> {
-        let shape = queries.shape().dims();
-        assert!(shape.len() == 2, "Expected 2D array (n_queries, dim)");
-        let (n_queries, dim) = (shape[0], shape[1]);
-        assert_eq!(dim, self.dim(), "Query dimension must match tree dimension");
+    ) -> Result<BatchNeighbors<Self::Float>, IronForestError> {
+        check_k(k)?;
+        let n_queries = check_queries(queries.shape().dims(), self.dim())?;
+        let dim = self.dim();
 
         let queries_cow = queries.as_contiguous_slice();
         let queries_slice: &[Self::Float] = &queries_cow;
 
-        if n_queries >= ANN_PAR_THRESHOLD {
+        Ok(if n_queries >= ANN_PAR_THRESHOLD {
             (0..n_queries)
                 .into_par_iter()
                 .map(|i| {
@@ -286,7 +285,7 @@ pub trait AnnQuery: SpatialTree {
                     self.query_ann_stochastic(query, k, n_candidates, n_probes)
                 })
                 .collect()
-        }
+        })
     }
 
     fn query_ann_stochastic(
@@ -303,14 +302,43 @@ pub trait AnnQuery: SpatialTree {
         
         let n_words = (self.n_points() + 63) / 64;
         let mut seen: Vec<u64> = vec![0u64; n_words];
-        
+
+        self.probe_candidates(query, k, n_candidates, n_probes, &mut candidates, &mut seen);
+
+        let mut results: Vec<(usize, Self::Float)> = candidates.into_iter()
+            .map(|item| {
+                let dist = if Self::REDUCED {
+                    self.metric().post_transform(item.distance)
+                } else {
+                    item.distance
+                };
+                (item.index, dist)
+            })
+            .collect();
+
+        results.sort_by(|a, b| a.1.total_cmp(&b.1));
+        results.truncate(k);
+        results
+    }
+
+    // Greedy probe followed by n_probes - 1 diverging ones. Candidates and the seen bitset
+    // (indexed by original point id) may already hold results from other trees.
+    fn probe_candidates(
+        &self,
+        query: &[Self::Float],
+        k: usize,
+        n_candidates: usize,
+        n_probes: usize,
+        candidates: &mut BinaryHeap<HeapItem<Self::Float>>,
+        seen: &mut [u64],
+    ) {
         let bail_threshold = 0.075;
 
         let mut path: Vec<(usize, usize, usize, Self::Float)> = Vec::new();
         let mut rng = Generator::from_seed(0);
         self.stochastic_probe(
             query, k, n_candidates, &mut rng, Self::Float::zero(),
-            &mut candidates, &mut seen, bail_threshold * 2.0,
+            candidates, seen, bail_threshold * 2.0,
             ProbeInit::FromRoot { path: &mut path },
         );
 
@@ -320,7 +348,7 @@ pub trait AnnQuery: SpatialTree {
             let mut margins: Vec<f64> = path.iter()
                 .map(|(_, _, _, m)| m.to_f64().unwrap())
                 .collect();
-            margins.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            margins.sort_by(|a, b| a.total_cmp(b));
             margins[margins.len() / 2]
         };
 
@@ -333,25 +361,10 @@ pub trait AnnQuery: SpatialTree {
                 let mut rng = Generator::from_seed(1 + i as u64);
                 self.stochastic_probe(
                     query, k, n_candidates, &mut rng, tau_i,
-                    &mut candidates, &mut seen, bail_threshold,
+                    candidates, seen, bail_threshold,
                     ProbeInit::FromPath { path: &path, diverge_depth },
                 );
             }
         }
-
-        let mut results: Vec<(usize, Self::Float)> = candidates.into_iter()
-            .map(|item| {
-                let dist = if Self::REDUCED {
-                    self.metric().post_transform(item.distance)
-                } else {
-                    item.distance
-                };
-                (item.index, dist)
-            })
-            .collect();
-
-        results.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-        results.truncate(k);
-        results
     }
 }
\ No newline at end of file
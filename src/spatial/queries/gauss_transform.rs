@@ -0,0 +1,310 @@
+use crate::array::{NdArray, Shape};
+use crate::error::{IronForestError, check_queries};
+use crate::spatial::common::{DistanceMetric, IronFloat, KernelType};
+use crate::spatial::trees::kd_tree::KDTree;
+use crate::spatial::SpatialTree;
+use rayon::prelude::*;
+
+const FGT_PAR_THRESHOLD: usize = 512;
+// Cramer's inequality, |H_n(t)| exp(-t^2 / 2) <= K 2^(n/2) sqrt(n!)
+const CRAMER: f64 = 1.086435;
+/// Largest number of Hermite coefficients kept per node, order^dim.
+pub const MAX_GAUSS_TERMS: usize = 1 << 16;
+
+/// Fast Gauss transform over a `KDTree`. Nodes holding at least order^dim points store
+/// the Hermite moments of their points about the box center, built from their children's
+/// moments where possible. Queries then evaluate each node as a midpoint of its kernel
+/// range, a truncated Hermite expansion, or directly, whichever is cheapest within the
+/// node's share of the error budget.
+struct GaussTransform<'a, T: IronFloat> {
+    tree: &'a KDTree<T>,
+    h: f64,
+    // sqrt(2) h, so the kernel is exp(-|y - x|^2 / scale^2)
+    scale: f64,
+    order: usize,
+    terms: usize,
+    centers: Vec<Vec<f64>>,
+    moments: Vec<Option<Vec<f64>>>,
+}
+
+impl<'a, T: IronFloat> GaussTransform<'a, T> {
+    fn new(tree: &'a KDTree<T>, h: f64, order: usize) -> Self {
+        let centers = tree.nodes.iter()
+            .map(|node| node.bbox_min.iter().zip(&node.bbox_max)
+                .map(|(lo, hi)| 0.5 * (lo.to_f64().unwrap() + hi.to_f64().unwrap()))
+                .collect())
+            .collect();
+        let mut fgt = GaussTransform {
+            tree,
+            h,
+            scale: std::f64::consts::SQRT_2 * h,
+            order,
+            terms: order.pow(tree.dim as u32),
+            centers,
+            moments: vec![None; tree.nodes.len()],
+        };
+        if tree.n_points > 0 {
+            fgt.build_moments(0);
+        }
+        fgt
+    }
+
+    fn count(&self, node_idx: usize) -> usize {
+        self.tree.nodes[node_idx].end - self.tree.nodes[node_idx].start
+    }
+
+    /// Fills moments bottom-up and reports whether `node_idx` has them.
+    fn build_moments(&mut self, node_idx: usize) -> bool {
+        let node = &self.tree.nodes[node_idx];
+        let children = match (node.left, node.right) {
+            (Some(l), Some(r)) => {
+                let both = self.build_moments(l) & self.build_moments(r);
+                both.then_some((l, r))
+            }
+            _ => None,
+        };
+        if self.count(node_idx) < self.terms {
+            return false;
+        }
+
+        let moments = match children {
+            Some((l, r)) => {
+                let mut moments = self.shifted_moments(l, node_idx);
+                for (m, c) in moments.iter_mut().zip(self.shifted_moments(r, node_idx)) {
+                    *m += c;
+                }
+                moments
+            }
+            None => self.direct_moments(node_idx),
+        };
+        self.moments[node_idx] = Some(moments);
+        true
+    }
+
+    /// A_alpha = sum_i prod_j s_ij^alpha_j / alpha_j!, with s = (x - c) / scale. Flat
+    /// indices store alpha_j at stride order^j.
+    fn direct_moments(&self, node_idx: usize) -> Vec<f64> {
+        let (dim, p) = (self.tree.dim, self.order);
+        let center = &self.centers[node_idx];
+        let mut moments = vec![0.0; self.terms];
+        let mut powers = vec![0.0; dim * p];
+        let mut product = Vec::with_capacity(self.terms);
+
+        for i in self.tree.nodes[node_idx].start..self.tree.nodes[node_idx].end {
+            let point = self.tree.get_point(i);
+            for j in 0..dim {
+                let s = (point[j].to_f64().unwrap() - center[j]) / self.scale;
+                powers[j * p] = 1.0;
+                for n in 1..p {
+                    powers[j * p + n] = powers[j * p + n - 1] * s / n as f64;
+                }
+            }
+            product.clear();
+            product.push(1.0);
+            for j in 0..dim {
+                let len = product.len();
+                for n in 1..p {
+                    for k in 0..len {
+                        let v = product[k] * powers[j * p + n];
+                        product.push(v);
+                    }
+                }
+            }
+            for (m, v) in moments.iter_mut().zip(&product) {
+                *m += v;
+            }
+        }
+        moments
+    }
+
+    /// Translates a child's moments to its parent's center. The binomial expansion of
+    /// (x - c) = (x - c_child) + (c_child - c) separates over dimensions.
+    fn shifted_moments(&self, child: usize, parent: usize) -> Vec<f64> {
+        let (dim, p) = (self.tree.dim, self.order);
+        let mut moments = self.moments[child].clone().unwrap();
+        let mut stride = 1;
+        for j in 0..dim {
+            let u = (self.centers[child][j] - self.centers[parent][j]) / self.scale;
+            let mut coeffs = vec![1.0; p];
+            for n in 1..p {
+                coeffs[n] = coeffs[n - 1] * u / n as f64;
+            }
+            let source = moments.clone();
+            for (f, m) in moments.iter_mut().enumerate() {
+                let alpha = (f / stride) % p;
+                *m = (0..=alpha).map(|k| source[f - (alpha - k) * stride] * coeffs[alpha - k]).sum();
+            }
+            stride *= p;
+        }
+        moments
+    }
+
+    /// Smallest truncation order whose error bound fits `budget`. Each coordinate of the
+    /// node lies within w_j of the center, and with r_j = w_j / h the per-dimension series
+    /// is dominated by r^n / sqrt(n!) via Cramer's inequality.
+    fn hermite_order(&self, node_idx: usize, query: &[f64], n: f64, budget: f64) -> Option<usize> {
+        let node = &self.tree.nodes[node_idx];
+        let center = &self.centers[node_idx];
+        let dim = self.tree.dim;
+        let t2: f64 = query.iter().zip(center).map(|(y, c)| ((y - c) / self.scale).powi(2)).sum();
+        let prefactor = n * CRAMER.powi(dim as i32) * (-0.5 * t2).exp();
+        let radii: Vec<f64> = node.bbox_min.iter().zip(&node.bbox_max)
+            .map(|(lo, hi)| 0.5 * (hi.to_f64().unwrap() - lo.to_f64().unwrap()) / self.h)
+            .collect();
+
+        let mut heads = vec![0.0; dim];
+        let mut terms = vec![1.0; dim];
+        for p in 1..=self.order {
+            let mut with_tail = 1.0;
+            let mut without = 1.0;
+            for j in 0..dim {
+                heads[j] += terms[j];
+                terms[j] *= radii[j] / (p as f64).sqrt();
+                let ratio = radii[j] / ((p + 1) as f64).sqrt();
+                let tail = if ratio < 1.0 { terms[j] / (1.0 - ratio) } else { f64::INFINITY };
+                with_tail *= heads[j] + tail;
+                without *= heads[j];
+            }
+            if prefactor * (with_tail - without) <= budget {
+                return Some(p);
+            }
+        }
+        None
+    }
+
+    /// sum_alpha A_alpha h_alpha(t) over alpha_j < p, with Hermite functions
+    /// h_n(t) = H_n(t) exp(-t^2).
+    fn hermite_eval(&self, node_idx: usize, query: &[f64], p: usize) -> f64 {
+        let dim = self.tree.dim;
+        let moments = self.moments[node_idx].as_ref().unwrap();
+        let center = &self.centers[node_idx];
+        let mut hermite = vec![0.0; dim * p];
+        for j in 0..dim {
+            let t = (query[j] - center[j]) / self.scale;
+            hermite[j * p] = (-t * t).exp();
+            if p > 1 {
+                hermite[j * p + 1] = 2.0 * t * hermite[j * p];
+            }
+            for n in 1..p.saturating_sub(1) {
+                hermite[j * p + n + 1] = 2.0 * t * hermite[j * p + n] - 2.0 * n as f64 * hermite[j * p + n - 1];
+            }
+        }
+
+        let mut digits = vec![0usize; dim];
+        let mut total = 0.0;
+        loop {
+            let mut flat = 0;
+            let mut stride = 1;
+            let mut value = 1.0;
+            for j in 0..dim {
+                flat += digits[j] * stride;
+                stride *= self.order;
+                value *= hermite[j * p + digits[j]];
+            }
+            total += moments[flat] * value;
+
+            let mut j = 0;
+            while j < dim && digits[j] + 1 == p {
+                digits[j] = 0;
+                j += 1;
+            }
+            if j == dim {
+                return total;
+            }
+            digits[j] += 1;
+        }
+    }
+
+    fn evaluate(&self, node_idx: usize, query: &[f64], atol_per_point: f64, acc: &mut f64) {
+        let node = &self.tree.nodes[node_idx];
+        let count = self.count(node_idx);
+        let n = count as f64;
+        let budget = atol_per_point * n;
+
+        let (mut near, mut far) = (0.0, 0.0);
+        for (j, &y) in query.iter().enumerate() {
+            let (lo, hi) = (node.bbox_min[j].to_f64().unwrap(), node.bbox_max[j].to_f64().unwrap());
+            near += (lo - y).max(y - hi).max(0.0).powi(2);
+            far += (y - lo).abs().max((y - hi).abs()).powi(2);
+        }
+        let k_hi = (-near / (self.scale * self.scale)).exp();
+        let k_lo = (-far / (self.scale * self.scale)).exp();
+        if 0.5 * n * (k_hi - k_lo) <= budget {
+            *acc += 0.5 * n * (k_hi + k_lo);
+            return;
+        }
+
+        let order = self.moments[node_idx].as_ref().and_then(|_| self.hermite_order(node_idx, query, n, budget));
+        if let Some(p) = order.filter(|p| p.pow(self.tree.dim as u32) < count) {
+            *acc += self.hermite_eval(node_idx, query, p);
+            return;
+        }
+
+        match (node.left, node.right) {
+            (Some(left), Some(right)) => {
+                self.evaluate(left, query, atol_per_point, acc);
+                self.evaluate(right, query, atol_per_point, acc);
+            }
+            _ => {
+                for i in node.start..node.end {
+                    let d2: f64 = self.tree.get_point(i).iter().zip(query)
+                        .map(|(x, y)| (x.to_f64().unwrap() - y).powi(2))
+                        .sum();
+                    *acc += (-d2 / (self.scale * self.scale)).exp();
+                }
+            }
+        }
+    }
+}
+
+impl<T: IronFloat> KDTree<T> {
+    /// Gaussian kernel density through a fast Gauss transform, for many queries in low
+    /// dimensions. Each query's kernel sum is within `atol` of the exact one, split
+    /// across nodes in proportion to their point count. Hermite expansions are truncated
+    /// at up to `order` terms per dimension, so memory grows as order^dim per large node.
+    pub fn gauss_transform(&self, queries: &NdArray<T>, bandwidth: f64, atol: f64, order: usize, normalize: bool) -> Result<NdArray<f64>, IronForestError> {
+        if !matches!(self.metric, DistanceMetric::Euclidean) {
+            return Err(IronForestError::invalid("The fast Gauss transform requires the euclidean metric"));
+        }
+        if bandwidth.is_nan() || bandwidth <= 0.0 {
+            return Err(IronForestError::invalid("Bandwidth must be positive"));
+        }
+        if atol.is_nan() || atol < 0.0 {
+            return Err(IronForestError::invalid("atol must be non-negative"));
+        }
+        if order < 1 {
+            return Err(IronForestError::invalid("Order must be at least 1"));
+        }
+        if order.checked_pow(self.dim as u32).is_none_or(|t| t > MAX_GAUSS_TERMS) {
+            return Err(IronForestError::invalid("order^dim exceeds the Hermite term limit"));
+        }
+        let n_queries = check_queries(queries.shape().dims(), self.dim)?;
+        let dim = self.dim;
+
+        let fgt = GaussTransform::new(self, bandwidth, order);
+        let queries_cow = queries.as_contiguous_slice();
+        let queries_slice: &[T] = &queries_cow;
+        let atol_per_point = atol / self.n_points.max(1) as f64;
+        let density_at = |i: usize| {
+            let query: Vec<f64> = queries_slice[i * dim..(i + 1) * dim].iter().map(|x| x.to_f64().unwrap()).collect();
+            let mut acc = 0.0;
+            if self.n_points > 0 {
+                fgt.evaluate(0, &query, atol_per_point, &mut acc);
+            }
+            acc
+        };
+        let mut results: Vec<f64> = if n_queries >= FGT_PAR_THRESHOLD {
+            (0..n_queries).into_par_iter().map(density_at).collect()
+        } else {
+            (0..n_queries).map(density_at).collect()
+        };
+
+        if normalize {
+            let norm = bandwidth.powi(dim as i32) * KernelType::Gaussian.normalization_constant(dim);
+            for val in &mut results {
+                *val /= norm;
+            }
+        }
+        Ok(NdArray::from_vec(Shape::new(vec![n_queries]), results))
+    }
+}
@@ -1,42 +1,62 @@
-use crate::{array::{NdArray, Shape}, spatial::common::KernelType};
+use crate::{array::{NdArray, Shape}, random::Generator, spatial::common::{DistanceMetric, KernelType, LogSumExp}};
 use rayon::prelude::*;
 use crate::spatial::SpatialTree;
+use crate::error::{IronForestError, check_queries};
 use num_traits::ToPrimitive;
 
 const KDE_PAR_THRESHOLD: usize = 512;
+// Relative pruning threshold for log-space accumulation, ln(1e-10)
+pub(crate) const LOG_KDE_RTOL: f64 = -23.025850929940457;
 
 pub trait KdeQuery: SpatialTree {
-    fn kernel_density(&self, queries: &NdArray<Self::Float>, bandwidth: f64, kernel: KernelType, normalize: bool) -> NdArray<f64> {
-        let shape = queries.shape().dims();
-        assert!(shape.len() == 2, "Expected 2D array (n_queries, dim)");
-        let n_queries = shape[0];
-        let dim = shape[1];
-        assert_eq!(dim, self.dim(), "Query dimension must match tree dimension");
+    fn kernel_density(&self, queries: &NdArray<Self::Float>, bandwidth: f64, kernel: KernelType, normalize: bool, log_density: bool) -> Result<NdArray<f64>, IronForestError> {
+        let n_queries = check_queries(queries.shape().dims(), self.dim())?;
+        let dim = self.dim();
+        if normalize {
+            kernel.check_normalizable(dim)?;
+        }
 
         let queries_cow = queries.as_contiguous_slice();
         let queries_slice: &[Self::Float] = &queries_cow;
-        let mut results = if n_queries >= KDE_PAR_THRESHOLD {
-            self.par_kde_recursion(kernel, bandwidth, queries_slice, n_queries, dim)
-        } else {
-            self.seq_kde_recursion(kernel, bandwidth, queries_slice, n_queries, dim)
+        let mut results = match (log_density, n_queries >= KDE_PAR_THRESHOLD) {
+            (false, true) => self.par_kde_recursion(kernel, bandwidth, queries_slice, n_queries, dim),
+            (false, false) => self.seq_kde_recursion(kernel, bandwidth, queries_slice, n_queries, dim),
+            (true, true) => self.par_log_kde_recursion(kernel, bandwidth, queries_slice, n_queries, dim),
+            (true, false) => self.seq_log_kde_recursion(kernel, bandwidth, queries_slice, n_queries, dim),
         };
 
         if normalize {
-            let h_d = bandwidth.powi(dim as i32);
-            let c_k = kernel.normalization_constant(dim);
-            let norm = h_d * c_k;
-            for val in &mut results {
-                *val /= norm;
+            if log_density {
+                let log_norm = dim as f64 * bandwidth.ln() + kernel.log_normalization_constant(dim);
+                for val in &mut results {
+                    *val -= log_norm;
+                }
+            } else {
+                let h_d = bandwidth.powi(dim as i32);
+                let c_k = kernel.normalization_constant(dim);
+                let norm = h_d * c_k;
+                for val in &mut results {
+                    *val /= norm;
+                }
             }
         }
-        NdArray::from_vec(Shape::new(vec![n_queries]), results)
+        Ok(NdArray::from_vec(Shape::new(vec![n_queries]), results))
+    }
+
+    /// True distance from a raw traversal value, which is reduced when `Self::REDUCED`.
+    #[inline]
+    fn kde_distance(&self, raw: Self::Float) -> f64 {
+        match Self::REDUCED {
+            true => self.metric().post_transform(raw).to_f64().unwrap(),
+            false => raw.to_f64().unwrap(),
+        }
     }
 
     fn kde_recursive(&self, node_idx: usize, query: &[Self::Float], h: f64, density: &mut f64, kernel: KernelType) {
         if self.is_leaf(node_idx) {
             for i in self.node_start(node_idx)..self.node_end(node_idx) {
                 let dist: f64 = match Self::REDUCED {
-                    true => self.metric().reduced_distance(query, self.get_point(i)).to_f64().unwrap(),
+                    true => self.kde_distance(self.metric().reduced_distance(query, self.get_point(i))),
                     false => self.metric().distance(query, self.get_point(i)).to_f64().unwrap(),
                 };
                 *density += kernel.evaluate(dist, h);
@@ -47,16 +67,162 @@ pub trait KdeQuery: SpatialTree {
         let plan = self.plan_traversal(node_idx, query);
 
         let first_n = (self.node_end(plan.first.child_idx) - self.node_start(plan.first.child_idx)) as f64;
-        if kernel.evaluate(plan.first.lower_bound.to_f64().unwrap(), h) * first_n >= 1e-10 {
+        if kernel.evaluate(self.kde_distance(plan.first.lower_bound), h) * first_n >= 1e-10 {
             self.kde_recursive(plan.first.child_idx, query, h, density, kernel);
         }
 
         let second_n = (self.node_end(plan.second.child_idx) - self.node_start(plan.second.child_idx)) as f64;
-        if kernel.evaluate(plan.second.lower_bound.to_f64().unwrap(), h) * second_n >= 1e-10 {
+        if kernel.evaluate(self.kde_distance(plan.second.lower_bound), h) * second_n >= 1e-10 {
             self.kde_recursive(plan.second.child_idx, query, h, density, kernel);
         }
     }
 
+    /// Log-space counterpart of `kde_recursive`. Children are pruned relative to the
+    /// mass accumulated so far rather than by an absolute threshold.
+    fn log_kde_recursive(&self, node_idx: usize, query: &[Self::Float], h: f64, acc: &mut LogSumExp, kernel: KernelType) {
+        if self.is_leaf(node_idx) {
+            for i in self.node_start(node_idx)..self.node_end(node_idx) {
+                let dist: f64 = match Self::REDUCED {
+                    true => self.kde_distance(self.metric().reduced_distance(query, self.get_point(i))),
+                    false => self.metric().distance(query, self.get_point(i)).to_f64().unwrap(),
+                };
+                acc.add(kernel.log_evaluate(dist, h));
+            }
+            return;
+        }
+
+        let plan = self.plan_traversal(node_idx, query);
+
+        for child in [plan.first, plan.second] {
+            let n = (self.node_end(child.child_idx) - self.node_start(child.child_idx)) as f64;
+            let log_bound = n.ln() + kernel.log_evaluate(self.kde_distance(child.lower_bound), h);
+            if log_bound == f64::NEG_INFINITY || log_bound < acc.value() + LOG_KDE_RTOL {
+                continue;
+            }
+            self.log_kde_recursive(child.child_idx, query, h, acc, kernel);
+        }
+    }
+
+    /// Conditional density p(y | x) from per-point responses, shape (n_queries, n_y).
+    /// Neighbours are weighted by the kernel in x and the response kernel is evaluated in y.
+    /// Rows are NaN where no indexed point carries weight.
+    fn conditional_density(
+        &self,
+        queries: &NdArray<Self::Float>,
+        responses: &[f64],
+        y: &[f64],
+        bandwidth: f64,
+        response_bandwidth: f64,
+        kernel: KernelType,
+    ) -> Result<NdArray<f64>, IronForestError> {
+        let n_queries = check_queries(queries.shape().dims(), self.dim())?;
+        let dim = self.dim();
+        if responses.len() != self.n_points() {
+            return Err(IronForestError::invalid("Expected one response per indexed point"));
+        }
+
+        let y_norm = response_bandwidth * kernel.normalization_constant(1);
+        let queries_cow = queries.as_contiguous_slice();
+        let queries_slice: &[Self::Float] = &queries_cow;
+        let density_at = |i: usize| {
+            let query = &queries_slice[i * dim..(i + 1) * dim];
+            let mut neighbours = Vec::new();
+            self.kde_weights_recursive(self.root(), query, bandwidth, kernel, &mut neighbours);
+            let total: f64 = neighbours.iter().map(|&(_, w)| w).sum();
+            y.iter().map(|&target| {
+                if total <= 0.0 {
+                    return f64::NAN;
+                }
+                let mass: f64 = neighbours.iter()
+                    .map(|&(idx, w)| w * kernel.evaluate((target - responses[idx]).abs(), response_bandwidth))
+                    .sum();
+                mass / (total * y_norm)
+            }).collect::<Vec<f64>>()
+        };
+        let rows: Vec<Vec<f64>> = if n_queries >= KDE_PAR_THRESHOLD {
+            (0..n_queries).into_par_iter().map(density_at).collect()
+        } else {
+            (0..n_queries).map(density_at).collect()
+        };
+
+        Ok(NdArray::from_vec(Shape::new(vec![n_queries, y.len()]), rows.into_iter().flatten().collect()))
+    }
+
+    /// Draws `n_samples` points from the kernel density estimate, shape (n_samples, dim).
+    /// Reference points are picked uniformly, or proportional to `weights` (one per point,
+    /// original order), and perturbed with kernel noise.
+    fn sample(&self, n_samples: usize, bandwidth: f64, kernel: KernelType, weights: Option<&[f64]>, rng: &mut Generator) -> Result<NdArray<f64>, IronForestError> {
+        if !matches!(self.metric(), DistanceMetric::Euclidean) {
+            return Err(IronForestError::invalid("KDE sampling requires the euclidean metric"));
+        }
+        let n = self.n_points();
+        let dim = self.dim();
+        if n == 0 {
+            return Err(IronForestError::EmptyIndex);
+        }
+
+        let cumulative: Option<Vec<f64>> = match weights {
+            Some(w) => {
+                if w.len() != n {
+                    return Err(IronForestError::invalid("Expected one weight per indexed point"));
+                }
+                if w.iter().any(|&x| !(x >= 0.0 && x.is_finite())) {
+                    return Err(IronForestError::invalid("Weights must be finite and non-negative"));
+                }
+                let mut total = 0.0;
+                let cumulative: Vec<f64> = self.indices().iter().map(|&orig| {
+                    total += w[orig];
+                    total
+                }).collect();
+                if total <= 0.0 {
+                    return Err(IronForestError::invalid("Weights must not all be zero"));
+                }
+                Some(cumulative)
+            }
+            None => None,
+        };
+
+        let mut samples = Vec::with_capacity(n_samples * dim);
+        for _ in 0..n_samples {
+            let slot = match &cumulative {
+                Some(c) => {
+                    let target = rng.next_f64() * c[n - 1];
+                    c.partition_point(|&v| v <= target).min(n - 1)
+                }
+                None => rng.usize_below(n),
+            };
+            let offset = kernel.sample_offset(dim, bandwidth, rng)?;
+            samples.extend(self.get_point(slot).iter().zip(&offset).map(|(x, o)| x.to_f64().unwrap() + o));
+        }
+        Ok(NdArray::from_vec(Shape::new(vec![n_samples, dim]), samples))
+    }
+
+    /// Collects (original index, kernel weight) for every point that survives pruning.
+    fn kde_weights_recursive(&self, node_idx: usize, query: &[Self::Float], h: f64, kernel: KernelType, out: &mut Vec<(usize, f64)>) {
+        if self.is_leaf(node_idx) {
+            for i in self.node_start(node_idx)..self.node_end(node_idx) {
+                let dist: f64 = match Self::REDUCED {
+                    true => self.kde_distance(self.metric().reduced_distance(query, self.get_point(i))),
+                    false => self.metric().distance(query, self.get_point(i)).to_f64().unwrap(),
+                };
+                let w = kernel.evaluate(dist, h);
+                if w > 0.0 {
+                    out.push((self.indices()[i], w));
+                }
+            }
+            return;
+        }
+
+        let plan = self.plan_traversal(node_idx, query);
+        for child in [plan.first, plan.second] {
+            let n = (self.node_end(child.child_idx) - self.node_start(child.child_idx)) as f64;
+            if kernel.evaluate(self.kde_distance(child.lower_bound), h) * n < 1e-10 {
+                continue;
+            }
+            self.kde_weights_recursive(child.child_idx, query, h, kernel, out);
+        }
+    }
+
     fn seq_kde_recursion(&self, kernel: KernelType, bandwidth: f64, queries: &[Self::Float], n_queries: usize, dim: usize) -> Vec<f64> {
         let mut results = vec![0.0; n_queries];
         for i in 0..n_queries {
@@ -77,4 +243,27 @@ pub trait KdeQuery: SpatialTree {
             })
             .collect()
     }
+
+    fn seq_log_kde_recursion(&self, kernel: KernelType, bandwidth: f64, queries: &[Self::Float], n_queries: usize, dim: usize) -> Vec<f64> {
+        (0..n_queries)
+            .map(|i| {
+                let query = &queries[i * dim..(i + 1) * dim];
+                let mut acc = LogSumExp::new();
+                self.log_kde_recursive(self.root(), query, bandwidth, &mut acc, kernel);
+                acc.value()
+            })
+            .collect()
+    }
+
+    fn par_log_kde_recursion(&self, kernel: KernelType, bandwidth: f64, queries: &[Self::Float], n_queries: usize, dim: usize) -> Vec<f64> {
+        (0..n_queries)
+            .into_par_iter()
+            .map(|i| {
+                let query = &queries[i * dim..(i + 1) * dim];
+                let mut acc = LogSumExp::new();
+                self.log_kde_recursive(self.root(), query, bandwidth, &mut acc, kernel);
+                acc.value()
+            })
+            .collect()
+    }
 }
@@ -2,13 +2,21 @@ use std::collections::BinaryHeap;
 use crate::{array::NdArray, spatial::HeapItem};
 use rayon::prelude::*;
 use crate::spatial::SpatialTree;
+use crate::spatial::queries::BatchNeighbors;
+use crate::error::{IronForestError, check_k, check_queries, check_query};
 use num_traits::float::Float as _;
 
 const KNN_PAR_THRESHOLD: usize = 512;
 
 
 pub trait KnnQuery: SpatialTree {
-    fn query_knn(&self, query: &[Self::Float], k: usize) -> Vec<(usize, Self::Float)> {
+    fn query_knn(&self, query: &[Self::Float], k: usize) -> Result<Vec<(usize, Self::Float)>, IronForestError> {
+        check_query(query.len(), self.dim())?;
+        self.check_raw_points()?;
+        Ok(self.knn_search(query, k))
+    }
+
+    fn knn_search(&self, query: &[Self::Float], k: usize) -> Vec<(usize, Self::Float)> {
         if k == 0 || self.n_points() == 0 {
             return Vec::new();
         }
@@ -64,27 +72,26 @@ pub trait KnnQuery: SpatialTree {
         }
     }
 
-    fn query_knn_batch(&self, queries: &NdArray<Self::Float>, k: usize) -> Vec<Vec<(usize, Self::Float)>> {
-        let shape = queries.shape().dims();
-        assert!(shape.len() == 2, "Expected 2D array (n_queries, dim)");
-        let n_queries = shape[0];
-        let dim = shape[1];
-        assert_eq!(dim, self.dim(), "Query dimension must match tree dimension");
+    fn query_knn_batch(&self, queries: &NdArray<Self::Float>, k: usize) -> Result<BatchNeighbors<Self::Float>, IronForestError> {
+        check_k(k)?;
+        let n_queries = check_queries(queries.shape().dims(), self.dim())?;
+        self.check_raw_points()?;
+        let dim = self.dim();
 
         let queries_cow = queries.as_contiguous_slice();
         let queries_slice: &[Self::Float] = &queries_cow;
-        if n_queries >= KNN_PAR_THRESHOLD {
+        Ok(if n_queries >= KNN_PAR_THRESHOLD {
             self.par_knn_batch(queries_slice, n_queries, dim, k)
         } else {
             self.seq_knn_batch(queries_slice, n_queries, dim, k)
-        }
+        })
     }
 
     fn seq_knn_batch(&self, queries: &[Self::Float], n_queries: usize, dim: usize, k: usize) -> Vec<Vec<(usize, Self::Float)>> {
         (0..n_queries)
             .map(|i| {
                 let query = &queries[i * dim..(i + 1) * dim];
-                self.query_knn(query, k)
+                self.knn_search(query, k)
             })
             .collect()
     }
@@ -94,7 +101,7 @@ pub trait KnnQuery: SpatialTree {
             .into_par_iter()
             .map(|i| {
                 let query = &queries[i * dim..(i + 1) * dim];
-                self.query_knn(query, k)
+                self.knn_search(query, k)
             })
             .collect()
     }
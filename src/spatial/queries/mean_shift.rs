@@ -0,0 +1,199 @@
+use crate::{array::{NdArray, Shape}, spatial::common::{DistanceMetric, KernelType}};
+use rayon::prelude::*;
+use crate::spatial::queries::KdeQuery;
+use crate::error::{IronForestError, check_queries};
+use num_traits::{NumCast, ToPrimitive};
+
+const SHIFT_PAR_THRESHOLD: usize = 512;
+
+/// Which weight a neighbour receives in a weighted sum.
+#[derive(Clone, Copy)]
+pub enum ShiftWeight {
+    /// K(r), the mean-shift update over the kernel's shadow.
+    Kernel,
+    /// -K'(r) / r, so that sum(w * (x_i - q)) is the density gradient.
+    Gradient,
+}
+
+pub trait MeanShiftQuery: KdeQuery {
+    /// Gradient of the kernel density estimate at each query, shape (n_queries, dim).
+    /// The uniform kernel has zero gradient almost everywhere.
+    fn kde_gradient(&self, queries: &NdArray<Self::Float>, bandwidth: f64, kernel: KernelType, normalize: bool) -> Result<NdArray<f64>, IronForestError> {
+        let n_queries = check_queries(queries.shape().dims(), self.dim())?;
+        let dim = self.dim();
+        if !matches!(self.metric(), DistanceMetric::Euclidean) {
+            return Err(IronForestError::invalid("KDE gradients require the euclidean metric"));
+        }
+        if normalize {
+            kernel.check_normalizable(dim)?;
+        }
+
+        let queries_cow = queries.as_contiguous_slice();
+        let queries_slice: &[Self::Float] = &queries_cow;
+        let gradient_at = |i: usize| {
+            let query = &queries_slice[i * dim..(i + 1) * dim];
+            let (total, weighted) = self.weighted_sum(query, bandwidth, kernel, ShiftWeight::Gradient);
+            weighted.iter().zip(query)
+                .map(|(wx, q)| wx - total * q.to_f64().unwrap())
+                .collect::<Vec<f64>>()
+        };
+        let rows: Vec<Vec<f64>> = if n_queries >= SHIFT_PAR_THRESHOLD {
+            (0..n_queries).into_par_iter().map(gradient_at).collect()
+        } else {
+            (0..n_queries).map(gradient_at).collect()
+        };
+
+        let mut results: Vec<f64> = rows.into_iter().flatten().collect();
+        if normalize {
+            let norm = bandwidth.powi(dim as i32) * kernel.normalization_constant(dim);
+            for val in &mut results {
+                *val /= norm;
+            }
+        }
+        Ok(NdArray::from_vec(Shape::new(vec![n_queries, dim]), results))
+    }
+
+    /// Iterates each seed to a local mode with mean-shift updates. Shape (n_seeds, dim).
+    /// Weights are the kernel itself, so the ascent is exact on the density of the
+    /// kernel's shadow (Gaussian for Gaussian, uniform for Epanechnikov).
+    fn mean_shift(&self, seeds: &NdArray<Self::Float>, bandwidth: f64, kernel: KernelType, max_iter: usize, tol: f64) -> Result<NdArray<f64>, IronForestError> {
+        let n_seeds = check_queries(seeds.shape().dims(), self.dim())?;
+        let dim = self.dim();
+        if !matches!(self.metric(), DistanceMetric::Euclidean) {
+            return Err(IronForestError::invalid("Mean shift requires the euclidean metric"));
+        }
+
+        let seeds_cow = seeds.as_contiguous_slice();
+        let seeds_slice: &[Self::Float] = &seeds_cow;
+        let shift = |i: usize| self.shift_to_mode(&seeds_slice[i * dim..(i + 1) * dim], bandwidth, kernel, max_iter, tol);
+        let modes: Vec<Vec<f64>> = if n_seeds >= SHIFT_PAR_THRESHOLD {
+            (0..n_seeds).into_par_iter().map(shift).collect()
+        } else {
+            (0..n_seeds).map(shift).collect()
+        };
+
+        Ok(NdArray::from_vec(Shape::new(vec![n_seeds, dim]), modes.into_iter().flatten().collect()))
+    }
+
+    /// Runs mean shift from every indexed point and merges modes closer than
+    /// `merge_radius`. Returns cluster centers, highest density first, and a label
+    /// per point in original index order.
+    fn mean_shift_cluster(&self, bandwidth: f64, kernel: KernelType, max_iter: usize, tol: f64, merge_radius: f64) -> Result<(NdArray<f64>, Vec<usize>), IronForestError> {
+        let n = self.n_points();
+        let dim = self.dim();
+        let mut seeds = Vec::with_capacity(n * dim);
+        for i in 0..n {
+            seeds.extend_from_slice(self.get_point(i));
+        }
+        let seeds = NdArray::from_vec(Shape::new(vec![n, dim]), seeds);
+        let modes = self.mean_shift(&seeds, bandwidth, kernel, max_iter, tol)?;
+        let modes_slice = modes.as_slice_unchecked();
+
+        let mode_queries: Vec<Self::Float> = modes_slice.iter()
+            .map(|&v| <Self::Float as NumCast>::from(v).unwrap())
+            .collect();
+        let mode_queries = NdArray::from_vec(Shape::new(vec![n, dim]), mode_queries);
+        let density = self.kernel_density(&mode_queries, bandwidth, kernel, false, true)?;
+        let density = density.as_slice_unchecked();
+
+        let mut order: Vec<usize> = (0..n).collect();
+        order.sort_by(|&a, &b| density[b].total_cmp(&density[a]));
+
+        let merge_sq = merge_radius * merge_radius;
+        let mut centers: Vec<f64> = Vec::new();
+        let mut slot_labels = vec![0usize; n];
+        for &slot in &order {
+            let mode = &modes_slice[slot * dim..(slot + 1) * dim];
+            let existing = centers.chunks_exact(dim).position(|c| {
+                c.iter().zip(mode).map(|(a, b)| (a - b) * (a - b)).sum::<f64>() <= merge_sq
+            });
+            slot_labels[slot] = match existing {
+                Some(label) => label,
+                None => {
+                    centers.extend_from_slice(mode);
+                    centers.len() / dim - 1
+                }
+            };
+        }
+
+        let mut labels = vec![0usize; n];
+        for (slot, &label) in slot_labels.iter().enumerate() {
+            labels[self.indices()[slot]] = label;
+        }
+        let n_clusters = centers.len() / dim;
+        Ok((NdArray::from_vec(Shape::new(vec![n_clusters, dim]), centers), labels))
+    }
+
+    fn shift_to_mode(&self, seed: &[Self::Float], bandwidth: f64, kernel: KernelType, max_iter: usize, tol: f64) -> Vec<f64> {
+        let mut current: Vec<f64> = seed.iter().map(|v| v.to_f64().unwrap()).collect();
+        let mut query: Vec<Self::Float> = seed.to_vec();
+        let tol_sq = (tol * bandwidth) * (tol * bandwidth);
+
+        for _ in 0..max_iter {
+            let (total, weighted) = self.weighted_sum(&query, bandwidth, kernel, ShiftWeight::Kernel);
+            if total <= 0.0 {
+                break;
+            }
+            let mut step_sq = 0.0;
+            for d in 0..current.len() {
+                let next = weighted[d] / total;
+                step_sq += (next - current[d]) * (next - current[d]);
+                current[d] = next;
+                query[d] = <Self::Float as NumCast>::from(next).unwrap();
+            }
+            if step_sq <= tol_sq {
+                break;
+            }
+        }
+        current
+    }
+
+    /// Returns (sum w_i, sum w_i * x_i) over the indexed points.
+    fn weighted_sum(&self, query: &[Self::Float], h: f64, kernel: KernelType, weight: ShiftWeight) -> (f64, Vec<f64>) {
+        let mut acc = (0.0, vec![0.0; self.dim()]);
+        self.weighted_sum_recursive(self.root(), query, h, kernel, weight, &mut acc);
+        acc
+    }
+
+    fn weighted_sum_recursive(
+        &self,
+        node_idx: usize,
+        query: &[Self::Float],
+        h: f64,
+        kernel: KernelType,
+        weight: ShiftWeight,
+        acc: &mut (f64, Vec<f64>),
+    ) {
+        if self.is_leaf(node_idx) {
+            for i in self.node_start(node_idx)..self.node_end(node_idx) {
+                let point = self.get_point(i);
+                let dist: f64 = match Self::REDUCED {
+                    true => self.kde_distance(self.metric().reduced_distance(query, point)),
+                    false => self.metric().distance(query, point).to_f64().unwrap(),
+                };
+                let w = match weight {
+                    ShiftWeight::Kernel => kernel.evaluate(dist, h),
+                    ShiftWeight::Gradient if dist > 0.0 => -kernel.first_derivative(dist, h) / dist,
+                    ShiftWeight::Gradient => 0.0,
+                };
+                if w == 0.0 {
+                    continue;
+                }
+                acc.0 += w;
+                for (sum, x) in acc.1.iter_mut().zip(point) {
+                    *sum += w * x.to_f64().unwrap();
+                }
+            }
+            return;
+        }
+
+        let plan = self.plan_traversal(node_idx, query);
+        for child in [plan.first, plan.second] {
+            let n = (self.node_end(child.child_idx) - self.node_start(child.child_idx)) as f64;
+            if kernel.evaluate(self.kde_distance(child.lower_bound), h) * n < 1e-10 {
+                continue;
+            }
+            self.weighted_sum_recursive(child.child_idx, query, h, kernel, weight, acc);
+        }
+    }
+}
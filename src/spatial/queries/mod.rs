@@ -2,9 +2,15 @@ pub(crate) mod knn;
 pub(crate) mod radius;
 pub(crate) mod kde;
 pub(crate) mod ann;
+pub(crate) mod mean_shift;
+pub(crate) mod gauss_transform;
 
 pub use knn::KnnQuery;
 pub use radius::RadiusQuery;
 pub use kde::KdeQuery;
 pub use ann::AnnQuery;
+pub use mean_shift::MeanShiftQuery;
+
+/// Per-query (original index, distance) pairs returned by batch queries.
+pub type BatchNeighbors<F> = Vec<Vec<(usize, F)>>;
 
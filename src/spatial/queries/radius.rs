@@ -1,11 +1,19 @@
 use crate::array::NdArray;
 use rayon::prelude::*;
 use crate::spatial::SpatialTree;
+use crate::spatial::queries::BatchNeighbors;
+use crate::error::{IronForestError, check_queries, check_query};
 
 const RAD_PAR_THRESHOLD: usize = 512;
 
 pub trait RadiusQuery: SpatialTree {
-    fn query_radius(&self, query: &[Self::Float], radius: Self::Float) -> Vec<(usize, Self::Float)> {
+    fn query_radius(&self, query: &[Self::Float], radius: Self::Float) -> Result<Vec<(usize, Self::Float)>, IronForestError> {
+        check_query(query.len(), self.dim())?;
+        self.check_raw_points()?;
+        Ok(self.radius_search(query, radius))
+    }
+
+    fn radius_search(&self, query: &[Self::Float], radius: Self::Float) -> Vec<(usize, Self::Float)> {
         let mut results = Vec::new();
         let rad = match Self::REDUCED {
             true => self.metric().to_reduced(radius),
@@ -46,27 +54,25 @@ pub trait RadiusQuery: SpatialTree {
         }
     }
 
-    fn query_radius_batch(&self, queries: &NdArray<Self::Float>, radius: Self::Float) -> Vec<Vec<(usize, Self::Float)>> {
-        let shape = queries.shape().dims();
-        assert!(shape.len() == 2, "Expected 2D array (n_queries, dim)");
-        let n_queries = shape[0];
-        let dim = shape[1];
-        assert_eq!(dim, self.dim(), "Query dimension must match tree dimension");
+    fn query_radius_batch(&self, queries: &NdArray<Self::Float>, radius: Self::Float) -> Result<BatchNeighbors<Self::Float>, IronForestError> {
+        let n_queries = check_queries(queries.shape().dims(), self.dim())?;
+        self.check_raw_points()?;
+        let dim = self.dim();
 
         let queries_cow = queries.as_contiguous_slice();
         let queries_slice: &[Self::Float] = &queries_cow;
-        if n_queries >= RAD_PAR_THRESHOLD {
+        Ok(if n_queries >= RAD_PAR_THRESHOLD {
             self.par_radius_batch(queries_slice, n_queries, dim, radius)
         } else {
             self.seq_radius_batch(queries_slice, n_queries, dim, radius)
-        }
+        })
     }
 
     fn seq_radius_batch(&self, queries: &[Self::Float], n_queries: usize, dim: usize, radius: Self::Float) -> Vec<Vec<(usize, Self::Float)>> {
         (0..n_queries)
             .map(|i| {
                 let query = &queries[i * dim..(i + 1) * dim];
-                self.query_radius(query, radius)
+                self.radius_search(query, radius)
             })
             .collect()
     }
@@ -76,7 +82,7 @@ pub trait RadiusQuery: SpatialTree {
             .into_par_iter()
             .map(|i| {
                 let query = &queries[i * dim..(i + 1) * dim];
-                self.query_radius(query, radius)
+                self.radius_search(query, radius)
             })
             .collect()
     }
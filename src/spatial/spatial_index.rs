@@ -1,18 +1,24 @@
 use num_traits::{ToPrimitive, NumCast};
 
 use crate::array::{NdArray, Shape};
-use crate::projection::ProjectionType;
+use crate::random::Generator;
+use crate::projection::{ProjectionType, SplitMode};
 use crate::spatial::trees::{
     BallTree, BruteForce, KDTree, RPTree, VPTree, VantagePointSelection,
     BallTree32, BruteForce32, KDTree32, RPTree32, VPTree32,
 };
-use crate::spatial::common::IronFloat;
+use crate::error::{IronForestError, check_k};
+use crate::spatial::common::{IronFloat, LogSumExp};
+use crate::spatial::nan_policy::{NanPolicy, NanRows, Neighbors};
 use crate::spatial::{DistanceMetric, KernelType, SpatialTree};
 use crate::spatial::queries::{KnnQuery, RadiusQuery, KdeQuery, AnnQuery};
+use crate::spatial::tree_selection::{select_tree, SelectionThresholds, Workload};
 
 
 #[derive(Clone, Copy, PartialEq, Debug)]
 pub enum TreeType {
+    /// Chosen from the data when the index is built, see `select_tree`.
+    Auto,
     KDTree,
     BallTree,
     VPTree,
@@ -20,6 +26,204 @@ pub enum TreeType {
     BruteForce,
 }
 
+/// Options used when the index builds a VPTree.
+#[derive(Clone, Copy, Debug)]
+pub struct VPTreeOptions {
+    pub selection: VantagePointSelection,
+}
+
+impl Default for VPTreeOptions {
+    fn default() -> Self {
+        VPTreeOptions { selection: VantagePointSelection::Variance { sample_size: 10 } }
+    }
+}
+
+/// Options used when the index builds an RPTree.
+#[derive(Clone, Copy, Debug)]
+pub struct RPTreeOptions {
+    pub projection: ProjectionType,
+    pub split: SplitMode,
+}
+
+impl Default for RPTreeOptions {
+    fn default() -> Self {
+        RPTreeOptions { projection: ProjectionType::Gaussian, split: SplitMode::Median }
+    }
+}
+
+/// Index data in either of the precisions it can store.
+pub enum IndexData {
+    F64(NdArray<f64>),
+    F32(NdArray<f32>),
+}
+
+/// Float types a `SpatialIndex` can be built over.
+pub trait IndexFloat: IronFloat {
+    fn into_index_data(data: NdArray<Self>) -> IndexData;
+}
+
+impl IndexFloat for f64 {
+    fn into_index_data(data: NdArray<f64>) -> IndexData {
+        IndexData::F64(data)
+    }
+}
+
+impl IndexFloat for f32 {
+    fn into_index_data(data: NdArray<f32>) -> IndexData {
+        IndexData::F32(data)
+    }
+}
+
+/// Configures and builds a `SpatialIndex` over f64 or f32 data. Defaults match the Python
+/// `SpatialIndex`: automatic tree selection, leaf size 20, euclidean metric.
+#[derive(Clone, Debug)]
+pub struct SpatialIndexBuilder {
+    tree_type: TreeType,
+    leaf_size: usize,
+    metric: DistanceMetric,
+    rebuild_threshold: usize,
+    seed: u64,
+    nan_policy: NanPolicy,
+    vp: VPTreeOptions,
+    rp: RPTreeOptions,
+    workload: Workload,
+    thresholds: SelectionThresholds,
+}
+
+impl Default for SpatialIndexBuilder {
+    fn default() -> Self {
+        SpatialIndexBuilder {
+            tree_type: TreeType::Auto,
+            leaf_size: 20,
+            metric: DistanceMetric::Euclidean,
+            rebuild_threshold: 1000,
+            seed: 0,
+            nan_policy: NanPolicy::Raise,
+            vp: VPTreeOptions::default(),
+            rp: RPTreeOptions::default(),
+            workload: Workload::Knn,
+            thresholds: SelectionThresholds::default(),
+        }
+    }
+}
+
+impl SpatialIndexBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tree_type(mut self, tree_type: TreeType) -> Self {
+        self.tree_type = tree_type;
+        self
+    }
+
+    pub fn leaf_size(mut self, leaf_size: usize) -> Self {
+        self.leaf_size = leaf_size;
+        self
+    }
+
+    pub fn metric(mut self, metric: DistanceMetric) -> Self {
+        self.metric = metric;
+        self
+    }
+
+    /// Number of buffered inserts that triggers a rebuild.
+    pub fn rebuild_threshold(mut self, rebuild_threshold: usize) -> Self {
+        self.rebuild_threshold = rebuild_threshold;
+        self
+    }
+
+    /// Seeds automatic tree selection, RPTree projections and VPTree vantage selection.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn nan_policy(mut self, nan_policy: NanPolicy) -> Self {
+        self.nan_policy = nan_policy;
+        self
+    }
+
+    pub fn vp_options(mut self, vp: VPTreeOptions) -> Self {
+        self.vp = vp;
+        self
+    }
+
+    pub fn rp_options(mut self, rp: RPTreeOptions) -> Self {
+        self.rp = rp;
+        self
+    }
+
+    /// The queries the index will mostly serve, used when resolving `TreeType::Auto`.
+    pub fn workload(mut self, workload: Workload) -> Self {
+        self.workload = workload;
+        self
+    }
+
+    /// Thresholds used when resolving `TreeType::Auto`.
+    pub fn thresholds(mut self, thresholds: SelectionThresholds) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
+
+    /// Builds the index over `data` of shape (n, dim), applying the NaN policy and
+    /// resolving `TreeType::Auto` from the rows that are kept.
+    pub fn build<T: IndexFloat>(&self, data: NdArray<T>) -> Result<SpatialIndex, IronForestError> {
+        self.check(&data)?;
+        let (data, nan) = NanRows::split(data, self.nan_policy)?;
+        let tree_type = self.resolve_tree_type(&data);
+        self.index(tree_type, T::into_index_data(data), nan)
+    }
+
+    /// Rejects data that is not (n, dim) and a zero leaf size, returning `dim`.
+    fn check<T: IronFloat>(&self, data: &NdArray<T>) -> Result<usize, IronForestError> {
+        let dims = data.shape().dims();
+        if dims.len() != 2 {
+            return Err(IronForestError::InvalidShape { ndim: dims.len() });
+        }
+        if self.leaf_size == 0 {
+            return Err(IronForestError::invalid("leaf_size must be at least 1"));
+        }
+        Ok(dims[1])
+    }
+
+    fn resolve_tree_type<T: IronFloat>(&self, data: &NdArray<T>) -> TreeType {
+        match self.tree_type {
+            TreeType::Auto => {
+                select_tree(data, self.workload, &self.thresholds, &mut Generator::from_seed(self.seed))
+            }
+            tree_type => tree_type,
+        }
+    }
+
+    fn index(&self, tree_type: TreeType, data: IndexData, nan: NanRows) -> Result<SpatialIndex, IronForestError> {
+        let (dim, use_f32) = match &data {
+            IndexData::F64(arr) => (arr.shape().dims()[1], false),
+            IndexData::F32(arr) => (arr.shape().dims()[1], true),
+        };
+        let mut idx = SpatialIndex {
+            tree: None,
+            buffer_f64: Vec::new(),
+            buffer_f32: Vec::new(),
+            tree_type,
+            dim,
+            use_f32,
+            leaf_size: self.leaf_size,
+            metric: self.metric,
+            rebuild_threshold: self.rebuild_threshold,
+            seed: self.seed,
+            vp: self.vp,
+            rp: self.rp,
+            nan,
+        };
+        idx.tree = Some(match data {
+            IndexData::F64(arr) => idx.build_tree_f64(arr)?,
+            IndexData::F32(arr) => idx.build_tree_f32(arr)?,
+        });
+        Ok(idx)
+    }
+}
+
 // =============================================================================
 // Inner Tree Enum
 // =============================================================================
@@ -54,6 +258,11 @@ macro_rules! dispatch_typed {
     };
 }
 
+/// `TreeType::Auto` names no tree; the builder resolves it with `select_tree` first.
+fn unresolved_auto() -> IronForestError {
+    IronForestError::invalid("TreeType::Auto must be resolved to a tree type before building")
+}
+
 pub struct QueryResult {
     pub indices: Vec<i64>,
     pub distances: Vec<f64>,
@@ -62,6 +271,55 @@ pub struct QueryResult {
     pub k: Option<usize>,
 }
 
+impl QueryResult {
+    /// Flattens per-query neighbors. kNN batches are padded to `k` with index -1 and a NaN
+    /// distance, which is what queries that find nothing under `NanPolicy::Drop` return.
+    pub fn from_neighbors(results: Vec<Vec<(usize, f64)>>, want: Neighbors) -> Self {
+        let n_queries = results.len();
+        let mut counts = Vec::with_capacity(n_queries);
+        let mut indices = Vec::new();
+        let mut distances = Vec::new();
+        for hits in results {
+            counts.push(hits.len() as i64);
+            let pad = match want {
+                Neighbors::Knn(k) if n_queries > 1 => k.saturating_sub(hits.len()),
+                _ => 0,
+            };
+            for (i, d) in hits {
+                indices.push(i as i64);
+                distances.push(d);
+            }
+            indices.extend(std::iter::repeat_n(-1, pad));
+            distances.extend(std::iter::repeat_n(f64::NAN, pad));
+        }
+        match (want, n_queries) {
+            (_, 1) => QueryResult { indices, distances, counts: None, n_queries, k: None },
+            (Neighbors::Knn(k), _) => QueryResult { indices, distances, counts: None, n_queries, k: Some(k) },
+            (Neighbors::Radius(_), _) => QueryResult { indices, distances, counts: Some(counts), n_queries, k: None },
+        }
+    }
+
+    pub fn into_neighbors(self) -> Vec<Vec<(usize, f64)>> {
+        let pairs: Vec<(usize, f64)> = self.indices.into_iter()
+            .zip(self.distances)
+            .map(|(i, d)| (i as usize, d))
+            .collect();
+        match (self.counts, self.k) {
+            (Some(counts), _) => {
+                let mut rest = pairs.as_slice();
+                counts.iter().map(|&c| {
+                    let (head, tail) = rest.split_at(c as usize);
+                    rest = tail;
+                    head.to_vec()
+                }).collect()
+            }
+            (None, Some(k)) if k > 0 => pairs.chunks(k).map(|c| c.to_vec()).collect(),
+            (None, Some(_)) => vec![Vec::new(); self.n_queries],
+            (None, None) => vec![pairs],
+        }
+    }
+}
+
 pub enum QueryInput<'a> {
     F64(&'a NdArray<f64>),
     F32(&'a NdArray<f32>),
@@ -80,15 +338,21 @@ pub struct SpatialIndex {
     metric: DistanceMetric,
     rebuild_threshold: usize,
 
-    // RPTree-specific
+    // RPTree projections and VPTree vantage selection
     seed: u64,
-    projection_type: ProjectionType,
+    vp: VPTreeOptions,
+    rp: RPTreeOptions,
 
-    // VPTree-specific
-    vp_selection: VantagePointSelection,
+    nan: NanRows,
 }
 
 impl SpatialIndex {
+    pub fn builder() -> SpatialIndexBuilder {
+        SpatialIndexBuilder::new()
+    }
+
+    /// Builds an index without NaN handling. `SpatialIndexBuilder` exposes every option.
+    #[allow(clippy::too_many_arguments)]
     pub fn new_f64(
         data: NdArray<f64>,
         tree_type: TreeType,
@@ -98,26 +362,14 @@ impl SpatialIndex {
         seed: u64,
         projection_type: ProjectionType,
         vp_selection: VantagePointSelection,
-    ) -> Self {
-        let dim = data.shape().dims()[1];
-        let mut idx = SpatialIndex {
-            tree: None,
-            buffer_f64: Vec::new(),
-            buffer_f32: Vec::new(),
-            tree_type,
-            dim,
-            use_f32: false,
-            leaf_size,
-            metric,
-            rebuild_threshold,
-            seed,
-            projection_type,
-            vp_selection,
-        };
-        idx.tree = Some(idx.build_tree_f64(data));
-        idx
+    ) -> Result<Self, IronForestError> {
+        let builder = Self::positional_builder(tree_type, leaf_size, metric, rebuild_threshold, seed, projection_type, vp_selection);
+        let dim = builder.check(&data)?;
+        builder.index(builder.resolve_tree_type(&data), IndexData::F64(data), NanRows::new(NanPolicy::Raise, dim))
     }
 
+    /// f32 counterpart of `new_f64`.
+    #[allow(clippy::too_many_arguments)]
     pub fn new_f32(
         data: NdArray<f32>,
         tree_type: TreeType,
@@ -127,28 +379,49 @@ impl SpatialIndex {
         seed: u64,
         projection_type: ProjectionType,
         vp_selection: VantagePointSelection,
-    ) -> Self {
-        let dim = data.shape().dims()[1];
-        let mut idx = SpatialIndex {
-            tree: None,
-            buffer_f64: Vec::new(),
-            buffer_f32: Vec::new(),
-            tree_type,
-            dim,
-            use_f32: true,
-            leaf_size,
-            metric,
-            rebuild_threshold,
-            seed,
-            projection_type,
-            vp_selection,
-        };
-        idx.tree = Some(idx.build_tree_f32(data));
-        idx
+    ) -> Result<Self, IronForestError> {
+        let builder = Self::positional_builder(tree_type, leaf_size, metric, rebuild_threshold, seed, projection_type, vp_selection);
+        let dim = builder.check(&data)?;
+        builder.index(builder.resolve_tree_type(&data), IndexData::F32(data), NanRows::new(NanPolicy::Raise, dim))
     }
 
-    fn tree_ref(&self) -> Result<&TreeInner, String> {
-        self.tree.as_ref().ok_or_else(|| "SpatialIndex is uninitialized".to_string())
+    fn positional_builder(
+        tree_type: TreeType,
+        leaf_size: usize,
+        metric: DistanceMetric,
+        rebuild_threshold: usize,
+        seed: u64,
+        projection: ProjectionType,
+        selection: VantagePointSelection,
+    ) -> SpatialIndexBuilder {
+        SpatialIndexBuilder::new()
+            .tree_type(tree_type)
+            .leaf_size(leaf_size)
+            .metric(metric)
+            .rebuild_threshold(rebuild_threshold)
+            .seed(seed)
+            .vp_options(VPTreeOptions { selection })
+            .rp_options(RPTreeOptions { projection, ..Default::default() })
+    }
+
+    /// Sets the NaN policy, with `nan` recording rows already set aside from the data the
+    /// index was built on.
+    pub fn set_nan_rows(&mut self, nan: NanRows) {
+        self.nan = nan;
+    }
+
+    pub fn nan_rows(&self) -> &NanRows {
+        &self.nan
+    }
+
+    fn tree_ref(&self) -> Result<&TreeInner, IronForestError> {
+        self.tree.as_ref().ok_or(IronForestError::Uninitialized)
+    }
+
+    /// Whether the tree reports reduced distances, which rows set aside by the NaN policy
+    /// must match.
+    fn reports_reduced(&self) -> Result<bool, IronForestError> {
+        Ok(dispatch_typed!(self.tree_ref()?, f64 |t| t.reports_reduced(), f32 |t| t.reports_reduced()))
     }
 
     pub fn buffer_count(&self) -> usize {
@@ -160,9 +433,8 @@ impl SpatialIndex {
         }
     }
 
-    pub fn rebuild(&mut self) -> Result<(), String> {
-        let tree_ref = self.tree.as_ref()
-            .ok_or_else(|| "SpatialIndex is uninitialized".to_string())?;
+    pub fn rebuild(&mut self) -> Result<(), IronForestError> {
+        let tree_ref = self.tree_ref()?;
 
         if self.use_f32 {
             let mut combined = extract_data_f32(tree_ref, self.dim);
@@ -170,20 +442,20 @@ impl SpatialIndex {
             self.buffer_f32.clear();
             let n = combined.len() / self.dim;
             let arr = NdArray::from_vec(Shape::new(vec![n, self.dim]), combined);
-            self.tree = Some(self.build_tree_f32(arr));
+            self.tree = Some(self.build_tree_f32(arr)?);
         } else {
             let mut combined = extract_data_f64(tree_ref, self.dim);
             combined.extend_from_slice(&self.buffer_f64);
             self.buffer_f64.clear();
             let n = combined.len() / self.dim;
             let arr = NdArray::from_vec(Shape::new(vec![n, self.dim]), combined);
-            self.tree = Some(self.build_tree_f64(arr));
+            self.tree = Some(self.build_tree_f64(arr)?);
         }
         Ok(())
     }
 
-    fn build_tree_f64(&self, data: NdArray<f64>) -> TreeInner {
-        match self.tree_type {
+    fn build_tree_f64(&self, data: NdArray<f64>) -> Result<TreeInner, IronForestError> {
+        Ok(match self.tree_type {
             TreeType::KDTree => {
                 TreeInner::KDTreeF64(KDTree::new(data, self.leaf_size, self.metric))
             }
@@ -191,19 +463,20 @@ impl SpatialIndex {
                 TreeInner::BallTreeF64(BallTree::new(data, self.leaf_size, self.metric))
             }
             TreeType::VPTree => {
-                TreeInner::VPTreeF64(VPTree::new(data, self.leaf_size, self.metric, self.vp_selection))
+                TreeInner::VPTreeF64(VPTree::new(data, self.leaf_size, self.metric, self.vp.selection, self.seed))
             }
             TreeType::RPTree => {
-                TreeInner::RPTreeF64(RPTree::new(data, self.leaf_size, self.metric, self.projection_type, self.seed))
+                TreeInner::RPTreeF64(RPTree::new(data, self.leaf_size, self.metric, self.rp.projection, self.rp.split, self.seed))
             }
             TreeType::BruteForce => {
                 TreeInner::BruteForceF64(BruteForce::new(data, self.metric))
             }
-        }
+            TreeType::Auto => return Err(unresolved_auto()),
+        })
     }
 
-    fn build_tree_f32(&self, data: NdArray<f32>) -> TreeInner {
-        match self.tree_type {
+    fn build_tree_f32(&self, data: NdArray<f32>) -> Result<TreeInner, IronForestError> {
+        Ok(match self.tree_type {
             TreeType::KDTree => {
                 TreeInner::KDTreeF32(KDTree32::new(data, self.leaf_size, self.metric))
             }
@@ -211,41 +484,44 @@ impl SpatialIndex {
                 TreeInner::BallTreeF32(BallTree32::new(data, self.leaf_size, self.metric))
             }
             TreeType::VPTree => {
-                TreeInner::VPTreeF32(VPTree32::new(data, self.leaf_size, self.metric, self.vp_selection))
+                TreeInner::VPTreeF32(VPTree32::new(data, self.leaf_size, self.metric, self.vp.selection, self.seed))
             }
             TreeType::RPTree => {
-                TreeInner::RPTreeF32(RPTree32::new(data, self.leaf_size, self.metric, self.projection_type, self.seed))
+                TreeInner::RPTreeF32(RPTree32::new(data, self.leaf_size, self.metric, self.rp.projection, self.rp.split, self.seed))
             }
             TreeType::BruteForce => {
                 TreeInner::BruteForceF32(BruteForce32::new(data, self.metric))
             }
-        }
+            TreeType::Auto => return Err(unresolved_auto()),
+        })
     }
 
 
-    pub fn insert_f64(&mut self, flat_data: &[f64], point_dim: usize) -> Result<(), String> {
+    pub fn insert_f64(&mut self, flat_data: &[f64], point_dim: usize) -> Result<(), IronForestError> {
         if point_dim != self.dim {
-            return Err(format!("Dimension mismatch: expected {}, got {}", self.dim, point_dim));
+            return Err(IronForestError::DimensionMismatch { expected: self.dim, got: point_dim });
         }
-        self.buffer_f64.extend_from_slice(flat_data);
+        let rows = self.nan.push_rows(flat_data, self.n_points()?)?;
+        self.buffer_f64.extend_from_slice(&rows);
         if self.buffer_count() >= self.rebuild_threshold {
             self.rebuild()?;
         }
         Ok(())
     }
 
-    pub fn insert_f32(&mut self, flat_data: &[f32], point_dim: usize) -> Result<(), String> {
+    pub fn insert_f32(&mut self, flat_data: &[f32], point_dim: usize) -> Result<(), IronForestError> {
         if point_dim != self.dim {
-            return Err(format!("Dimension mismatch: expected {}, got {}", self.dim, point_dim));
+            return Err(IronForestError::DimensionMismatch { expected: self.dim, got: point_dim });
         }
-        self.buffer_f32.extend_from_slice(flat_data);
+        let rows = self.nan.push_rows(flat_data, self.n_points()?)?;
+        self.buffer_f32.extend_from_slice(&rows);
         if self.buffer_count() >= self.rebuild_threshold {
             self.rebuild()?;
         }
         Ok(())
     }
 
-    pub fn flush(&mut self) -> Result<(), String> {
+    pub fn flush(&mut self) -> Result<(), IronForestError> {
         if self.buffer_count() > 0 {
             self.rebuild()
         } else {
@@ -265,7 +541,7 @@ impl SpatialIndex {
         self.dim
     }
 
-    pub fn n_points(&self) -> Result<usize, String> {
+    pub fn n_points(&self) -> Result<usize, IronForestError> {
         let tree_ref = self.tree_ref()?;
         let tree_points = dispatch_typed!(tree_ref,
             f64 |t| t.n_points(),
@@ -294,61 +570,114 @@ impl SpatialIndex {
     // Queries
     // =========================================================================
 
-    pub fn query_knn(&self, query: QueryInput<'_>, is_batch: bool, k: usize) -> Result<QueryResult, String> {
+    pub fn query_knn(&self, query: QueryInput<'_>, is_batch: bool, k: usize) -> Result<QueryResult, IronForestError> {
+        check_k(k)?;
+        self.with_nan_policy(query, is_batch, Neighbors::Knn(k), |q, batch| self.query_knn_inner(q, batch, k))
+    }
+
+    pub fn query_ann(
+        &self,
+        query: QueryInput<'_>,
+        is_batch: bool,
+        k: usize,
+        n_candidates: usize,
+        n_probes: Option<usize>,
+    ) -> Result<QueryResult, IronForestError> {
+        check_k(k)?;
+        self.with_nan_policy(query, is_batch, Neighbors::Knn(k), |q, batch| {
+            self.query_ann_inner(q, batch, k, n_candidates, n_probes)
+        })
+    }
+
+    pub fn query_radius(&self, query: QueryInput<'_>, is_batch: bool, radius: f64) -> Result<QueryResult, IronForestError> {
+        self.with_nan_policy(query, is_batch, Neighbors::Radius(radius), |q, batch| self.query_radius_inner(q, batch, radius))
+    }
+
+    /// Runs `run` directly when neither the queries nor the index involve NaN rows, and
+    /// otherwise on the queries without NaN, completing the results under the NaN policy.
+    fn with_nan_policy(
+        &self,
+        query: QueryInput<'_>,
+        is_batch: bool,
+        want: Neighbors,
+        run: impl Fn(QueryInput<'_>, bool) -> Result<QueryResult, IronForestError>,
+    ) -> Result<QueryResult, IronForestError> {
+        let results = match query {
+            QueryInput::F64(q) => {
+                if self.nan.is_identity() && self.nan.missing_queries(q)?.is_empty() {
+                    return run(QueryInput::F64(q), is_batch);
+                }
+                self.nan.neighbors(self.metric, self.reports_reduced()?, q, want, || self.internal_data().unwrap_or_default(), |sub| {
+                    run(QueryInput::F64(sub), true).map(QueryResult::into_neighbors)
+                })?
+            }
+            QueryInput::F32(q) => {
+                if self.nan.is_identity() && self.nan.missing_queries(q)?.is_empty() {
+                    return run(QueryInput::F32(q), is_batch);
+                }
+                self.nan.neighbors(self.metric, self.reports_reduced()?, q, want, || self.internal_data().unwrap_or_default(), |sub| {
+                    run(QueryInput::F32(sub), true).map(QueryResult::into_neighbors)
+                })?
+            }
+        };
+        Ok(QueryResult::from_neighbors(results, want))
+    }
+
+    fn query_knn_inner(&self, query: QueryInput<'_>, is_batch: bool, k: usize) -> Result<QueryResult, IronForestError> {
         let tree_ref = self.tree_ref()?;
         match query {
             QueryInput::F64(q) => {
                 dispatch_typed!(tree_ref,
                     f64 |t| knn_impl(t, q, is_batch, k, &self.buffer_f64, self.dim, &self.metric),
-                    f32 |_t| Err("f64 query provided for f32 tree".to_string())
+                    f32 |_t| Err(IronForestError::invalid("f64 query provided for f32 tree"))
                 )
             }
             QueryInput::F32(q) => {
                 dispatch_typed!(tree_ref,
-                    f64 |_t| Err("f32 query provided for f64 tree".to_string()),
+                    f64 |_t| Err(IronForestError::invalid("f32 query provided for f64 tree")),
                     f32 |t| knn_impl(t, q, is_batch, k, &self.buffer_f32, self.dim, &self.metric)
                 )
             }
         }
     }
 
-    pub fn query_ann(
+    fn query_ann_inner(
         &self,
         query: QueryInput<'_>,
         is_batch: bool,
         k: usize,
         n_candidates: usize,
         n_probes: Option<usize>,
-    ) -> Result<QueryResult, String> {
+    ) -> Result<QueryResult, IronForestError> {
         let tree_ref = self.tree_ref()?;
         match query {
             QueryInput::F64(q) => {
                 dispatch_typed!(tree_ref,
                     f64 |t| ann_impl(t, q, is_batch, k, n_candidates, n_probes, &self.buffer_f64, self.dim, &self.metric),
-                    f32 |_t| Err("f64 query provided for f32 tree".to_string())
+                    f32 |_t| Err(IronForestError::invalid("f64 query provided for f32 tree"))
                 )
             }
             QueryInput::F32(q) => {
                 dispatch_typed!(tree_ref,
-                    f64 |_t| Err("f32 query provided for f64 tree".to_string()),
+                    f64 |_t| Err(IronForestError::invalid("f32 query provided for f64 tree")),
                     f32 |t| ann_impl(t, q, is_batch, k, n_candidates, n_probes, &self.buffer_f32, self.dim, &self.metric)
                 )
             }
         }
     }
 
-    pub fn query_radius(&self, query: QueryInput<'_>, is_batch: bool, radius: f64) -> Result<QueryResult, String> {
+    fn query_radius_inner(&self, query: QueryInput<'_>, is_batch: bool, radius: f64) -> Result<QueryResult, IronForestError> {
         let tree_ref = self.tree_ref()?;
         match query {
             QueryInput::F64(q) => {
                 dispatch_typed!(tree_ref,
                     f64 |t| radius_impl(t, q, is_batch, radius, &self.buffer_f64, self.dim, &self.metric),
-                    f32 |_t| Err("f64 query provided for f32 tree".to_string())
+                    f32 |_t| Err(IronForestError::invalid("f64 query provided for f32 tree"))
                 )
             }
             QueryInput::F32(q) => {
                 dispatch_typed!(tree_ref,
-                    f64 |_t| Err("f32 query provided for f64 tree".to_string()),
+                    f64 |_t| Err(IronForestError::invalid("f32 query provided for f64 tree")),
                     f32 |t| {
                         let rad: f32 = <f32 as NumCast>::from(radius).unwrap();
                         radius_impl(t, q, is_batch, rad, &self.buffer_f32, self.dim, &self.metric)
@@ -364,112 +693,89 @@ impl SpatialIndex {
         bandwidth: f64,
         kernel: KernelType,
         normalize: bool,
-    ) -> Result<NdArray<f64>, String> {
+        log_density: bool,
+    ) -> Result<NdArray<f64>, IronForestError> {
         let tree_ref = self.tree_ref()?;
-        match queries {
+        if normalize {
+            kernel.check_normalizable(self.dim)?;
+        }
+        let rows = || self.internal_data().unwrap_or_default();
+        let densities = match queries {
             Some(QueryInput::F64(q)) => {
-                dispatch_typed!(tree_ref,
-                    f64 |t| {
-                        let mut result = t.kernel_density(q, bandwidth, kernel, normalize);
-                        if !self.buffer_f64.is_empty() {
-                            add_buffer_kde(&mut result, q, &self.buffer_f64, self.dim, &self.metric, bandwidth, kernel, normalize);
-                        }
-                        Ok(result)
-                    },
-                    f32 |_t| Err("f64 query provided for f32 tree".to_string())
-                )
+                self.nan.density(self.metric, self.reports_reduced()?, q, kernel, bandwidth, normalize, log_density, rows, |sub| {
+                    dispatch_typed!(tree_ref,
+                        f64 |t| {
+                            let mut result = t.kernel_density(sub, bandwidth, kernel, normalize, log_density)?;
+                            if !self.buffer_f64.is_empty() {
+                                add_buffer_kde(&mut result, sub, &self.buffer_f64, self.dim, &self.metric, bandwidth, kernel, normalize, log_density);
+                            }
+                            Ok(result.as_slice_unchecked().to_vec())
+                        },
+                        f32 |_t| Err(IronForestError::invalid("f64 query provided for f32 tree"))
+                    )
+                })?
             }
             Some(QueryInput::F32(q)) => {
-                dispatch_typed!(tree_ref,
-                    f64 |_t| Err("f32 query provided for f64 tree".to_string()),
-                    f32 |t| {
-                        let mut result = t.kernel_density(q, bandwidth, kernel, normalize);
-                        if !self.buffer_f32.is_empty() {
-                            add_buffer_kde(&mut result, q, &self.buffer_f32, self.dim, &self.metric, bandwidth, kernel, normalize);
+                self.nan.density(self.metric, self.reports_reduced()?, q, kernel, bandwidth, normalize, log_density, rows, |sub| {
+                    dispatch_typed!(tree_ref,
+                        f64 |_t| Err(IronForestError::invalid("f32 query provided for f64 tree")),
+                        f32 |t| {
+                            let mut result = t.kernel_density(sub, bandwidth, kernel, normalize, log_density)?;
+                            if !self.buffer_f32.is_empty() {
+                                add_buffer_kde(&mut result, sub, &self.buffer_f32, self.dim, &self.metric, bandwidth, kernel, normalize, log_density);
+                            }
+                            Ok(result.as_slice_unchecked().to_vec())
                         }
-                        Ok(result)
-                    }
-                )
+                    )
+                })?
             }
             None => {
-                dispatch_typed!(tree_ref,
-                    f64 |t| {
-                        let queries_arr = NdArray::from_vec(
-                            Shape::new(vec![t.n_points(), t.dim]),
-                            t.data().to_vec()
-                        );
-                        let mut result = t.kernel_density(&queries_arr, bandwidth, kernel, normalize);
-                        if !self.buffer_f64.is_empty() {
-                            add_buffer_kde(&mut result, &queries_arr, &self.buffer_f64, self.dim, &self.metric, bandwidth, kernel, normalize);
-                        }
-                        Ok(result)
-                    },
-                    f32 |t| {
-                        let queries_arr = NdArray::from_vec(
-                            Shape::new(vec![t.n_points(), t.dim]),
-                            t.data().to_vec()
-                        );
-                        let mut result = t.kernel_density(&queries_arr, bandwidth, kernel, normalize);
-                        if !self.buffer_f32.is_empty() {
-                            add_buffer_kde(&mut result, &queries_arr, &self.buffer_f32, self.dim, &self.metric, bandwidth, kernel, normalize);
-                        }
-                        Ok(result)
-                    }
-                )
+                // every point in input order, like `data`, so rows set aside by the NaN
+                // policy get the policy's density
+                let points = self.nan.input_data(self.internal_data()?);
+                let shape = || Shape::new(vec![points.len() / self.dim.max(1), self.dim]);
+                return if self.use_f32 {
+                    let queries = NdArray::from_vec(shape(), points.iter().map(|&v| v as f32).collect());
+                    self.kernel_density(Some(QueryInput::F32(&queries)), bandwidth, kernel, normalize, log_density)
+                } else {
+                    let queries = NdArray::from_vec(shape(), points);
+                    self.kernel_density(Some(QueryInput::F64(&queries)), bandwidth, kernel, normalize, log_density)
+                };
             }
+        };
+        Ok(NdArray::from_vec(Shape::d1(densities.len()), densities))
+    }
+
+    /// Indexed points of the tree and buffer, in the order they were added.
+    fn internal_data(&self) -> Result<Vec<f64>, IronForestError> {
+        let mut full = extract_data_f64(self.tree_ref()?, self.dim);
+        if self.use_f32 {
+            full.extend(self.buffer_f32.iter().map(|&v| v as f64));
+        } else {
+            full.extend_from_slice(&self.buffer_f64);
         }
+        Ok(full)
     }
 
-    pub fn data(&self, indices: Option<&[i64]>) -> Result<(Vec<f64>, usize, usize), String> {
-        let tree_ref = self.tree_ref()?;
+    /// Points in input order, including rows set aside for containing NaN.
+    pub fn data(&self, indices: Option<&[i64]>) -> Result<(Vec<f64>, usize, usize), IronForestError> {
+        let total_n = self.n_points()? + self.nan.rows.len();
+        let full = self.nan.input_data(self.internal_data()?);
 
-        let tree_n = dispatch_typed!(tree_ref, f64 |t| t.n_points(), f32 |t| t.n_points());
-        let buf_n = self.buffer_count();
-        let total_n = tree_n + buf_n;
-
-        let tree_data = extract_data_f64(tree_ref, self.dim);
-
-        if buf_n == 0 {
-            match indices {
-                None => Ok((tree_data, tree_n, self.dim)),
-                Some(idx) => {
-                    let mut result = Vec::with_capacity(idx.len() * self.dim);
-                    for &orig_idx in idx {
-                        let i = orig_idx as usize;
-                        if i >= tree_n {
-                            return Err(format!(
-                                "Index {} out of bounds for index with {} points", orig_idx, tree_n
-                            ));
-                        }
-                        result.extend_from_slice(&tree_data[i * self.dim..(i + 1) * self.dim]);
+        match indices {
+            None => Ok((full, total_n, self.dim)),
+            Some(idx) => {
+                let mut result = Vec::with_capacity(idx.len() * self.dim);
+                for &orig_idx in idx {
+                    let i = orig_idx as usize;
+                    if i >= total_n {
+                        return Err(IronForestError::invalid(format!(
+                            "Index {} out of bounds for index with {} points", orig_idx, total_n
+                        )));
                     }
-                    Ok((result, idx.len(), self.dim))
-                }
-            }
-        } else {
-            //need to build a new vec with the buffered data included
-            let mut full = tree_data;
-            if self.use_f32 {
-                full.extend(self.buffer_f32.iter().map(|&v| v as f64));
-            } else {
-                full.extend_from_slice(&self.buffer_f64);
-            }
-
-            match indices {
-                None => Ok((full, total_n, self.dim)),
-                Some(idx) => {
-                    let mut result = Vec::with_capacity(idx.len() * self.dim);
-                    for &orig_idx in idx {
-                        let i = orig_idx as usize;
-                        if i >= total_n {
-                            return Err(format!(
-                                "Index {} out of bounds for index with {} points", orig_idx, total_n
-                            ));
-                        }
-                        result.extend_from_slice(&full[i * self.dim..(i + 1) * self.dim]);
-                    }
-                    Ok((result, idx.len(), self.dim))
+                    result.extend_from_slice(&full[i * self.dim..(i + 1) * self.dim]);
                 }
+                Ok((result, idx.len(), self.dim))
             }
         }
     }
@@ -555,7 +861,7 @@ fn merge_buffer_topk<T: IronFloat>(
         } else {
             continue;
         }
-        results.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        results.sort_by(|a, b| a.1.total_cmp(&b.1));
     }
 }
 
@@ -588,6 +894,7 @@ fn add_buffer_kde<T: IronFloat>(
     bandwidth: f64,
     kernel: KernelType,
     normalized: bool,
+    log_density: bool,
 ) {
     let n_queries = densities.shape().dims()[0];
     let n_buf = buffer.len() / dim;
@@ -599,6 +906,23 @@ fn add_buffer_kde<T: IronFloat>(
 
     for qi in 0..n_queries {
         let q = &queries_slice[qi * dim..(qi + 1) * dim];
+        if log_density {
+            let mut acc = LogSumExp::new();
+            for bi in 0..n_buf {
+                let p = &buffer[bi * dim..(bi + 1) * dim];
+                let dist = buffer_distance(metric, q, p).to_f64().unwrap();
+                acc.add(kernel.log_evaluate(dist, bandwidth));
+            }
+            let mut contrib = acc.value();
+            if normalized {
+                contrib -= dim as f64 * bandwidth.ln() + kernel.log_normalization_constant(dim);
+            }
+            let mut total = LogSumExp::new();
+            total.add(density_slice[qi]);
+            total.add(contrib);
+            density_slice[qi] = total.value();
+            continue;
+        }
         let mut contrib = 0.0f64;
         for bi in 0..n_buf {
             let p = &buffer[bi * dim..(bi + 1) * dim];
@@ -626,7 +950,7 @@ fn knn_impl<T, F>(
     buffer: &[F],
     dim: usize,
     metric: &DistanceMetric,
-) -> Result<QueryResult, String>
+) -> Result<QueryResult, IronForestError>
 where
     T: SpatialTree<Float = F> + KnnQuery,
     F: IronFloat,
@@ -634,7 +958,7 @@ where
     let offset = tree.n_points();
     let n_queries = queries.shape().dims()[0];
     if is_batch {
-        let mut results = tree.query_knn_batch(queries, k);
+        let mut results = tree.query_knn_batch(queries, k)?;
         if !buffer.is_empty() {
             let qs = queries.as_contiguous_slice();
             for (qi, res) in results.iter_mut().enumerate() {
@@ -652,7 +976,7 @@ where
         }
     } else {
         let query_slice = &queries.as_slice_unchecked()[..dim];
-        let mut results = tree.query_knn(query_slice, k);
+        let mut results = tree.query_knn(query_slice, k)?;
         merge_buffer_topk(&mut results, buffer, dim, metric, query_slice, k, offset);
         let (indices, distances): (Vec<i64>, Vec<f64>) = results.into_iter()
             .map(|(i, d)| (i as i64, d.to_f64().unwrap())).unzip();
@@ -670,7 +994,7 @@ fn ann_impl<T, F>(
     buffer: &[F],
     dim: usize,
     metric: &DistanceMetric,
-) -> Result<QueryResult, String>
+) -> Result<QueryResult, IronForestError>
 where
     T: SpatialTree<Float = F> + AnnQuery,
     F: IronFloat,
@@ -679,8 +1003,8 @@ where
     let n_queries = queries.shape().dims()[0];
     if is_batch {
         let mut results = match n_probes {
-            Some(np) => tree.query_ann_stochastic_batch(queries, k, n_candidates, np),
-            None => tree.query_ann_batch(queries, k, n_candidates),
+            Some(np) => tree.query_ann_stochastic_batch(queries, k, n_candidates, np)?,
+            None => tree.query_ann_batch(queries, k, n_candidates)?,
         };
         if !buffer.is_empty() {
             let qs = queries.as_contiguous_slice();
@@ -718,7 +1042,7 @@ fn radius_impl<T, F>(
     buffer: &[F],
     dim: usize,
     metric: &DistanceMetric,
-) -> Result<QueryResult, String>
+) -> Result<QueryResult, IronForestError>
 where
     T: SpatialTree<Float = F> + RadiusQuery,
     F: IronFloat,
@@ -726,7 +1050,7 @@ where
     let offset = tree.n_points();
     if is_batch {
         let n_queries = queries.shape().dims()[0];
-        let mut results = tree.query_radius_batch(queries, radius);
+        let mut results = tree.query_radius_batch(queries, radius)?;
         if !buffer.is_empty() {
             let qs = queries.as_contiguous_slice();
             for (qi, res) in results.iter_mut().enumerate() {
@@ -753,10 +1077,130 @@ where
         }
     } else {
         let query_slice = &queries.as_slice_unchecked()[..dim];
-        let mut results = tree.query_radius(query_slice, radius);
+        let mut results = tree.query_radius(query_slice, radius)?;
         merge_buffer_radius(&mut results, buffer, dim, metric, query_slice, radius, offset);
         let (indices, distances): (Vec<i64>, Vec<f64>) = results.into_iter()
             .map(|(i, d)| (i as i64, d.to_f64().unwrap())).unzip();
         Ok(QueryResult { indices, distances, counts: None, n_queries: 1, k: None })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normal_data(n: usize, dim: usize, seed: u64) -> NdArray<f64> {
+        Generator::from_seed(seed).standard_normal(Shape::new(vec![n, dim]))
+    }
+
+    fn to_f32(data: &NdArray<f64>) -> NdArray<f32> {
+        let values = data.as_slice_unchecked().iter().map(|&v| v as f32).collect();
+        NdArray::from_vec(Shape::new(data.shape().dims().to_vec()), values)
+    }
+
+    #[test]
+    fn builder_defaults() {
+        let index = SpatialIndexBuilder::default().build(normal_data(50, 3, 1)).unwrap();
+        // Below `min_tree_points` the default thresholds pick a brute force scan.
+        assert_eq!(index.tree_type(), TreeType::BruteForce);
+        assert!(matches!(index.metric(), DistanceMetric::Euclidean));
+        assert_eq!(index.rebuild_threshold(), 1000);
+        assert!(!index.use_f32());
+        assert_eq!(index.dim(), 3);
+        assert_eq!(index.n_points().unwrap(), 50);
+    }
+
+    #[test]
+    fn auto_resolves_like_select_tree() {
+        let data = normal_data(200, 4, 2);
+        let thresholds = || SelectionThresholds { min_tree_points: 100, ..Default::default() };
+        for workload in [Workload::Knn, Workload::Ann, Workload::Kde] {
+            let expected = select_tree(&data, workload, &thresholds(), &mut Generator::from_seed(7));
+            assert_ne!(expected, TreeType::Auto);
+            for index in [
+                SpatialIndexBuilder::new().workload(workload).thresholds(thresholds()).seed(7).build(data.clone()).unwrap(),
+                SpatialIndexBuilder::new().workload(workload).thresholds(thresholds()).seed(7).build(to_f32(&data)).unwrap(),
+            ] {
+                assert_eq!(index.tree_type(), expected, "{:?}", workload);
+            }
+        }
+    }
+
+    #[test]
+    fn explicit_tree_types_match_brute_force() {
+        let data = normal_data(300, 5, 3);
+        let queries = normal_data(10, 5, 4);
+        let k = 7;
+        let knn = |tree_type: TreeType, use_f32: bool| {
+            let builder = SpatialIndexBuilder::new().tree_type(tree_type).leaf_size(8);
+            let index = match use_f32 {
+                true => builder.build(to_f32(&data)).unwrap(),
+                false => builder.build(data.clone()).unwrap(),
+            };
+            assert_eq!(index.tree_type(), tree_type);
+            assert_eq!(index.use_f32(), use_f32);
+            let result = match use_f32 {
+                true => index.query_knn(QueryInput::F32(&to_f32(&queries)), true, k),
+                false => index.query_knn(QueryInput::F64(&queries), true, k),
+            };
+            result.unwrap().indices
+        };
+        for use_f32 in [false, true] {
+            let expected = knn(TreeType::BruteForce, use_f32);
+            for tree_type in [TreeType::KDTree, TreeType::BallTree, TreeType::VPTree, TreeType::RPTree] {
+                assert_eq!(knn(tree_type, use_f32), expected, "{:?} f32={}", tree_type, use_f32);
+            }
+        }
+    }
+
+    #[test]
+    fn rebuild_keeps_resolved_tree_type() {
+        let mut index = SpatialIndexBuilder::new().tree_type(TreeType::KDTree).build(normal_data(40, 2, 5)).unwrap();
+        index.insert_f64(normal_data(10, 2, 6).as_slice_unchecked(), 2).unwrap();
+        index.flush().unwrap();
+        assert_eq!(index.tree_type(), TreeType::KDTree);
+        assert_eq!(index.n_points().unwrap(), 50);
+    }
+
+    #[test]
+    fn default_density_queries_follow_data_order() {
+        let mut values = normal_data(60, 3, 10).as_slice_unchecked().to_vec();
+        values[4 * 3 + 1] = f64::NAN;
+        let data = NdArray::from_vec(Shape::new(vec![60, 3]), values);
+        let index = SpatialIndexBuilder::new()
+            .tree_type(TreeType::KDTree)
+            .leaf_size(4)
+            .nan_policy(NanPolicy::Drop)
+            .build(data)
+            .unwrap();
+
+        let (points, n, dim) = index.data(None).unwrap();
+        assert_eq!(n, 60);
+        let queries = NdArray::from_vec(Shape::new(vec![n, dim]), points);
+        let expected = index.kernel_density(Some(QueryInput::F64(&queries)), 0.5, KernelType::Gaussian, true, false).unwrap();
+        let defaults = index.kernel_density(None, 0.5, KernelType::Gaussian, true, false).unwrap();
+
+        assert_eq!(defaults.shape().dims(), &[60]);
+        assert!(defaults.as_slice_unchecked()[4].is_nan());
+        for (i, (a, b)) in defaults.as_slice_unchecked().iter().zip(expected.as_slice_unchecked()).enumerate() {
+            assert!(i == 4 || a == b, "row {}: {} vs {}", i, a, b);
+        }
+    }
+
+    #[test]
+    fn invalid_builds_are_errors() {
+        let flat = || NdArray::from_vec(Shape::new(vec![6]), vec![0.0; 6]);
+        assert!(matches!(SpatialIndexBuilder::new().build(flat()), Err(IronForestError::InvalidShape { ndim: 1 })));
+        let zero_leaf = SpatialIndexBuilder::new().leaf_size(0).build(normal_data(10, 2, 8));
+        assert!(matches!(zero_leaf, Err(IronForestError::InvalidArgument(_))));
+
+        let positional = SpatialIndex::new_f64(flat(), TreeType::KDTree, 20, DistanceMetric::Euclidean, 1000, 0, ProjectionType::Gaussian, VantagePointSelection::First);
+        assert!(matches!(positional, Err(IronForestError::InvalidShape { ndim: 1 })));
+        let flat32 = NdArray::from_vec(Shape::new(vec![6]), vec![0.0f32; 6]);
+        let positional = SpatialIndex::new_f32(flat32, TreeType::KDTree, 20, DistanceMetric::Euclidean, 1000, 0, ProjectionType::Gaussian, VantagePointSelection::First);
+        assert!(matches!(positional, Err(IronForestError::InvalidShape { ndim: 1 })));
+
+        let unresolved = SpatialIndexBuilder::new().index(TreeType::Auto, IndexData::F64(normal_data(10, 2, 9)), NanRows::new(NanPolicy::Raise, 2));
+        assert!(matches!(unresolved, Err(IronForestError::InvalidArgument(_))));
+    }
+}
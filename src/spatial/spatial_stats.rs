@@ -1,8 +1,34 @@
 use crate::{Generator, IronFloat, NdArray, Shape};
+use crate::spatial::DistanceMetric;
+use crate::spatial::queries::KnnQuery;
+use crate::spatial::trees::brute_force::BruteForce;
 
+/// Scaling region of the correlation integral, as fractions of sampled pairs.
+const CORRELATION_QUANTILES: [f64; 6] = [0.005, 0.01, 0.02, 0.03, 0.05, 0.1];
 
+/// How to estimate the intrinsic dimension of a point set.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IntrinsicDimEstimator {
+    /// Principal axes needed to explain `variance_threshold` of the variance. Overestimates
+    /// on curved manifolds.
+    Pca { variance_threshold: f64 },
+    /// Levina-Bickel maximum likelihood over the `k` nearest neighbors, with the per-point
+    /// estimates combined as MacKay and Ghahramani suggest.
+    Mle { k: usize },
+    /// Facco et al.'s TwoNN, from the ratio of second to first neighbor distances.
+    TwoNn,
+    /// Grassberger-Procaccia slope of the pair correlation integral at small radii.
+    Correlation,
+}
+
+impl Default for IntrinsicDimEstimator {
+    fn default() -> Self {
+        IntrinsicDimEstimator::Mle { k: 10 }
+    }
+}
 
 impl<T: IronFloat> NdArray<T> {
+    /// At most `k` distinct rows, drawn without replacement.
     pub fn sample_rows(&self, k: usize, rng: &mut Generator) -> NdArray<T> {
         assert_eq!(self.ndim(), 2, "sample_rows() requires a 2D array");
         let dims = self.shape().dims();
@@ -13,11 +39,12 @@ impl<T: IronFloat> NdArray<T> {
             return self.clone();
         }
 
+        let mut indices: Vec<usize> = (0..n).collect();
+        rng.partial_shuffle(&mut indices, k);
+
         let mut out = Vec::with_capacity(k * d);
-        for _ in 0..k {
-            let idx = rng.next_usize() % n;
-            let row = self.row(idx);
-            out.extend_from_slice(row);
+        for &idx in &indices[..k] {
+            out.extend_from_slice(self.row(idx));
         }
 
         NdArray::from_vec(Shape::new(vec![k, d]), out)
@@ -48,30 +75,46 @@ impl<T: IronFloat> NdArray<T> {
             self.set_row(i, &buf);
         }
 
-        let xt = self.transpose();
-        let gram = xt.matmul(self);
-
-        let mut cov = gram.as_f64();
+        // upper triangle of X^T X from one pass over the rows, then mirrored
+        let mut cov = vec![0.0f64; d * d];
+        let mut centred = vec![0.0f64; d];
+        for i in 0..n {
+            for (c, x) in centred.iter_mut().zip(self.row(i)) {
+                *c = x.to_f64().unwrap_or(0.0);
+            }
+            for j in 0..d {
+                let cj = centred[j];
+                for (acc, ck) in cov[j * d + j..(j + 1) * d].iter_mut().zip(&centred[j..]) {
+                    *acc += cj * ck;
+                }
+            }
+        }
         let inv = 1.0 / (n - 1) as f64;
-        for v in cov.as_mut_slice().expect("cov is owned").iter_mut() {
-            *v *= inv;
+        for j in 0..d {
+            for k in j..d {
+                let v = cov[j * d + k] * inv;
+                cov[j * d + k] = v;
+                cov[k * d + j] = v;
+            }
         }
-        cov
+        NdArray::from_vec(Shape::new(vec![d, d]), cov)
     }
 
 
-    pub fn intrinsic_dim(
+    /// Cumulative fraction of variance explained by the leading principal axes of a
+    /// sample of at most `sample_cap` rows, one entry per dimension. All zeros when the
+    /// sample has no variance.
+    pub fn explained_variance(
         &self,
-        variance_threshold: f64,
         sample_cap: usize,
         rng: &mut Generator,
-    ) -> Result<usize, &'static str> {
-        assert_eq!(self.ndim(), 2, "intrinsic_dim requires a 2D array");
+    ) -> Result<Vec<f64>, &'static str> {
+        assert_eq!(self.ndim(), 2, "explained_variance requires a 2D array");
         let dims = self.shape().dims();
         let n = dims[0];
         let d = dims[1];
 
-        if n < 2 {
+        if n.min(sample_cap) < 2 {
             return Err("intrinsic_dim requires at least 2 samples");
         }
         if d == 0 {
@@ -82,29 +125,203 @@ impl<T: IronFloat> NdArray<T> {
 
         let cov = sample.covariance_mut();
 
-        let (eigvals, _) = cov.eig()?;
+        let eigvals = cov.eigvalsh()?;
 
         let mut vals: Vec<f64> = eigvals
             .as_slice_unchecked()
             .iter()
             .map(|&v| v.max(0.0))
             .collect();
-        vals.sort_by(|a, b| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+        vals.sort_by(|a, b| b.total_cmp(a));
 
         let total: f64 = vals.iter().sum();
         if total <= 0.0 {
-            return Ok(0);
+            return Ok(vec![0.0; d]);
         }
 
-        let target = variance_threshold * total;
         let mut cumsum = 0.0;
-        for (i, &v) in vals.iter().enumerate() {
+        Ok(vals.iter().map(|&v| {
             cumsum += v;
-            if cumsum >= target {
-                return Ok(i + 1);
+            (cumsum / total).min(1.0)
+        }).collect())
+    }
+
+    /// Principal axes needed to explain `variance_threshold` of the variance of a sample.
+    pub fn intrinsic_dim(
+        &self,
+        variance_threshold: f64,
+        sample_cap: usize,
+        rng: &mut Generator,
+    ) -> Result<usize, &'static str> {
+        let curve = self.explained_variance(sample_cap, rng)?;
+        Ok(dim_for_variance(&curve, variance_threshold))
+    }
+
+    /// Estimates the intrinsic dimension from a sample of at most `sample_cap` rows, drawn
+    /// without replacement. Neighbor-based estimators use euclidean distances.
+    pub fn estimate_intrinsic_dim(
+        &self,
+        estimator: IntrinsicDimEstimator,
+        sample_cap: usize,
+        rng: &mut Generator,
+    ) -> Result<f64, &'static str> {
+        match estimator {
+            IntrinsicDimEstimator::Pca { variance_threshold } => {
+                self.intrinsic_dim(variance_threshold, sample_cap, rng).map(|id| id as f64)
             }
+            IntrinsicDimEstimator::Mle { k } => self.mle_intrinsic_dim(k, sample_cap, rng),
+            IntrinsicDimEstimator::TwoNn => self.twonn_intrinsic_dim(sample_cap, rng),
+            IntrinsicDimEstimator::Correlation => self.correlation_dim(sample_cap, rng),
         }
+    }
+
+    /// Levina-Bickel estimate over each sampled row's `k` nearest sampled neighbors.
+    /// Rows with a duplicate are skipped.
+    pub fn mle_intrinsic_dim(
+        &self,
+        k: usize,
+        sample_cap: usize,
+        rng: &mut Generator,
+    ) -> Result<f64, &'static str> {
+        if k < 2 {
+            return Err("mle intrinsic dimension requires k >= 2");
+        }
+        let neighbors = self.sample_knn_distances(k, sample_cap, rng)?;
+
+        // MacKay-Ghahramani: average the inverse per-point estimates
+        let mut inv_sum = 0.0;
+        let mut count = 0usize;
+        for dists in &neighbors {
+            if dists[0] <= 0.0 {
+                continue;
+            }
+            let t_k = dists[k - 1];
+            let log_sum: f64 = dists[..k - 1].iter().map(|&t| (t_k / t).ln()).sum();
+            inv_sum += log_sum / (k - 1) as f64;
+            count += 1;
+        }
+        if count == 0 || inv_sum <= 0.0 {
+            return Err("intrinsic dimension is undefined when every neighbor is equidistant");
+        }
+        Ok(count as f64 / inv_sum)
+    }
+
+    /// TwoNN estimate from each sampled row's two nearest sampled neighbors. Rows with a
+    /// duplicate are skipped.
+    pub fn twonn_intrinsic_dim(
+        &self,
+        sample_cap: usize,
+        rng: &mut Generator,
+    ) -> Result<f64, &'static str> {
+        let neighbors = self.sample_knn_distances(2, sample_cap, rng)?;
+
+        let mut log_sum = 0.0;
+        let mut count = 0usize;
+        for dists in &neighbors {
+            if dists[0] <= 0.0 {
+                continue;
+            }
+            log_sum += (dists[1] / dists[0]).ln();
+            count += 1;
+        }
+        if count == 0 || log_sum <= 0.0 {
+            return Err("intrinsic dimension is undefined when every neighbor is equidistant");
+        }
+        Ok(count as f64 / log_sum)
+    }
+
+    /// Correlation dimension: the least-squares slope of log C(r) against log r, where C(r)
+    /// is the fraction of sampled pairs closer than r, over the smallest 0.5% to 10% of
+    /// pair distances. Quadratic in the sample size.
+    pub fn correlation_dim(
+        &self,
+        sample_cap: usize,
+        rng: &mut Generator,
+    ) -> Result<f64, &'static str> {
+        assert_eq!(self.ndim(), 2, "correlation_dim requires a 2D array");
+        let sample = self.sample_rows(sample_cap, rng);
+        let m = sample.shape().dims()[0];
+        if m < 3 {
+            return Err("correlation dimension requires at least 3 samples");
+        }
+
+        let mut log_dists = Vec::with_capacity(m * (m - 1) / 2);
+        for i in 0..m {
+            let a = sample.row(i);
+            for j in i + 1..m {
+                let d2: f64 = a.iter().zip(sample.row(j))
+                    .map(|(x, y)| {
+                        let diff = x.to_f64().unwrap_or(0.0) - y.to_f64().unwrap_or(0.0);
+                        diff * diff
+                    })
+                    .sum();
+                if d2 > 0.0 {
+                    log_dists.push(0.5 * d2.ln());
+                }
+            }
+        }
+        let n_pairs = m * (m - 1) / 2;
+        log_dists.sort_by(|a, b| a.total_cmp(b));
+
+        let mut points = Vec::with_capacity(CORRELATION_QUANTILES.len());
+        for &q in &CORRELATION_QUANTILES {
+            let rank = (q * n_pairs as f64).ceil() as usize;
+            // pairs at distance 0 sit below every radius
+            let zeros = n_pairs - log_dists.len();
+            if rank > zeros && rank - zeros <= log_dists.len() {
+                points.push((log_dists[rank - zeros - 1], q.ln()));
+            }
+        }
+        let slope = least_squares_slope(&points)
+            .ok_or("correlation dimension is undefined when sampled distances don't vary")?;
+        Ok(slope)
+    }
+
+    /// Distances from each sampled row to its `k` nearest other sampled rows, nearest first.
+    fn sample_knn_distances(
+        &self,
+        k: usize,
+        sample_cap: usize,
+        rng: &mut Generator,
+    ) -> Result<Vec<Vec<f64>>, &'static str> {
+        assert_eq!(self.ndim(), 2, "intrinsic dimension estimators require a 2D array");
+        let sample = self.sample_rows(sample_cap, rng);
+        if sample.shape().dims()[0] <= k {
+            return Err("intrinsic dimension estimators require more than k samples");
+        }
+        let tree = BruteForce::new(sample.clone(), DistanceMetric::Euclidean);
+        let neighbors = tree.query_knn_batch(&sample, k + 1)
+            .map_err(|_| "intrinsic dimension estimators failed to query neighbors")?;
+        Ok(neighbors.into_iter().enumerate().map(|(i, row)| {
+            row.into_iter()
+                .filter(|&(j, _)| j != i)
+                .take(k)
+                .map(|(_, d)| d.to_f64().unwrap_or(0.0))
+                .collect()
+        }).collect())
+    }
+}
+
+fn least_squares_slope(points: &[(f64, f64)]) -> Option<f64> {
+    if points.len() < 2 {
+        return None;
+    }
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    let sxy: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    if sxx <= 0.0 {
+        return None;
+    }
+    Some(sxy / sxx)
+}
 
-        Ok(d)
+/// Number of leading axes whose cumulative explained variance reaches `threshold`, or 0
+/// when there is no variance.
+pub fn dim_for_variance(curve: &[f64], threshold: f64) -> usize {
+    if curve.last().is_none_or(|&c| c <= 0.0) {
+        return 0;
     }
-}
\ No newline at end of file
+    curve.iter().position(|&c| c >= threshold).map_or(curve.len(), |i| i + 1)
+}
@@ -1,4 +1,5 @@
 use crate::spatial::common::{DistanceMetric, IronFloat};
+use crate::error::IronForestError;
 
 pub struct ChildTraversal<F> {
     pub child_idx: usize,
@@ -27,6 +28,10 @@ pub trait SpatialTree: Sync {
 
     fn root(&self) -> usize { 0 }
 
+    /// Whether reported distances are post-transformed reduced distances rather than
+    /// `DistanceMetric::distance`. The two differ for the cosine metric.
+    fn reports_reduced(&self) -> bool { Self::REDUCED }
+
     fn is_leaf(&self, idx: usize) -> bool {
         self.node_left(idx).is_none()
     }
@@ -54,4 +59,9 @@ pub trait SpatialTree: Sync {
     }
 
     fn n_points(&self) -> usize;
+
+    /// Fails for trees that no longer hold the rows neighbor queries compare against.
+    fn check_raw_points(&self) -> Result<(), IronForestError> {
+        Ok(())
+    }
 }
@@ -0,0 +1,328 @@
+use std::time::Instant;
+
+use crate::array::NdArray;
+use crate::error::IronForestError;
+use crate::random::Generator;
+use crate::spatial::common::{IronFloat, KernelType};
+use crate::spatial::spatial_index::{IndexData, IndexFloat, QueryInput, SpatialIndexBuilder, TreeType};
+use crate::spatial::spatial_stats::{dim_for_variance, IntrinsicDimEstimator};
+
+/// The queries an index is expected to serve, which changes the best tree in high dimensions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Workload {
+    /// Exact nearest neighbors.
+    #[default]
+    Knn,
+    /// Approximate nearest neighbors, where projection trees win in high dimensions.
+    Ann,
+    /// Kernel density, which needs tight node bounds to prune.
+    Kde,
+}
+
+/// Thresholds used by automatic tree selection.
+#[derive(Clone, Copy, Debug)]
+pub struct SelectionThresholds {
+    /// Below this many points a brute force scan is used.
+    pub min_tree_points: usize,
+    /// KDTree up to this dimension.
+    pub max_kd_dim: usize,
+    /// Above this dimension exact queries use a brute force scan.
+    pub max_tree_dim: usize,
+    /// RPTree when intrinsic / ambient dimension falls below this ratio.
+    pub max_rp_ratio: f64,
+    /// BallTree up to this intrinsic dimension.
+    pub max_ball_intrinsic_dim: usize,
+    /// How the intrinsic dimension is estimated.
+    pub estimator: IntrinsicDimEstimator,
+    /// Rows sampled to estimate the intrinsic dimension.
+    pub sample_size: usize,
+}
+
+impl Default for SelectionThresholds {
+    fn default() -> Self {
+        SelectionThresholds {
+            min_tree_points: 1000,
+            max_kd_dim: 16,
+            max_tree_dim: 512,
+            max_rp_ratio: 0.3,
+            max_ball_intrinsic_dim: 20,
+            estimator: IntrinsicDimEstimator::default(),
+            sample_size: 2000,
+        }
+    }
+}
+
+/// The rule that decided a tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionRule {
+    SmallN,
+    LowDim,
+    AnnHighDim,
+    VeryHighDim,
+    IntrinsicDimUnavailable,
+    LowIntrinsicRatio,
+    LowIntrinsicDim,
+    HighIntrinsicDim,
+}
+
+impl SelectionRule {
+    pub fn name(self) -> &'static str {
+        match self {
+            SelectionRule::SmallN => "small_n",
+            SelectionRule::LowDim => "low_dim",
+            SelectionRule::AnnHighDim => "ann_high_dim",
+            SelectionRule::VeryHighDim => "very_high_dim",
+            SelectionRule::IntrinsicDimUnavailable => "intrinsic_dim_unavailable",
+            SelectionRule::LowIntrinsicRatio => "low_intrinsic_ratio",
+            SelectionRule::LowIntrinsicDim => "low_intrinsic_dim",
+            SelectionRule::HighIntrinsicDim => "high_intrinsic_dim",
+        }
+    }
+}
+
+/// Build and query time of one candidate tree.
+#[derive(Clone, Copy, Debug)]
+pub struct TreeBenchmark {
+    pub tree_type: TreeType,
+    pub build_seconds: f64,
+    pub query_seconds: f64,
+}
+
+/// A chosen tree with the statistics and rule behind it.
+#[derive(Clone, Debug)]
+pub struct TreeRecommendation {
+    pub tree_type: TreeType,
+    pub rule: SelectionRule,
+    /// The rule applied to the measured statistics, in words.
+    pub reason: String,
+    pub workload: Workload,
+    pub n_points: usize,
+    pub dim: usize,
+    /// `None` when the sample is too small or the estimate failed.
+    pub intrinsic_dim: Option<f64>,
+    pub estimator: IntrinsicDimEstimator,
+    /// Cumulative explained variance per principal axis, empty when unavailable.
+    pub explained_variance: Vec<f64>,
+    /// Candidate trees, empty unless a benchmark was requested.
+    pub benchmark: Vec<TreeBenchmark>,
+}
+
+/// Applies the selection rules in order. `intrinsic_dim` is only called when a rule needs it.
+fn select(
+    n: usize,
+    d: usize,
+    workload: Workload,
+    t: &SelectionThresholds,
+    intrinsic_dim: impl FnOnce() -> Option<f64>,
+) -> (TreeType, SelectionRule) {
+    if n < t.min_tree_points {
+        return (TreeType::BruteForce, SelectionRule::SmallN);
+    }
+    if d <= t.max_kd_dim {
+        return (TreeType::KDTree, SelectionRule::LowDim);
+    }
+    if workload == Workload::Ann {
+        return (TreeType::RPTree, SelectionRule::AnnHighDim);
+    }
+    if d > t.max_tree_dim {
+        return (TreeType::BruteForce, SelectionRule::VeryHighDim);
+    }
+
+    let Some(id) = intrinsic_dim() else {
+        return (TreeType::KDTree, SelectionRule::IntrinsicDimUnavailable);
+    };
+
+    if workload != Workload::Kde && id / (d as f64) < t.max_rp_ratio {
+        return (TreeType::RPTree, SelectionRule::LowIntrinsicRatio);
+    }
+    if id <= t.max_ball_intrinsic_dim as f64 {
+        return (TreeType::BallTree, SelectionRule::LowIntrinsicDim);
+    }
+    match workload {
+        Workload::Kde => (TreeType::BruteForce, SelectionRule::HighIntrinsicDim),
+        _ => (TreeType::VPTree, SelectionRule::HighIntrinsicDim),
+    }
+}
+
+fn describe(
+    rule: SelectionRule,
+    n: usize,
+    d: usize,
+    id: Option<f64>,
+    workload: Workload,
+    t: &SelectionThresholds,
+) -> String {
+    let id = id.unwrap_or(0.0);
+    match rule {
+        SelectionRule::SmallN => format!("n = {} < {}: a brute force scan beats building a tree", n, t.min_tree_points),
+        SelectionRule::LowDim => format!("d = {} <= {}: axis-aligned KD splits prune well", d, t.max_kd_dim),
+        SelectionRule::AnnHighDim => format!("d = {} > {} with an approximate workload: random projection trees", d, t.max_kd_dim),
+        SelectionRule::VeryHighDim => format!("d = {} > {}: tree bounds rarely prune, so exact queries scan", d, t.max_tree_dim),
+        SelectionRule::IntrinsicDimUnavailable => "intrinsic dimension could not be estimated, falling back to KDTree".to_string(),
+        SelectionRule::LowIntrinsicRatio => format!(
+            "intrinsic dimension {:.1} is {:.2} of d = {} (< {}): random projections follow the low-dimensional structure",
+            id, id / d as f64, d, t.max_rp_ratio
+        ),
+        SelectionRule::LowIntrinsicDim => format!("intrinsic dimension {:.1} <= {}: ball bounds prune well", id, t.max_ball_intrinsic_dim),
+        SelectionRule::HighIntrinsicDim => match workload {
+            Workload::Kde => format!("intrinsic dimension {:.1} > {}: density sums reach most points, so KDE scans", id, t.max_ball_intrinsic_dim),
+            _ => format!("intrinsic dimension {:.1} > {}: vantage point partitions", id, t.max_ball_intrinsic_dim),
+        },
+    }
+}
+
+/// Picks a tree for `data` and `workload`, estimating the intrinsic dimension with
+/// `thresholds.estimator` only when the size and dimension rules don't decide.
+pub fn select_tree<T: IronFloat>(
+    data: &NdArray<T>,
+    workload: Workload,
+    thresholds: &SelectionThresholds,
+    rng: &mut Generator,
+) -> TreeType {
+    let dims = data.shape().dims();
+    select(dims[0], dims[1], workload, thresholds, || {
+        data.estimate_intrinsic_dim(thresholds.estimator, thresholds.sample_size, rng).ok()
+    }).0
+}
+
+/// Picks a tree for `data` from its size, dimension and estimated intrinsic dimension, with
+/// the default thresholds and a kNN workload.
+pub fn auto_select_tree<T: IronFloat>(data: &NdArray<T>, rng: &mut Generator) -> TreeType {
+    select_tree(data, Workload::Knn, &SelectionThresholds::default(), rng)
+}
+
+/// Picks a tree like `select_tree` and reports why. The explained-variance curve is always
+/// measured. With `benchmark_queries > 0`, every tree is also built and timed on that many
+/// sampled rows.
+pub fn recommend_tree<T: IndexFloat>(
+    data: &NdArray<T>,
+    workload: Workload,
+    thresholds: &SelectionThresholds,
+    benchmark_queries: usize,
+    seed: u64,
+) -> Result<TreeRecommendation, IronForestError> {
+    let dims = data.shape().dims();
+    if dims.len() != 2 {
+        return Err(IronForestError::InvalidShape { ndim: dims.len() });
+    }
+    let (n, d) = (dims[0], dims[1]);
+    // each estimate samples with its own generator, so the intrinsic dimension matches
+    // `select_tree` seeded with `seed`
+    let explained_variance = data.explained_variance(thresholds.sample_size, &mut Generator::from_seed(seed))
+        .unwrap_or_default();
+    let intrinsic_dim = match thresholds.estimator {
+        IntrinsicDimEstimator::Pca { variance_threshold } => match explained_variance.is_empty() {
+            true => None,
+            false => Some(dim_for_variance(&explained_variance, variance_threshold) as f64),
+        },
+        estimator => data.estimate_intrinsic_dim(estimator, thresholds.sample_size, &mut Generator::from_seed(seed)).ok(),
+    };
+
+    let (tree_type, rule) = select(n, d, workload, thresholds, || intrinsic_dim);
+    let benchmark = match benchmark_queries {
+        0 => Vec::new(),
+        q => benchmark_trees(data, workload, q, seed)?,
+    };
+    Ok(TreeRecommendation {
+        tree_type,
+        rule,
+        reason: describe(rule, n, d, intrinsic_dim, workload, thresholds),
+        workload,
+        n_points: n,
+        dim: d,
+        intrinsic_dim,
+        estimator: thresholds.estimator,
+        explained_variance,
+        benchmark,
+    })
+}
+
+const BENCHMARK_K: usize = 10;
+
+/// Builds every tree over `data` and times `n_queries` sampled rows under `workload`: a
+/// 10-NN query, aNN with 20 candidates, or Gaussian KDE at Scott's bandwidth.
+pub fn benchmark_trees<T: IndexFloat>(
+    data: &NdArray<T>,
+    workload: Workload,
+    n_queries: usize,
+    seed: u64,
+) -> Result<Vec<TreeBenchmark>, IronForestError> {
+    let dims = data.shape().dims();
+    let n = dims[0];
+    if n == 0 {
+        return Err(IronForestError::EmptyIndex);
+    }
+    let queries = data.sample_rows(n_queries, &mut Generator::from_seed(seed));
+    let queries = T::into_index_data(queries);
+    let k = BENCHMARK_K.min(n);
+    let bandwidth = scott_bandwidth(data);
+
+    let candidates = [TreeType::KDTree, TreeType::BallTree, TreeType::VPTree, TreeType::RPTree, TreeType::BruteForce];
+    let mut results = Vec::with_capacity(candidates.len());
+    for tree_type in candidates {
+        let builder = SpatialIndexBuilder::new().tree_type(tree_type).seed(seed);
+        let start = Instant::now();
+        let index = builder.build(data.clone())?;
+        let build_seconds = start.elapsed().as_secs_f64();
+
+        let query = match &queries {
+            IndexData::F64(q) => QueryInput::F64(q),
+            IndexData::F32(q) => QueryInput::F32(q),
+        };
+        let start = Instant::now();
+        match workload {
+            Workload::Knn => { index.query_knn(query, true, k)?; }
+            Workload::Ann => { index.query_ann(query, true, k, 2 * k, None)?; }
+            Workload::Kde => { index.kernel_density(Some(query), bandwidth, KernelType::Gaussian, false, false)?; }
+        }
+        results.push(TreeBenchmark { tree_type, build_seconds, query_seconds: start.elapsed().as_secs_f64() });
+    }
+    Ok(results)
+}
+
+/// Scott's rule on the mean per-dimension standard deviation.
+fn scott_bandwidth<T: IronFloat>(data: &NdArray<T>) -> f64 {
+    let dims = data.shape().dims();
+    let (n, d) = (dims[0], dims[1]);
+    let mut spread = 0.0;
+    for j in 0..d {
+        let col: Vec<f64> = (0..n).map(|i| data.row(i)[j].to_f64().unwrap()).collect();
+        let mean = col.iter().sum::<f64>() / n as f64;
+        spread += (col.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n as f64).sqrt();
+    }
+    let sigma = (spread / d.max(1) as f64).max(f64::EPSILON);
+    sigma * (n as f64).powf(-1.0 / (d as f64 + 4.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::array::Shape;
+
+    #[test]
+    fn recommend_tree_agrees_with_select_tree_on_sampled_data() {
+        // more rows than `sample_size`, so every estimate draws a sample
+        let (n, d, seed) = (600, 24, 3);
+        let data = Generator::from_seed(1).standard_normal(Shape::new(vec![n, d]));
+        let estimators = [
+            IntrinsicDimEstimator::Pca { variance_threshold: 0.9 },
+            IntrinsicDimEstimator::Mle { k: 10 },
+            IntrinsicDimEstimator::TwoNn,
+            IntrinsicDimEstimator::Correlation,
+        ];
+        for estimator in estimators {
+            let thresholds = SelectionThresholds { min_tree_points: 100, sample_size: 200, estimator, ..Default::default() };
+            assert!(n > thresholds.sample_size);
+
+            let recommendation = recommend_tree(&data, Workload::Knn, &thresholds, 0, seed).unwrap();
+            let expected = data.estimate_intrinsic_dim(estimator, thresholds.sample_size, &mut Generator::from_seed(seed)).ok();
+            assert_eq!(recommendation.intrinsic_dim, expected, "{:?}", estimator);
+            assert_eq!(
+                recommendation.tree_type,
+                select_tree(&data, Workload::Knn, &thresholds, &mut Generator::from_seed(seed)),
+                "{:?}",
+                estimator,
+            );
+        }
+    }
+}
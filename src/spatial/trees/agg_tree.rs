@@ -1,5 +1,10 @@
-use crate::{KernelType, Shape, array::NdArray, spatial::common::{DistanceMetric, IronFloat}};
+use crate::{KernelType, Shape, array::NdArray, random::Generator, spatial::common::{DistanceMetric, IronFloat, LogSumExp}};
+use crate::spatial::queries::kde::LOG_KDE_RTOL;
+use crate::spatial::queries::{KnnQuery, RadiusQuery};
+use crate::spatial::SpatialTree;
+use crate::error::{IronForestError, check_queries};
 use rayon::prelude::*;
+use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 
 const KDE_PAR_THRESHOLD: usize = 512;
@@ -18,6 +23,10 @@ pub struct AggNode<T> {
     pub end: usize,
     pub left: Option<usize>,
     pub right: Option<usize>,
+    // Full covariance (dim x dim) or principal axes scaled by their standard deviation
+    // (rank x dim), depending on the tree's `MomentMode`. Empty when isotropic.
+    #[serde(default)]
+    pub covariance: Vec<f64>,
 }
 
 
@@ -34,17 +43,74 @@ pub struct AggTree<T: IronFloat> {
     pub kernel: KernelType,
     pub bandwidth: f64,
     pub atol: f64,
+    // Trees saved before query-time parameters existed were always compacted
+    #[serde(default = "compact_default")]
+    pub compact: bool,
+    #[serde(default)]
+    pub moments: MomentMode,
+    // Index given to the next inserted point
+    #[serde(default)]
+    pub next_index: usize,
+}
+
+fn compact_default() -> bool {
+    true
+}
+
+const POWER_ITERS: usize = 100;
+
+/// Second moments each node stores for its Taylor approximation.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum MomentMode {
+    /// Scalar moments of the distance to the centroid, assuming isotropic spread.
+    #[default]
+    Isotropic,
+    /// Full covariance matrix per node.
+    Covariance,
+    /// The leading principal axes of each node's covariance, with the remaining
+    /// variance spread evenly over the other directions.
+    LowRank(usize),
+}
+
+/// How `AggTree` decides which nodes to approximate during a density query.
+#[derive(Clone, Copy, Debug)]
+pub enum ErrorTarget {
+    /// Approximate nodes whose estimated error for the query parameters is below this value.
+    Absolute(f64),
+    /// Refine traversal until the certified error bound is within this fraction of the density.
+    Relative(f64),
 }
 
 impl<T: IronFloat> AggTree<T> {
+    /// Builds the full hierarchy down to `leaf_size` and keeps every point, so the
+    /// bandwidth, kernel and tolerance can be changed per query; the values given here
+    /// are the query defaults. With `compact`, splitting stops once a node's error bound
+    /// is below `atol` and the points under it are dropped. That uses less memory but
+    /// ties queries to the build kernel and bandwidth. Anisotropic `moments` require the
+    /// euclidean metric, and low-rank moments are capped at the data dimension.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         mut data: NdArray<T>, leaf_size: usize, metric: DistanceMetric,
-        kernel: KernelType, bandwidth: f64, atol: f64,
+        kernel: KernelType, bandwidth: f64, atol: f64, compact: bool, moments: MomentMode,
     ) -> Self {
         let shape = data.shape().dims();
         assert!(shape.len() == 2, "Expected 2D array (n_points, dim)");
         let n_points = shape[0];
         let dim = shape[1];
+        match moments {
+            MomentMode::Isotropic => {}
+            MomentMode::Covariance => {
+                assert!(matches!(metric, DistanceMetric::Euclidean), "Anisotropic moments require the euclidean metric");
+            }
+            MomentMode::LowRank(rank) => {
+                assert!(matches!(metric, DistanceMetric::Euclidean), "Anisotropic moments require the euclidean metric");
+                assert!(rank >= 1, "Rank must be at least 1");
+            }
+        }
+        let moments = match moments {
+            MomentMode::LowRank(rank) => MomentMode::LowRank(rank.min(dim)),
+            other => other,
+        };
 
         if !data.is_owned() {
             data = data.to_contiguous();
@@ -67,28 +133,21 @@ impl<T: IronFloat> AggTree<T> {
             kernel,
             bandwidth,
             atol,
+            compact,
+            moments,
+            next_index: n_points,
         };
 
         tree.build_recursive(0, n_points);
-        tree.reorder_data();
-
-        let mut live = Vec::new();
-        tree.collect_live_ranges(0, &mut live);
-        let remap = tree.compact_data(&live);
-        tree.remap_nodes(&remap);
-
-        tree
-    }
 
-    fn reorder_data(&mut self) {
-        let mut new_data = vec![T::zero(); self.data.len()];
-
-        for (new_idx, &old_idx) in self.indices.iter().enumerate() {
-            let dst = new_idx * self.dim;
-            new_data[dst..dst + self.dim].copy_from_slice(self.data.row(old_idx));
+        if compact {
+            let mut live = Vec::new();
+            tree.collect_live_ranges(0, &mut live);
+            let remap = tree.compact_data(&live);
+            tree.remap_nodes(&remap);
         }
 
-        self.data = NdArray::from_vec(Shape::new(vec![self.n_points, self.dim]), new_data);
+        tree
     }
 
     fn collect_live_ranges(&self, node_idx: usize, live: &mut Vec<(usize, usize)>) {
@@ -177,17 +236,101 @@ impl<T: IronFloat> AggTree<T> {
         (centroid, max_dist, variance, moment3, moment4)
     }
 
+    fn node_covariance(&self, start: usize, end: usize, centroid: &[T]) -> Vec<f64> {
+        let rank = match self.moments {
+            MomentMode::Isotropic => return Vec::new(),
+            MomentMode::Covariance => None,
+            MomentMode::LowRank(rank) => Some(rank),
+        };
+
+        let d = self.dim;
+        let mut cov = vec![0.0f64; d * d];
+        let mut diff = vec![0.0f64; d];
+        for i in start..end {
+            let p = self.data.row(i);
+            for j in 0..d {
+                diff[j] = (p[j] - centroid[j]).to_f64().unwrap();
+            }
+            for a in 0..d {
+                for b in a..d {
+                    cov[a * d + b] += diff[a] * diff[b];
+                }
+            }
+        }
+        let n = (end - start) as f64;
+        for a in 0..d {
+            for b in a..d {
+                cov[a * d + b] /= n;
+                cov[b * d + a] = cov[a * d + b];
+            }
+        }
+
+        match rank {
+            None => cov,
+            Some(rank) => principal_axes(cov, d, rank),
+        }
+    }
+
+    /// Variance of a node's points along the unit direction `dir`.
+    fn directional_variance(&self, node: &AggNode<T>, dir: &[f64]) -> f64 {
+        let d = self.dim;
+        match self.moments {
+            MomentMode::Isotropic => node.variance / d as f64,
+            MomentMode::Covariance => (0..d)
+                .map(|a| dir[a] * (0..d).map(|b| node.covariance[a * d + b] * dir[b]).sum::<f64>())
+                .sum(),
+            MomentMode::LowRank(rank) => {
+                let mut along = 0.0;
+                let mut captured = 0.0;
+                let mut explained = 0.0;
+                for axis in node.covariance.chunks_exact(d) {
+                    let proj: f64 = axis.iter().zip(dir).map(|(a, u)| a * u).sum();
+                    let lambda: f64 = axis.iter().map(|a| a * a).sum();
+                    along += proj * proj;
+                    explained += lambda;
+                    if lambda > 0.0 {
+                        captured += proj * proj / lambda;
+                    }
+                }
+                let residual = match d > rank {
+                    true => (node.variance - explained).max(0.0) / (d - rank) as f64,
+                    false => 0.0,
+                };
+                along + residual * (1.0 - captured).max(0.0)
+            }
+        }
+    }
+
+    /// Taylor estimate relative to K(r_c). With anisotropic moments the quadratic term is
+    /// the multivariate one, K'' u'Su + K'/r (tr S - u'Su) for the direction u to the query.
+    fn taylor_factor(&self, query: &[T], node: &AggNode<T>, r_c: f64, h: f64, kernel: KernelType) -> f64 {
+        let (r2, r3, r4) = kernel.derivative_ratios(r_c, h);
+        if matches!(self.moments, MomentMode::Isotropic) {
+            return 1.0 + 0.5 * r2 * node.variance + (1.0 / 6.0) * r3 * node.moment3 + (1.0 / 24.0) * r4 * node.moment4;
+        }
+        // K'/r tends to K'' at the centroid
+        if r_c <= 1e-12 * h {
+            return 1.0 + 0.5 * r2 * node.variance;
+        }
+        let dir: Vec<f64> = query.iter().zip(&node.center).map(|(q, c)| (*q - *c).to_f64().unwrap() / r_c).collect();
+        let along = self.directional_variance(node, &dir);
+        let r1 = kernel.first_derivative_ratio(r_c, h) / r_c;
+        1.0 + 0.5 * (r2 * along + r1 * (node.variance - along))
+    }
+
 
     fn furthest_from(&self, query: &[T], start: usize, end: usize) -> usize {
         (start..end)
             .max_by(|&a, &b| {
                 let da = self.metric.post_transform(self.metric.reduced_distance(query, self.data.row(a)));
                 let db = self.metric.post_transform(self.metric.reduced_distance(query, self.data.row(b)));
-                da.partial_cmp(&db).unwrap()
+                da.total_cmp(&db)
             })
             .unwrap()
     }
 
+    /// Splits [start, end) at the median projection onto the axis between two far points.
+    /// Rows are permuted together with their indices so every node owns a contiguous block.
     fn pivot_partition(&mut self, start: usize, end: usize, centroid: &[T]) -> usize {
         let p1_slot = self.furthest_from(centroid, start, end);
         let p1 = self.data.row(p1_slot).to_vec();
@@ -201,33 +344,36 @@ impl<T: IronFloat> AggTree<T> {
             .map(|i| {
                 let p = self.data.row(i);
                 let proj: T = p.iter().zip(&axis).map(|(x, a)| *x * *a).sum();
-                (proj, self.indices[i])
+                (proj, i)
             })
             .collect();
 
         let mid_offset = (end - start) / 2;
         projections.select_nth_unstable_by(mid_offset, |a, b| {
-            a.0.partial_cmp(&b.0).unwrap()
+            a.0.total_cmp(&b.0)
         });
 
-        self.indices[start..end].copy_from_slice(
-            &projections.iter().map(|&(_, idx)| idx).collect::<Vec<_>>()
-        );
+        let dim = self.dim;
+        let mut rows = Vec::with_capacity((end - start) * dim);
+        let mut ids = Vec::with_capacity(end - start);
+        for &(_, slot) in &projections {
+            rows.extend_from_slice(self.data.row(slot));
+            ids.push(self.indices[slot]);
+        }
+        self.indices[start..end].copy_from_slice(&ids);
+        self.data.as_mut_slice().expect("AggTree data must be owned")[start * dim..end * dim]
+            .copy_from_slice(&rows);
 
         start + mid_offset
     }
 
-
-    fn build_recursive(&mut self, start: usize, end: usize) -> usize {
+    fn make_node(&self, start: usize, end: usize) -> AggNode<T> {
         let (center, radius, variance, moment3, moment4) = self.init_node(start, end);
+        let covariance = self.node_covariance(start, end, &center);
         let n = (end - start) as f64;
-        let radius_f64 = radius.to_f64().unwrap();
-
-        let max_abs_error = self.kernel.node_error_bound(n, radius_f64, self.bandwidth);
-        let mut mid = self.pivot_partition(start, end, &center);
+        let max_abs_error = self.node_error_bound(self.kernel, n, radius.to_f64().unwrap(), self.bandwidth);
 
-        let node_idx = self.nodes.len();
-        self.nodes.push(AggNode {
+        AggNode {
             center,
             radius,
             variance,
@@ -238,13 +384,29 @@ impl<T: IronFloat> AggTree<T> {
             end,
             left: None,
             right: None,
-        });
+            covariance,
+        }
+    }
+
+    fn build_recursive(&mut self, start: usize, end: usize) -> usize {
+        let node = self.make_node(start, end);
+        let is_leaf = end - start <= self.leaf_size || (self.compact && node.max_abs_error < self.atol);
 
-        let count = end - start;
-        if count <= self.leaf_size || max_abs_error < self.atol {
-            return node_idx;
+        let node_idx = self.nodes.len();
+        self.nodes.push(node);
+        if !is_leaf {
+            self.split_node(node_idx);
         }
 
+        node_idx
+    }
+
+    /// Partitions a node's points and builds both subtrees under it.
+    fn split_node(&mut self, node_idx: usize) {
+        let (start, end) = (self.nodes[node_idx].start, self.nodes[node_idx].end);
+        let center = self.nodes[node_idx].center.clone();
+        let mut mid = self.pivot_partition(start, end, &center);
+
         if mid == start { mid = start + 1; }
         else if mid == end { mid = end - 1; }
 
@@ -253,106 +415,773 @@ impl<T: IronFloat> AggTree<T> {
 
         self.nodes[node_idx].left = Some(left_idx);
         self.nodes[node_idx].right = Some(right_idx);
-
-        node_idx
     }
 
     fn min_distance_to_node_inner(&self, node_idx: usize, query: &[T]) -> f64 {
         let node = &self.nodes[node_idx];
         let d = self.metric.post_transform(self.metric.reduced_distance(query, &node.center));
-        (d - node.radius).max(T::zero()).to_f64().unwrap()
+        self.distance_range(d.to_f64().unwrap(), node.radius.to_f64().unwrap()).0
+    }
+
+    /// Smallest and largest distance from a query to any point within `radius` of a
+    /// centroid at distance `r_c`. Cosine distance is half the squared euclidean distance
+    /// between unit vectors, so the triangle inequality is applied to the euclidean ones.
+    fn distance_range(&self, r_c: f64, radius: f64) -> (f64, f64) {
+        match self.metric {
+            DistanceMetric::Cosine => {
+                let (e, r) = ((2.0 * r_c).sqrt(), (2.0 * radius).sqrt());
+                (0.5 * (e - r).max(0.0).powi(2), 0.5 * (e + r).powi(2))
+            }
+            _ => ((r_c - radius).max(0.0), r_c + radius),
+        }
+    }
+
+    /// Radius enclosing a child of radius `child_radius` whose centroid is `offset` away.
+    fn enclosing_radius(&self, offset: T, child_radius: T) -> T {
+        match self.metric {
+            DistanceMetric::Cosine => {
+                let two = T::one() + T::one();
+                let e = (two * offset).sqrt() + (two * child_radius).sqrt();
+                e * e / two
+            }
+            _ => offset + child_radius,
+        }
     }
 
-    fn approx_kde_for_node(&self, query: &[T], node: &AggNode<T>, h: f64, kernel: KernelType) -> f64 {
+    /// Approximation error bound for a node. The Taylor expansion only holds for euclidean
+    /// distances about the centroid; other metrics fall back to the midpoint of the
+    /// kernel's range over the node.
+    fn node_error_bound(&self, kernel: KernelType, n: f64, radius: f64, h: f64) -> f64 {
+        match self.metric {
+            DistanceMetric::Euclidean => kernel.node_error_bound(n, radius, h),
+            _ => kernel.midpoint_error_bound(n, radius, h),
+        }
+    }
+
+    /// Estimate of a node's kernel sum with a certified error bound. Kernels are
+    /// non-increasing, so every point contributes between K(r_max) and K(r_min) and the
+    /// estimate is clamped to that range. Euclidean trees use the Taylor expansion and
+    /// other metrics the midpoint of the range. Returns ln(n K(r_min)) followed by the
+    /// estimate and the bound, both relative to n K(r_min).
+    fn certified_node(&self, query: &[T], node: &AggNode<T>, h: f64, kernel: KernelType) -> (f64, f64, f64) {
         let n = (node.end - node.start) as f64;
         let r_c: f64 = self.metric.post_transform(self.metric.reduced_distance(query, &node.center)).to_f64().unwrap();
+        let (r_min, r_max) = self.distance_range(r_c, node.radius.to_f64().unwrap());
+
+        let log_hi = kernel.log_evaluate(r_min, h);
+        if log_hi == f64::NEG_INFINITY {
+            return (f64::NEG_INFINITY, 0.0, 0.0);
+        }
+        let lo = (kernel.log_evaluate(r_max, h) - log_hi).exp().min(1.0);
 
-        let k0 = kernel.evaluate(r_c, h);
-        let k2 = kernel.evaluate_second_derivative(r_c, h);
-        let k3 = kernel.third_derivative(r_c, h);
-        let k4 = kernel.fourth_derivative(r_c, h);
+        let estimate = match self.metric {
+            DistanceMetric::Euclidean => {
+                let log_k0 = kernel.log_evaluate(r_c, h);
+                self.taylor_factor(query, node, r_c, h, kernel) * (log_k0 - log_hi).exp()
+            }
+            _ => f64::NAN,
+        };
+        let estimate = if estimate.is_nan() { 0.5 * (lo + 1.0) } else { estimate.clamp(lo, 1.0) };
+
+        (n.ln() + log_hi, estimate, (estimate - lo).max(1.0 - estimate))
+    }
 
-        n * (k0 + 0.5 * k2 * node.variance + (1.0 / 6.0) * k3 * node.moment3 + (1.0 / 24.0) * k4 * node.moment4)
+    /// Leaves whose points were dropped at build time, which can only be approximated.
+    fn is_compacted(&self, node: &AggNode<T>) -> bool {
+        self.compact && node.left.is_none() && node.max_abs_error < self.atol
     }
 
-    fn kde_recursive(&self, node_idx: usize, query: &[T], h: f64, density: &mut f64, kernel: KernelType) {
+    fn should_approximate(&self, node: &AggNode<T>, h: f64, kernel: KernelType, atol: f64) -> bool {
+        if self.is_compacted(node) {
+            return true;
+        }
+        let n = (node.end - node.start) as f64;
+        self.node_error_bound(kernel, n, node.radius.to_f64().unwrap(), h) < atol
+    }
+
+    /// Accumulates (density, error bound). Pruned nodes add their largest possible mass
+    /// to the bound.
+    fn kde_recursive(&self, node_idx: usize, query: &[T], h: f64, acc: &mut (f64, f64), kernel: KernelType, atol: f64) {
         let node = &self.nodes[node_idx];
+        let n = (node.end - node.start) as f64;
+        let transformed_dist = self.min_distance_to_node_inner(node_idx, query);
+        let max_mass = kernel.evaluate(transformed_dist, h) * n;
+        if max_mass < 1e-10 {
+            acc.1 += max_mass;
+            return;
+        }
 
-        if node.left.is_none() {
-            if node.max_abs_error < self.atol {
-                *density += self.approx_kde_for_node(query, node, h, kernel);
-            } else {
+        if self.should_approximate(node, h, kernel, atol) {
+            let (log_scale, estimate, bound) = self.certified_node(query, node, h, kernel);
+            let scale = log_scale.exp();
+            acc.0 += scale * estimate;
+            acc.1 += scale * bound;
+            return;
+        }
+
+        match (node.left, node.right) {
+            (Some(left), Some(right)) => {
+                self.kde_recursive(left, query, h, acc, kernel, atol);
+                self.kde_recursive(right, query, h, acc, kernel, atol);
+            }
+            _ => {
                 for i in node.start..node.end {
                     let dist: f64 = self.metric.post_transform(self.metric.reduced_distance(query, self.data.row(i))).to_f64().unwrap();
-                    *density += kernel.evaluate(dist, h);
+                    acc.0 += kernel.evaluate(dist, h);
                 }
             }
-            return;
         }
-        let n = (self.nodes[node_idx].end - self.nodes[node_idx].start) as f64;
+    }
+
+    /// Log-space counterpart of `kde_recursive`, accumulating ln(density) and ln(bound).
+    fn log_kde_recursive(&self, node_idx: usize, query: &[T], h: f64, acc: &mut (LogSumExp, LogSumExp), kernel: KernelType, atol: f64) {
+        let node = &self.nodes[node_idx];
+        let n = (node.end - node.start) as f64;
         let transformed_dist = self.min_distance_to_node_inner(node_idx, query);
-        if kernel.evaluate(transformed_dist, h) * n < 1e-10 {
+        let log_bound = n.ln() + kernel.log_evaluate(transformed_dist, h);
+        if log_bound == f64::NEG_INFINITY {
+            return;
+        }
+        if log_bound < acc.0.value() + LOG_KDE_RTOL {
+            acc.1.add(log_bound);
             return;
         }
 
-        if let Some(left) = node.left {
-            self.kde_recursive(left, query, h, density, kernel);
+        if self.should_approximate(node, h, kernel, atol) {
+            let (log_scale, estimate, bound) = self.certified_node(query, node, h, kernel);
+            acc.0.add(log_scale + estimate.ln());
+            acc.1.add(log_scale + bound.ln());
+            return;
         }
-        if let Some(right) = node.right {
-            self.kde_recursive(right, query, h, density, kernel);
+
+        match (node.left, node.right) {
+            (Some(left), Some(right)) => {
+                self.log_kde_recursive(left, query, h, acc, kernel, atol);
+                self.log_kde_recursive(right, query, h, acc, kernel, atol);
+            }
+            _ => {
+                for i in node.start..node.end {
+                    let dist: f64 = self.metric.post_transform(self.metric.reduced_distance(query, self.data.row(i))).to_f64().unwrap();
+                    acc.0.add(kernel.log_evaluate(dist, h));
+                }
+            }
         }
     }
 
-    fn seq_kde_recursion(&self, kernel: KernelType, bandwidth: f64, queries: &[T], n_queries: usize, dim: usize) -> Vec<f64> {
-        let mut results = vec![0.0; n_queries];
+    /// Approximates a node only when its certified bound fits the per-point budget
+    /// `ln(rtol * L / N)`, where L is a lower bound on the density. The bounds of the
+    /// accepted nodes then sum to at most rtol * L. Compacted leaves are always accepted.
+    fn refine_recursive(&self, node_idx: usize, query: &[T], h: f64, kernel: KernelType, log_budget: f64, acc: &mut (LogSumExp, LogSumExp)) {
+        let node = &self.nodes[node_idx];
+        let (log_scale, estimate, bound) = self.certified_node(query, node, h, kernel);
+        if log_scale == f64::NEG_INFINITY {
+            return;
+        }
 
-        for i in 0..n_queries {
-            let query = &queries[i * dim..(i + 1) * dim];
-            let mut density = 0.0;
-            self.kde_recursive(0, query, bandwidth, &mut density, kernel);
-            results[i] = density;
+        let n = (node.end - node.start) as f64;
+        if log_scale + bound.ln() <= log_budget + n.ln() || self.is_compacted(node) {
+            acc.0.add(log_scale + estimate.ln());
+            acc.1.add(log_scale + bound.ln());
+            return;
         }
-        results
-    }
 
-    fn par_kde_recursion(&self, kernel: KernelType, bandwidth: f64, queries: &[T], n_queries: usize, dim: usize) -> Vec<f64> {
-        let results: Vec<f64> = (0..n_queries)
-            .into_par_iter()
-            .map(|i| {
-                let query = &queries[i * dim..(i + 1) * dim];
-                let mut density = 0.0;
-                self.kde_recursive(0, query, bandwidth, &mut density, kernel);
-                density
-            })
-            .collect();
-        results
+        match (node.left, node.right) {
+            (Some(left), Some(right)) => {
+                self.refine_recursive(left, query, h, kernel, log_budget, acc);
+                self.refine_recursive(right, query, h, kernel, log_budget, acc);
+            }
+            _ => {
+                for i in node.start..node.end {
+                    let dist: f64 = self.metric.post_transform(self.metric.reduced_distance(query, self.data.row(i))).to_f64().unwrap();
+                    acc.0.add(kernel.log_evaluate(dist, h));
+                }
+            }
+        }
     }
 
-    pub fn kernel_density(&self, queries: &NdArray<T>, normalize: bool) -> NdArray<f64> {
-        let shape = queries.shape().dims();
-        assert!(shape.len() == 2, "Expected 2D array (n_queries, dim)");
+    /// Unnormalized (density, error bound) for one query, in log space when `log_density`.
+    fn density_at(&self, query: &[T], h: f64, kernel: KernelType, target: ErrorTarget, log_density: bool) -> (f64, f64) {
+        let rtol = match target {
+            ErrorTarget::Absolute(atol) if !log_density => {
+                let mut acc = (0.0, 0.0);
+                self.kde_recursive(0, query, h, &mut acc, kernel, atol);
+                return acc;
+            }
+            ErrorTarget::Absolute(atol) => {
+                let mut acc = (LogSumExp::new(), LogSumExp::new());
+                self.log_kde_recursive(0, query, h, &mut acc, kernel, atol);
+                return (acc.0.value(), acc.1.value());
+            }
+            ErrorTarget::Relative(rtol) => rtol,
+        };
+
+        // A cheap pass at the default tolerance often meets the target already. The error
+        // relative to the true density is at most bound / (estimate - bound).
+        let mut acc = (LogSumExp::new(), LogSumExp::new());
+        self.log_kde_recursive(0, query, h, &mut acc, kernel, self.atol);
+        let (mut log_est, mut log_err) = (acc.0.value(), acc.1.value());
+        if log_err - log_est > (rtol / (1.0 + rtol)).ln() {
+            let log_lower = match log_err < log_est {
+                true => log_est + (-(log_err - log_est).exp()).ln_1p(),
+                false => f64::NEG_INFINITY,
+            };
+            let log_budget = rtol.ln() + log_lower - (self.n_points as f64).ln();
+            let mut acc = (LogSumExp::new(), LogSumExp::new());
+            self.refine_recursive(0, query, h, kernel, log_budget, &mut acc);
+            (log_est, log_err) = (acc.0.value(), acc.1.value());
+        }
 
-        let n_queries = shape[0];
-        let dim = shape[1];
-        assert_eq!(dim, self.dim, "Query dimension must match tree dimension");
+        match log_density {
+            true => (log_est, log_err),
+            false => (log_est.exp(), log_err.exp()),
+        }
+    }
+
+    /// Densities together with a certified upper bound on each query's error. The bound
+    /// is absolute, or on the error of the log density when `log_density`. Nodes are
+    /// approximated per query, so one tree serves any bandwidth and kernel; compact trees
+    /// only accept their build kernel and bandwidth.
+    pub fn kernel_density(
+        &self, queries: &NdArray<T>, bandwidth: f64, kernel: KernelType,
+        target: ErrorTarget, normalize: bool, log_density: bool,
+    ) -> Result<(NdArray<f64>, NdArray<f64>), IronForestError> {
+        let n_queries = check_queries(queries.shape().dims(), self.dim)?;
+        let dim = self.dim;
+        self.check_compact_params(bandwidth, kernel)?;
+        if normalize {
+            kernel.check_normalizable(dim)?;
+        }
 
         let queries_cow = queries.as_contiguous_slice();
         let queries_slice: &[T] = &queries_cow;
-        let mut results = if n_queries >= KDE_PAR_THRESHOLD {
-            self.par_kde_recursion(self.kernel, self.bandwidth, queries_slice, n_queries, dim)
+        let density_at = |i: usize| {
+            let query = self.metric.pre_transform(&queries_slice[i * dim..(i + 1) * dim]);
+            self.density_at(&query, bandwidth, kernel, target, log_density)
+        };
+        let rows: Vec<(f64, f64)> = if n_queries >= KDE_PAR_THRESHOLD {
+            (0..n_queries).into_par_iter().map(density_at).collect()
         } else {
-            self.seq_kde_recursion(self.kernel, self.bandwidth, queries_slice, n_queries, dim)
+            (0..n_queries).map(density_at).collect()
         };
+        let (mut results, mut errors): (Vec<f64>, Vec<f64>) = rows.into_iter().unzip();
+
+        if log_density {
+            for (val, err) in results.iter().zip(errors.iter_mut()) {
+                *err = match (*err, *val) {
+                    (e, _) if e == f64::NEG_INFINITY => 0.0,
+                    (e, v) if e >= v => f64::INFINITY,
+                    (e, v) => -(-(e - v).exp()).ln_1p(),
+                };
+            }
+        }
 
         if normalize {
-            let h_d = self.bandwidth.powi(dim as i32);
-            let c_k = self.kernel.normalization_constant(dim);
-            let norm = h_d * c_k;
-            for val in &mut results {
-                *val /= norm;
+            if log_density {
+                let log_norm = dim as f64 * bandwidth.ln() + kernel.log_normalization_constant(dim);
+                for val in &mut results {
+                    *val -= log_norm;
+                }
+            } else {
+                let h_d = bandwidth.powi(dim as i32);
+                let c_k = kernel.normalization_constant(dim);
+                let norm = h_d * c_k;
+                for val in results.iter_mut().chain(errors.iter_mut()) {
+                    *val /= norm;
+                }
+            }
+        }
+
+        Ok((
+            NdArray::from_vec(Shape::new(vec![n_queries]), results),
+            NdArray::from_vec(Shape::new(vec![n_queries]), errors),
+        ))
+    }
+
+    /// Draws `n_samples` points from the density estimate, shape (n_samples, dim).
+    /// Leaves are picked proportional to their point count. Leaves whose points were
+    /// compacted away use a reference point drawn from a Gaussian matching the node's
+    /// centroid and variance.
+    pub fn sample(&self, n_samples: usize, bandwidth: f64, kernel: KernelType, rng: &mut Generator) -> Result<NdArray<f64>, IronForestError> {
+        if !matches!(self.metric, DistanceMetric::Euclidean) {
+            return Err(IronForestError::invalid("KDE sampling requires the euclidean metric"));
+        }
+        self.check_compact_params(bandwidth, kernel)?;
+        let leaves: Vec<&AggNode<T>> = self.nodes.iter().filter(|node| node.left.is_none()).collect();
+        let mut total = 0.0;
+        let cumulative: Vec<f64> = leaves.iter().map(|node| {
+            total += (node.end - node.start) as f64;
+            total
+        }).collect();
+
+        let mut samples = Vec::with_capacity(n_samples * self.dim);
+        for _ in 0..n_samples {
+            let target = rng.next_f64() * total;
+            let leaf = leaves[cumulative.partition_point(|&v| v <= target).min(leaves.len() - 1)];
+            let reference: Vec<f64> = if self.is_compacted(leaf) {
+                let spread = (leaf.variance / self.dim as f64).sqrt();
+                leaf.center.iter().map(|c| c.to_f64().unwrap() + spread * rng.next_gaussian()).collect()
+            } else {
+                let slot = leaf.start + rng.usize_below(leaf.end - leaf.start);
+                self.data.row(slot).iter().map(|x| x.to_f64().unwrap()).collect()
+            };
+            let offset = kernel.sample_offset(self.dim, bandwidth, rng)?;
+            samples.extend(reference.iter().zip(&offset).map(|(x, o)| x + o));
+        }
+        Ok(NdArray::from_vec(Shape::new(vec![n_samples, self.dim]), samples))
+    }
+
+    /// Adds points to the tree, indexed after every point inserted so far. Each point
+    /// descends towards the nearest centroid; touched leaves are recomputed and split once
+    /// they outgrow `leaf_size`, and their ancestors merge the updated child moments.
+    pub fn insert(&mut self, points: &NdArray<T>) -> Result<(), IronForestError> {
+        self.check_updatable()?;
+        let n_new = check_queries(points.shape().dims(), self.dim)?;
+        if n_new == 0 {
+            return Ok(());
+        }
+
+        let mut dirty = vec![false; self.nodes.len()];
+        let mut layout = Layout {
+            keep: vec![true; self.n_points],
+            pending: vec![Vec::new(); self.nodes.len()],
+            added: Vec::with_capacity(n_new * self.dim),
+            first_id: self.next_index.max(self.n_points),
+            data: Vec::with_capacity((self.n_points + n_new) * self.dim),
+            indices: Vec::with_capacity(self.n_points + n_new),
+        };
+
+        for k in 0..n_new {
+            let point = self.metric.pre_transform(points.row(k)).into_owned();
+            let mut node_idx = 0;
+            dirty[node_idx] = true;
+            while let (Some(left), Some(right)) = (self.nodes[node_idx].left, self.nodes[node_idx].right) {
+                let dl = self.metric.reduced_distance(&point, &self.nodes[left].center);
+                let dr = self.metric.reduced_distance(&point, &self.nodes[right].center);
+                node_idx = if dl <= dr { left } else { right };
+                dirty[node_idx] = true;
+            }
+            layout.pending[node_idx].push(k);
+            layout.added.extend_from_slice(&point);
+        }
+
+        self.next_index = layout.first_id + n_new;
+        self.apply_layout(layout, &dirty);
+        Ok(())
+    }
+
+    /// Removes the points with the given indices. Leaves left empty are collapsed into
+    /// their parent and the remaining nodes along each affected path are updated.
+    pub fn remove(&mut self, ids: &[usize]) -> Result<(), IronForestError> {
+        self.check_updatable()?;
+        if ids.is_empty() {
+            return Ok(());
+        }
+
+        let slots: HashMap<usize, usize> = self.indices.iter().enumerate().map(|(slot, &id)| (id, slot)).collect();
+        let mut keep = vec![true; self.n_points];
+        let mut dirty = vec![false; self.nodes.len()];
+        for id in ids {
+            let slot = *slots.get(id)
+                .ok_or_else(|| IronForestError::invalid(format!("Index {} is not in the tree", id)))?;
+            if !keep[slot] {
+                return Err(IronForestError::invalid(format!("Index {} is given more than once", id)));
+            }
+            keep[slot] = false;
+
+            let mut node_idx = 0;
+            dirty[node_idx] = true;
+            while let (Some(left), Some(right)) = (self.nodes[node_idx].left, self.nodes[node_idx].right) {
+                node_idx = if slot < self.nodes[left].end { left } else { right };
+                dirty[node_idx] = true;
+            }
+        }
+
+        if ids.len() == self.n_points {
+            return Err(IronForestError::invalid("Cannot remove every point from an AggTree"));
+        }
+
+        let layout = Layout {
+            keep,
+            pending: vec![Vec::new(); self.nodes.len()],
+            added: Vec::new(),
+            first_id: self.next_index.max(self.n_points),
+            data: Vec::with_capacity((self.n_points - ids.len()) * self.dim),
+            indices: Vec::with_capacity(self.n_points - ids.len()),
+        };
+        self.next_index = layout.first_id;
+        self.apply_layout(layout, &dirty);
+        Ok(())
+    }
+
+    fn apply_layout(&mut self, mut layout: Layout<T>, dirty: &[bool]) {
+        self.relayout_recursive(0, &mut layout);
+        self.n_points = layout.indices.len();
+        self.data = NdArray::from_vec(Shape::new(vec![self.n_points, self.dim]), layout.data);
+        self.indices = layout.indices;
+        self.refresh_recursive(0, dirty);
+        self.renumber_nodes();
+    }
+
+    /// Rewrites the data in depth-first leaf order, keeping each leaf's surviving rows
+    /// followed by its pending points, and collapses children that end up empty.
+    fn relayout_recursive(&mut self, node_idx: usize, layout: &mut Layout<T>) {
+        match (self.nodes[node_idx].left, self.nodes[node_idx].right) {
+            (Some(left), Some(right)) => {
+                self.relayout_recursive(left, layout);
+                self.relayout_recursive(right, layout);
+                if self.nodes[left].start == self.nodes[left].end {
+                    self.nodes[node_idx] = self.nodes[right].clone();
+                } else if self.nodes[right].start == self.nodes[right].end {
+                    self.nodes[node_idx] = self.nodes[left].clone();
+                } else {
+                    self.nodes[node_idx].start = self.nodes[left].start;
+                    self.nodes[node_idx].end = self.nodes[right].end;
+                }
+            }
+            _ => {
+                let (start, end) = (self.nodes[node_idx].start, self.nodes[node_idx].end);
+                let new_start = layout.indices.len();
+                for slot in start..end {
+                    if layout.keep[slot] {
+                        layout.data.extend_from_slice(self.data.row(slot));
+                        layout.indices.push(self.indices[slot]);
+                    }
+                }
+                for &k in &layout.pending[node_idx] {
+                    layout.data.extend_from_slice(&layout.added[k * self.dim..(k + 1) * self.dim]);
+                    layout.indices.push(layout.first_id + k);
+                }
+                self.nodes[node_idx].start = new_start;
+                self.nodes[node_idx].end = layout.indices.len();
+            }
+        }
+    }
+
+    /// Recomputes touched leaves exactly, splitting oversized ones, then merges the
+    /// children of touched internal nodes.
+    fn refresh_recursive(&mut self, node_idx: usize, dirty: &[bool]) {
+        if !dirty[node_idx] {
+            return;
+        }
+        match (self.nodes[node_idx].left, self.nodes[node_idx].right) {
+            (Some(left), Some(right)) => {
+                self.refresh_recursive(left, dirty);
+                self.refresh_recursive(right, dirty);
+                self.merge_children(node_idx);
+            }
+            _ => {
+                let (start, end) = (self.nodes[node_idx].start, self.nodes[node_idx].end);
+                self.nodes[node_idx] = self.make_node(start, end);
+                if end - start > self.leaf_size {
+                    self.split_node(node_idx);
+                }
+            }
+        }
+    }
+
+    /// Combines the children's moments about the merged centroid. The centroid, variance
+    /// and covariance are exact for the euclidean metric; the third and fourth moments drop
+    /// the odd cross terms, which the certified bound does not rely on. The radius is the
+    /// triangle-inequality bound over the children.
+    fn merge_children(&mut self, node_idx: usize) {
+        let (left, right) = (self.nodes[node_idx].left.unwrap(), self.nodes[node_idx].right.unwrap());
+        let children = [&self.nodes[left], &self.nodes[right]];
+        let d = self.dim;
+        let counts: Vec<f64> = children.iter().map(|c| (c.end - c.start) as f64).collect();
+        let n = counts[0] + counts[1];
+
+        let center: Vec<T> = (0..d)
+            .map(|j| {
+                let sum: f64 = children.iter().zip(&counts).map(|(c, w)| w * c.center[j].to_f64().unwrap()).sum();
+                T::from(sum / n).unwrap()
+            })
+            .collect();
+
+        let mut radius = T::zero();
+        let mut variance = 0.0;
+        let mut moment3 = 0.0;
+        let mut moment4 = 0.0;
+        let mut cov = vec![0.0f64; if self.moments == MomentMode::Isotropic { 0 } else { d * d }];
+        for (child, &count) in children.iter().zip(&counts) {
+            let w = count / n;
+            let dist = self.metric.post_transform(self.metric.reduced_distance(&center, &child.center));
+            radius = radius.max(self.enclosing_radius(dist, child.radius));
+
+            let shift = dist.to_f64().unwrap();
+            let shift2 = shift * shift;
+            let along = if shift > 0.0 {
+                let dir: Vec<f64> = child.center.iter().zip(&center).map(|(c, m)| (*c - *m).to_f64().unwrap() / shift).collect();
+                self.directional_variance(child, &dir)
+            } else {
+                0.0
+            };
+            let var = child.variance + shift2;
+            let m4 = child.moment4 + shift2 * shift2 + 2.0 * shift2 * child.variance + 4.0 * shift2 * along;
+            let ratio = match child.variance * child.moment4 > 0.0 {
+                true => (child.moment3 / (child.variance * child.moment4).sqrt()).min(1.0),
+                false => 1.0,
+            };
+            variance += w * var;
+            moment3 += w * ratio * (var * m4).sqrt();
+            moment4 += w * m4;
+
+            if !cov.is_empty() {
+                let child_cov = self.dense_covariance(child);
+                let delta: Vec<f64> = child.center.iter().zip(&center).map(|(c, m)| (*c - *m).to_f64().unwrap()).collect();
+                for a in 0..d {
+                    for b in 0..d {
+                        cov[a * d + b] += w * (child_cov[a * d + b] + delta[a] * delta[b]);
+                    }
+                }
+            }
+        }
+        let covariance = match self.moments {
+            MomentMode::LowRank(rank) => principal_axes(cov, d, rank),
+            _ => cov,
+        };
+
+        let max_abs_error = self.node_error_bound(self.kernel, n, radius.to_f64().unwrap(), self.bandwidth);
+        let node = &mut self.nodes[node_idx];
+        node.center = center;
+        node.radius = radius;
+        node.variance = variance;
+        node.moment3 = moment3;
+        node.moment4 = moment4;
+        node.max_abs_error = max_abs_error;
+        node.covariance = covariance;
+    }
+
+    /// A node's covariance as a full d x d matrix. Low-rank nodes spread their residual
+    /// variance evenly over the directions orthogonal to the stored axes.
+    fn dense_covariance(&self, node: &AggNode<T>) -> Vec<f64> {
+        let d = self.dim;
+        let rank = match self.moments {
+            MomentMode::LowRank(rank) => rank,
+            _ => return node.covariance.clone(),
+        };
+        let mut cov = vec![0.0; d * d];
+        let mut explained = 0.0;
+        for axis in node.covariance.chunks_exact(d) {
+            let lambda: f64 = axis.iter().map(|a| a * a).sum();
+            explained += lambda;
+            for a in 0..d {
+                for b in 0..d {
+                    cov[a * d + b] += axis[a] * axis[b];
+                }
+            }
+        }
+        if d > rank {
+            let residual = (node.variance - explained).max(0.0) / (d - rank) as f64;
+            for a in 0..d {
+                cov[a * d + a] += residual;
+            }
+            for axis in node.covariance.chunks_exact(d) {
+                let lambda: f64 = axis.iter().map(|a| a * a).sum();
+                if lambda > 0.0 {
+                    for a in 0..d {
+                        for b in 0..d {
+                            cov[a * d + b] -= residual * axis[a] * axis[b] / lambda;
+                        }
+                    }
+                }
+            }
+        }
+        cov
+    }
+
+    /// Drops nodes no longer reachable from the root and stores the rest in pre-order.
+    fn renumber_nodes(&mut self) {
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![0];
+        while let Some(node_idx) = stack.pop() {
+            order.push(node_idx);
+            if let (Some(left), Some(right)) = (self.nodes[node_idx].left, self.nodes[node_idx].right) {
+                stack.push(right);
+                stack.push(left);
             }
         }
 
-        NdArray::from_vec(Shape::new(vec![n_queries]), results)
+        let mut remap = vec![usize::MAX; self.nodes.len()];
+        for (new_idx, &old_idx) in order.iter().enumerate() {
+            remap[old_idx] = new_idx;
+        }
+        let mut old: Vec<Option<AggNode<T>>> = std::mem::take(&mut self.nodes).into_iter().map(Some).collect();
+        self.nodes = order
+            .iter()
+            .map(|&old_idx| {
+                let mut node = old[old_idx].take().unwrap();
+                node.left = node.left.map(|i| remap[i]);
+                node.right = node.right.map(|i| remap[i]);
+                node
+            })
+            .collect();
+    }
+
+    fn check_updatable(&self) -> Result<(), IronForestError> {
+        if self.compact {
+            return Err(IronForestError::invalid("Compact AggTree does not support insert or remove; rebuild with compact=false"));
+        }
+        Ok(())
+    }
+
+    fn check_compact_params(&self, bandwidth: f64, kernel: KernelType) -> Result<(), IronForestError> {
+        if self.compact && (bandwidth != self.bandwidth || kernel != self.kernel) {
+            return Err(IronForestError::invalid("Compact AggTree only supports its build kernel and bandwidth"));
+        }
+        Ok(())
+    }
+}
+
+impl<T: IronFloat> SpatialTree for AggTree<T> {
+    type Node = AggNode<T>;
+    type Float = T;
+    const REDUCED: bool = true;
+
+    fn nodes(&self) -> &[AggNode<T>] { &self.nodes }
+    fn indices(&self) -> &[usize] { &self.indices }
+    fn data(&self) -> &[T] { self.data.as_slice_unchecked() }
+    fn dim(&self) -> usize { self.dim }
+    fn metric(&self) -> &DistanceMetric { &self.metric }
+    fn n_points(&self) -> usize { self.n_points }
+    fn data_is_reordered(&self) -> bool { true }
+
+    // Compacted leaves no longer have rows, and the remaining rows are renumbered
+    fn check_raw_points(&self) -> Result<(), IronForestError> {
+        if self.compact {
+            return Err(IronForestError::invalid(
+                "Compact AggTree dropped the raw points needed for neighbor queries; rebuild with compact=false",
+            ));
+        }
+        Ok(())
+    }
+
+    fn node_start(&self, idx: usize) -> usize { self.nodes[idx].start }
+    fn node_end(&self, idx: usize) -> usize { self.nodes[idx].end }
+    fn node_left(&self, idx: usize) -> Option<usize> { self.nodes[idx].left }
+    fn node_right(&self, idx: usize) -> Option<usize> { self.nodes[idx].right }
+
+    fn child_lower_bound(&self, child_idx: usize, query: &[T]) -> T {
+        let min_dist = T::from(self.min_distance_to_node_inner(child_idx, query)).unwrap();
+        match self.metric {
+            // Reduced cosine distance is the squared euclidean one, twice the true distance
+            DistanceMetric::Cosine => min_dist + min_dist,
+            _ => self.metric.to_reduced(min_dist),
+        }
+    }
+
+    fn traversal_order(&self, node_idx: usize, query: &[T]) -> (usize, usize) {
+        let node = &self.nodes[node_idx];
+        let (l, r) = (node.left.unwrap(), node.right.unwrap());
+        let dl = self.metric.reduced_distance(query, &self.nodes[l].center);
+        let dr = self.metric.reduced_distance(query, &self.nodes[r].center);
+        if dl <= dr { (l, r) } else { (r, l) }
+    }
+}
+
+impl<T: IronFloat> KnnQuery for AggTree<T> {}
+impl<T: IronFloat> RadiusQuery for AggTree<T> {}
+
+/// Work buffers for rewriting the data after an insert or remove.
+struct Layout<T> {
+    keep: Vec<bool>,
+    pending: Vec<Vec<usize>>,
+    added: Vec<T>,
+    first_id: usize,
+    data: Vec<T>,
+    indices: Vec<usize>,
+}
+
+/// Leading eigenvectors of a symmetric positive semi-definite matrix by power iteration
+/// with deflation, as rows scaled by the square root of their eigenvalue.
+fn principal_axes(mut cov: Vec<f64>, dim: usize, rank: usize) -> Vec<f64> {
+    let mut axes = Vec::with_capacity(rank * dim);
+    for _ in 0..rank {
+        // Start from the coordinate with the most remaining variance
+        let start = (0..dim).max_by(|&a, &b| cov[a * dim + a].total_cmp(&cov[b * dim + b])).unwrap();
+        let mut v = vec![0.0; dim];
+        v[start] = 1.0;
+
+        for _ in 0..POWER_ITERS {
+            let w: Vec<f64> = (0..dim).map(|a| (0..dim).map(|b| cov[a * dim + b] * v[b]).sum()).collect();
+            let norm = w.iter().map(|x| x * x).sum::<f64>().sqrt();
+            if norm <= 0.0 {
+                break;
+            }
+            let delta: f64 = w.iter().zip(&v).map(|(x, y)| (x / norm - y) * (x / norm - y)).sum();
+            v = w.iter().map(|x| x / norm).collect();
+            if delta < 1e-20 {
+                break;
+            }
+        }
+
+        let lambda: f64 = (0..dim)
+            .map(|a| v[a] * (0..dim).map(|b| cov[a * dim + b] * v[b]).sum::<f64>())
+            .sum::<f64>()
+            .max(0.0);
+        for a in 0..dim {
+            for b in 0..dim {
+                cov[a * dim + b] -= lambda * v[a] * v[b];
+            }
+        }
+        axes.extend(v.iter().map(|x| x * lambda.sqrt()));
+    }
+    axes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree(compact: bool) -> AggTree<f64> {
+        let data = Generator::from_seed(7).standard_normal(Shape::new(vec![200, 2]));
+        AggTree::new(data, 10, DistanceMetric::Euclidean, KernelType::Gaussian, 0.5, 0.01, compact, MomentMode::Isotropic)
+    }
+
+    #[test]
+    fn compact_tree_rejects_neighbor_queries() {
+        let tree = tree(true);
+        let queries = NdArray::from_vec(Shape::new(vec![1, 2]), vec![0.0, 0.0]);
+        assert!(matches!(tree.query_knn(&[0.0, 0.0], 3), Err(IronForestError::InvalidArgument(_))));
+        assert!(matches!(tree.query_knn_batch(&queries, 3), Err(IronForestError::InvalidArgument(_))));
+        assert!(matches!(tree.query_radius(&[0.0, 0.0], 1.0), Err(IronForestError::InvalidArgument(_))));
+        assert!(matches!(tree.query_radius_batch(&queries, 1.0), Err(IronForestError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn neighbor_queries_check_dimension() {
+        let tree = tree(false);
+        assert_eq!(tree.query_knn(&[0.0, 0.0, 0.0], 3), Err(IronForestError::DimensionMismatch { expected: 2, got: 3 }));
+        assert_eq!(tree.query_knn(&[0.0, 0.0], 3).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn compact_tree_rejects_updates() {
+        let mut tree = tree(true);
+        let points = NdArray::from_vec(Shape::new(vec![1, 2]), vec![0.0, 0.0]);
+        assert!(matches!(tree.insert(&points), Err(IronForestError::InvalidArgument(_))));
+        assert!(matches!(tree.remove(&[0]), Err(IronForestError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn insert_checks_point_shape() {
+        let mut tree = tree(false);
+        let points = NdArray::from_vec(Shape::new(vec![1, 3]), vec![0.0, 0.0, 0.0]);
+        assert_eq!(tree.insert(&points), Err(IronForestError::DimensionMismatch { expected: 2, got: 3 }));
+        let points = NdArray::from_vec(Shape::new(vec![2]), vec![0.0, 0.0]);
+        assert_eq!(tree.insert(&points), Err(IronForestError::InvalidShape { ndim: 1 }));
+        assert_eq!(tree.n_points, 200);
+    }
+
+    #[test]
+    fn remove_rejects_bad_indices() {
+        let mut tree = tree(false);
+        assert!(matches!(tree.remove(&[500]), Err(IronForestError::InvalidArgument(_))));
+        assert!(matches!(tree.remove(&[3, 3]), Err(IronForestError::InvalidArgument(_))));
+        let every: Vec<usize> = (0..200).collect();
+        assert!(matches!(tree.remove(&every), Err(IronForestError::InvalidArgument(_))));
+        assert_eq!(tree.n_points, 200);
+
+        tree.remove(&[3, 4]).unwrap();
+        assert_eq!(tree.n_points, 198);
     }
 }
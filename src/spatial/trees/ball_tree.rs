@@ -1,5 +1,5 @@
 use crate::{Shape, array::NdArray, spatial::common::{DistanceMetric, IronFloat}};
-use crate::spatial::queries::{KnnQuery, RadiusQuery, KdeQuery, AnnQuery};
+use crate::spatial::queries::{KnnQuery, RadiusQuery, KdeQuery, AnnQuery, MeanShiftQuery};
 use crate::spatial::SpatialTree;
 use serde::{Deserialize, Serialize};
 
@@ -109,7 +109,7 @@ impl<T: IronFloat> BallTree<T> {
             .max_by(|&a, &b| {
                 let da = self.metric.reduced_distance(query, self.data.row(self.indices[a]));
                 let db = self.metric.reduced_distance(query, self.data.row(self.indices[b]));
-                da.partial_cmp(&db).unwrap()
+                da.total_cmp(&db)
             })
             .unwrap()
     }
@@ -143,7 +143,7 @@ impl<T: IronFloat> BallTree<T> {
 
         let mid_offset = (end - start) / 2;
         projections.select_nth_unstable_by(mid_offset, |a, b| {
-            a.0.partial_cmp(&b.0).unwrap()
+            a.0.total_cmp(&b.0)
         });
 
         self.indices[start..end].copy_from_slice(
@@ -229,4 +229,5 @@ impl<T: IronFloat> SpatialTree for BallTree<T> {
 impl<T: IronFloat> KnnQuery for BallTree<T> {}
 impl<T: IronFloat> RadiusQuery for BallTree<T> {}
 impl<T: IronFloat> KdeQuery for BallTree<T> {}
+impl<T: IronFloat> MeanShiftQuery for BallTree<T> {}
 impl<T: IronFloat> AnnQuery for BallTree<T> {}
@@ -1,7 +1,12 @@
 use crate::{array::NdArray, spatial::common::{DistanceMetric, IronFloat}};
-use crate::spatial::queries::{KnnQuery, RadiusQuery, KdeQuery, AnnQuery};
-use crate::spatial::SpatialTree;
+use crate::spatial::queries::{KnnQuery, RadiusQuery, KdeQuery, AnnQuery, MeanShiftQuery};
+use crate::spatial::{HeapItem, SpatialTree};
 use serde::{Deserialize, Serialize};
+use std::collections::BinaryHeap;
+
+// Points per block scanned by the approximate queries
+const ANN_BLOCK: usize = 64;
+const ANN_BAIL_THRESHOLD: f64 = 0.075;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BFNode {
@@ -59,6 +64,63 @@ impl<T: IronFloat> BruteForce<T> {
     }
 }
 
+impl<T: IronFloat> BruteForce<T> {
+    /// Approximate search by early termination. Blocks of points are visited in a strided
+    /// order that spreads them over the data, and each of `rounds` passes stops once blocks
+    /// rarely improve the `n_candidates` best seen so far, like the bail-out of stochastic
+    /// probes. Later rounds resume where the previous one stopped.
+    fn ann_scan(&self, query: &[T], k: usize, n_candidates: usize, rounds: usize) -> Vec<(usize, T)> {
+        let n_blocks = self.n_points.div_ceil(ANN_BLOCK);
+        let mut stride = ((n_blocks as f64 * 0.618) as usize).max(1);
+        while gcd(stride, n_blocks) != 1 {
+            stride += 1;
+        }
+
+        let mut candidates: BinaryHeap<HeapItem<T>> = BinaryHeap::new();
+        let alpha = 0.3;
+        let min_blocks = 3;
+        let mut visited = 0;
+        for _ in 0..rounds {
+            let mut improvement_rate = 1.0;
+            let mut round_blocks = 0;
+            while visited < n_blocks {
+                let block = visited * stride % n_blocks;
+                visited += 1;
+                round_blocks += 1;
+
+                let mut improved = false;
+                for i in block * ANN_BLOCK..((block + 1) * ANN_BLOCK).min(self.n_points) {
+                    let dist = self.metric.reduced_distance(query, self.get_point(i));
+                    if candidates.len() < n_candidates {
+                        candidates.push(HeapItem { distance: dist, index: i });
+                        improved = true;
+                    } else if dist < candidates.peek().unwrap().distance {
+                        candidates.pop();
+                        candidates.push(HeapItem { distance: dist, index: i });
+                        improved = true;
+                    }
+                }
+
+                improvement_rate = alpha * (if improved { 1.0 } else { 0.0 }) + (1.0 - alpha) * improvement_rate;
+                if candidates.len() >= k && round_blocks >= min_blocks && improvement_rate < ANN_BAIL_THRESHOLD {
+                    break;
+                }
+            }
+        }
+
+        let mut results: Vec<(usize, T)> = candidates.into_iter()
+            .map(|item| (self.indices[item.index], self.metric.post_transform(item.distance)))
+            .collect();
+        results.sort_by(|a, b| a.1.total_cmp(&b.1));
+        results.truncate(k);
+        results
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
 impl<T: IronFloat> SpatialTree for BruteForce<T> {
     type Node = BFNode;
     type Float = T;
@@ -87,4 +149,16 @@ impl<T: IronFloat> SpatialTree for BruteForce<T> {
 impl<T: IronFloat> KnnQuery for BruteForce<T> {}
 impl<T: IronFloat> RadiusQuery for BruteForce<T> {}
 impl<T: IronFloat> KdeQuery for BruteForce<T> {}
-impl<T: IronFloat> AnnQuery for BruteForce<T> {}
+impl<T: IronFloat> MeanShiftQuery for BruteForce<T> {}
+impl<T: IronFloat> AnnQuery for BruteForce<T> {
+    fn ann_candidates_inner(&self, query: &[T], k: usize, n_candidates: usize) -> Vec<(usize, T)> {
+        self.ann_scan(query, k, n_candidates, 1)
+    }
+
+    fn query_ann_stochastic(&self, query: &[T], k: usize, n_candidates: usize, n_probes: usize) -> Vec<(usize, T)> {
+        if k == 0 || self.n_points == 0 {
+            return Vec::new();
+        }
+        self.ann_scan(query, k, n_candidates.max(k), n_probes.max(1))
+    }
+}
@@ -1,5 +1,5 @@
 use crate::spatial::common::{DistanceMetric, IronFloat};
-use crate::spatial::queries::{KnnQuery, RadiusQuery, KdeQuery, AnnQuery};
+use crate::spatial::queries::{KnnQuery, RadiusQuery, KdeQuery, AnnQuery, MeanShiftQuery};
 use crate::spatial::SpatialTree;
 use crate::{NdArray, Shape};
 use serde::{Deserialize, Serialize};
@@ -113,7 +113,7 @@ impl<T: IronFloat> KDTree<T> {
         let mid_offset = (end - start) / 2;
 
         slots.select_nth_unstable_by(mid_offset, |a, b| {
-            a.0.partial_cmp(&b.0).unwrap()
+            a.0.total_cmp(&b.0)
         });
 
         let new_order: Vec<usize> = slots
@@ -204,4 +204,5 @@ impl<T: IronFloat> SpatialTree for KDTree<T> {
 impl<T: IronFloat> KnnQuery for KDTree<T> {}
 impl<T: IronFloat> RadiusQuery for KDTree<T> {}
 impl<T: IronFloat> KdeQuery for KDTree<T> {}
+impl<T: IronFloat> MeanShiftQuery for KDTree<T> {}
 impl<T: IronFloat> AnnQuery for KDTree<T> {}
@@ -1,7 +1,8 @@
-use crate::{KernelType, array::NdArray, spatial::{HeapItem, common::{DistanceMetric, IronFloat}}};
-use crate::spatial::queries::{KnnQuery, RadiusQuery, KdeQuery};
+use crate::{KernelType, array::NdArray, spatial::{HeapItem, common::{DistanceMetric, IronFloat, LogSumExp}}};
+use crate::spatial::queries::{KnnQuery, RadiusQuery, KdeQuery, AnnQuery, kde::LOG_KDE_RTOL};
 use crate::spatial::SpatialTree;
 use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
 use std::collections::BinaryHeap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -391,7 +392,7 @@ impl<T: IronFloat> MTree<T> {
                     children.push((min_dist_real, d_real, entry.child_idx));
                 }
 
-                children.sort_unstable_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+                children.sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
 
                 for (min_dist_reduced, d_to_routing_real, child_idx) in children {
                     let best = heap.peek().map(|h| h.distance).unwrap_or(T::infinity());
@@ -404,6 +405,91 @@ impl<T: IronFloat> MTree<T> {
         }
     }
 
+    /// Best-first aNN over the routing entries, keeping the `n_candidates` nearest points
+    /// seen and stopping once the next node cannot beat them. With `probes`, the search
+    /// also bails once leaves rarely improve the candidates, like stochastic probes, and
+    /// each further probe resumes it with a fresh improvement rate.
+    fn ann_search(&self, query: &[T], k: usize, n_candidates: usize, probes: Option<usize>) -> Vec<(usize, T)> {
+        let mut queue: BinaryHeap<Reverse<HeapItem<T>>> = BinaryHeap::new();
+        let mut candidates: BinaryHeap<HeapItem<T>> = BinaryHeap::new();
+        // queued nodes with the query's distance to their routing object
+        let mut pending: Vec<(usize, T)> = vec![(self.root, T::infinity())];
+        queue.push(Reverse(HeapItem { distance: T::zero(), index: 0 }));
+
+        let worst = |c: &BinaryHeap<HeapItem<T>>| c.peek().map(|h| h.distance).unwrap_or(T::infinity());
+        let alpha = 0.3;
+        let min_leaves = 3;
+        let mut improvement_rate: f64 = 1.0;
+        let mut leaves_visited = 0;
+        let mut probes_left = probes.unwrap_or(0);
+
+        while let Some(Reverse(HeapItem { distance: node_dist, index: slot })) = queue.pop() {
+            if candidates.len() >= k && node_dist > worst(&candidates) {
+                break;
+            }
+            let (node_idx, d_query_parent) = pending[slot];
+            let lower_bound = |dist_to_parent: T| if d_query_parent.is_finite() {
+                (d_query_parent - dist_to_parent).abs()
+            } else {
+                T::zero()
+            };
+
+            match &self.nodes[node_idx] {
+                MNode::Leaf { entries, .. } => {
+                    let mut improved = false;
+                    for entry in entries {
+                        if candidates.len() == n_candidates && lower_bound(entry.dist_to_parent) > worst(&candidates) {
+                            continue;
+                        }
+                        let dist = self.metric.distance(query, &entry.object);
+                        if candidates.len() < n_candidates {
+                            candidates.push(HeapItem { distance: dist, index: entry.point_idx });
+                            improved = true;
+                        } else if dist < worst(&candidates) {
+                            candidates.pop();
+                            candidates.push(HeapItem { distance: dist, index: entry.point_idx });
+                            improved = true;
+                        }
+                    }
+
+                    if probes.is_none() {
+                        continue;
+                    }
+                    leaves_visited += 1;
+                    improvement_rate = alpha * (if improved { 1.0 } else { 0.0 })
+                        + (1.0 - alpha) * improvement_rate;
+                    if candidates.len() >= k && leaves_visited >= min_leaves && improvement_rate < 0.075 {
+                        probes_left -= 1;
+                        if probes_left == 0 {
+                            break;
+                        }
+                        improvement_rate = 1.0;
+                        leaves_visited = 0;
+                    }
+                }
+                MNode::Internal { entries, .. } => {
+                    for entry in entries {
+                        let lb_child = (lower_bound(entry.dist_to_parent) - entry.covering_radius).max(T::zero());
+                        if candidates.len() == n_candidates && lb_child > worst(&candidates) {
+                            continue;
+                        }
+                        let d_real = self.metric.distance(query, &entry.object);
+                        let min_dist_real = (d_real - entry.covering_radius).max(T::zero());
+                        queue.push(Reverse(HeapItem { distance: min_dist_real, index: pending.len() }));
+                        pending.push((entry.child_idx, d_real));
+                    }
+                }
+            }
+        }
+
+        let mut results: Vec<(usize, T)> = candidates.into_iter()
+            .map(|item| (item.index, item.distance))
+            .collect();
+        results.sort_by(|a, b| a.1.total_cmp(&b.1));
+        results.truncate(k);
+        results
+    }
+
     fn radius_recursive_inner(
         &self,
         node_idx: usize,
@@ -509,6 +595,60 @@ impl<T: IronFloat> MTree<T> {
             }
         }
     }
+
+    fn log_kde_recursive_inner(
+        &self,
+        node_idx: usize,
+        query: &[T],
+        d_query_parent: T,
+        h: f64,
+        acc: &mut LogSumExp,
+        kernel: KernelType,
+    ) {
+        match &self.nodes[node_idx] {
+            MNode::Leaf { entries, .. } => {
+                for entry in entries {
+                    let lb: f64 = if d_query_parent.is_finite() {
+                        (d_query_parent - entry.dist_to_parent).abs().to_f64().unwrap()
+                    } else {
+                        0.0
+                    };
+
+                    if kernel.log_evaluate(lb, h) < acc.value() + LOG_KDE_RTOL {
+                        continue;
+                    }
+
+                    let dist: f64 = self.metric.distance(query, &entry.object).to_f64().unwrap();
+                    acc.add(kernel.log_evaluate(dist, h));
+                }
+            }
+            MNode::Internal { entries, .. } => {
+                for entry in entries {
+                    let lb: f64 = if d_query_parent.is_finite() {
+                        (d_query_parent - entry.dist_to_parent).abs().to_f64().unwrap()
+                    } else {
+                        0.0
+                    };
+                    let covering_r_f64 = entry.covering_radius.to_f64().unwrap();
+                    let min_dist_lb = (lb - covering_r_f64).max(0.0);
+                    let n = self.nodes[entry.child_idx].count() as f64;
+
+                    if n.ln() + kernel.log_evaluate(min_dist_lb, h) < acc.value() + LOG_KDE_RTOL {
+                        continue;
+                    }
+
+                    let d_real = self.metric.distance(query, &entry.object);
+                    let min_dist = (d_real.to_f64().unwrap() - covering_r_f64).max(0.0);
+
+                    if n.ln() + kernel.log_evaluate(min_dist, h) < acc.value() + LOG_KDE_RTOL {
+                        continue;
+                    }
+
+                    self.log_kde_recursive_inner(entry.child_idx, query, d_real, h, acc, kernel);
+                }
+            }
+        }
+    }
 }
 
 impl<T: IronFloat> SpatialTree for MTree<T> {
@@ -566,6 +706,20 @@ impl<T: IronFloat> KnnQuery for MTree<T> {
     }
 }
 
+// Stochastic probes assume binary splits, so M-tree probes resume a best-first search
+impl<T: IronFloat> AnnQuery for MTree<T> {
+    fn ann_candidates_inner(&self, query: &[T], k: usize, n_candidates: usize) -> Vec<(usize, T)> {
+        self.ann_search(query, k, n_candidates, None)
+    }
+
+    fn query_ann_stochastic(&self, query: &[T], k: usize, n_candidates: usize, n_probes: usize) -> Vec<(usize, T)> {
+        if k == 0 || self.n_points == 0 {
+            return Vec::new();
+        }
+        self.ann_search(query, k, n_candidates.max(k), Some(n_probes.max(1)))
+    }
+}
+
 impl<T: IronFloat> RadiusQuery for MTree<T> {
     fn query_radius_recursive(&self, node_idx: usize, query: &[T], radius: T, results: &mut Vec<(usize, T)>) {
         self.radius_recursive_inner(node_idx, query, T::infinity(), radius, results);
@@ -576,4 +730,59 @@ impl<T: IronFloat> KdeQuery for MTree<T> {
     fn kde_recursive(&self, node_idx: usize, query: &[T], h: f64, density: &mut f64, kernel: KernelType) {
         self.kde_recursive_inner(node_idx, query, T::infinity(), h, density, kernel);
     }
+
+    fn log_kde_recursive(&self, node_idx: usize, query: &[T], h: f64, acc: &mut LogSumExp, kernel: KernelType) {
+        self.log_kde_recursive_inner(node_idx, query, T::infinity(), h, acc, kernel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::array::Shape;
+    use crate::random::Generator;
+    use crate::spatial::trees::BruteForce;
+
+    fn recall(tree: &MTree<f64>, ann: impl Fn(&MTree<f64>, &[f64]) -> Vec<(usize, f64)>) -> f64 {
+        let (dim, k) = (8, 10);
+        let queries = Generator::from_seed(12).standard_normal(Shape::new(vec![50, dim]));
+        let brute = BruteForce::new(data(), DistanceMetric::Euclidean);
+
+        let mut hits = 0;
+        for query in queries.as_slice_unchecked().chunks(dim) {
+            let exact: Vec<usize> = brute.query_knn(query, k).unwrap().into_iter().map(|(i, _)| i).collect();
+            let approx = ann(tree, query);
+            assert_eq!(approx.len(), k);
+            assert!(approx.windows(2).all(|w| w[0].1 <= w[1].1));
+            hits += approx.iter().filter(|(i, _)| exact.contains(i)).count();
+        }
+        hits as f64 / (50 * k) as f64
+    }
+
+    fn data() -> NdArray<f64> {
+        Generator::from_seed(11).standard_normal(Shape::new(vec![2000, 8]))
+    }
+
+    fn tree() -> MTree<f64> {
+        MTree::from_ndarray(&data(), 16, DistanceMetric::Euclidean)
+    }
+
+    #[test]
+    fn ann_recall_against_brute_force() {
+        // Without probes the best-first search only stops once no node can beat the
+        // candidates, so every candidate count finds the exact neighbors.
+        let tree = tree();
+        for n_candidates in [10, 100, 2000] {
+            assert_eq!(recall(&tree, |t, q| t.query_ann(q, 10, n_candidates)), 1.0);
+        }
+    }
+
+    #[test]
+    fn stochastic_ann_recall_against_brute_force() {
+        let tree = tree();
+        let one = recall(&tree, |t, q| t.query_ann_stochastic(q, 10, 50, 1));
+        let several = recall(&tree, |t, q| t.query_ann_stochastic(q, 10, 50, 8));
+        assert!(several >= 0.95, "recall with 8 probes: {}", several);
+        assert!(several > one, "{} <= {}", several, one);
+    }
 }
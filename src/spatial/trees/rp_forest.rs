@@ -0,0 +1,175 @@
+use std::collections::BinaryHeap;
+use crate::{array::NdArray, random::SeedSequence, spatial::{HeapItem, common::{DistanceMetric, IronFloat}, spatial_tree::TraversalPlan}};
+use crate::projection::{ProjectionType, SplitMode};
+use crate::spatial::queries::{AnnQuery, BatchNeighbors};
+use crate::spatial::trees::rp_tree::{RPBuilder, RPNode, rp_plan};
+use crate::spatial::SpatialTree;
+use crate::error::{IronForestError, check_k, check_queries};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const FOREST_PAR_THRESHOLD: usize = 512;
+
+/// Partition of the forest's data by one tree. Only the permutation is stored, the
+/// points stay in the forest's single copy of the data.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(bound = "T: IronFloat")]
+pub struct RPForestTree<T: IronFloat> {
+    pub nodes: Vec<RPNode<T>>,
+    pub indices: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound = "T: IronFloat")]
+pub struct RPForest<T: IronFloat> {
+    pub trees: Vec<RPForestTree<T>>,
+    pub data: NdArray<T>,
+    pub n_points: usize,
+    pub dim: usize,
+    pub leaf_size: usize,
+    pub metric: DistanceMetric,
+    pub projection_type: ProjectionType,
+    pub split_mode: SplitMode,
+}
+
+impl<T: IronFloat> RPForest<T> {
+    /// Builds `n_trees` random projection trees in parallel, each seeded from a child of
+    /// `SeedSequence::new(seed)`.
+    pub fn new(
+        mut data: NdArray<T>,
+        n_trees: usize,
+        leaf_size: usize,
+        metric: DistanceMetric,
+        projection_type: ProjectionType,
+        split_mode: SplitMode,
+        seed: u64,
+    ) -> Self {
+        let shape = data.shape().dims();
+        assert!(shape.len() == 2, "Expected 2D array (n_points, dim)");
+        assert!(n_trees >= 1, "n_trees must be at least 1");
+        let n_points = shape[0];
+        let dim = shape[1];
+
+        if (matches!(metric, DistanceMetric::Cosine) && !data.is_owned()) || !data.is_contiguous() {
+            data = data.to_contiguous();
+        }
+        if matches!(metric, DistanceMetric::Cosine) {
+            for i in 0..n_points {
+                let normed = metric.pre_transform(data.row(i)).into_owned();
+                data.set_row(i, &normed);
+            }
+        }
+
+        let mut seeds = SeedSequence::new(seed);
+        let children: Vec<SeedSequence> = (0..n_trees).map(|_| seeds.spawn()).collect();
+        let trees = children.into_par_iter()
+            .map(|child| {
+                let mut rng = child.into_generator();
+                let mut nodes = Vec::new();
+                let mut indices: Vec<usize> = (0..n_points).collect();
+                RPBuilder {
+                    data: &data,
+                    indices: &mut indices,
+                    nodes: &mut nodes,
+                    leaf_size,
+                    projection_type,
+                    split_mode,
+                    rng: &mut rng,
+                }.build(0, n_points);
+                RPForestTree { nodes, indices }
+            })
+            .collect();
+
+        RPForest { trees, data, n_points, dim, leaf_size, metric, projection_type, split_mode }
+    }
+
+    /// Like its member trees, the forest reports post-transformed reduced distances.
+    pub fn reports_reduced(&self) -> bool {
+        <RPTreeView<'_, T> as SpatialTree>::REDUCED
+    }
+
+    pub fn n_trees(&self) -> usize {
+        self.trees.len()
+    }
+
+    fn view<'a>(&'a self, tree: &'a RPForestTree<T>) -> RPTreeView<'a, T> {
+        RPTreeView { forest: self, tree }
+    }
+
+    /// aNN merged across trees. Every tree runs `n_probes` stochastic probes into one
+    /// candidate heap, and a shared seen bitset skips points already found by earlier trees.
+    pub fn query_ann_stochastic(&self, query: &[T], k: usize, n_candidates: usize, n_probes: usize) -> Vec<(usize, T)> {
+        if k == 0 || self.n_points == 0 {
+            return Vec::new();
+        }
+        let n_candidates = n_candidates.max(k);
+        let mut candidates: BinaryHeap<HeapItem<T>> = BinaryHeap::new();
+        let mut seen: Vec<u64> = vec![0u64; self.n_points.div_ceil(64)];
+        for tree in &self.trees {
+            self.view(tree).probe_candidates(query, k, n_candidates, n_probes.max(1), &mut candidates, &mut seen);
+        }
+
+        let mut results: Vec<(usize, T)> = candidates.into_iter()
+            .map(|item| (item.index, self.metric.post_transform(item.distance)))
+            .collect();
+        results.sort_by(|a, b| a.1.total_cmp(&b.1));
+        results.truncate(k);
+        results
+    }
+
+    pub fn query_ann(&self, query: &[T], k: usize, n_candidates: usize) -> Vec<(usize, T)> {
+        self.query_ann_stochastic(query, k, n_candidates, 1)
+    }
+
+    pub fn query_ann_stochastic_batch(&self, queries: &NdArray<T>, k: usize, n_candidates: usize, n_probes: usize) -> Result<BatchNeighbors<T>, IronForestError> {
+        check_k(k)?;
+        let n_queries = check_queries(queries.shape().dims(), self.dim)?;
+        let dim = self.dim;
+
+        let queries_cow = queries.as_contiguous_slice();
+        let queries_slice: &[T] = &queries_cow;
+        let query_at = |i: usize| self.query_ann_stochastic(&queries_slice[i * dim..(i + 1) * dim], k, n_candidates, n_probes);
+        Ok(if n_queries >= FOREST_PAR_THRESHOLD {
+            (0..n_queries).into_par_iter().map(query_at).collect()
+        } else {
+            (0..n_queries).map(query_at).collect()
+        })
+    }
+
+    pub fn query_ann_batch(&self, queries: &NdArray<T>, k: usize, n_candidates: usize) -> Result<BatchNeighbors<T>, IronForestError> {
+        self.query_ann_stochastic_batch(queries, k, n_candidates, 1)
+    }
+}
+
+/// One tree of the forest seen as a `SpatialTree` over the shared, unreordered data.
+struct RPTreeView<'a, T: IronFloat> {
+    forest: &'a RPForest<T>,
+    tree: &'a RPForestTree<T>,
+}
+
+impl<T: IronFloat> SpatialTree for RPTreeView<'_, T> {
+    type Node = RPNode<T>;
+    type Float = T;
+    const REDUCED: bool = true;
+
+    fn nodes(&self) -> &[RPNode<T>] { &self.tree.nodes }
+    fn indices(&self) -> &[usize] { &self.tree.indices }
+    fn data(&self) -> &[T] { self.forest.data.as_slice_unchecked() }
+    fn dim(&self) -> usize { self.forest.dim }
+    fn metric(&self) -> &DistanceMetric { &self.forest.metric }
+    fn n_points(&self) -> usize { self.forest.n_points }
+    fn data_is_reordered(&self) -> bool { false }
+
+    fn node_start(&self, idx: usize) -> usize { self.tree.nodes[idx].start }
+    fn node_end(&self, idx: usize) -> usize { self.tree.nodes[idx].end }
+    fn node_left(&self, idx: usize) -> Option<usize> { self.tree.nodes[idx].left }
+    fn node_right(&self, idx: usize) -> Option<usize> { self.tree.nodes[idx].right }
+
+    fn child_lower_bound(&self, _child_idx: usize, _query: &[T]) -> T { T::zero() }
+
+    fn plan_traversal(&self, node_idx: usize, query: &[T]) -> TraversalPlan<T> {
+        rp_plan(&self.tree.nodes, node_idx, query)
+    }
+}
+
+impl<T: IronFloat> AnnQuery for RPTreeView<'_, T> {}
@@ -1,26 +1,27 @@
-use crate::{Generator, array::{NdArray, Shape}, projection::{ProjectionType, RandomProjection, random_projection::ProjectionDirection}, spatial::{HeapItem, common::{DistanceMetric, IronFloat}, spatial_tree::{ChildTraversal, TraversalPlan}}};
-use crate::spatial::queries::{KnnQuery, RadiusQuery, AnnQuery, KdeQuery};
+use crate::{Generator, array::{NdArray, Shape}, projection::{ProjectionType, RandomProjection, SplitMode, random_projection::ProjectionDirection}, spatial::{HeapItem, common::{DistanceMetric, IronFloat}, spatial_tree::{ChildTraversal, TraversalPlan}}};
+use crate::spatial::queries::{KnnQuery, RadiusQuery, AnnQuery, KdeQuery, MeanShiftQuery};
 use crate::spatial::SpatialTree;
 use serde::{Deserialize, Serialize};
 
 
-// RPNode stores the split value and projection direction in f64 regardless of
-// the tree's data type. Projections are fundamentally f64 computations currently.
+// RPNode stores the split value and projection direction in the tree's data type, so
+// f32 trees project queries with f32 SIMD dot products.
 #[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct RPNode {
+#[serde(bound = "T: IronFloat")]
+pub struct RPNode<T: IronFloat> {
     pub start: usize,
     pub end: usize,
     pub left: Option<usize>,
     pub right: Option<usize>,
-    pub direction: ProjectionDirection,
-    pub split: f64,
+    pub direction: ProjectionDirection<T>,
+    pub split: T,
 }
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(bound = "T: IronFloat")]
 pub struct RPTree<T: IronFloat> {
-    pub nodes: Vec<RPNode>,
+    pub nodes: Vec<RPNode<T>>,
     pub indices: Vec<usize>,
     pub data: NdArray<T>,
     pub n_points: usize,
@@ -30,6 +31,8 @@ pub struct RPTree<T: IronFloat> {
     pub projection_type: ProjectionType,
     rng: Generator,
     pub data_is_reordered: bool,
+    #[serde(default)]
+    pub split_mode: SplitMode,
 }
 
 impl<T: IronFloat> RPTree<T> {
@@ -38,6 +41,7 @@ impl<T: IronFloat> RPTree<T> {
         leaf_size: usize,
         metric: DistanceMetric,
         projection_type: ProjectionType,
+        split_mode: SplitMode,
         seed: u64,
     ) -> Self {
         let shape = data.shape().dims();
@@ -69,6 +73,7 @@ impl<T: IronFloat> RPTree<T> {
             projection_type,
             rng,
             data_is_reordered: false,
+            split_mode,
         };
 
         tree.build_recursive(0, n_points);
@@ -91,6 +96,33 @@ impl<T: IronFloat> RPTree<T> {
     }
 
     fn build_recursive(&mut self, start: usize, end: usize) -> usize {
+        let mut builder = RPBuilder {
+            data: &self.data,
+            indices: &mut self.indices,
+            nodes: &mut self.nodes,
+            leaf_size: self.leaf_size,
+            projection_type: self.projection_type,
+            split_mode: self.split_mode,
+            rng: &mut self.rng,
+        };
+        builder.build(start, end)
+    }
+}
+
+/// Recursive median splits along random directions. Shared by `RPTree` and `RPForest`,
+/// which partition the same data without reordering it.
+pub(crate) struct RPBuilder<'a, T: IronFloat> {
+    pub data: &'a NdArray<T>,
+    pub indices: &'a mut Vec<usize>,
+    pub nodes: &'a mut Vec<RPNode<T>>,
+    pub leaf_size: usize,
+    pub projection_type: ProjectionType,
+    pub split_mode: SplitMode,
+    pub rng: &'a mut Generator,
+}
+
+impl<T: IronFloat> RPBuilder<'_, T> {
+    pub fn build(&mut self, start: usize, end: usize) -> usize {
         let node_idx = self.nodes.len();
 
         self.nodes.push(RPNode {
@@ -99,7 +131,7 @@ impl<T: IronFloat> RPTree<T> {
             left: None,
             right: None,
             direction: ProjectionDirection::Empty,
-            split: 0.0,
+            split: T::zero(),
         });
 
         let count = end - start;
@@ -107,21 +139,26 @@ impl<T: IronFloat> RPTree<T> {
             return node_idx;
         }
 
-        let (direction, split, mid) = RandomProjection::rp_split(
-            &self.data,
-            &mut self.indices,
-            start,
-            end,
-            self.dim,
+        let direction = RandomProjection::split_direction(
+            self.data,
+            &self.indices[start..end],
             self.projection_type,
-            &mut self.rng,
+            self.split_mode,
+            self.rng,
+        );
+        let (split, offset) = RandomProjection::split_indices(
+            self.data,
+            &mut self.indices[start..end],
+            &direction,
+            self.split_mode,
         );
+        let mid = start + offset;
 
         self.nodes[node_idx].direction = direction;
         self.nodes[node_idx].split = split;
 
-        let left_idx = self.build_recursive(start, mid);
-        let right_idx = self.build_recursive(mid, end);
+        let left_idx = self.build(start, mid);
+        let right_idx = self.build(mid, end);
 
         self.nodes[node_idx].left = Some(left_idx);
         self.nodes[node_idx].right = Some(right_idx);
@@ -130,13 +167,29 @@ impl<T: IronFloat> RPTree<T> {
     }
 }
 
+/// Descends toward the query's side of the split first. The other side is bounded by the
+/// squared projected distance to the split, in reduced units.
+pub(crate) fn rp_plan<T: IronFloat>(nodes: &[RPNode<T>], node_idx: usize, query: &[T]) -> TraversalPlan<T> {
+    let node = &nodes[node_idx];
+    let (l, r) = (node.left.unwrap(), node.right.unwrap());
+    let proj = node.direction.project(query);
+    let bound = (proj - node.split).powi(2);
+
+    let (first, second) = if proj <= node.split { (l, r) } else { (r, l) };
+
+    TraversalPlan {
+        first: ChildTraversal { child_idx: first, lower_bound: T::zero() },
+        second: ChildTraversal { child_idx: second, lower_bound: bound },
+    }
+}
+
 
 impl<T: IronFloat> SpatialTree for RPTree<T> {
-    type Node = RPNode;
+    type Node = RPNode<T>;
     type Float = T;
     const REDUCED: bool = true;
 
-    fn nodes(&self) -> &[RPNode] { &self.nodes }
+    fn nodes(&self) -> &[RPNode<T>] { &self.nodes }
     fn indices(&self) -> &[usize] { &self.indices }
     fn data(&self) -> &[T] { self.data.as_slice_unchecked() }
     fn dim(&self) -> usize { self.dim }
@@ -153,22 +206,12 @@ impl<T: IronFloat> SpatialTree for RPTree<T> {
     fn traversal_order(&self, node_idx: usize, query: &[T]) -> (usize, usize) {
         let node = &self.nodes[node_idx];
         let (l, r) = (node.left.unwrap(), node.right.unwrap());
-        let proj = node.direction.project_t(query);
+        let proj = node.direction.project(query);
         if proj <= node.split { (l, r) } else { (r, l) }
     }
 
     fn plan_traversal(&self, node_idx: usize, query: &[T]) -> TraversalPlan<T> {
-        let node = &self.nodes[node_idx];
-        let (l, r) = (node.left.unwrap(), node.right.unwrap());
-        let proj = node.direction.project_t(query);
-        let bound = T::from((proj - node.split).abs().powi(2)).unwrap();
-
-        let (first, second) = if proj <= node.split { (l, r) } else { (r, l) };
-
-        TraversalPlan {
-            first: ChildTraversal { child_idx: first, lower_bound: T::zero() },
-            second: ChildTraversal { child_idx: second, lower_bound: bound },
-        }
+        rp_plan(&self.nodes, node_idx, query)
     }
 }
 
@@ -181,7 +224,60 @@ impl<T: IronFloat> RadiusQuery for RPTree<T> {
 }
 
 impl<T: IronFloat> KdeQuery for RPTree<T> {}
+impl<T: IronFloat> MeanShiftQuery for RPTree<T> {}
 
 impl<T: IronFloat> AnnQuery for RPTree<T> {
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial::trees::BruteForce;
+
+    /// Tight clusters whose centers, like the points within them, shrink by half along
+    /// each successive axis. The last `n_queries` rows are held out as queries.
+    fn anisotropic_clusters(n: usize, n_queries: usize, dim: usize) -> (NdArray<f64>, Vec<f64>) {
+        let mut rng = Generator::from_seed(21);
+        let noise = rng.standard_normal(Shape::new(vec![n + n_queries, dim]));
+        let centers = rng.standard_normal(Shape::new(vec![200, dim]));
+        let values: Vec<f64> = noise.as_slice_unchecked().iter().enumerate()
+            .map(|(i, v)| {
+                let (row, col) = (i / dim, i % dim);
+                let center = centers.as_slice_unchecked()[(row % 200) * dim + col];
+                (0.05 * v + center) * 0.5f64.powi(col as i32)
+            })
+            .collect();
+        let points = NdArray::from_vec(Shape::new(vec![n, dim]), values[..n * dim].to_vec());
+        (points, values[n * dim..].to_vec())
+    }
+
+    #[test]
+    fn split_modes_beat_median_recall_on_anisotropic_data() {
+        let (n, dim, k) = (2000, 16, 10);
+        let (points, queries) = anisotropic_clusters(n, 100, dim);
+        let brute = BruteForce::new(points.clone(), DistanceMetric::Euclidean);
+        let exact: Vec<Vec<usize>> = queries.chunks(dim)
+            .map(|q| brute.query_knn(q, k).unwrap().into_iter().map(|(i, _)| i).collect())
+            .collect();
+
+        // a single probe with k candidates, so recall reflects how well the splits
+        // keep neighbors together
+        let recall = |split_mode: SplitMode| {
+            let mut hits = 0;
+            for seed in 0..10 {
+                let tree = RPTree::new(points.clone(), 10, DistanceMetric::Euclidean, ProjectionType::Gaussian, split_mode, seed);
+                for (q, truth) in queries.chunks(dim).zip(&exact) {
+                    hits += tree.query_ann_stochastic(q, k, k, 1).iter().filter(|(i, _)| truth.contains(i)).count();
+                }
+            }
+            hits as f64 / (10 * exact.len() * k) as f64
+        };
+
+        let median = recall(SplitMode::Median);
+        for split_mode in [SplitMode::BestOf(8), SplitMode::Principal, SplitMode::MaxMargin] {
+            let r = recall(split_mode);
+            assert!(r > median, "{:?} recall {} <= median {}", split_mode, r, median);
+        }
+    }
+}
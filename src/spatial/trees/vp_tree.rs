@@ -1,8 +1,9 @@
 use std::cmp::Ordering;
 use crate::{array::{NdArray, Shape}, spatial::common::{DistanceMetric, IronFloat}};
 use crate::random::Generator;
-use crate::spatial::queries::{KnnQuery, RadiusQuery, KdeQuery, AnnQuery};
+use crate::spatial::queries::{KnnQuery, RadiusQuery, KdeQuery, AnnQuery, MeanShiftQuery};
 use crate::spatial::SpatialTree;
+use crate::spatial::spatial_tree::{ChildTraversal, TraversalPlan};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
@@ -21,16 +22,15 @@ impl VantagePointSelection {
         data: &NdArray<T>,
         indices: &[usize],
         metric: &DistanceMetric,
+        rng: &mut Generator,
     ) -> usize {
         match self {
             VantagePointSelection::First => start,
             VantagePointSelection::Random => {
-                let mut rng = Generator::new();
                 let i = rng.randint(start as i64, end as i64, crate::Shape::scalar());
                 i.item() as usize
             },
             VantagePointSelection::Variance { sample_size } => {
-                let mut rng = Generator::new();
                 let n = end - start;
                 let k = (*sample_size).min(n);
 
@@ -84,10 +84,14 @@ pub struct VPTree<T: IronFloat> {
     pub metric: DistanceMetric,
     pub selection_method: VantagePointSelection,
     pub data_is_reordered: bool,
+    #[serde(default)]
+    pub seed: u64,
 }
 
 impl<T: IronFloat> VPTree<T> {
-    pub fn new(mut data: NdArray<T>, leaf_size: usize, metric: DistanceMetric, selection_method: VantagePointSelection) -> Self {
+    /// Random and variance vantage selection draw from a generator seeded with `seed`, so
+    /// the same data and seed always build the same tree.
+    pub fn new(mut data: NdArray<T>, leaf_size: usize, metric: DistanceMetric, selection_method: VantagePointSelection, seed: u64) -> Self {
         let shape = data.shape().dims();
         assert!(shape.len() == 2, "Expected 2D array (n_points, dim)");
         let n_points = shape[0];
@@ -114,9 +118,11 @@ impl<T: IronFloat> VPTree<T> {
             metric,
             selection_method,
             data_is_reordered: false,
+            seed,
         };
 
-        tree.build_recursive(0, n_points);
+        let mut rng = Generator::from_seed(seed);
+        tree.build_recursive(0, n_points, &mut rng);
         if will_reorder {
             tree.reorder_data();
             tree.data_is_reordered = true;
@@ -135,13 +141,14 @@ impl<T: IronFloat> VPTree<T> {
         self.data = NdArray::from_vec(Shape::new(vec![self.n_points, self.dim]), new_data);
     }
 
-    fn init_node(&mut self, start: usize, end: usize) -> (T, usize) {
+    fn init_node(&mut self, start: usize, end: usize, rng: &mut Generator) -> (T, usize) {
         let vantage_idx = self.selection_method.select_vantage(
             start,
             end,
             &self.data,
             &self.indices,
             &self.metric,
+            rng,
         );
         self.indices.swap(start, vantage_idx);
 
@@ -180,7 +187,7 @@ impl<T: IronFloat> VPTree<T> {
         (median_radius, start + 1 + mid)
     }
 
-    fn build_recursive(&mut self, start: usize, end: usize) -> usize {
+    fn build_recursive(&mut self, start: usize, end: usize, rng: &mut Generator) -> usize {
         let count = end - start;
         let dim = self.dim;
 
@@ -214,7 +221,7 @@ impl<T: IronFloat> VPTree<T> {
             return node_idx;
         }
 
-        let (radius, mid) = self.init_node(start, end);
+        let (radius, mid) = self.init_node(start, end, rng);
 
         let node_idx = self.nodes.len();
         self.nodes.push(VPNode {
@@ -227,8 +234,8 @@ impl<T: IronFloat> VPTree<T> {
             bounding_radius,
         });
 
-        let left_idx = self.build_recursive(start, mid);
-        let right_idx = self.build_recursive(mid, end);
+        let left_idx = self.build_recursive(start, mid, rng);
+        let right_idx = self.build_recursive(mid, end, rng);
 
         self.nodes[node_idx].left = Some(left_idx);
         self.nodes[node_idx].right = Some(right_idx);
@@ -267,9 +274,51 @@ impl<T: IronFloat> SpatialTree for VPTree<T> {
         let (l, r) = (node.left.unwrap(), node.right.unwrap());
         if d < node.radius { (l, r) } else { (r, l) }
     }
+
+    fn plan_traversal(&self, node_idx: usize, query: &[T]) -> TraversalPlan<T> {
+        let node = &self.nodes[node_idx];
+        let d = self.metric.distance(query, self.get_point(node.start));
+        let (l, r) = (node.left.unwrap(), node.right.unwrap());
+        let (first, second) = if d < node.radius { (l, r) } else { (r, l) };
+        // Points across the median radius are at least |d - radius| away by the triangle inequality
+        let margin = (d - node.radius).abs();
+
+        TraversalPlan {
+            first: ChildTraversal { child_idx: first, lower_bound: self.child_lower_bound(first, query) },
+            second: ChildTraversal {
+                child_idx: second,
+                lower_bound: self.child_lower_bound(second, query).max(margin),
+            },
+        }
+    }
 }
 
 impl<T: IronFloat> KnnQuery for VPTree<T> {}
 impl<T: IronFloat> RadiusQuery for VPTree<T> {}
 impl<T: IronFloat> KdeQuery for VPTree<T> {}
+impl<T: IronFloat> MeanShiftQuery for VPTree<T> {}
 impl<T: IronFloat> AnnQuery for VPTree<T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vantage_points(selection: VantagePointSelection, seed: u64) -> Vec<usize> {
+        let data = Generator::from_seed(5).standard_normal(Shape::new(vec![300, 5]));
+        let tree = VPTree::new(data, 10, DistanceMetric::Euclidean, selection, seed);
+        tree.nodes.iter()
+            .filter(|node| node.left.is_some())
+            .map(|node| tree.indices[node.start])
+            .collect()
+    }
+
+    #[test]
+    fn seed_decides_vantage_selection() {
+        for selection in [VantagePointSelection::Random, VantagePointSelection::Variance { sample_size: 20 }] {
+            assert_eq!(vantage_points(selection, 7), vantage_points(selection, 7));
+            assert_ne!(vantage_points(selection, 7), vantage_points(selection, 8), "{:?}", selection);
+        }
+        // first-point selection draws nothing, so the seed cannot change it
+        assert_eq!(vantage_points(VantagePointSelection::First, 7), vantage_points(VantagePointSelection::First, 8));
+    }
+}
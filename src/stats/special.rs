@@ -1,35 +1,36 @@
-pub fn gamma(x: f64) -> f64 {
+const LANCZOS_G: f64 = 7.0;
+const LANCZOS_C: [f64; 9] = [
+    0.99999999999980993,
+    676.5203681218851,
+    -1259.1392167224028,
+    771.32342877765313,
+    -176.61502916214059,
+    12.507343278686905,
+    -0.13857109526572012,
+    9.9843695780195716e-6,
+    1.5056327351493116e-7,
+];
+
+pub fn ln_gamma(x: f64) -> f64 {
     if x <= 0.0 && x == x.floor() {
-        panic!("gamma undefined for non-positive integers");
+        panic!("ln_gamma undefined for non-positive integers");
     }
 
     if x < 0.5 {
-        std::f64::consts::PI / ((std::f64::consts::PI * x).sin() * gamma(1.0 - x))
+        // Reflection formula, yields ln|gamma(x)|
+        (std::f64::consts::PI / (std::f64::consts::PI * x).sin().abs()).ln() - ln_gamma(1.0 - x)
     } else {
-        lanczos_gamma(x)
+        lanczos_ln_gamma(x)
     }
 }
 
-fn lanczos_gamma(x: f64) -> f64 {
-    const G: f64 = 7.0;
-    const C: [f64; 9] = [
-        0.99999999999980993,
-        676.5203681218851,
-        -1259.1392167224028,
-        771.32342877765313,
-        -176.61502916214059,
-        12.507343278686905,
-        -0.13857109526572012,
-        9.9843695780195716e-6,
-        1.5056327351493116e-7,
-    ];
-
+fn lanczos_ln_gamma(x: f64) -> f64 {
     let x = x - 1.0;
-    let mut sum = C[0];
-    for (i, &c) in C[1..].iter().enumerate() {
+    let mut sum = LANCZOS_C[0];
+    for (i, &c) in LANCZOS_C[1..].iter().enumerate() {
         sum += c / (x + i as f64 + 1.0);
     }
 
-    let t = x + G + 0.5;
-    (2.0 * std::f64::consts::PI).sqrt() * t.powf(x + 0.5) * (-t).exp() * sum
-}
\ No newline at end of file
+    let t = x + LANCZOS_G + 0.5;
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
@@ -1,6 +1,8 @@
 use crate::IronFloat;
 use crate::array::ndarray::{NdArray};
 use crate::array::shape::Shape;
+use crate::error::IronForestError;
+
 impl NdArray<f64> {
     pub fn sum(&self) -> f64 {
         self.as_slice_unchecked().iter().sum()
@@ -112,6 +114,74 @@ impl NdArray<f64> {
             .copied()
             .fold(f64::INFINITY, f64::min)
     }
+
+    /// Row length and row count of a 1D or 2D array, treating a 1D array as one row.
+    fn last_axis_rows(&self) -> Result<(usize, usize), IronForestError> {
+        match self.shape().dims() {
+            [n] => Ok((*n, 1)),
+            [rows, cols] => Ok((*cols, *rows)),
+            dims => Err(IronForestError::invalid(format!(
+                "Expected a 1D or 2D array, got {}D", dims.len()
+            ))),
+        }
+    }
+
+    /// Softmax along the last axis. Rows whose entries are all -inf carry no mass;
+    /// they take the softmax of `fallback` when given and NaN otherwise.
+    pub fn softmax(&self, fallback: Option<&[f64]>) -> Result<NdArray<f64>, IronForestError> {
+        let (cols, rows) = self.last_axis_rows()?;
+        let fallback = match fallback {
+            Some(f) if f.len() != cols => {
+                return Err(IronForestError::DimensionMismatch { expected: cols, got: f.len() });
+            }
+            Some(f) => Some(softmax_row(f)),
+            None => None,
+        };
+
+        let data = self.as_contiguous_slice();
+        let mut out = Vec::with_capacity(rows * cols);
+        for row in data.chunks(cols.max(1)).take(rows) {
+            if row.iter().all(|&v| v == f64::NEG_INFINITY) {
+                match &fallback {
+                    Some(f) => out.extend_from_slice(f),
+                    None => out.extend(std::iter::repeat_n(f64::NAN, cols)),
+                }
+            } else {
+                out.extend(softmax_row(row));
+            }
+        }
+        Ok(NdArray::from_vec(self.shape().clone(), out))
+    }
+
+    /// Index of the largest entry along the last axis, one per row. Ties go to the
+    /// first index and NaN entries are skipped.
+    pub fn argmax(&self) -> Result<NdArray<i64>, IronForestError> {
+        let (cols, rows) = self.last_axis_rows()?;
+        if cols == 0 {
+            return Err(IronForestError::invalid("argmax of an empty axis"));
+        }
+
+        let data = self.as_contiguous_slice();
+        let out: Vec<i64> = data.chunks(cols).take(rows).map(|row| {
+            let mut best = 0;
+            let mut best_val = f64::NEG_INFINITY;
+            for (j, &v) in row.iter().enumerate() {
+                if v > best_val {
+                    best = j;
+                    best_val = v;
+                }
+            }
+            best as i64
+        }).collect();
+        Ok(NdArray::from_vec(Shape::d1(out.len()), out))
+    }
+}
+
+fn softmax_row(row: &[f64]) -> Vec<f64> {
+    let m = row.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let exps: Vec<f64> = row.iter().map(|&v| (v - m).exp()).collect();
+    let total: f64 = exps.iter().sum();
+    exps.into_iter().map(|e| e / total).collect()
 }
 
 impl<T> NdArray<T> { 
@@ -163,4 +233,40 @@ impl<T: IronFloat> NdArray<T> {
     }
 
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn softmax_rows_sum_to_one_and_use_fallback() {
+        let a = NdArray::from_vec(
+            Shape::new(vec![2, 3]),
+            vec![0.0, 1.0, 2.0, f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY],
+        );
+        let fallback = [0.5f64.ln(), 0.25f64.ln(), 0.25f64.ln()];
+
+        let p = a.softmax(Some(&fallback)).unwrap();
+        let row0 = p.row(0);
+        assert!((row0.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+        assert!(row0[0] < row0[1] && row0[1] < row0[2]);
+        for (got, want) in p.row(1).iter().zip([0.5, 0.25, 0.25]) {
+            assert!((got - want).abs() < 1e-12);
+        }
+
+        assert!(a.softmax(None).unwrap().row(1).iter().all(|v| v.is_nan()));
+        assert!(a.softmax(Some(&[0.0])).is_err());
+    }
+
+    #[test]
+    fn argmax_picks_first_maximum_per_row() {
+        let a = NdArray::from_vec(
+            Shape::new(vec![3, 3]),
+            vec![1.0, 3.0, 3.0, f64::NAN, -1.0, -2.0, f64::NEG_INFINITY, f64::NEG_INFINITY, 0.0],
+        );
+        assert_eq!(a.argmax().unwrap().as_slice_unchecked(), &[1, 1, 2]);
+
+        let empty = NdArray::<f64>::from_vec(Shape::new(vec![2, 0]), vec![]);
+        assert!(empty.argmax().is_err());
+    }
+}
@@ -170,6 +170,19 @@ def test_eig_f32_input_returns_f64():
     assert vals.dtype == "float64"
     assert vecs.dtype == "float64"
 
+def test_eigh_f32_input_returns_f64():
+    a = ndutils.asarray(np.array([[2.0, 1.0], [1.0, 2.0]], dtype=np.float32))
+    vals, vecs = linalg.eigh(a)
+    assert vals.dtype == "float64"
+    assert vecs.dtype == "float64"
+
+def test_svd_f32_input_returns_f64():
+    a = ndutils.asarray(np.ones((3, 2), dtype=np.float32))
+    u, s, vt = linalg.svd(a)
+    assert u.dtype == "float64"
+    assert s.dtype == "float64"
+    assert vt.dtype == "float64"
+
 # ---------------------------------------------------------------------------
 # 8. Spatial trees accept f32 arrays
 # ---------------------------------------------------------------------------
@@ -202,6 +215,44 @@ def test_spatial_tree_f32_knn_query(name, constructor):
     assert result.indices.shape[1] == 3
 
 
+# ---------------------------------------------------------------------------
+# 9. Random projections stay f32
+# ---------------------------------------------------------------------------
+
+PROJECTION_DATA = RNG.standard_normal((200, 48))
+
+@pytest.mark.parametrize("projection_type", ["gaussian", "sparse", "achlioptas", "very_sparse", "hadamard"])
+def test_projection_reducer_f32_preserves_dtype(projection_type):
+    reducer = spatial.ProjectionReducer(48, 16, projection_type=projection_type, seed=0)
+    out32 = reducer.transform(ndutils.asarray(PROJECTION_DATA.astype(np.float32)))
+    out64 = reducer.transform(ndutils.asarray(PROJECTION_DATA))
+    assert out32.dtype == "float32"
+    assert out64.dtype == "float64"
+    np.testing.assert_allclose(ndutils.to_numpy(out32), ndutils.to_numpy(out64), rtol=1e-4, atol=1e-4)
+
+def test_projection_reducer_f32_single_row():
+    reducer = spatial.ProjectionReducer(48, 16, seed=0)
+    out = reducer.transform(ndutils.asarray(PROJECTION_DATA[0].astype(np.float32)))
+    assert out.dtype == "float32"
+    assert out.shape == [16]
+
+def test_projection_reducer_fit_transform_f32():
+    reducer, out = spatial.ProjectionReducer.fit_transform(
+        ndutils.asarray(PROJECTION_DATA.astype(np.float32)), 16, seed=0
+    )
+    assert out.dtype == "float32"
+    assert reducer.output_dim == 16
+
+@pytest.mark.parametrize("split", ["median", "principal", "best_of", "max_margin"])
+def test_rp_tree_f32_matches_f64(split):
+    t32 = spatial.RPTree(ndutils.asarray(PROJECTION_DATA.astype(np.float32)), split=split, seed=0)
+    t64 = spatial.RPTree(ndutils.asarray(PROJECTION_DATA), split=split, seed=0)
+    q = PROJECTION_DATA[:10]
+    idx32 = np.sort(ndutils.to_numpy(t32.query_knn(ndutils.asarray(q.astype(np.float32)), k=5).indices), axis=1)
+    idx64 = np.sort(ndutils.to_numpy(t64.query_knn(ndutils.asarray(q), k=5).indices), axis=1)
+    np.testing.assert_array_equal(idx32, idx64)
+
+
 # ---------------------------------------------------------------------------
 # 10. Arithmetic dtype rules
 # ---------------------------------------------------------------------------
@@ -0,0 +1,69 @@
+"""
+Tests for the symmetric eigensolver and SVD in ironforest.linalg, checked
+against numpy.
+"""
+
+import numpy as np
+import pytest
+
+from ironforest import linalg
+
+RNG = np.random.default_rng(7)
+
+
+def to_np(arr) -> np.ndarray:
+    return np.asarray(arr.tolist(), dtype=np.float64)
+
+
+def random_symmetric(n):
+    x = RNG.standard_normal((n + 3, n))
+    return x.T @ x
+
+
+@pytest.mark.parametrize("n", [1, 2, 5, 30])
+def test_eigh_matches_numpy(n):
+    a = random_symmetric(n)
+    vals, vecs = linalg.eigh(a)
+    vals, vecs = to_np(vals), to_np(vecs)
+    np.testing.assert_allclose(vals, np.linalg.eigvalsh(a), rtol=1e-10, atol=1e-10)
+    np.testing.assert_allclose(vecs @ np.diag(vals) @ vecs.T, a, atol=1e-9)
+    np.testing.assert_allclose(vecs.T @ vecs, np.eye(n), atol=1e-10)
+
+
+def test_eigh_reads_lower_triangle():
+    a = random_symmetric(4)
+    upper_garbage = np.tril(a) + np.triu(RNG.standard_normal((4, 4)), 1)
+    np.testing.assert_allclose(to_np(linalg.eigvalsh(upper_garbage)), np.linalg.eigvalsh(a), atol=1e-10)
+
+
+def test_eigh_repeated_eigenvalues():
+    vals, vecs = linalg.eigh(np.eye(3) * 2.0)
+    np.testing.assert_allclose(to_np(vals), [2.0, 2.0, 2.0])
+    np.testing.assert_allclose(to_np(vecs).T @ to_np(vecs), np.eye(3), atol=1e-12)
+
+
+def test_eigh_rejects_non_square():
+    with pytest.raises(ValueError):
+        linalg.eigh(np.ones((2, 3)))
+
+
+@pytest.mark.parametrize("shape", [(6, 3), (3, 6), (5, 5), (1, 4), (40, 12)])
+@pytest.mark.parametrize("full_matrices", [False, True])
+def test_svd_matches_numpy(shape, full_matrices):
+    a = RNG.standard_normal(shape)
+    u, s, vt = (to_np(x) for x in linalg.svd(a, full_matrices=full_matrices))
+    nu, ns, nvt = np.linalg.svd(a, full_matrices=full_matrices)
+    assert u.shape == nu.shape and vt.shape == nvt.shape
+    np.testing.assert_allclose(s, ns, rtol=1e-10, atol=1e-12)
+    k = min(shape)
+    np.testing.assert_allclose(u[:, :k] @ np.diag(s) @ vt[:k], a, atol=1e-10)
+    np.testing.assert_allclose(u.T @ u, np.eye(u.shape[1]), atol=1e-10)
+    np.testing.assert_allclose(vt @ vt.T, np.eye(vt.shape[0]), atol=1e-10)
+
+
+def test_svd_rank_deficient():
+    a = RNG.standard_normal((6, 2)) @ RNG.standard_normal((2, 5))
+    u, s, vt = (to_np(x) for x in linalg.svd(a))
+    np.testing.assert_allclose(s[2:], 0.0, atol=1e-10)
+    np.testing.assert_allclose(u.T @ u, np.eye(5), atol=1e-10)
+    np.testing.assert_allclose(u @ np.diag(s) @ vt, a, atol=1e-10)
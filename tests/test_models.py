@@ -3,7 +3,7 @@ Correctness, robustness, and edge-case tests for all model classes.
 
 Covers: DecisionTreeClassifier, DecisionTreeRegressor,
 RandomForestClassifier, RandomForestRegressor, IsolationForest,
-KNNClassifier, KNNRegressor, LinearRegression.
+KNNClassifier, KNNRegressor, LinearRegression, KDEClassifier, ConditionalKDE.
 """
 
 import math
@@ -17,6 +17,8 @@ from ironforest.models import (
     KNNClassifier,
     KNNRegressor,
     LinearRegression,
+    KDEClassifier,
+    ConditionalKDE,
 )
 
 # ---------------------------------------------------------------------------
@@ -213,3 +215,104 @@ def test_linear_regression_predict_before_fit_raises():
     model = LinearRegression()
     with pytest.raises(RuntimeError, match="(?i)fitted|fit"):
         model.predict(np.ones((5, 2)))
+
+
+# ---------------------------------------------------------------------------
+# Section 9 – KDEClassifier
+# ---------------------------------------------------------------------------
+
+def make_blob_data(n=200, seed=0):
+    rng = np.random.default_rng(seed)
+    y = (np.arange(n) % 2).astype(float)
+    X = rng.standard_normal((n, 2)) * 0.5 + y[:, None] * 3.0
+    return X, y
+
+
+@pytest.mark.parametrize("tree_type", ["kd", "ball", "vp", "brute", "agg"])
+def test_kdec_all_tree_types(tree_type):
+    X, y = make_blob_data()
+    clf = KDEClassifier(bandwidth=0.5, tree=tree_type).fit(X, y)
+    assert accuracy(y, clf.predict(X)) > 0.95
+
+
+def test_kdec_predict_proba_sums_to_one():
+    X, y = make_clf_data(n=200, n_classes=3)
+    clf = KDEClassifier(bandwidth=0.5).fit(X, y)
+    proba = irn.ndutils.to_numpy(clf.predict_proba(X))
+    assert proba.shape == (200, 3)
+    np.testing.assert_allclose(proba.sum(axis=1), np.ones(200), atol=1e-9)
+
+
+def test_kdec_matches_bayes_rule():
+    X, y = make_blob_data(n=60)
+    clf = KDEClassifier(bandwidth=0.7, tree="brute").fit(X, y)
+    q = np.array([[1.2, 1.5]])
+    dens = []
+    for c in (0.0, 1.0):
+        diff = X[y == c] - q
+        dens.append(np.exp(-0.5 * (diff ** 2).sum(axis=1) / 0.7 ** 2).mean() * 0.5)
+    expected = np.array(dens) / sum(dens)
+    proba = irn.ndutils.to_numpy(clf.predict_proba(q)).flatten()
+    np.testing.assert_allclose(proba, expected, rtol=1e-6)
+
+
+def test_kdec_priors_shift_prediction():
+    X, y = make_blob_data()
+    mid = np.array([[1.5, 1.5]])
+    low = KDEClassifier(bandwidth=0.5, priors=[0.99, 0.01]).fit(X, y)
+    high = KDEClassifier(bandwidth=0.5, priors=[0.01, 0.99]).fit(X, y)
+    assert low.predict(mid) == 0.0
+    assert high.predict(mid) == 1.0
+
+
+def test_kdec_compact_kernel_outside_support_uses_priors():
+    X, y = make_blob_data()
+    clf = KDEClassifier(bandwidth=0.5, kernel="epanechnikov", priors=[0.25, 0.75]).fit(X, y)
+    proba = irn.ndutils.to_numpy(clf.predict_proba(np.array([[100.0, 100.0]]))).flatten()
+    np.testing.assert_allclose(proba, [0.25, 0.75], atol=1e-12)
+
+
+def test_kdec_bad_priors_raise():
+    X, y = make_blob_data()
+    with pytest.raises(ValueError):
+        KDEClassifier(priors=[1.0]).fit(X, y)
+    with pytest.raises(ValueError):
+        KDEClassifier(priors=[1.0, 0.0]).fit(X, y)
+
+
+# ---------------------------------------------------------------------------
+# Section 10 – ConditionalKDE
+# ---------------------------------------------------------------------------
+
+@pytest.mark.parametrize("tree_type", ["kd", "ball", "vp", "brute"])
+@pytest.mark.parametrize("kernel", ["gaussian", "epanechnikov"])
+def test_ckde_integrates_to_one(tree_type, kernel):
+    rng = np.random.default_rng(11)
+    X = rng.uniform(-2, 2, (300, 1))
+    y = 2.0 * X[:, 0] + rng.standard_normal(300) * 0.2
+    model = ConditionalKDE(bandwidth=0.3, response_bandwidth=0.2, kernel=kernel, tree=tree_type).fit(X, y)
+    grid = np.linspace(-8, 8, 4001)
+    dens = irn.ndutils.to_numpy(model.density(np.array([[0.5], [-1.0]]), grid))
+    assert dens.shape == (2, 4001)
+    step = grid[1] - grid[0]
+    np.testing.assert_allclose(dens.sum(axis=1) * step, [1.0, 1.0], atol=1e-3)
+    means = (dens * grid).sum(axis=1) * step
+    np.testing.assert_allclose(means, [1.0, -2.0], atol=0.2)
+
+
+def test_ckde_single_query_shape():
+    rng = np.random.default_rng(12)
+    X = rng.standard_normal((100, 2))
+    y = X[:, 0]
+    model = ConditionalKDE(bandwidth=0.5).fit(X, y)
+    dens = irn.ndutils.to_numpy(model.density(np.zeros(2), np.linspace(-1, 1, 5)))
+    assert dens.shape == (5,)
+    assert np.all(dens > 0.0)
+
+
+def test_ckde_no_support_is_nan():
+    rng = np.random.default_rng(13)
+    X = rng.standard_normal((100, 2))
+    model = ConditionalKDE(bandwidth=0.5, kernel="epanechnikov").fit(X, X[:, 0])
+    dens = irn.ndutils.to_numpy(model.density(np.full(2, 100.0), [0.0]))
+    assert np.all(np.isnan(dens))
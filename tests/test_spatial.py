@@ -42,6 +42,8 @@ ANN_TREES = {
     "KDTree":   lambda d: spatial.KDTree.from_array(d, leaf_size=20),
     "BallTree": lambda d: spatial.BallTree.from_array(d, leaf_size=20),
     "RPTree":   lambda d: spatial.RPTree.from_array(d, leaf_size=20),
+    "VPTree":   lambda d: spatial.VPTree.from_array(d, leaf_size=20),
+    "BruteForce": lambda d: spatial.BruteForce.from_array(d),
 }
 
 ALL_NAMES      = list(TREES.keys())
@@ -145,14 +147,13 @@ def test_k_exceeds_n_points(tree_name):
     assert count <= n
 
 
-@pytest.mark.xfail(strict=False, reason="documenting: k=0 returns empty or raises — behavior not yet specified")
 @pytest.mark.parametrize("tree_name", ALL_NAMES)
 def test_k_equals_zero(tree_name):
     data = RNG.standard_normal((10, 3))
     tree = make_tree(tree_name, data)
     q = make_irn(RNG.standard_normal((1, 3)))
-    result = tree.query_knn(q, 0)
-    assert result.is_empty()
+    with pytest.raises(irn.InvalidKError):
+        tree.query_knn(q, 0)
 
 
 @pytest.mark.parametrize("tree_name", ALL_NAMES)
@@ -362,6 +363,89 @@ def test_ann_recall_reasonable(tree_name):
     assert mean_recall >= 0.6, f"{tree_name} ANN recall {mean_recall:.2f} < 0.6"
 
 
+def test_brute_force_ann_probes_improve_recall():
+    data = RNG.standard_normal((20000, 4))
+    queries = RNG.standard_normal((30, 4))
+    tree = spatial.BruteForce.from_array(make_irn(data))
+    k = 10
+
+    def recall(n_probes):
+        total = 0
+        for i in range(len(queries)):
+            q = make_irn(queries[i:i+1])
+            exact = set(to_np(tree.query_knn(q, k).indices).flatten().tolist())
+            approx = set(to_np(tree.query_ann(q, k, n_probes=n_probes).indices).flatten().tolist())
+            total += len(exact & approx) / k
+        return total / len(queries)
+
+    assert recall(1) <= recall(8) <= recall(10_000) == 1.0
+
+
+@pytest.mark.parametrize("tree_type", ["vp", "brute_force"])
+def test_spatial_index_ann_for_all_trees(tree_type):
+    data = RNG.standard_normal((300, 4))
+    index = spatial.SpatialIndex(make_irn(data), tree_type=tree_type)
+    result = index.query_ann(make_irn(RNG.standard_normal((5, 4))), 3, n_probes=2)
+    assert to_np(result.indices).shape == (5, 3)
+
+
+def _forest_recall(forest, data, queries, k, n_candidates):
+    total = 0
+    for q in queries:
+        exact = set(np.argsort(np.linalg.norm(data - q, axis=1))[:k].tolist())
+        approx = set(to_np(forest.query_ann(make_irn(q), k, n_candidates=n_candidates).indices).flatten().tolist())
+        total += len(exact & approx) / k
+    return total / len(queries)
+
+
+def test_rp_forest_more_trees_improve_recall():
+    data = RNG.standard_normal((3000, 16))
+    queries = RNG.standard_normal((40, 16))
+    one = spatial.RPForest.from_array(make_irn(data), n_trees=1, seed=1)
+    many = spatial.RPForest.from_array(make_irn(data), n_trees=12, seed=1)
+    assert many.n_trees == 12
+    assert _forest_recall(many, data, queries, 10, 20) >= _forest_recall(one, data, queries, 10, 20)
+    assert _forest_recall(many, data, queries, 10, 50) >= 0.8
+
+
+def test_rp_forest_batch_and_roundtrip():
+    data = RNG.standard_normal((500, 8))
+    forest = spatial.RPForest(make_irn(data), n_trees=4, seed=3)
+    queries = make_irn(RNG.standard_normal((6, 8)))
+    result = forest.query_ann(queries, 5, n_probes=2)
+    assert to_np(result.indices).shape == (6, 5)
+    np.testing.assert_allclose(to_np(forest.data()), data)
+
+    loaded = pickle.loads(pickle.dumps(forest))
+    np.testing.assert_array_equal(to_np(loaded.query_ann(queries, 5).indices), to_np(forest.query_ann(queries, 5).indices))
+    with pytest.raises(ValueError):
+        spatial.RPForest(make_irn(data), n_trees=0)
+
+
+
+@pytest.mark.parametrize("split", ["median", "principal", "best_of", "max_margin"])
+def test_rp_tree_split_modes(split):
+    latent = RNG.standard_normal((2000, 4))
+    data = latent @ RNG.standard_normal((4, 24)) + 0.01 * RNG.standard_normal((2000, 24))
+    queries = data[:30] + 0.01 * RNG.standard_normal((30, 24))
+    tree = spatial.RPTree.from_array(make_irn(data), split=split, seed=2)
+    assert _forest_recall(tree, data, queries, 5, 40) >= 0.6
+
+    loaded = pickle.loads(pickle.dumps(tree))
+    q = make_irn(queries[:4])
+    np.testing.assert_array_equal(to_np(loaded.query_ann(q, 5).indices), to_np(tree.query_ann(q, 5).indices))
+
+    forest = spatial.RPForest(make_irn(data), n_trees=3, split=split, seed=2)
+    assert to_np(forest.query_ann(q, 5).indices).shape == (4, 5)
+
+
+def test_rp_tree_invalid_split():
+    data = make_irn(RNG.standard_normal((50, 3)))
+    with pytest.raises(ValueError):
+        spatial.RPTree(data, split="widest")
+    with pytest.raises(ValueError):
+        spatial.RPForest(data, split="widest")
+
 # ---------------------------------------------------------------------------
 # Section 6 – SpatialResult aggregation
 # ---------------------------------------------------------------------------
@@ -480,6 +564,14 @@ def test_pickle_roundtrip(tree_name):
         assert orig_idx == loaded_idx, f"{tree_name}: pickle mismatch at query {i}"
 
 
+
+@pytest.mark.parametrize("selection", ["random", "variance"])
+def test_vp_tree_seeded_build_is_reproducible(selection):
+    data = RNG.standard_normal((300, 5))
+    a = spatial.VPTree(make_irn(data), selection=selection, seed=7)
+    b = spatial.VPTree(make_irn(data), selection=selection, seed=7)
+    assert pickle.dumps(a) == pickle.dumps(b)
+
 # ---------------------------------------------------------------------------
 # Section 8 – Input format robustness
 # ---------------------------------------------------------------------------
@@ -585,7 +677,13 @@ def test_kde_batch_returns_array(tree_name):
     assert all(v > 0.0 for v in vals)
 
 
-@pytest.mark.parametrize("kernel", ["gaussian", "epanechnikov", "uniform", "triangular"])
+ALL_KERNELS = [
+    "gaussian", "epanechnikov", "uniform", "triangular", "exponential",
+    "cosine", "biweight", "triweight", "cauchy", "student_t",
+]
+
+
+@pytest.mark.parametrize("kernel", ALL_KERNELS)
 def test_kde_all_kernels_no_crash(kernel):
     data = RNG.standard_normal((50, 2))
     tree = spatial.KDTree.from_array(make_irn(data), leaf_size=10)
@@ -596,9 +694,977 @@ def test_kde_all_kernels_no_crash(kernel):
     assert val >= 0.0
 
 
+@pytest.mark.parametrize("kernel", ALL_KERNELS)
+def test_kde_normalized_integrates_to_one(kernel):
+    data = np.zeros((1, 1))
+    tree = spatial.BruteForce.from_array(make_irn(data))
+    grid = np.linspace(-400.0, 400.0, 400_001).reshape(-1, 1)
+    dens = to_np(tree.kernel_density(make_irn(grid), bandwidth=0.5, kernel=kernel, normalize=True)).flatten() # type: ignore
+    step = grid[1, 0] - grid[0, 0]
+    assert dens.sum() * step == pytest.approx(1.0, abs=5e-3)
+
+
+def test_kde_cauchy_normalize_high_dim_raises():
+    data = RNG.standard_normal((50, 3))
+    tree = spatial.KDTree.from_array(make_irn(data), leaf_size=10)
+    q = make_irn(np.zeros((1, 3)))
+    with pytest.raises(ValueError):
+        tree.kernel_density(q, bandwidth=1.0, kernel="cauchy", normalize=True)
+    assert tree.kernel_density(q, bandwidth=1.0, kernel="cauchy", normalize=False) > 0.0
+
+
+@pytest.mark.parametrize("nu", [1.5, 3.0, 10.0])
+def test_kde_student_t_nu_integrates_to_one(nu):
+    tree = spatial.BruteForce.from_array(make_irn(np.zeros((1, 1))))
+    grid = np.linspace(-400.0, 400.0, 400_001).reshape(-1, 1)
+    dens = to_np(tree.kernel_density(make_irn(grid), bandwidth=0.5, kernel="student_t", normalize=True, nu=nu)).flatten() # type: ignore
+    step = grid[1, 0] - grid[0, 0]
+    assert dens.sum() * step == pytest.approx(1.0, abs=5e-3)
+
+
+def test_kde_student_t_nu_changes_tails():
+    tree = spatial.BruteForce.from_array(make_irn(np.zeros((1, 1))))
+    q = make_irn(np.array([[5.0]]))
+    heavy = tree.kernel_density(q, kernel="student_t", nu=1.0)
+    light = tree.kernel_density(q, kernel="student_t", nu=30.0)
+    assert heavy > light
+
+
+def test_kde_student_t_nu_validation():
+    data = RNG.standard_normal((50, 3))
+    tree = spatial.KDTree.from_array(make_irn(data), leaf_size=10)
+    q = make_irn(np.zeros((1, 3)))
+    with pytest.raises(ValueError):
+        tree.kernel_density(q, kernel="student_t", nu=0.0)
+    with pytest.raises(ValueError):
+        tree.kernel_density(q, kernel="student_t", nu=1.5, normalize=True)
+    with pytest.raises(ValueError):
+        tree.sample(5, kernel="student_t", nu=2.0, seed=0)
+    assert tree.kernel_density(q, kernel="student_t", nu=2.5, normalize=True) > 0.0
+    agg = spatial.AggTree(data, kernel="student_t", nu=1.5)
+    with pytest.raises(ValueError):
+        agg.kernel_density(q)
+    with pytest.raises(ValueError):
+        agg.sample(5, seed=0)
+    assert agg.kernel_density(q, nu=4.0) > 0.0
+
+
 def test_kde_invalid_kernel_raises():
     data = RNG.standard_normal((50, 2))
     tree = spatial.KDTree.from_array(make_irn(data), leaf_size=10)
     q = make_irn(np.zeros((1, 2)))
     with pytest.raises((ValueError, RuntimeError)):
         tree.kernel_density(q, bandwidth=1.0, kernel="rbf") # type: ignore
+
+
+@pytest.mark.parametrize("tree_name", ["KDTree", "BallTree", "BruteForce"])
+@pytest.mark.parametrize("kernel", ["gaussian", "epanechnikov"])
+def test_kde_log_density_matches_log(tree_name, kernel):
+    data = RNG.standard_normal((200, 3))
+    tree = make_tree(tree_name, data)
+    queries = make_irn(RNG.standard_normal((10, 3)) * 0.5)
+    dens = to_np(tree.kernel_density(queries, bandwidth=0.8, kernel=kernel, normalize=True)).flatten() # type: ignore
+    log_dens = to_np(tree.kernel_density(queries, bandwidth=0.8, kernel=kernel, normalize=True, log_density=True)).flatten() # type: ignore
+    np.testing.assert_allclose(log_dens, np.log(dens), rtol=1e-6)
+
+
+def test_kde_log_density_no_underflow():
+    data = RNG.standard_normal((500, 30))
+    tree = spatial.KDTree.from_array(make_irn(data), leaf_size=20)
+    queries = make_irn(data[:5] + 0.1)
+    dens = to_np(tree.kernel_density(queries, bandwidth=0.01, normalize=False)).flatten() # type: ignore
+    log_dens = to_np(tree.kernel_density(queries, bandwidth=0.01, normalize=True, log_density=True)).flatten() # type: ignore
+    assert np.all(dens == 0.0)
+    assert np.all(np.isfinite(log_dens))
+
+
+def test_agg_tree_log_density_matches_log():
+    data = RNG.standard_normal((500, 2))
+    tree = spatial.AggTree(make_irn(data), bandwidth=0.5, atol=1e-6)
+    queries = make_irn(RNG.standard_normal((10, 2)))
+    dens = to_np(tree.kernel_density(queries, normalize=True)).flatten() # type: ignore
+    log_dens = to_np(tree.kernel_density(queries, normalize=True, log_density=True)).flatten() # type: ignore
+    np.testing.assert_allclose(log_dens, np.log(dens), rtol=1e-4)
+
+
+MEAN_SHIFT_NAMES = ["KDTree", "BallTree", "VPTree", "BruteForce"]
+
+
+def two_blobs(n: int = 200) -> np.ndarray:
+    centers = np.array([[0.0, 0.0], [5.0, 5.0]])
+    return centers[np.arange(n) % 2] + RNG.standard_normal((n, 2)) * 0.3
+
+
+@pytest.mark.parametrize("tree_name", MEAN_SHIFT_NAMES)
+@pytest.mark.parametrize("kernel", ["gaussian", "epanechnikov", "biweight"])
+def test_kde_gradient_matches_finite_difference(tree_name, kernel):
+    data = RNG.standard_normal((200, 2))
+    tree = make_tree(tree_name, data)
+    q = np.array([0.3, -0.2])
+    grad = to_np(tree.kde_gradient(make_irn(q), bandwidth=0.8, kernel=kernel, normalize=True))
+    assert grad.shape == (2,)
+    eps = 1e-5
+    for d in range(2):
+        step = np.zeros(2)
+        step[d] = eps
+        fp = float(to_np(tree.kernel_density(make_irn(q + step), bandwidth=0.8, kernel=kernel, normalize=True)).flat[0]) # type: ignore
+        fm = float(to_np(tree.kernel_density(make_irn(q - step), bandwidth=0.8, kernel=kernel, normalize=True)).flat[0]) # type: ignore
+        assert grad[d] == pytest.approx((fp - fm) / (2 * eps), rel=1e-4, abs=1e-8)
+
+
+@pytest.mark.parametrize("tree_name", MEAN_SHIFT_NAMES)
+def test_kde_gradient_batch_shape(tree_name):
+    tree = make_tree(tree_name, RNG.standard_normal((100, 3)))
+    grad = to_np(tree.kde_gradient(make_irn(RNG.standard_normal((7, 3))), bandwidth=1.0))
+    assert grad.shape == (7, 3)
+
+
+@pytest.mark.parametrize("tree_name", MEAN_SHIFT_NAMES)
+def test_mean_shift_finds_blob_modes(tree_name):
+    tree = make_tree(tree_name, two_blobs())
+    seeds = make_irn(np.array([[0.5, -0.5], [4.5, 5.5]]))
+    modes = to_np(tree.mean_shift(seeds, bandwidth=1.0))
+    np.testing.assert_allclose(modes[0], [0.0, 0.0], atol=0.15)
+    np.testing.assert_allclose(modes[1], [5.0, 5.0], atol=0.15)
+
+
+@pytest.mark.parametrize("tree_name", MEAN_SHIFT_NAMES)
+def test_mean_shift_default_seeds_in_original_order(tree_name):
+    data = two_blobs()
+    tree = make_tree(tree_name, data)
+    modes = to_np(tree.mean_shift(bandwidth=1.0))
+    assert modes.shape == data.shape
+    expected = np.array([[0.0, 0.0], [5.0, 5.0]])[np.arange(len(data)) % 2]
+    np.testing.assert_allclose(modes, expected, atol=0.15)
+
+
+@pytest.mark.parametrize("tree_name", MEAN_SHIFT_NAMES)
+def test_mean_shift_cluster_labels(tree_name):
+    data = two_blobs()
+    tree = make_tree(tree_name, data)
+    centers, labels = tree.mean_shift_cluster(bandwidth=1.0)
+    centers, labels = to_np(centers), to_np(labels)
+    assert centers.shape == (2, 2)
+    assert labels.shape == (len(data),)
+    assert labels[0] != labels[1]
+    assert np.all(labels[0::2] == labels[0])
+    assert np.all(labels[1::2] == labels[1])
+
+
+def test_mean_shift_non_euclidean_raises():
+    tree = spatial.KDTree.from_array(make_irn(two_blobs()), metric="manhattan")
+    with pytest.raises(ValueError):
+        tree.mean_shift(bandwidth=1.0)
+    with pytest.raises(ValueError):
+        tree.kde_gradient(make_irn(np.zeros(2)), bandwidth=1.0)
+
+
+@pytest.mark.parametrize("tree_name", MEAN_SHIFT_NAMES)
+def test_sample_shape_and_seed(tree_name):
+    tree = make_tree(tree_name, RNG.standard_normal((100, 3)))
+    a = to_np(tree.sample(50, bandwidth=0.5, seed=7))
+    b = to_np(tree.sample(50, bandwidth=0.5, seed=7))
+    assert a.shape == (50, 3)
+    np.testing.assert_array_equal(a, b)
+
+
+def test_sample_gaussian_moments():
+    data = RNG.standard_normal((500, 2)) * 2.0 + 1.0
+    tree = spatial.KDTree.from_array(make_irn(data), leaf_size=20)
+    samples = to_np(tree.sample(20000, bandwidth=0.5, seed=1))
+    np.testing.assert_allclose(samples.mean(axis=0), data.mean(axis=0), atol=0.1)
+    np.testing.assert_allclose(samples.var(axis=0), data.var(axis=0) + 0.25, rtol=0.05)
+
+
+@pytest.mark.parametrize("kernel", ["uniform", "epanechnikov", "triangular", "cosine", "biweight", "triweight"])
+def test_sample_compact_kernel_within_bandwidth(kernel):
+    data = RNG.standard_normal((50, 2)) * 10.0
+    tree = spatial.BallTree.from_array(make_irn(data), leaf_size=10)
+    samples = to_np(tree.sample(500, bandwidth=0.3, kernel=kernel, seed=2))
+    nearest = np.sqrt(((samples[:, None, :] - data[None, :, :]) ** 2).sum(axis=2)).min(axis=1)
+    assert np.all(nearest < 0.3)
+
+
+def test_sample_weights_pick_reference_points():
+    data = np.array([[0.0, 0.0], [10.0, 10.0], [-10.0, 5.0]])
+    tree = spatial.BruteForce.from_array(make_irn(data))
+    samples = to_np(tree.sample(200, bandwidth=0.1, kernel="epanechnikov", weights=[0.0, 1.0, 0.0], seed=3))
+    assert np.all(np.abs(samples - data[1]) < 0.1)
+
+
+def test_sample_bad_weights_raise():
+    tree = spatial.KDTree.from_array(make_irn(RNG.standard_normal((20, 2))))
+    with pytest.raises(ValueError):
+        tree.sample(10, weights=[1.0] * 5)
+    with pytest.raises(ValueError):
+        tree.sample(10, weights=[0.0] * 20)
+
+
+def test_sample_non_euclidean_raises():
+    tree = spatial.KDTree.from_array(make_irn(RNG.standard_normal((20, 2))), metric="manhattan")
+    with pytest.raises(ValueError):
+        tree.sample(10)
+
+
+@pytest.mark.parametrize("compact", [False, True])
+@pytest.mark.parametrize("atol", [1e-8, 0.5])
+def test_agg_tree_sample_moments(atol, compact):
+    data = RNG.standard_normal((2000, 2)) + np.array([3.0, -1.0])
+    tree = spatial.AggTree(make_irn(data), bandwidth=0.2, atol=atol, compact=compact)
+    samples = to_np(tree.sample(20000, seed=4))
+    assert samples.shape == (20000, 2)
+    np.testing.assert_allclose(samples.mean(axis=0), data.mean(axis=0), atol=0.1)
+    np.testing.assert_allclose(samples.var(axis=0), data.var(axis=0) + 0.04, rtol=0.1)
+
+
+@pytest.mark.parametrize("kernel", ["gaussian", "epanechnikov"])
+@pytest.mark.parametrize("bandwidth", [0.2, 0.5, 2.0])
+def test_agg_tree_query_time_matches_rebuild(bandwidth, kernel):
+    data = RNG.standard_normal((1000, 2))
+    queries = make_irn(RNG.standard_normal((20, 2)))
+    tree = spatial.AggTree(make_irn(data), bandwidth=1.0, atol=0.1)
+    rebuilt = spatial.AggTree(make_irn(data), bandwidth=bandwidth, kernel=kernel, atol=0.1, compact=True)
+    dens = to_np(tree.kernel_density(queries, bandwidth=bandwidth, kernel=kernel)).flatten() # type: ignore
+    expected = to_np(rebuilt.kernel_density(queries)).flatten() # type: ignore
+    np.testing.assert_allclose(dens, expected, rtol=1e-9, atol=1e-12)
+
+
+def test_agg_tree_zero_atol_is_exact():
+    data = RNG.standard_normal((1000, 3))
+    queries = make_irn(RNG.standard_normal((20, 3)))
+    tree = spatial.AggTree(make_irn(data), bandwidth=1.0, atol=0.5)
+    ball = spatial.BallTree.from_array(make_irn(data), leaf_size=20)
+    for bandwidth in [0.3, 1.5]:
+        dens = to_np(tree.kernel_density(queries, bandwidth=bandwidth, atol=0.0)).flatten() # type: ignore
+        expected = to_np(ball.kernel_density(queries, bandwidth=bandwidth)).flatten() # type: ignore
+        np.testing.assert_allclose(dens, expected, rtol=1e-9)
+
+
+def test_agg_tree_compact_rejects_overrides():
+    data = make_irn(RNG.standard_normal((200, 2)))
+    tree = spatial.AggTree(data, bandwidth=0.5, atol=0.1, compact=True)
+    tree.kernel_density(data, bandwidth=0.5, atol=0.2)
+    with pytest.raises(ValueError):
+        tree.kernel_density(data, bandwidth=1.0)
+    with pytest.raises(ValueError):
+        tree.kernel_density(data, kernel="epanechnikov")
+    with pytest.raises(ValueError):
+        tree.sample(10, bandwidth=1.0)
+
+
+def brute_kde(data: np.ndarray, queries: np.ndarray, bandwidth: float) -> np.ndarray:
+    sq = ((queries[:, None, :] - data[None, :, :]) ** 2).sum(axis=2)
+    return np.exp(-0.5 * sq / bandwidth ** 2).sum(axis=1)
+
+
+@pytest.mark.parametrize("bandwidth", [0.1, 0.5, 2.0])
+def test_agg_tree_error_bound_is_certified(bandwidth):
+    data = RNG.standard_normal((2000, 2))
+    queries = RNG.standard_normal((50, 2)) * 2.0
+    tree = spatial.AggTree(make_irn(data), bandwidth=bandwidth, atol=0.5)
+    dens, err = tree.kernel_density(make_irn(queries), normalize=False, return_error=True) # type: ignore
+    dens, err = to_np(dens).flatten(), to_np(err).flatten()
+    exact = brute_kde(data, queries, bandwidth)
+    assert np.all(err >= 0.0)
+    assert np.all(np.abs(dens - exact) <= err * (1 + 1e-9) + 1e-9 * exact)
+
+
+def test_agg_tree_log_error_bound_is_certified():
+    data = RNG.standard_normal((2000, 2))
+    queries = RNG.standard_normal((50, 2)) * 2.0
+    tree = spatial.AggTree(make_irn(data), bandwidth=0.3, atol=0.5)
+    log_dens, err = tree.kernel_density(make_irn(queries), normalize=False, log_density=True, return_error=True) # type: ignore
+    log_dens, err = to_np(log_dens).flatten(), to_np(err).flatten()
+    exact = np.log(brute_kde(data, queries, 0.3))
+    assert np.all(np.abs(log_dens - exact) <= err + 1e-9)
+
+
+@pytest.mark.parametrize("rtol", [1e-2, 1e-4])
+def test_agg_tree_rtol_meets_target(rtol):
+    data = RNG.standard_normal((2000, 2))
+    queries = RNG.standard_normal((50, 2)) * 2.0
+    tree = spatial.AggTree(make_irn(data), bandwidth=0.5, atol=1.0)
+    dens, err = tree.kernel_density(make_irn(queries), normalize=False, rtol=rtol, return_error=True) # type: ignore
+    dens, err = to_np(dens).flatten(), to_np(err).flatten()
+    exact = brute_kde(data, queries, 0.5)
+    assert np.all(err <= rtol * exact * (1 + 1e-9))
+    assert np.all(np.abs(dens - exact) <= rtol * exact * (1 + 1e-9))
+
+
+def test_agg_tree_exact_query_has_zero_error():
+    data = make_irn(RNG.standard_normal((300, 2)))
+    tree = spatial.AggTree(data, bandwidth=0.5)
+    _, err = tree.kernel_density(data, atol=0.0, kernel="epanechnikov", return_error=True) # type: ignore
+    assert np.all(to_np(err) == 0.0)
+
+
+def test_agg_tree_rtol_must_be_positive():
+    data = make_irn(RNG.standard_normal((50, 2)))
+    tree = spatial.AggTree(data)
+    with pytest.raises(ValueError):
+        tree.kernel_density(data, rtol=0.0)
+
+
+def elongated_clusters(n: int = 3000) -> np.ndarray:
+    t = RNG.standard_normal(n) * 0.5
+    centers = (np.arange(n) % 5) * 3.0
+    data = np.stack([centers + t / np.sqrt(2), t / np.sqrt(2)], axis=1)
+    return data + RNG.standard_normal((n, 2)) * 0.05
+
+
+@pytest.mark.parametrize("moments,rank", [("covariance", None), ("low_rank", 1)])
+def test_agg_tree_anisotropic_moments_more_accurate(moments, rank):
+    data = elongated_clusters()
+    queries = np.stack([RNG.uniform(-1.0, 13.0, 100), RNG.standard_normal(100) * 0.8], axis=1)
+    exact = brute_kde(data, queries, 1.0)
+    iso = spatial.AggTree(make_irn(data), bandwidth=1.0, atol=1.0)
+    aniso = spatial.AggTree(make_irn(data), bandwidth=1.0, atol=1.0, moments=moments, rank=rank)
+    iso_err = np.abs(to_np(iso.kernel_density(make_irn(queries), normalize=False)).flatten() - exact) # type: ignore
+    aniso_err = np.abs(to_np(aniso.kernel_density(make_irn(queries), normalize=False)).flatten() - exact) # type: ignore
+    assert aniso_err.mean() < 0.1 * iso_err.mean()
+
+
+def test_agg_tree_covariance_bound_is_certified():
+    data = elongated_clusters()
+    queries = RNG.standard_normal((50, 2)) * 3.0
+    tree = spatial.AggTree(make_irn(data), bandwidth=0.5, atol=1.0, moments="covariance")
+    dens, err = tree.kernel_density(make_irn(queries), normalize=False, return_error=True) # type: ignore
+    exact = brute_kde(data, queries, 0.5)
+    assert np.all(np.abs(to_np(dens).flatten() - exact) <= to_np(err).flatten() * (1 + 1e-9) + 1e-9 * exact)
+
+
+def test_agg_tree_moments_validation():
+    data = make_irn(RNG.standard_normal((50, 2)))
+    with pytest.raises(ValueError):
+        spatial.AggTree(data, moments="full")
+    with pytest.raises(ValueError):
+        spatial.AggTree(data, moments="low_rank", rank=0)
+    with pytest.raises(ValueError):
+        spatial.AggTree(data, metric="manhattan", moments="covariance")
+
+
+def test_agg_tree_moments_survive_pickle():
+    data = make_irn(elongated_clusters(500))
+    tree = spatial.AggTree(data, bandwidth=0.5, moments="low_rank", rank=1)
+    restored = pickle.loads(pickle.dumps(tree))
+    np.testing.assert_allclose(
+        to_np(restored.kernel_density(data)), to_np(tree.kernel_density(data)) # type: ignore
+    )
+
+
+def test_agg_tree_insert_matches_brute_force():
+    data = RNG.standard_normal((500, 2))
+    extra = RNG.standard_normal((400, 2)) + 2.0
+    queries = RNG.standard_normal((50, 2)) * 2.0
+    tree = spatial.AggTree(make_irn(data), leaf_size=10, bandwidth=0.5, atol=0.5)
+    tree.insert(make_irn(extra[:300]))
+    for point in extra[300:]:
+        tree.insert(make_irn(point))
+    combined = np.vstack([data, extra])
+    exact = brute_kde(combined, queries, 0.5)
+    dens = to_np(tree.kernel_density(make_irn(queries), atol=0.0, normalize=False)).flatten() # type: ignore
+    np.testing.assert_allclose(dens, exact, rtol=1e-9)
+    dens, err = tree.kernel_density(make_irn(queries), normalize=False, return_error=True) # type: ignore
+    assert np.all(np.abs(to_np(dens).flatten() - exact) <= to_np(err).flatten() * (1 + 1e-9) + 1e-9 * exact)
+
+
+@pytest.mark.parametrize("moments,rank", [("isotropic", None), ("covariance", None), ("low_rank", 1)])
+def test_agg_tree_remove_matches_brute_force(moments, rank):
+    data = elongated_clusters(1000)
+    extra = RNG.standard_normal((200, 2))
+    queries = RNG.standard_normal((50, 2)) * 3.0
+    tree = spatial.AggTree(make_irn(data), leaf_size=10, bandwidth=0.5, atol=0.5, moments=moments, rank=rank)
+    tree.insert(make_irn(extra))
+    removed = list(range(0, 1200, 3))
+    tree.remove(removed)
+    combined = np.delete(np.vstack([data, extra]), removed, axis=0)
+    exact = brute_kde(combined, queries, 0.5)
+    dens = to_np(tree.kernel_density(make_irn(queries), atol=0.0, normalize=False)).flatten() # type: ignore
+    np.testing.assert_allclose(dens, exact, rtol=1e-9)
+    dens, err = tree.kernel_density(make_irn(queries), normalize=False, return_error=True) # type: ignore
+    assert np.all(np.abs(to_np(dens).flatten() - exact) <= to_np(err).flatten() * (1 + 1e-9) + 1e-9 * exact)
+
+
+def test_agg_tree_updates_survive_pickle():
+    data = make_irn(RNG.standard_normal((200, 2)))
+    tree = spatial.AggTree(data, bandwidth=0.5)
+    tree.insert(make_irn(RNG.standard_normal((50, 2))))
+    tree.remove([0, 210])
+    restored = pickle.loads(pickle.dumps(tree))
+    restored.remove([220])
+    tree.remove([220])
+    np.testing.assert_allclose(
+        to_np(restored.kernel_density(data)), to_np(tree.kernel_density(data)) # type: ignore
+    )
+
+
+def test_agg_tree_update_validation():
+    data = make_irn(RNG.standard_normal((50, 2)))
+    compact = spatial.AggTree(data, compact=True)
+    with pytest.raises(ValueError):
+        compact.insert(data)
+    with pytest.raises(ValueError):
+        compact.remove([0])
+    tree = spatial.AggTree(data)
+    with pytest.raises(ValueError):
+        tree.insert(make_irn(RNG.standard_normal((5, 3))))
+    with pytest.raises(ValueError):
+        tree.remove([50])
+    with pytest.raises(ValueError):
+        tree.remove([1, 1])
+    with pytest.raises(ValueError):
+        tree.remove(list(range(50)))
+
+
+def brute_metric_kde(data: np.ndarray, queries: np.ndarray, bandwidth: float, metric: str) -> np.ndarray:
+    diff = queries[:, None, :] - data[None, :, :]
+    if metric == "manhattan":
+        dist = np.abs(diff).sum(axis=2)
+    elif metric == "chebyshev":
+        dist = np.abs(diff).max(axis=2)
+    else:
+        unit_q = queries / np.linalg.norm(queries, axis=1, keepdims=True)
+        unit_d = data / np.linalg.norm(data, axis=1, keepdims=True)
+        dist = 1.0 - unit_q @ unit_d.T
+    return np.exp(-0.5 * (dist / bandwidth) ** 2).sum(axis=1)
+
+
+@pytest.mark.parametrize("metric", ["manhattan", "chebyshev", "cosine"])
+def test_agg_tree_non_euclidean_bound_is_certified(metric):
+    data = RNG.standard_normal((2000, 3))
+    queries = RNG.standard_normal((50, 3)) * 2.0
+    tree = spatial.AggTree(make_irn(data), metric=metric, bandwidth=0.5, atol=5.0)
+    exact = brute_metric_kde(data, queries, 0.5, metric)
+    dens, err = tree.kernel_density(make_irn(queries), normalize=False, return_error=True) # type: ignore
+    dens, err = to_np(dens).flatten(), to_np(err).flatten()
+    assert np.all(np.abs(dens - exact) <= err * (1 + 1e-9) + 1e-9 * exact)
+    dens = to_np(tree.kernel_density(make_irn(queries), atol=0.0, normalize=False)).flatten() # type: ignore
+    np.testing.assert_allclose(dens, exact, rtol=1e-9)
+
+
+@pytest.mark.parametrize("metric", ["euclidean", "manhattan", "chebyshev"])
+def test_agg_tree_neighbors_match_ball_tree(metric):
+    data = RNG.standard_normal((1000, 3))
+    queries = make_irn(RNG.standard_normal((20, 3)))
+    agg = spatial.AggTree(make_irn(data), metric=metric, leaf_size=16)
+    ball = spatial.BallTree.from_array(make_irn(data), metric=metric, leaf_size=16)
+    knn_agg, knn_ball = agg.query_knn(queries, 7), ball.query_knn(queries, 7)
+    np.testing.assert_array_equal(to_np(knn_agg.indices), to_np(knn_ball.indices))
+    np.testing.assert_allclose(to_np(knn_agg.distances), to_np(knn_ball.distances))
+    for a, b in zip(agg.query_radius(queries, 0.8).split(), ball.query_radius(queries, 0.8).split()):
+        assert sorted(a.indices.tolist()) == sorted(b.indices.tolist())
+
+
+def test_agg_tree_neighbors_after_updates():
+    data = RNG.standard_normal((500, 2))
+    extra = RNG.standard_normal((100, 2)) + 3.0
+    tree = spatial.AggTree(make_irn(data), leaf_size=8)
+    tree.insert(make_irn(extra))
+    tree.remove(list(range(0, 600, 2)))
+    combined = np.vstack([data, extra])
+    query = np.array([3.0, 3.0])
+    result = tree.query_knn(make_irn(query), 5)
+    dist = np.linalg.norm(combined - query, axis=1)
+    dist[::2] = np.inf
+    assert sorted(to_np(result.indices).flatten().tolist()) == sorted(np.argsort(dist)[:5].tolist())
+    result = tree.query_radius(make_irn(query), 1.0)
+    assert sorted(to_np(result.indices).flatten().tolist()) == sorted(np.flatnonzero(dist <= 1.0).tolist())
+
+
+def test_agg_tree_compact_rejects_neighbor_queries():
+    data = make_irn(RNG.standard_normal((200, 2)))
+    tree = spatial.AggTree(data, compact=True)
+    with pytest.raises(ValueError):
+        tree.query_knn(data, 3)
+    with pytest.raises(ValueError):
+        tree.query_radius(data, 0.5)
+
+
+@pytest.mark.parametrize("dim,bandwidth", [(1, 0.05), (1, 0.5), (2, 0.2), (2, 1.0)])
+def test_kd_tree_gauss_transform_within_atol(dim, bandwidth):
+    data = RNG.standard_normal((3000, dim))
+    queries = RNG.standard_normal((100, dim)) * 1.5
+    tree = spatial.KDTree.from_array(make_irn(data), leaf_size=16)
+    exact = brute_kde(data, queries, bandwidth)
+    for atol in [1e-6, 1e-2, 1.0]:
+        dens = to_np(tree.gauss_transform(make_irn(queries), bandwidth=bandwidth, atol=atol)).flatten() # type: ignore
+        assert np.all(np.abs(dens - exact) <= atol * (1 + 1e-9) + 1e-9 * exact)
+
+
+def test_kd_tree_gauss_transform_normalize_matches_kde():
+    data = RNG.standard_normal((1000, 2))
+    queries = RNG.standard_normal((20, 2))
+    tree = spatial.KDTree.from_array(make_irn(data))
+    dens = to_np(tree.gauss_transform(make_irn(queries), bandwidth=0.5, atol=1e-8, normalize=True)).flatten() # type: ignore
+    expected = to_np(tree.kernel_density(make_irn(queries), bandwidth=0.5, kernel="gaussian", normalize=True)).flatten() # type: ignore
+    np.testing.assert_allclose(dens, expected, rtol=1e-6)
+    assert isinstance(tree.gauss_transform(make_irn(queries[0]), bandwidth=0.5), float)
+    assert to_np(tree.gauss_transform(bandwidth=0.5)).shape == (1000,) # type: ignore
+
+
+def test_kd_tree_gauss_transform_validation():
+    data = make_irn(RNG.standard_normal((200, 3)))
+    with pytest.raises(ValueError):
+        spatial.KDTree.from_array(data, metric="manhattan").gauss_transform(data)
+    tree = spatial.KDTree.from_array(data)
+    with pytest.raises(ValueError):
+        tree.gauss_transform(data, bandwidth=0.0)
+    with pytest.raises(ValueError):
+        tree.gauss_transform(data, atol=-1.0)
+    with pytest.raises(ValueError):
+        tree.gauss_transform(data, order=0)
+    with pytest.raises(ValueError):
+        tree.gauss_transform(data, order=50)
+
+
+@pytest.mark.parametrize("tree_name", ["KDTree", "BallTree", "VPTree", "RPTree", "AggTree"])
+@pytest.mark.parametrize("preserve_array", [True, False])
+def test_data_and_default_queries_follow_input_order(tree_name, preserve_array):
+    data = RNG.standard_normal((200, 3))
+    tree = getattr(spatial, tree_name).from_array(make_irn(data), preserve_array=preserve_array)
+    if tree_name != "AggTree":
+        np.testing.assert_allclose(to_np(tree.data()), data)
+    np.testing.assert_allclose(
+        to_np(tree.kernel_density(bandwidth=0.5)),
+        to_np(tree.kernel_density(make_irn(data), bandwidth=0.5)),
+    )
+
+
+def test_compact_agg_tree_default_queries_raise():
+    data = RNG.standard_normal((200, 3))
+    tree = spatial.AggTree(make_irn(data), compact=True)
+    with pytest.raises(ValueError):
+        tree.kernel_density()
+
+
+@pytest.mark.parametrize("tree_name", list(TREES))
+def test_batch_knn_pads_when_k_exceeds_points(tree_name):
+    data = RNG.standard_normal((5, 2))
+    tree = make_tree(tree_name, data)
+    result = tree.query_knn(make_irn(data[:3]), 8)
+    indices = to_np(result.indices)
+    distances = to_np(result.distances)
+    assert indices.shape == (3, 8)
+    assert np.all(indices[:, 5:] == -1)
+    assert np.all(np.isnan(distances[:, 5:]))
+    assert sorted(indices[0, :5].tolist()) == list(range(5))
+
+
+def nan_data():
+    data = RNG.standard_normal((300, 3))
+    data[[4, 57], [1, 0]] = np.nan
+    return data
+
+
+@pytest.mark.parametrize("tree_cls", [spatial.KDTree, spatial.BallTree, spatial.VPTree, spatial.BruteForce])
+def test_nan_policy_raise_rejects_data_and_queries(tree_cls):
+    with pytest.raises(ValueError):
+        tree_cls(nan_data())
+    tree = tree_cls(RNG.standard_normal((100, 3)))
+    with pytest.raises(ValueError):
+        tree.query_knn([0.0, np.nan, 0.0], 3)
+    with pytest.raises(ValueError):
+        tree.kernel_density([[0.0, np.nan, 0.0]])
+
+
+@pytest.mark.parametrize("tree_cls", [spatial.KDTree, spatial.BallTree, spatial.VPTree, spatial.BruteForce])
+def test_nan_policy_raise_rejects_infinity(tree_cls):
+    data = RNG.standard_normal((100, 3))
+    data[7, 2] = -np.inf
+    with pytest.raises(irn.NonFiniteError):
+        tree_cls(data)
+    tree = tree_cls(RNG.standard_normal((100, 3)))
+    with pytest.raises(irn.NonFiniteError):
+        tree.query_knn([0.0, np.inf, 0.0], 3)
+    with pytest.raises(irn.NonFiniteError):
+        tree.kernel_density([[np.inf, 0.0, 0.0]])
+
+
+@pytest.mark.parametrize("tree_cls", [spatial.KDTree, spatial.BallTree, spatial.VPTree, spatial.BruteForce])
+def test_nan_policy_drop_keeps_input_numbering(tree_cls):
+    data = nan_data()
+    tree = tree_cls(data, nan_policy="drop")
+    assert tree.nan_policy == "drop"
+    assert to_np(tree.nan_rows).tolist() == [4, 57]
+    np.testing.assert_array_equal(to_np(tree.data()), data)
+
+    result = tree.query_knn(data[[10, 4, 200]], 2)
+    indices = to_np(result.indices)
+    assert indices[0, 0] == 10 and indices[2, 0] == 200
+    assert indices[1].tolist() == [-1, -1]
+    assert np.all(np.isnan(to_np(result.distances)[1]))
+
+    hits = to_np(tree.query_radius(data[58], 10.0).indices)
+    assert 4 not in hits and 57 not in hits
+    assert np.isnan(to_np(tree.kernel_density(data[[4, 5]]))[0])
+
+
+@pytest.mark.parametrize("tree_cls", [spatial.KDTree, spatial.BallTree, spatial.RPTree, spatial.BruteForce])
+def test_nan_policy_ignore_dims_finds_partial_rows(tree_cls):
+    data = nan_data()
+    tree = tree_cls(data, nan_policy="ignore_dims")
+    assert to_np(tree.query_knn(data[4], 1).indices).tolist() == [4]
+    query = data[57].copy()
+    query[0] = 0.0
+    assert 57 in to_np(tree.query_knn(query, 5).indices)
+    dens = to_np(tree.kernel_density(data[[4, 5]], bandwidth=0.5))
+    assert np.all(np.isfinite(dens))
+
+
+def test_nan_policy_ignore_dims_partial_distance_is_scaled():
+    data = np.array([[0.0, 0.0], [3.0, np.nan]])
+    tree = spatial.BruteForce(data, nan_policy="ignore_dims")
+    result = tree.query_knn([0.0, 0.0], 2)
+    assert to_np(result.indices).tolist() == [0, 1]
+    np.testing.assert_allclose(to_np(result.distances), [0.0, 3.0 * math.sqrt(2.0)])
+
+
+def test_nan_policy_invalid():
+    with pytest.raises(ValueError):
+        spatial.KDTree(RNG.standard_normal((50, 2)), nan_policy="skip")
+
+
+def test_nan_policy_survives_pickle():
+    data = nan_data()
+    tree = spatial.KDTree(data, nan_policy="drop")
+    restored = pickle.loads(pickle.dumps(tree))
+    assert restored.nan_policy == "drop"
+    assert to_np(restored.nan_rows).tolist() == [4, 57]
+    np.testing.assert_array_equal(to_np(restored.query_knn(data[100], 1).indices), [100])
+
+
+def test_spatial_index_nan_policy_insert():
+    data = RNG.standard_normal((100, 3))
+    index = spatial.SpatialIndex(data, tree_type="kd")
+    with pytest.raises(ValueError):
+        index.insert([1.0, np.nan, 0.0])
+    assert index.n_points == 100
+
+    index = spatial.SpatialIndex(nan_data(), tree_type="kd", nan_policy="ignore_dims")
+    index.insert([[5.0, np.nan, 5.0], [6.0, 6.0, 6.0]])
+    assert to_np(index.nan_rows).tolist() == [4, 57, 300]
+    assert to_np(index.query_knn([6.0, 6.0, 6.0], 1).indices).tolist() == [301]
+    assert to_np(index.query_knn([5.0, 0.0, 5.0], 1).indices).tolist() == [300]
+    assert to_np(index.data()).shape == (302, 3)
+
+
+def test_error_classes_are_value_errors():
+    for exc in (irn.DimensionMismatchError, irn.EmptyIndexError, irn.InvalidKError,
+                irn.NonFiniteError, irn.UninitializedError):
+        assert issubclass(exc, irn.IronForestError)
+    assert issubclass(irn.IronForestError, ValueError)
+    assert spatial.InvalidKError is irn.InvalidKError
+
+
+@pytest.mark.parametrize("tree_cls", [spatial.KDTree, spatial.BallTree, spatial.VPTree, spatial.BruteForce])
+def test_query_errors_map_to_exception_classes(tree_cls):
+    tree = tree_cls(RNG.standard_normal((100, 3)))
+    with pytest.raises(irn.DimensionMismatchError):
+        tree.query_knn(RNG.standard_normal((4, 2)), 3)
+    with pytest.raises(irn.DimensionMismatchError):
+        tree.kernel_density(RNG.standard_normal((4, 5)))
+    with pytest.raises(irn.InvalidKError):
+        tree.query_ann(RNG.standard_normal((4, 3)), 0)
+    with pytest.raises(irn.NonFiniteError):
+        tree.query_radius([0.0, np.nan, 0.0], 1.0)
+    with pytest.raises(irn.NonFiniteError):
+        tree_cls(nan_data())
+
+
+def test_spatial_index_errors_map_to_exception_classes():
+    index = spatial.SpatialIndex(RNG.standard_normal((100, 3)), tree_type="kd")
+    with pytest.raises(irn.DimensionMismatchError):
+        index.insert(RNG.standard_normal((2, 4)))
+    with pytest.raises(irn.InvalidKError):
+        index.query_knn([0.0, 0.0, 0.0], 0)
+    with pytest.raises(irn.NonFiniteError):
+        index.query_knn([0.0, np.nan, 0.0], 1)
+    assert index.n_points == 100
+
+
+def low_rank_data(n=2000, dim=64, rank=4):
+    return RNG.standard_normal((n, rank)) @ RNG.standard_normal((rank, dim))
+
+
+def test_recommend_tree_reports_rule_and_stats():
+    rec = spatial.recommend_tree(low_rank_data())
+    assert rec.tree_type == "rp_tree"
+    assert rec.rule == "low_intrinsic_ratio"
+    assert (rec.n_points, rec.dim) == (2000, 64)
+    assert rec.estimator == "mle"
+    assert 2.5 < rec.intrinsic_dim < 5
+    curve = to_np(rec.explained_variance)
+    assert curve.shape == (64,)
+    assert np.all(np.diff(curve) >= -1e-12)
+    assert curve[-1] == pytest.approx(1.0)
+    assert rec.benchmark == {}
+
+
+@pytest.mark.parametrize("workload, tree_type", [("knn", "rp_tree"), ("ann", "rp_tree"), ("kde", "ball_tree")])
+def test_recommend_tree_workload(workload, tree_type):
+    assert spatial.recommend_tree(low_rank_data(), workload=workload).tree_type == tree_type
+
+
+def test_recommend_tree_matches_auto_index():
+    data = low_rank_data()
+    for workload in ("knn", "ann", "kde"):
+        index = spatial.SpatialIndex(data, workload=workload)
+        assert index.tree_type == spatial.recommend_tree(data, workload=workload).tree_type
+
+
+def test_recommend_tree_thresholds():
+    data = RNG.standard_normal((500, 3))
+    assert spatial.recommend_tree(data).rule == "small_n"
+    rec = spatial.recommend_tree(data, thresholds={"min_tree_points": 100})
+    assert (rec.tree_type, rec.rule) == ("kd_tree", "low_dim")
+    with pytest.raises(ValueError):
+        spatial.recommend_tree(data, thresholds={"max_depth": 3})
+    with pytest.raises(ValueError):
+        spatial.recommend_tree(data, workload="radius")
+
+
+def test_recommend_tree_benchmark():
+    rec = spatial.recommend_tree(RNG.standard_normal((300, 4)), benchmark=20)
+    assert set(rec.benchmark) == {"kd_tree", "ball_tree", "vp_tree", "rp_tree", "brute_force"}
+    for times in rec.benchmark.values():
+        assert times["build_seconds"] >= 0.0 and times["query_seconds"] >= 0.0
+
+
+
+def swiss_roll(n=2000):
+    t = 1.5 * np.pi * (1 + 2 * RNG.random(n))
+    h = 21 * RNG.random(n)
+    return np.column_stack([t * np.cos(t), h, t * np.sin(t)])
+
+
+@pytest.mark.parametrize("method", ["mle", "twonn", "correlation"])
+def test_intrinsic_dim_curved_manifold(method):
+    assert spatial.intrinsic_dim(swiss_roll(), method=method) == pytest.approx(2.0, abs=0.5)
+
+
+def test_intrinsic_dim_pca_overestimates_curved_manifold():
+    assert spatial.intrinsic_dim(swiss_roll(), method="pca") == 3.0
+
+
+@pytest.mark.parametrize("method", ["mle", "twonn", "correlation"])
+def test_intrinsic_dim_linear_subspace(method):
+    assert spatial.intrinsic_dim(low_rank_data(rank=6), method=method) == pytest.approx(6.0, rel=0.3)
+
+
+def test_intrinsic_dim_seeded():
+    data = low_rank_data()
+    a = spatial.intrinsic_dim(data, sample_size=500, seed=3)
+    assert a == spatial.intrinsic_dim(data, sample_size=500, seed=3)
+
+
+def test_intrinsic_dim_invalid_input():
+    with pytest.raises(ValueError):
+        spatial.intrinsic_dim(np.ones((50, 3)))
+    with pytest.raises(ValueError):
+        spatial.intrinsic_dim(RNG.standard_normal((5, 3)), k=10)
+    with pytest.raises(ValueError):
+        spatial.intrinsic_dim(RNG.standard_normal((50, 3)), method="svd")
+    with pytest.raises(irn.DimensionMismatchError):
+        spatial.intrinsic_dim([1.0, 2.0, 3.0])
+
+
+def test_recommend_tree_estimator_threshold():
+    rec = spatial.recommend_tree(low_rank_data(), thresholds={"estimator": "pca", "variance_threshold": 0.99})
+    assert rec.estimator == "pca"
+    assert rec.intrinsic_dim == 4.0
+
+
+# ---------------------------------------------------------------------------
+# PCAReducer
+# ---------------------------------------------------------------------------
+
+def test_pca_reducer_matches_numpy_svd():
+    data = RNG.standard_normal((300, 8)) * np.arange(1, 9)
+    pca = spatial.PCAReducer(data, n_components=3)
+    centered = data - data.mean(axis=0)
+    _, s, vt = np.linalg.svd(centered, full_matrices=False)
+    np.testing.assert_allclose(to_np(pca.mean), data.mean(axis=0), atol=1e-12)
+    np.testing.assert_allclose(to_np(pca.explained_variance), s[:3] ** 2 / 299, rtol=1e-9)
+    np.testing.assert_allclose(to_np(pca.explained_variance_ratio), s[:3] ** 2 / np.sum(s ** 2), rtol=1e-9)
+    np.testing.assert_allclose(np.abs(to_np(pca.components) @ vt[:3].T), np.eye(3), atol=1e-8)
+    assert (pca.input_dim, pca.output_dim) == (8, 3)
+
+
+def test_pca_reducer_inverse_transform_roundtrip():
+    data = low_rank_data(n=500, dim=20, rank=4)
+    pca, reduced = spatial.PCAReducer.fit_transform(data, n_components=4)
+    assert to_np(reduced).shape == (500, 4)
+    np.testing.assert_allclose(to_np(pca.inverse_transform(reduced)), data, atol=1e-8)
+    np.testing.assert_allclose(to_np(pca.transform(data[0])), to_np(reduced)[0], atol=1e-12)
+
+
+def test_pca_reducer_whiten():
+    data = RNG.standard_normal((400, 6)) * np.array([10.0, 5.0, 2.0, 1.0, 0.5, 0.1])
+    pca = spatial.PCAReducer(data, n_components=4, whiten=True)
+    reduced = to_np(pca.transform(data))
+    np.testing.assert_allclose(reduced.var(axis=0, ddof=1), np.ones(4), rtol=1e-9)
+    plain = spatial.PCAReducer(data, n_components=4)
+    np.testing.assert_allclose(
+        to_np(pca.inverse_transform(reduced)),
+        to_np(plain.inverse_transform(plain.transform(data))),
+        atol=1e-8,
+    )
+
+
+def test_pca_reducer_variance_threshold():
+    data = low_rank_data(n=500, dim=20, rank=4) + 1e-3 * RNG.standard_normal((500, 20))
+    pca = spatial.PCAReducer(data, variance_threshold=0.99)
+    assert pca.output_dim == 4
+    assert to_np(pca.explained_variance_ratio).sum() >= 0.99
+    assert spatial.PCAReducer(data).output_dim == 20
+
+
+def test_pca_reducer_randomized_matches_full():
+    data = low_rank_data(n=1000, dim=64, rank=6) + 0.01 * RNG.standard_normal((1000, 64))
+    full = spatial.PCAReducer(data, n_components=6, solver="full")
+    rand = spatial.PCAReducer(data, n_components=6, solver="randomized", seed=0)
+    np.testing.assert_allclose(to_np(rand.explained_variance), to_np(full.explained_variance), rtol=1e-6)
+    overlap = np.abs(to_np(rand.components) @ to_np(full.components).T)
+    np.testing.assert_allclose(overlap, np.eye(6), atol=1e-4)
+
+
+def test_pca_reducer_wide_data():
+    data = RNG.standard_normal((20, 50))
+    pca = spatial.PCAReducer(data, solver="full")
+    assert pca.output_dim == 50
+    np.testing.assert_allclose(to_np(pca.inverse_transform(pca.transform(data))), data, atol=1e-8)
+
+
+def test_pca_reducer_serialization(tmp_path_str):
+    data = RNG.standard_normal((100, 5))
+    pca = spatial.PCAReducer(data, n_components=3, whiten=True)
+    expected = to_np(pca.transform(data))
+    loaded = pickle.loads(pickle.dumps(pca))
+    np.testing.assert_array_equal(to_np(loaded.transform(data)), expected)
+    assert loaded.whiten
+    pca.save(tmp_path_str)
+    np.testing.assert_array_equal(to_np(spatial.PCAReducer.load(tmp_path_str).transform(data)), expected)
+
+
+@pytest.mark.parametrize("tree_name", list(TREES))
+def test_pca_reducer_preprocesses_trees(tree_name):
+    data = low_rank_data(n=500, dim=32, rank=3)
+    pca = spatial.PCAReducer(data, n_components=3)
+    tree = make_tree(tree_name, to_np(pca.transform(data)))
+    reduced_idx = to_np(tree.query_knn(pca.transform(data[:5]), 5).indices)
+    full_idx = to_np(spatial.BruteForce.from_array(make_irn(data)).query_knn(make_irn(data[:5]), 5).indices)
+    np.testing.assert_array_equal(np.sort(reduced_idx, axis=1), np.sort(full_idx, axis=1))
+
+
+def test_pca_reducer_invalid_input():
+    data = RNG.standard_normal((50, 4))
+    with pytest.raises(ValueError):
+        spatial.PCAReducer(data, n_components=2, variance_threshold=0.9)
+    with pytest.raises(ValueError):
+        spatial.PCAReducer(data, n_components=5)
+    with pytest.raises(ValueError):
+        spatial.PCAReducer(data, variance_threshold=1.5)
+    with pytest.raises(ValueError):
+        spatial.PCAReducer(data, variance_threshold=0.9, solver="randomized")
+    with pytest.raises(ValueError):
+        spatial.PCAReducer(data, solver="arpack")
+    with pytest.raises(ValueError):
+        spatial.PCAReducer(data[:1])
+    pca = spatial.PCAReducer(data, n_components=2)
+    with pytest.raises(irn.DimensionMismatchError):
+        pca.transform(RNG.standard_normal((3, 5)))
+    with pytest.raises(irn.DimensionMismatchError):
+        pca.inverse_transform(RNG.standard_normal((3, 4)))
+
+
+# ---------------------------------------------------------------------------
+# Random projection types
+# ---------------------------------------------------------------------------
+
+PROJECTION_TYPES = ["gaussian", "sparse", "achlioptas", "very_sparse", "hadamard"]
+
+
+def pairwise_sq_dists(x):
+    diff = x[:, None, :] - x[None, :, :]
+    return (diff ** 2).sum(axis=-1)
+
+
+@pytest.mark.parametrize("projection_type", PROJECTION_TYPES)
+def test_projection_reducer_preserves_distances(projection_type):
+    data = RNG.standard_normal((60, 300))
+    reducer = spatial.ProjectionReducer(300, 256, projection_type=projection_type, seed=0)
+    reduced = to_np(reducer.transform(data))
+    assert reduced.shape == (60, 256)
+    mask = ~np.eye(60, dtype=bool)
+    ratios = pairwise_sq_dists(reduced)[mask] / pairwise_sq_dists(data)[mask]
+    assert abs(ratios.mean() - 1.0) < 0.1
+    assert np.all(np.abs(ratios - 1.0) < 0.5)
+
+
+def test_hadamard_projection_full_rank_is_isometry():
+    data = RNG.standard_normal((10, 5))
+    reducer = spatial.ProjectionReducer(5, 8, projection_type="hadamard", seed=1)
+    reduced = to_np(reducer.transform(data))
+    np.testing.assert_allclose(np.linalg.norm(reduced, axis=1), np.linalg.norm(data, axis=1), rtol=1e-12)
+
+
+@pytest.mark.parametrize("projection_type", ["achlioptas", "very_sparse", "hadamard"])
+def test_projection_reducer_serialization(projection_type, tmp_path_str):
+    data = RNG.standard_normal((20, 40))
+    reducer = spatial.ProjectionReducer(40, 16, projection_type=projection_type, seed=2)
+    expected = to_np(reducer.transform(data))
+    np.testing.assert_array_equal(to_np(pickle.loads(pickle.dumps(reducer)).transform(data)), expected)
+    reducer.save(tmp_path_str)
+    np.testing.assert_array_equal(to_np(spatial.ProjectionReducer.load(tmp_path_str).transform(data)), expected)
+
+
+def test_johnson_lindenstrauss_min_dim():
+    for n, eps in [(1000, 0.1), (100_000, 0.2), (10, 0.5)]:
+        expected = math.ceil(4 * math.log(n) / (eps ** 2 / 2 - eps ** 3 / 3))
+        assert spatial.johnson_lindenstrauss_min_dim(n, eps) == expected
+    with pytest.raises(ValueError):
+        spatial.johnson_lindenstrauss_min_dim(100, eps=1.0)
+    with pytest.raises(ValueError):
+        spatial.johnson_lindenstrauss_min_dim(0)
+
+
+def test_projection_reducer_fit_transform_eps():
+    data = RNG.standard_normal((50, 2000))
+    reducer, reduced = spatial.ProjectionReducer.fit_transform(data, projection_type="hadamard", eps=0.5, seed=0)
+    assert reducer.output_dim == spatial.johnson_lindenstrauss_min_dim(50, 0.5)
+    assert to_np(reduced).shape == (50, reducer.output_dim)
+    with pytest.raises(ValueError):
+        spatial.ProjectionReducer.fit_transform(RNG.standard_normal((50, 20)), eps=0.1)
+    with pytest.raises(ValueError, match="not both"):
+        spatial.ProjectionReducer.fit_transform(data, output_dim=10, eps=0.5)
+
+
+def test_projection_reducer_invalid_dims():
+    with pytest.raises(ValueError):
+        spatial.ProjectionReducer(5, 9, projection_type="hadamard")
+    with pytest.raises(ValueError):
+        spatial.ProjectionReducer.fit_transform(RNG.standard_normal((10, 5)), output_dim=9, projection_type="hadamard")
+    with pytest.raises(ValueError):
+        spatial.ProjectionReducer(5, 2, projection_type="fourier")
+    for density in [0.0, -0.1, 1.5, float("nan")]:
+        with pytest.raises(ValueError):
+            spatial.ProjectionReducer(5, 2, projection_type="sparse", density=density)
+
+
+@pytest.mark.parametrize("projection", PROJECTION_TYPES)
+def test_rp_tree_projection_types_exact_knn(projection):
+    data = RNG.standard_normal((400, 24))
+    tree = spatial.RPTree(data, leaf_size=20, projection=projection, seed=0)
+    expected = to_np(spatial.BruteForce(data).query_knn(data[:5], 5).indices)
+    got = to_np(tree.query_knn(data[:5], 5).indices)
+    np.testing.assert_array_equal(np.sort(got, axis=1), np.sort(expected, axis=1))
+
+
+@pytest.mark.parametrize("projection", PROJECTION_TYPES)
+def test_rp_forest_projection_types_ann_recall(projection):
+    data = RNG.standard_normal((400, 8))
+    forest = spatial.RPForest(data, leaf_size=20, projection=projection, seed=0)
+    expected = to_np(spatial.BruteForce(data).query_knn(data[:20], 5).indices)
+    got = to_np(forest.query_ann(data[:20], 5, n_candidates=200).indices)
+    recall = np.mean([len(set(g) & set(e)) / 5 for g, e in zip(got, expected)])
+    assert recall >= 0.8